@@ -0,0 +1,290 @@
+use anyhow::*;
+use image::{codecs::gif::GifEncoder, Delay, Frame, RgbaImage};
+use std::{
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::ops::{OpSpec, MAX_PARAMS, PROGRESS_PARAM};
+
+/// A parameter interpolated linearly across the output sequence, written as
+/// `key=start..end` on the command line.
+#[derive(Clone)]
+pub struct Animation {
+    pub key: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl FromStr for Animation {
+    type Err = Error;
+
+    fn from_str(arg: &str) -> Result<Self> {
+        let (key, range) = arg
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected key=start..end, got '{}'", arg))?;
+        let (start, end) = range
+            .split_once("..")
+            .ok_or_else(|| anyhow!("Expected a start..end range, got '{}'", range))?;
+
+        Ok(Self {
+            key: key.to_string(),
+            start: start.trim().parse().context("Invalid animation start")?,
+            end: end.trim().parse().context("Invalid animation end")?,
+        })
+    }
+}
+
+impl fmt::Display for Animation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}..{}", self.key, self.start, self.end)
+    }
+}
+
+/// Parameter values for every frame, starting from `base` and applying each
+/// animation at `t = frame / (frames - 1)`. Animations have to stay within
+/// the ranges of their parameters, like static ones.
+pub fn frame_params(
+    op: &OpSpec<'_>,
+    base: [f32; MAX_PARAMS],
+    animations: &[Animation],
+    frames: u32,
+) -> Result<Vec<[f32; MAX_PARAMS]>> {
+    let mut animations = animations.to_vec();
+
+    if let Some(index) = op.param_index(PROGRESS_PARAM) {
+        let progress = &op.params[index];
+        let explicit = animations
+            .iter()
+            .any(|animation| op.param_index(&animation.key) == Some(index));
+
+        if frames > 1 && !explicit {
+            animations.push(Animation {
+                key: PROGRESS_PARAM.to_string(),
+                start: progress.min,
+                end: progress.max,
+            });
+        }
+    }
+
+    let indices = animations
+        .iter()
+        .map(|animation| {
+            op.param_index(&animation.key).ok_or_else(|| {
+                anyhow!(
+                    "Operation '{}' has no parameter '{}' to animate",
+                    op.name,
+                    animation.key
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Values between the ends of an animation are within range if the ends
+    // are.
+    for (animation, &index) in animations.iter().zip(&indices) {
+        op.check_param(index, animation.start)
+            .and_then(|()| op.check_param(index, animation.end))
+            .with_context(|| format!("Invalid animation {}", animation))?;
+    }
+
+    let output = (0..frames)
+        .map(|frame| {
+            let t = if frames > 1 {
+                frame as f32 / (frames - 1) as f32
+            } else {
+                0.0
+            };
+
+            let mut params = base;
+            for (animation, index) in animations.iter().zip(&indices) {
+                params[*index] = animation.start + (animation.end - animation.start) * t;
+            }
+
+            params
+        })
+        .collect();
+
+    Ok(output)
+}
+
+/// Destination for the rendered frames: the requested output for a single
+/// frame, numbered image files next to it, or a single animated GIF.
+pub enum SequenceWriter {
+    Single {
+        path: PathBuf,
+    },
+    Images {
+        path: PathBuf,
+        digits: usize,
+    },
+    Gif {
+        encoder: GifEncoder<File>,
+        delay: Delay,
+    },
+}
+
+impl SequenceWriter {
+    pub fn single(path: &Path) -> Self {
+        Self::Single {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn images(path: &Path, frames: u32) -> Self {
+        Self::Images {
+            path: path.to_path_buf(),
+            digits: frame_digits(frames),
+        }
+    }
+
+    /// Files the writer for `frames` frames at `path` creates, before
+    /// creating it.
+    pub fn paths(path: &Path, frames: u32, gif: bool) -> Vec<PathBuf> {
+        if frames == 1 {
+            vec![path.to_path_buf()]
+        } else if gif {
+            vec![path.with_extension("gif")]
+        } else {
+            (0..frames as usize)
+                .map(|index| frame_path(path, index, frame_digits(frames)))
+                .collect()
+        }
+    }
+
+    pub fn gif(path: &Path, fps: u32) -> Result<Self> {
+        let file = File::create(path.with_extension("gif"))?;
+
+        let mut encoder = GifEncoder::new(file);
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+        Ok(Self::Gif {
+            encoder,
+            delay: Delay::from_numer_denom_ms(1000, fps.max(1)),
+        })
+    }
+
+    pub fn write(&mut self, index: usize, width: u32, height: u32, buffer: Vec<u8>) -> Result<()> {
+        match self {
+            Self::Single { path } => {
+                image::save_buffer(path, &buffer, width, height, image::ColorType::Rgba8)?;
+            }
+            Self::Images { path, digits } => {
+                image::save_buffer(
+                    frame_path(path, index, *digits),
+                    &buffer,
+                    width,
+                    height,
+                    image::ColorType::Rgba8,
+                )?;
+            }
+            Self::Gif { encoder, delay } => {
+                let image = RgbaImage::from_raw(width, height, buffer)
+                    .ok_or_else(|| anyhow!("Frame buffer does not match {}x{}", width, height))?;
+
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, *delay))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn frame_digits(frames: u32) -> usize {
+    (frames.max(2) - 1).to_string().len().max(4)
+}
+
+fn frame_path(path: &Path, index: usize, digits: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string());
+
+    path.with_file_name(format!(
+        "{}_{:0width$}.{}",
+        stem,
+        index,
+        extension,
+        width = digits
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops;
+
+    fn animation(arg: &str) -> Animation {
+        arg.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        let parsed = animation("angle= -90 .. 45.5");
+
+        assert_eq!(parsed.key, "angle");
+        assert_eq!((parsed.start, parsed.end), (-90.0, 45.5));
+        assert_eq!(parsed.to_string(), "angle=-90..45.5");
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        for arg in ["angle", "angle=90", "angle=..1", "angle=a..1", "angle=0..b"] {
+            assert!(arg.parse::<Animation>().is_err(), "{}", arg);
+        }
+    }
+
+    #[test]
+    fn sweeps_progress_unless_animated() {
+        let wipe = ops::find("wipe").unwrap();
+        let base = wipe.resolve_params(&[]).unwrap();
+
+        let frames = frame_params(wipe, base, &[], 3).unwrap();
+        let progress: Vec<_> = frames.iter().map(|params| params[0]).collect();
+        assert_eq!(progress, [0.0, 0.5, 1.0]);
+
+        let frames = frame_params(wipe, base, &[animation("progress=1..0")], 2).unwrap();
+        assert_eq!((frames[0][0], frames[1][0]), (1.0, 0.0));
+    }
+
+    #[test]
+    fn keeps_the_start_of_a_single_frame() {
+        let wipe = ops::find("wipe").unwrap();
+        let base = wipe.resolve_params(&[]).unwrap();
+
+        let frames = frame_params(wipe, base, &[animation("angle=10..20")], 1).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0][1], 10.0);
+        // A single frame keeps the progress it is given.
+        assert_eq!(frames[0][0], base[0]);
+
+        assert!(frame_params(wipe, base, &[], 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn accepts_prefixed_keys() {
+        let wipe = ops::find("wipe").unwrap();
+        let base = wipe.resolve_params(&[]).unwrap();
+
+        let frames = frame_params(wipe, base, &[animation("wipe-angle=0..90")], 2).unwrap();
+        assert_eq!(frames[1][1], 90.0);
+    }
+
+    #[test]
+    fn rejects_unknown_and_out_of_range_keyframes() {
+        let wipe = ops::find("wipe").unwrap();
+        let base = wipe.resolve_params(&[]).unwrap();
+
+        assert!(frame_params(wipe, base, &[animation("radius=0..1")], 2).is_err());
+        assert!(frame_params(wipe, base, &[animation("angle=0..720")], 2).is_err());
+        assert!(frame_params(wipe, base, &[animation("softness=-1..0.5")], 2).is_err());
+        // The ends are checked for a single frame too.
+        assert!(frame_params(wipe, base, &[animation("progress=0..2")], 1).is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use crate::resources::GLYPH_RAMP;
+use anyhow::*;
+use image::RgbaImage;
+use std::{fs, path::Path};
+
+/// Writes the characters the `ascii` operation draws for `image`, one line
+/// per row of `cell` texels square blocks. Blocks pick their glyph the same
+/// way the shader does, from the mean luminance of their texels.
+pub fn save_text(image: &RgbaImage, cell: u32, path: &Path) -> Result<()> {
+    let ramp: Vec<char> = GLYPH_RAMP.chars().collect();
+    let (width, height) = image.dimensions();
+
+    let mut text = String::new();
+
+    for y in (0..height).step_by(cell as usize) {
+        for x in (0..width).step_by(cell as usize) {
+            let (end_x, end_y) = ((x + cell).min(width), (y + cell).min(height));
+
+            let mut sum = [0.0; 3];
+            for block_y in y..end_y {
+                for block_x in x..end_x {
+                    let pixel = image.get_pixel(block_x, block_y);
+                    for (sum, value) in sum.iter_mut().zip(pixel.0) {
+                        *sum += value as f32 / 255.0;
+                    }
+                }
+            }
+
+            let texels = ((end_x - x) * (end_y - y)) as f32;
+            let luminance = (0.2126 * sum[0] + 0.7152 * sum[1] + 0.0722 * sum[2]) / texels;
+            let index = ((luminance * ramp.len() as f32) as usize).min(ramp.len() - 1);
+
+            text.push(ramp[index]);
+        }
+
+        text.push('\n');
+    }
+
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
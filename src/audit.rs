@@ -0,0 +1,267 @@
+use anyhow::*;
+use image::RgbaImage;
+use serde::Serialize;
+use std::{borrow::Cow, collections::HashMap, path::PathBuf};
+use wgpu::util::DeviceExt;
+
+use crate::shader;
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    /// The only color present, for textures that are a single flat color.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub solid_color: Option<[u8; 4]>,
+    /// An earlier input with exactly the same pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+}
+
+/// An input that couldn't be audited, such as one that doesn't decode.
+#[derive(Serialize)]
+pub struct AuditFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct AuditReport {
+    pub textures: Vec<AuditEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<AuditFailure>,
+    pub solid: usize,
+    pub duplicates: usize,
+    /// RGBA8 memory that could be saved by replacing solid textures with a
+    /// single texel and dropping duplicates.
+    pub wasted_bytes: u64,
+}
+
+struct Auditor {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+/// Result of the GPU pass: whether all texels are equal and an
+/// order-independent hash of texel positions and values.
+struct Fingerprint {
+    solid: bool,
+    hash: u64,
+}
+
+impl Auditor {
+    fn new(device: &wgpu::Device) -> Result<Self> {
+        let shader = shader::preprocess(include_str!("shaders/audit.wgsl"), None)?;
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Audit Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Audit Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Audit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Audit Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "audit_texture",
+        });
+
+        Ok(Self {
+            bind_group_layout,
+            pipeline,
+        })
+    }
+
+    async fn fingerprint(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &RgbaImage,
+    ) -> Result<Fingerprint> {
+        let texture_size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+
+        let texture = crate::create_input_texture(device, queue, texture_size, image.as_raw());
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let audit_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Audit Buffer"),
+            contents: bytemuck::cast_slice(&[0u32; 4]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Audit Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: audit_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Audit Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Audit Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        let values = crate::read_buffer(device, queue, &audit_buffer).await?;
+        let [differs, hash_xor, hash_sum, _]: [u32; 4] = bytemuck::pod_read_unaligned(&values);
+
+        Ok(Fingerprint {
+            solid: differs == 0,
+            hash: ((hash_xor as u64) << 32) | hash_sum as u64,
+        })
+    }
+}
+
+/// Finds inputs that are a single flat color or have exactly the same pixels
+/// as an earlier input. Hash matches are confirmed on the CPU. Inputs that
+/// fail to load are reported and skipped, unless all of them do.
+pub async fn audit(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    paths: &[PathBuf],
+) -> Result<AuditReport> {
+    let auditor = Auditor::new(device)?;
+
+    let mut textures = Vec::with_capacity(paths.len());
+    let mut seen: HashMap<(u32, u32, u64), Vec<usize>> = HashMap::new();
+    let mut wasted_bytes = 0;
+    let mut errors = Vec::new();
+
+    for (index, path) in paths.iter().enumerate() {
+        let image = match crate::load_image(path) {
+            Result::Ok(image) => image,
+            Err(err) => {
+                errors.push((path, err));
+                continue;
+            }
+        };
+        let fingerprint = auditor.fingerprint(device, queue, &image).await?;
+
+        let size_in_bytes = image.as_raw().len() as u64;
+        let key = (image.width(), image.height(), fingerprint.hash);
+
+        let mut duplicate_of = None;
+        for candidate in seen.get(&key).into_iter().flatten() {
+            if crate::load_image(&paths[*candidate])?.as_raw() == image.as_raw() {
+                duplicate_of = Some(paths[*candidate].display().to_string());
+                break;
+            }
+        }
+
+        let solid_color = fingerprint.solid.then(|| image.get_pixel(0, 0).0);
+
+        if duplicate_of.is_some() {
+            wasted_bytes += size_in_bytes;
+        } else {
+            seen.entry(key).or_default().push(index);
+
+            if solid_color.is_some() {
+                wasted_bytes += size_in_bytes - 4;
+            }
+        }
+
+        textures.push(AuditEntry {
+            path: path.display().to_string(),
+            width: image.width(),
+            height: image.height(),
+            solid_color,
+            duplicate_of,
+        });
+    }
+
+    if textures.is_empty() && !errors.is_empty() {
+        return Err(errors.swap_remove(0).1);
+    }
+
+    Ok(AuditReport {
+        failed: errors
+            .into_iter()
+            .map(|(path, err)| AuditFailure {
+                path: path.display().to_string(),
+                error: format!("{:#}", err),
+            })
+            .collect(),
+        solid: textures.iter().filter(|t| t.solid_color.is_some()).count(),
+        duplicates: textures.iter().filter(|t| t.duplicate_of.is_some()).count(),
+        textures,
+        wasted_bytes,
+    })
+}
+
+impl AuditReport {
+    pub fn print(&self) {
+        for texture in &self.textures {
+            if let Some([r, g, b, a]) = texture.solid_color {
+                println!(
+                    "{}: solid color #{:02x}{:02x}{:02x}{:02x}",
+                    texture.path, r, g, b, a
+                );
+            }
+
+            if let Some(original) = &texture.duplicate_of {
+                println!("{}: duplicate of {}", texture.path, original);
+            }
+        }
+
+        println!(
+            "{} textures, {} solid, {} duplicates, {} bytes wasted",
+            self.textures.len(),
+            self.solid,
+            self.duplicates,
+            self.wasted_bytes
+        );
+    }
+}
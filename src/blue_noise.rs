@@ -0,0 +1,303 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::shader;
+
+/// Spread of the Gaussian filter, the value Ulichney recommends.
+const SIGMA: f32 = 1.5;
+/// Texels the filter reaches on either side, where its weight falls below
+/// one in fifty thousand.
+const RADIUS: i32 = 7;
+/// Fraction of minority texels the initial pattern starts with.
+const DENSITY: f32 = 0.1;
+/// Swaps between checks of whether the prototype pattern converged.
+const SWAPS_PER_CHECK: u32 = 64;
+/// Steps recorded into one command buffer.
+const STEPS_PER_SUBMIT: u32 = 256;
+
+/// Layout of `Settings` in `blue_noise.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    size: u32,
+    seed: u32,
+    density: f32,
+    sigma: f32,
+    radius: i32,
+    _padding: [u32; 3],
+}
+
+/// Layout of `State` in `blue_noise.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct State {
+    chosen: u32,
+    sign: f32,
+    removed: u32,
+    converged: u32,
+    minority: u32,
+    prototype: u32,
+}
+
+struct Pipelines {
+    seed: wgpu::ComputePipeline,
+    count: wgpu::ComputePipeline,
+    convolve: wgpu::ComputePipeline,
+    cluster_rows: wgpu::ComputePipeline,
+    void_rows: wgpu::ComputePipeline,
+    swap_remove: wgpu::ComputePipeline,
+    swap_insert: wgpu::ComputePipeline,
+    save: wgpu::ComputePipeline,
+    restore: wgpu::ComputePipeline,
+    rank_remove: wgpu::ComputePipeline,
+    rank_insert: wgpu::ComputePipeline,
+    update: wgpu::ComputePipeline,
+}
+
+/// Ranks every texel of a tileable `size`x`size` blue-noise dither array
+/// with void-and-cluster, from 0 to `size * size - 1` in row-major order.
+pub async fn generate(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: u32,
+    seed: u32,
+) -> Result<Vec<u32>> {
+    let shader = shader::preprocess(include_str!("shaders/blue_noise.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Blue Noise Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Blue Noise Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage_entry(1),
+            storage_entry(2),
+            storage_entry(3),
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Blue Noise Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Blue Noise Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let pipelines = Pipelines {
+        seed: pipeline("seed"),
+        count: pipeline("count"),
+        convolve: pipeline("convolve"),
+        cluster_rows: pipeline("cluster_rows"),
+        void_rows: pipeline("void_rows"),
+        swap_remove: pipeline("swap_remove"),
+        swap_insert: pipeline("swap_insert"),
+        save: pipeline("save"),
+        restore: pipeline("restore"),
+        rank_remove: pipeline("rank_remove"),
+        rank_insert: pipeline("rank_insert"),
+        update: pipeline("update"),
+    };
+
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Blue Noise Settings Buffer"),
+        contents: bytemuck::bytes_of(&Settings {
+            size,
+            seed,
+            density: DENSITY,
+            sigma: SIGMA,
+            radius: RADIUS,
+            _padding: [0; 3],
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let texel_count = size * size;
+
+    let texels_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Blue Noise Texels Buffer"),
+        size: 20 * texel_count as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let candidates_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Blue Noise Candidates Buffer"),
+        size: 8 * size as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    let state_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Blue Noise State Buffer"),
+        size: std::mem::size_of::<State>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Blue Noise Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: settings_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: texels_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: candidates_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: state_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let window = (2 * RADIUS as u32 + 1).min(size);
+
+    // Records a pass running `setup` over every texel, then `steps` times
+    // every pair of a search for candidates and the pick among them, each
+    // pick followed by updating the energy around the texel it chose.
+    let run = |setup: Option<&wgpu::ComputePipeline>,
+               steps: u32,
+               picks: &[(&wgpu::ComputePipeline, &wgpu::ComputePipeline)]| {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Blue Noise Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Blue Noise Pass"),
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+
+            if let Some(setup) = setup {
+                compute_pass.set_pipeline(setup);
+                compute_pass.dispatch_workgroups(size, size, 1);
+            }
+
+            for _ in 0..steps {
+                for (rows, pick) in picks {
+                    compute_pass.set_pipeline(rows);
+                    compute_pass.dispatch_workgroups(size, 1, 1);
+
+                    compute_pass.set_pipeline(pick);
+                    compute_pass.dispatch_workgroups(1, 1, 1);
+
+                    compute_pass.set_pipeline(&pipelines.update);
+                    compute_pass.dispatch_workgroups(window, window, 1);
+                }
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+    };
+
+    let read_state = || async {
+        let bytes = crate::read_buffer(device, queue, &state_buffer).await?;
+        Ok(bytemuck::pod_read_unaligned::<State>(&bytes))
+    };
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Blue Noise Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Blue Noise Pass"),
+        });
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+
+        compute_pass.set_pipeline(&pipelines.seed);
+        compute_pass.dispatch_workgroups(size, size, 1);
+
+        compute_pass.set_pipeline(&pipelines.count);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+
+        compute_pass.set_pipeline(&pipelines.convolve);
+        compute_pass.dispatch_workgroups(size, size, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    // Moves the tightest cluster into the largest void until that leaves the
+    // pattern as it was. Every swap lowers the total energy, so it ends long
+    // before this many.
+    let swap = [
+        (&pipelines.cluster_rows, &pipelines.swap_remove),
+        (&pipelines.void_rows, &pipelines.swap_insert),
+    ];
+    for _ in 0..texel_count.div_ceil(SWAPS_PER_CHECK) {
+        run(None, SWAPS_PER_CHECK, &swap);
+
+        if read_state().await?.converged != 0 {
+            break;
+        }
+    }
+
+    let minority = read_state().await?.minority;
+
+    // Ranks the prototype's texels by removing them, then, starting over
+    // from it, all others by filling the voids they leave.
+    let mut setup = Some(&pipelines.save);
+    for start in (0..minority).step_by(STEPS_PER_SUBMIT as usize) {
+        let steps = STEPS_PER_SUBMIT.min(minority - start);
+        run(
+            setup.take(),
+            steps,
+            &[(&pipelines.cluster_rows, &pipelines.rank_remove)],
+        );
+    }
+
+    let mut setup = Some(&pipelines.restore);
+    for start in (minority..texel_count).step_by(STEPS_PER_SUBMIT as usize) {
+        let steps = STEPS_PER_SUBMIT.min(texel_count - start);
+        run(
+            setup.take(),
+            steps,
+            &[(&pipelines.void_rows, &pipelines.rank_insert)],
+        );
+    }
+
+    let bytes = crate::read_buffer(device, queue, &texels_buffer).await?;
+    let texels: Vec<[u32; 5]> = bytemuck::pod_collect_to_vec(&bytes);
+
+    Ok(texels.iter().map(|&[_, _, rank, _, _]| rank).collect())
+}
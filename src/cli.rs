@@ -0,0 +1,1210 @@
+use clap::{
+    builder::BoolishValueParser, Args as ClapArgs, CommandFactory, FromArgMatches, Parser,
+    Subcommand, ValueEnum,
+};
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    animation::Animation,
+    compare::CompareLayout,
+    completions::Shell,
+    exit,
+    gradient::Gradient,
+    job_queue::MAX_PRIORITY,
+    memory::parse_bytes,
+    mipmap::{FilterSpace, MipFilter},
+    montage::MontageLabel,
+    ops::parse_param,
+    output::{IfExists, OutputFormat},
+    pack::PackSpec,
+    panorama::Projection,
+    preset::Preset,
+    preview::Mesh,
+    sprite::Grid,
+    stack::StackMode,
+    thumbnail::ThumbnailFormat,
+    trim::Bounds,
+    AdapterOptions, AdapterSelector,
+};
+
+#[derive(Parser)]
+#[command(
+    about = "Run compute shaders over images with wgpu",
+    args_conflicts_with_subcommands = true,
+    disable_help_subcommand = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // The options for every command can also be set from `WTC_` environment
+    // variables, which flags on the command line override.
+    /// Graphics APIs to look for GPUs on, all of them by default.
+    #[arg(
+        long,
+        global = true,
+        env = "WTC_BACKEND",
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub backend: Vec<Backend>,
+
+    /// GPU to run on: an index or name from `adapters`, or a backend such as
+    /// vulkan or gl. Defaults to the one wgpu picks.
+    #[arg(
+        long,
+        global = true,
+        env = "WTC_ADAPTER",
+        value_name = "INDEX|NAME|BACKEND"
+    )]
+    pub adapter: Option<AdapterSelector>,
+
+    /// Kind of GPU to prefer when --adapter doesn't pick one.
+    #[arg(
+        long,
+        global = true,
+        env = "WTC_POWER_PREFERENCE",
+        value_enum,
+        default_value_t = PowerPreference::LowPower
+    )]
+    pub power_preference: PowerPreference,
+
+    /// Only run on a software adapter such as llvmpipe or WARP, for machines
+    /// without a GPU.
+    #[arg(long, global = true, env = "WTC_FALLBACK_ADAPTER", value_parser = BoolishValueParser::new())]
+    pub fallback_adapter: bool,
+
+    /// How often to recreate the GPU context and run again when the device
+    /// is lost midway, such as by a driver reset. Batches such as
+    /// `thumbnail` and `fisheye` run the image it was lost on again, this
+    /// often for every image, and keep the ones written already.
+    #[arg(long, global = true, env = "WTC_RETRIES", default_value_t = 2)]
+    pub retries: u32,
+
+    /// Record a wgpu API trace of the run into this directory, to attach to
+    /// GPU bug reports. Needs a build with the `trace` feature.
+    #[arg(long, global = true, env = "WTC_TRACE", value_name = "DIR")]
+    pub trace: Option<PathBuf>,
+
+    /// Print nothing but errors, for scripts that go by the exit code: 0 on
+    /// success, 2 if some inputs of a batch failed, 3 without a matching
+    /// adapter, 4 if wgpu rejected the work, 5 when a file couldn't be read
+    /// or written and 1 otherwise.
+    #[arg(short, long, global = true, env = "WTC_QUIET", value_parser = BoolishValueParser::new())]
+    pub quiet: bool,
+
+    /// Directory for the scratch files of a run, such as the files of the
+    /// jobs `serve` runs. The system's temporary directory by default.
+    #[arg(long, global = true, env = "WTC_CACHE_DIR", value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Threads for the work done on the CPU, such as decoding batches of
+    /// images. All cores by default.
+    #[arg(long, global = true, env = "WTC_THREADS", value_name = "N")]
+    pub threads: Option<NonZeroUsize>,
+
+    #[command(flatten)]
+    pub process: Args,
+}
+
+impl Cli {
+    /// Parses the command line, taking what it leaves out from `--preset`.
+    pub fn parse_with_preset() -> anyhow::Result<Self> {
+        let matches = Self::command()
+            .try_get_matches()
+            .unwrap_or_else(|err| exit_with(err));
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|err| exit_with(err));
+
+        if let Some(path) = cli.process.preset.clone() {
+            Preset::load(&path)?.apply(&mut cli.process, |id| matches.value_source(id));
+        }
+
+        Ok(cli)
+    }
+
+    /// Parses `args`, the command line of a run without the program name,
+    /// failing rather than exiting on mistakes. The `--preset` is read by
+    /// `load_preset`.
+    pub fn parse_run(
+        args: &[String],
+        load_preset: impl FnOnce(&Path) -> anyhow::Result<Preset>,
+    ) -> anyhow::Result<Args> {
+        let program = std::iter::once("wgpu_texture_copy".to_string());
+        let matches = Self::command().try_get_matches_from(program.chain(args.iter().cloned()))?;
+        let mut cli = Self::from_arg_matches(&matches)?;
+        if cli.command.is_some() {
+            anyhow::bail!("Only runs of an operation can be submitted, not commands");
+        }
+
+        if let Some(path) = cli.process.preset.clone() {
+            load_preset(&path)?.apply(&mut cli.process, |id| matches.value_source(id));
+        }
+
+        Ok(cli.process)
+    }
+
+    /// Directory for the scratch files of a run.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir.clone().unwrap_or_else(std::env::temp_dir)
+    }
+
+    /// What the device of a context is requested with.
+    pub fn adapter_options(&self) -> AdapterOptions {
+        AdapterOptions {
+            backends: match self.backend.is_empty() {
+                true => wgpu::Backends::all(),
+                false => self.backend.iter().map(|&backend| backend.into()).collect(),
+            },
+            selector: self.adapter.clone().unwrap_or_default(),
+            power_preference: self.power_preference.into(),
+            force_fallback_adapter: self.fallback_adapter,
+            trace: self.trace.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Like [`clap::Error::exit`], but mistakes on the command line exit with
+/// [`exit::FAILURE`] rather than clap's 2, which means a partial failure here.
+fn exit_with(err: clap::Error) -> ! {
+    if !err.use_stderr() {
+        // Help and version.
+        err.exit();
+    }
+
+    let _ = err.print();
+    std::process::exit(exit::FAILURE.into())
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Vulkan => Self::VULKAN,
+            Backend::Metal => Self::METAL,
+            Backend::Dx12 => Self::DX12,
+            Backend::Dx11 => Self::DX11,
+            Backend::Gl => Self::GL,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PowerPreference {
+    /// Often an integrated GPU.
+    LowPower,
+    /// Often a discrete GPU.
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> Self {
+        match preference {
+            PowerPreference::LowPower => Self::LowPower,
+            PowerPreference::HighPerformance => Self::HighPerformance,
+        }
+    }
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Command {
+    /// Compose a numbered sequence of frames into a sprite sheet.
+    Assemble(AssembleArgs),
+    /// Lay out many images into one contact sheet, with optional labels.
+    Montage(MontageArgs),
+    /// Find textures that are a single flat color or duplicate another input.
+    Audit(AuditArgs),
+    /// Make small previews of many images, a batch at a time on the GPU.
+    Thumbnail(ThumbnailArgs),
+    /// Blend a source patch seamlessly into a destination image.
+    Clone(CloneArgs),
+    /// Fill masked holes of an image from their surroundings.
+    Inpaint(InpaintArgs),
+    /// Group an image into superpixels with SLIC.
+    Superpixels(SuperpixelArgs),
+    /// Average or median-stack aligned images of the same size.
+    Stack(StackArgs),
+    /// Merge bracketed exposures into an HDR image.
+    HdrMerge(HdrMergeArgs),
+    /// Stitch pre-aligned tiles into a panorama.
+    Panorama(PanoramaArgs),
+    /// Turn dual-lens fisheye frames into equirectangular ones.
+    Fisheye(FisheyeArgs),
+    /// Write the summed-area table of an image.
+    Integral(IntegralArgs),
+    /// Wear down a heightmap by simulated rain and rivers.
+    Erode(ErodeArgs),
+    /// Prefilter an equirectangular environment map for image-based lighting.
+    Ibl(IblArgs),
+    /// Project an equirectangular environment map onto spherical harmonics.
+    Sh(ShArgs),
+    /// Bake a texture that doesn't start from an input image.
+    Generate(GenerateArgs),
+    /// Take jobs from `submit` over the network and run them on the GPU of
+    /// this machine, by priority and in the order they come in.
+    Serve(ServeArgs),
+    /// Run an operation on a `serve` machine, sending it the files the run
+    /// reads and writing back the ones it writes.
+    Submit(SubmitArgs),
+    /// List the jobs queued, running and last finished on a `serve` machine.
+    Status(StatusArgs),
+    /// List the GPUs --adapter can pick.
+    Adapters,
+    /// List the operations --op can run, with their parameters.
+    ListEffects(ListEffectsArgs),
+    /// Print a completion script for a shell.
+    Completions(CompletionsArgs),
+    /// Describe a command, or an operation with its parameters.
+    Help(HelpArgs),
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct ServeArgs {
+    /// Address to take jobs on. Anyone who can reach it can run jobs, so
+    /// bind to a loopback or trusted network only.
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:7878")]
+    pub listen: String,
+
+    /// Jobs taken at once; more wait in the queue. Their files are received
+    /// and sent back side by side, but they run on the GPU one at a time.
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_jobs: u32,
+
+    /// Most jobs one client address may have queued or running at once;
+    /// further ones are turned down.
+    #[arg(long, value_name = "N")]
+    pub quota: Option<u32>,
+
+    /// Also serve Prometheus metrics at `/metrics` of this address: jobs by
+    /// outcome, the queue, stage latencies and GPU memory.
+    #[arg(long, value_name = "ADDRESS")]
+    pub metrics: Option<String>,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct SubmitArgs {
+    /// Address of the `serve` machine.
+    #[arg(
+        long,
+        env = "WTC_SERVER",
+        value_name = "ADDRESS",
+        default_value = "127.0.0.1:7878"
+    )]
+    pub server: String,
+
+    /// Jobs of higher priority run first, equal ones in the order they came
+    /// in. From -10 to 10.
+    #[arg(
+        long,
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-(MAX_PRIORITY as i64)..=MAX_PRIORITY as i64)
+    )]
+    pub priority: i32,
+
+    /// Command line of the run as it would be given here, after `--`. The
+    /// files it reads are sent along with the shader includes, preset bases
+    /// and DeepZoom tiles next to them.
+    #[arg(last = true, required = true, value_name = "ARGS")]
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct StatusArgs {
+    /// Address of the `serve` machine.
+    #[arg(
+        long,
+        env = "WTC_SERVER",
+        value_name = "ADDRESS",
+        default_value = "127.0.0.1:7878"
+    )]
+    pub server: String,
+
+    /// Print JSON instead.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct HelpArgs {
+    /// Command or operation to describe, everything if left out.
+    pub topic: Option<String>,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct ListEffectsArgs {
+    /// Print JSON instead, for building parameter forms.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct ShArgs {
+    /// Equirectangular environment map; HDR and EXR ones keep their range,
+    /// others are taken as sRGB.
+    pub input: PathBuf,
+
+    /// Bands to keep: 2 for four coefficients, 3 for nine.
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(2..=3))]
+    pub order: u32,
+
+    /// Convolve the coefficients with the clamped cosine, so evaluating
+    /// them at a normal gives its diffuse irradiance.
+    #[arg(long)]
+    pub irradiance: bool,
+
+    /// JSON file to write the coefficients to.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct GenerateArgs {
+    #[command(subcommand)]
+    pub texture: Generated,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum Generated {
+    /// Split-sum BRDF integration lookup table pairing with `ibl`.
+    BrdfLut(BrdfLutArgs),
+    /// Tileable blue-noise dither array made with void-and-cluster.
+    BlueNoise(BlueNoiseArgs),
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct BrdfLutArgs {
+    /// Width and height of the table.
+    #[arg(long, default_value_t = 512, value_parser = clap::value_parser!(u32).range(1..=4096))]
+    pub size: u32,
+
+    /// Importance samples per texel.
+    #[arg(long, default_value_t = 1024, value_parser = clap::value_parser!(u32).range(1..))]
+    pub samples: u32,
+
+    /// Table to write, N.V along the columns and roughness down the rows.
+    /// KTX2 keeps the scale and bias as 32-bit floats in red and green,
+    /// EXR and HDR as floats too, other formats at 16 bits where they can.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct BlueNoiseArgs {
+    /// Width and height of the texture.
+    #[arg(long, default_value_t = 128, value_parser = clap::value_parser!(u32).range(8..=1024))]
+    pub size: u32,
+
+    /// Independent patterns, one per channel.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=4))]
+    pub channels: u8,
+
+    /// Seed of the initial pattern, the following channels taking the next
+    /// ones.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u32,
+
+    /// Texture to write, as grayscale, grayscale with alpha, RGB or RGBA
+    /// after the channels.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct AuditArgs {
+    /// Textures to inspect.
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Write the findings as JSON.
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct ThumbnailArgs {
+    /// Images to make previews of.
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Longest side of a thumbnail, in texels. Smaller images keep their
+    /// size.
+    #[arg(long, value_name = "TEXELS", default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max: u32,
+
+    /// Directory to write the thumbnails to, named after the inputs.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = ThumbnailFormat::Jpeg)]
+    pub format: ThumbnailFormat,
+
+    /// JPEG quality.
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: u8,
+
+    /// Thumbnails made on the GPU at once, as the layers of one array
+    /// texture. Larger batches take more memory and fewer round trips.
+    #[arg(long, value_name = "N", default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch: u32,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct AssembleArgs {
+    /// Frame files, ordered by the number in their file name.
+    #[arg(required = true)]
+    pub frames: Vec<PathBuf>,
+
+    /// Sheet file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Cells per row; defaults to a roughly square sheet.
+    #[arg(long)]
+    pub columns: Option<u32>,
+
+    /// Transparent pixels around and between cells.
+    #[arg(long, default_value_t = 0)]
+    pub padding: u32,
+
+    /// Round the sheet up to power-of-two dimensions.
+    #[arg(long)]
+    pub power_of_two: bool,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct MontageArgs {
+    /// Images to lay out, row by row in the order given.
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Sheet file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+    pub columns: u32,
+
+    /// Width and height of a cell, in texels. Larger images are shrunk to
+    /// fit, smaller ones are centered at their size.
+    #[arg(long, value_name = "TEXELS", default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..))]
+    pub cell: u32,
+
+    /// Background texels around and between cells.
+    #[arg(long, default_value_t = 8)]
+    pub padding: u32,
+
+    /// Text to write under every cell.
+    #[arg(long, value_enum)]
+    pub label: Option<MontageLabel>,
+
+    /// Size of the label text, as texels per texel of the 5x7 font.
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub label_scale: u32,
+
+    #[arg(long, value_name = "COLOR", default_value = "#ffffff", value_parser = parse_color)]
+    pub label_color: [f32; 4],
+
+    #[arg(long, value_name = "COLOR", default_value = "#202020", value_parser = parse_color)]
+    pub background: [f32; 4],
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct CloneArgs {
+    /// Image the patch is cloned into.
+    pub destination: PathBuf,
+
+    /// Patch to clone.
+    pub source: PathBuf,
+
+    /// Grayscale image selecting the texels of the source to clone, instead
+    /// of its alpha.
+    #[arg(long, value_name = "PATH")]
+    pub mask: Option<PathBuf>,
+
+    /// Position of the source's top left corner in the destination.
+    #[arg(long, value_name = "X,Y", value_parser = parse_offset, default_value = "0,0")]
+    pub offset: (u32, u32),
+
+    /// Jacobi iterations; larger patches need more to converge.
+    #[arg(long, default_value_t = 2000)]
+    pub iterations: u32,
+
+    /// Image file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct InpaintArgs {
+    /// Image to clean up.
+    pub input: PathBuf,
+
+    /// Grayscale image, the size of the input, that is white over the texels
+    /// to fill.
+    #[arg(long, value_name = "PATH")]
+    pub mask: PathBuf,
+
+    /// Diffusion iterations; wider holes need more to fill in.
+    #[arg(long, default_value_t = 2000)]
+    pub iterations: u32,
+
+    /// Image file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct SuperpixelArgs {
+    /// Image to segment.
+    pub input: PathBuf,
+
+    /// Approximate superpixel size in texels.
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(2..))]
+    pub spacing: u32,
+
+    /// Higher values give more regular superpixels, lower ones follow color
+    /// edges more closely.
+    #[arg(long, default_value_t = 10.0)]
+    pub compactness: f32,
+
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
+
+    /// Write every texel's superpixel index as a 24-bit number in RGB,
+    /// least significant byte in red, instead of the superpixel's mean color.
+    #[arg(long)]
+    pub labels: bool,
+
+    /// Image file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct StackArgs {
+    /// Images to combine, all of the same size.
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = StackMode::Mean)]
+    pub mode: StackMode,
+
+    /// Image file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct HdrMergeArgs {
+    /// Exposures of the same scene, at least three.
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Exposure of every input in stops relative to the others, e.g.
+    /// `-2,0,2`.
+    #[arg(
+        long,
+        value_name = "EV,...",
+        value_delimiter = ',',
+        required = true,
+        allow_negative_numbers = true
+    )]
+    pub ev: Vec<f32>,
+
+    /// Image file to write, as EXR for full range.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct PanoramaArgs {
+    /// Tiles taken from one spot, all of the same size.
+    #[arg(required = true)]
+    pub tiles: Vec<PathBuf>,
+
+    /// Horizontal direction of every tile in degrees, e.g. `0,40,80`.
+    #[arg(
+        long,
+        value_name = "DEGREES,...",
+        value_delimiter = ',',
+        required = true,
+        allow_negative_numbers = true
+    )]
+    pub yaw: Vec<f32>,
+
+    /// Horizontal field of view of the tiles in degrees.
+    #[arg(long, default_value_t = 60.0)]
+    pub fov: f32,
+
+    #[arg(long, value_enum, default_value_t = Projection::Cylindrical)]
+    pub projection: Projection,
+
+    /// Bands blended separately; more hide seams between tiles of different
+    /// exposure better.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..=12))]
+    pub levels: u32,
+
+    /// Image file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct FisheyeArgs {
+    /// Frames with the front lens image on the left and the back one on the
+    /// right, ordered by the number in their file name.
+    #[arg(required = true)]
+    pub frames: Vec<PathBuf>,
+
+    /// Field of view of each lens in degrees.
+    #[arg(long, default_value_t = 190.0)]
+    pub fov: f32,
+
+    /// Offset of the front lens center from the middle of the left half, in
+    /// texels.
+    #[arg(
+        long,
+        value_name = "X,Y",
+        value_parser = parse_lens_offset,
+        default_value = "0,0",
+        allow_negative_numbers = true
+    )]
+    pub front_offset: (f32, f32),
+
+    /// Offset of the back lens center from the middle of the right half.
+    #[arg(
+        long,
+        value_name = "X,Y",
+        value_parser = parse_lens_offset,
+        default_value = "0,0",
+        allow_negative_numbers = true
+    )]
+    pub back_offset: (f32, f32),
+
+    /// Image circle radius in texels; defaults to touching the frame edges.
+    #[arg(long)]
+    pub radius: Option<f32>,
+
+    /// Output size; defaults to the frame width by half of it.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+
+    /// Write the frames as one animated GIF instead of numbered images.
+    #[arg(long)]
+    pub gif: bool,
+
+    /// Playback rate of the GIF.
+    #[arg(long, default_value_t = 30)]
+    pub fps: u32,
+
+    /// Image file to write, numbered for more than one frame.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct IntegralArgs {
+    /// Image to sum up.
+    pub input: PathBuf,
+
+    /// Image file to write, as EXR for full range. Every texel holds the
+    /// channel sums of the texels above and left of it, inclusive.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct ErodeArgs {
+    /// Grayscale heightmap, white being highest.
+    pub input: PathBuf,
+
+    /// Simulation steps; more carve deeper valleys.
+    #[arg(long, default_value_t = 500, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
+
+    /// Water falling on every texel per time unit, in texels of depth.
+    #[arg(long, default_value_t = 0.02)]
+    pub rain: f32,
+
+    /// Height of white in the heightmap, in texels. Larger ones make for
+    /// steeper slopes that erode faster.
+    #[arg(long, default_value_t = 64.0)]
+    pub height_scale: f32,
+
+    /// Also write where the water flows at the end, brighter for more water.
+    #[arg(long, value_name = "PATH")]
+    pub flow: Option<PathBuf>,
+
+    /// Also write where eroded material settled, brighter for more.
+    #[arg(long, value_name = "PATH")]
+    pub sediment: Option<PathBuf>,
+
+    /// Eroded heightmap to write, 16-bit where the format allows.
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Clone, ClapArgs)]
+pub struct IblArgs {
+    /// Equirectangular environment map; HDR and EXR ones keep their range,
+    /// others are taken as sRGB.
+    pub input: PathBuf,
+
+    /// Face size of the sharpest specular level, a power of two.
+    #[arg(long, default_value_t = 256)]
+    pub size: u32,
+
+    /// Specular mip levels, from mirror-like to fully rough. Defaults to
+    /// stopping at 8x8 faces.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub levels: Option<u32>,
+
+    /// Face size of the irradiance cubemap.
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    pub irradiance_size: u32,
+
+    /// Importance samples per specular texel.
+    #[arg(long, default_value_t = 512, value_parser = clap::value_parser!(u32).range(1..))]
+    pub samples: u32,
+
+    /// Prefiltered specular cubemap to write, with a mip level per
+    /// roughness.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Diffuse irradiance cubemap to write, `<output>_irradiance.ktx2` by
+    /// default.
+    #[arg(long)]
+    pub irradiance: Option<PathBuf>,
+}
+
+/// Options for running an operation over the input image.
+#[derive(Clone, ClapArgs)]
+pub struct Args {
+    /// Image to process; `--pack` gathers its inputs itself instead. DeepZoom
+    /// descriptors (`.dzi`) and TIFFs too large for a texture are read and
+    /// processed a tile at a time into a DeepZoom pyramid, see --tile-halo.
+    #[arg(required_unless_present = "pack", conflicts_with = "pack")]
+    pub input: Option<PathBuf>,
+
+    /// Image to write. Frame sequences, mip levels and other extra outputs
+    /// are named after it. Only ever `None` when a command runs instead.
+    #[arg(short, long, required = true)]
+    pub output: Option<PathBuf>,
+
+    /// Format to write in, replacing the extension of the output.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// What to do about outputs that are there already.
+    #[arg(long, value_enum, default_value_t = IfExists::Overwrite)]
+    pub if_exists: IfExists,
+
+    /// Operation to run over the input.
+    #[arg(long, default_value = "copy")]
+    pub op: String,
+
+    /// Run a kernel of one's own from this WGSL file instead of an
+    /// operation. It is bound like `copy`: the input at binding 0, the
+    /// output at 1, the globals at 2 and `--second` at 3. A struct of
+    /// parameters it declares as `var<uniform>` at `@group(2) @binding(0)`
+    /// takes the `--param` values by member name, `tint.x` for a component
+    /// and zero when left out. Includes are looked up next to it first, then
+    /// in the bundled library. Enough workgroups of the size it declares are
+    /// dispatched to cover the output, so invocations past its edges need to
+    /// return early.
+    #[arg(long, value_name = "PATH", conflicts_with = "op")]
+    pub shader: Option<PathBuf>,
+
+    /// Entry point of the `--shader` kernel.
+    #[arg(long, value_name = "NAME", default_value = "main", requires = "shader")]
+    pub entry_point: String,
+
+    /// Second input for operations that combine two images, such as
+    /// transitions. It is resized to match the first input if needed.
+    #[arg(long, value_name = "PATH")]
+    pub second: Option<PathBuf>,
+
+    /// Grayscale inputs of the `pack` operation by output channel, e.g.
+    /// `r=roughness.png,g=metal.png,b=ao.png`. They replace the regular input.
+    #[arg(long, value_name = "CHANNEL=PATH,...")]
+    pub pack: Option<PackSpec>,
+
+    /// Colors of the `gradient-map` operation from dark to bright, e.g.
+    /// `#102040,#ff8800@0.6,#ffffff`.
+    #[arg(long, value_name = "STOPS", conflicts_with = "gradient_image")]
+    pub gradient: Option<Gradient>,
+
+    /// Take the `gradient-map` colors from the first row of an image instead.
+    #[arg(long, value_name = "PATH")]
+    pub gradient_image: Option<PathBuf>,
+
+    /// Also write the characters of the `ascii` operation as a text file, one
+    /// line per row of cells.
+    #[arg(long, value_name = "PATH")]
+    pub ascii_text: Option<PathBuf>,
+
+    /// Output size for operations that produce a differently sized image,
+    /// such as `nine-slice`.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+
+    /// Shrink the input to this size by seam carving before running the
+    /// operation, removing low-detail paths instead of scaling everything.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+    pub resize_content_aware: Option<(u32, u32)>,
+
+    /// Number of colors the `segment` operation clusters the input into,
+    /// shorthand for `--param clusters=N`.
+    #[arg(long, value_name = "N")]
+    pub clusters: Option<u32>,
+
+    /// Set an operation parameter, e.g. `--param blur-sigma=4`, or a member
+    /// of the parameter struct of a `--shader`.
+    #[arg(long = "param", value_name = "KEY=VALUE", value_parser = parse_param)]
+    pub params: Vec<(String, f32)>,
+
+    /// Specialize an `override` constant of the shader by name or `@id`,
+    /// e.g. `--constant RADIUS=8`, when the pipeline is created. Constants
+    /// left out keep their defaults.
+    #[arg(long = "constant", value_name = "NAME=VALUE", value_parser = parse_param)]
+    pub constants: Vec<(String, f32)>,
+
+    /// Dispatch the operation this many times per frame, every pass reading
+    /// the output of the pass before instead of the input. Simulations such
+    /// as `reaction-diffusion` take it as their number of steps.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: Option<u32>,
+
+    /// Seed for stochastic effects, exposed to shaders as `globals.seed`.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u32,
+
+    /// Start from the operation, parameters, iterations and seed of a preset
+    /// file. Whatever the command line sets wins.
+    #[arg(long, value_name = "PATH")]
+    pub preset: Option<PathBuf>,
+
+    /// Save the operation, parameters, iterations and seed of the run, with
+    /// the ones of --preset, as a preset file.
+    #[arg(long, value_name = "PATH")]
+    pub save_preset: Option<PathBuf>,
+
+    /// Save the kernel of the run as resolved, with the WGSL of the operation
+    /// and its includes and every parameter, defaults included, as a lock
+    /// file for --from-lock.
+    #[arg(long, value_name = "PATH")]
+    pub emit_lock: Option<PathBuf>,
+
+    /// Run the kernel saved with --emit-lock, with its shaders, parameters,
+    /// constants, iterations, seed, workgroup size and strict math, whatever
+    /// the presets, defaults and bundled shaders are now.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["op", "shader", "entry_point", "preset", "params", "clusters", "animate", "constants", "iterations", "seed", "workgroup_size", "strict_math"])]
+    pub from_lock: Option<PathBuf>,
+
+    /// Number of frames to render; more than one writes a numbered sequence.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: u32,
+
+    /// Treat the input as a sprite sheet of `COLUMNSxROWS` cells and run the
+    /// operation on every cell separately.
+    #[arg(long, value_name = "COLUMNSxROWS")]
+    pub grid: Option<Grid>,
+
+    /// Also write every processed cell of the sprite sheet to its own file.
+    #[arg(long)]
+    pub export_cells: bool,
+
+    /// Interpolate a parameter across the frames, e.g. `blur-sigma=0..10`.
+    #[arg(long, value_name = "KEY=START..END")]
+    pub animate: Vec<Animation>,
+
+    /// Crop the output to the bounding box of its visible texels and record
+    /// the offsets in the report. Sprite sheet cells are trimmed when
+    /// exported, the sheet itself keeps its grid.
+    #[arg(long)]
+    pub trim_alpha: bool,
+
+    /// Alpha (0..1) a texel must exceed to count as visible when trimming.
+    #[arg(long, default_value_t = 0.0)]
+    pub trim_threshold: f32,
+
+    /// Read back and write only this rectangle of the output, e.g.
+    /// `64,32,128x128`, which saves transferring the rest of it.
+    #[arg(long, value_name = "X,Y,WIDTHxHEIGHT", value_parser = parse_region)]
+    pub out_region: Option<Bounds>,
+
+    /// Write a JSON report describing the run.
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+
+    /// Write `<output>.json` next to every output, recording the hashes of
+    /// the inputs and shaders, the operations in order with their
+    /// parameters, the adapter and the version, to reproduce or audit it.
+    #[arg(long)]
+    pub sidecar: bool,
+
+    /// Also write the mip chain of the output, level N as `<name>_mipN`.
+    #[arg(long)]
+    pub mipmaps: bool,
+
+    /// Scale alpha in every mip level so the fraction of texels above this
+    /// alpha-test cutoff matches the base level. Implies `--mipmaps`.
+    #[arg(long, value_name = "CUTOFF")]
+    pub preserve_alpha_coverage: Option<f32>,
+
+    /// Generate the mip chain of a tangent-space normal map, renormalizing
+    /// the vectors on every level. Implies `--mipmaps`.
+    #[arg(long)]
+    pub normal_map: bool,
+
+    /// With `--normal-map`, treat alpha as roughness and raise it on every
+    /// level by the variance of the filtered normals.
+    #[arg(long, requires = "normal_map")]
+    pub toksvig: bool,
+
+    /// Downsampling filter for the mip chain.
+    #[arg(long, value_enum, default_value_t = MipFilter::Box)]
+    pub mip_filter: MipFilter,
+
+    /// Whether mip colors are averaged on the stored sRGB values or in
+    /// linear space.
+    #[arg(long, value_enum, default_value_t = FilterSpace::Gamma)]
+    pub mip_space: FilterSpace,
+
+    /// Filter the mip chain of the output both on the stored sRGB values and
+    /// in linear space and write every level as a gamma | linear | difference
+    /// comparison, `<name>_srgb_checkN`, to audit gamma-incorrect mips.
+    #[arg(long)]
+    pub verify_srgb: bool,
+
+    /// Also run the operation on the CPU and report how far the GPU output
+    /// deviates from it. Only some operations have a CPU implementation.
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Time the compute pass and the copy of the output on the GPU with
+    /// timestamp queries and report them per frame. Needs an adapter with
+    /// timestamp queries.
+    #[arg(long)]
+    pub gpu_timings: bool,
+
+    /// Invocations per workgroup of the built-in operations. Shaders loaded
+    /// with --shader get it by declaring `@workgroup_size(WORKGROUP_SIZE)`.
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        value_parser = parse_size,
+        default_value = "8x8"
+    )]
+    pub workgroup_size: (u32, u32),
+
+    /// Compile the operations for outputs that are the same bit for bit on
+    /// every run on a device: their weighted sums add up in a fixed order,
+    /// with every product rounded before it is added, rather than however
+    /// the driver fuses and reorders them. Only blur, kuwahara, alpha-bleed,
+    /// morph and ascii are compiled so, and not with --mipmaps. Shaders
+    /// loaded with --shader get it through the `strict` helpers of
+    /// `globals.wgsl`.
+    #[arg(long)]
+    pub strict_math: bool,
+
+    /// Count the compute shader invocations of every frame with pipeline
+    /// statistics queries, to check that a shader's dispatch covers the
+    /// output and nothing more. Needs an adapter with those queries.
+    #[arg(long)]
+    pub pipeline_stats: bool,
+
+    /// Run the operation on a single texture it reads and writes, which
+    /// takes half the GPU memory of an input and an output texture. Only for
+    /// operations that read nothing but the texel they write, such as
+    /// `copy`, `gradient-map`, `segment`, `crosshatch` or shaders including
+    /// `pointwise.wgsl`, on adapters with read-write storage textures. Where
+    /// those can't be RGBA8, texels are packed into 32-bit ones, which rules
+    /// out trimming, mipmaps, `--verify-srgb` and `--skip-unchanged`.
+    #[arg(long)]
+    pub in_place: bool,
+
+    /// Most GPU memory the textures and buffers of the operation may take,
+    /// e.g. `2GiB` or `512MB`. The output is read back in bands of rows when
+    /// reading it back at once would exceed it.
+    #[arg(long, value_name = "BYTES", value_parser = parse_bytes)]
+    pub memory_budget: Option<u64>,
+
+    /// Also render the output onto `sphere`, `cube` or the mesh of an OBJ
+    /// file with basic lighting and write it as `<name>_preview`.
+    #[arg(long = "preview-3d", value_name = "MESH")]
+    pub preview_3d: Option<Mesh>,
+
+    /// Size of the `--preview-3d` render.
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        value_parser = parse_size,
+        default_value = "512x512",
+        requires = "preview_3d"
+    )]
+    pub preview_size: (u32, u32),
+
+    /// Also write the input next to the output as `<name>_compare`: `side`
+    /// by side, split along a diagonal with `slider`, or interleaved in a
+    /// `checker`board.
+    #[arg(long, value_name = "LAYOUT")]
+    pub compare_output: Option<CompareLayout>,
+
+    /// Write the frames as one animated GIF instead of numbered images.
+    #[arg(long)]
+    pub gif: bool,
+
+    /// Playback rate of the GIF output.
+    #[arg(long, default_value_t = 24)]
+    pub fps: u32,
+
+    /// Leave out the frames of a numbered sequence whose channels differ from
+    /// the last frame written by at most this many 8-bit steps, compared on
+    /// the GPU, and write `<name>_frames.json` naming the file that shows
+    /// every frame. Not for sprite sheets, whose cells would be compared
+    /// with each other rather than with those of the last frame.
+    #[arg(long, value_name = "STEPS", conflicts_with_all = ["gif", "grid"])]
+    pub skip_unchanged: Option<u32>,
+
+    /// Write the output as a DeepZoom pyramid for web viewers instead: the
+    /// `<name>.dzi` descriptor and tiles of every zoom level under
+    /// `<name>_files`, in the format of the output. Levels are filtered with
+    /// --mip-filter and --mip-space.
+    #[arg(long, conflicts_with = "gif")]
+    pub deepzoom: bool,
+
+    /// Edge of a DeepZoom tile in texels.
+    #[arg(long, default_value_t = 254, requires = "deepzoom")]
+    pub tile_size: u32,
+
+    /// Texels DeepZoom tiles repeat of their neighbours on each side.
+    #[arg(long, default_value_t = 1, requires = "deepzoom")]
+    pub tile_overlap: u32,
+
+    /// Texels around every tile of a tiled input that are processed along
+    /// with it, at least as far as the operation samples its neighbourhood
+    /// for seamless tiles. Operations see every tile as an image of its own,
+    /// so patterns laid across the image, such as halftone screens, restart
+    /// at every tile.
+    #[arg(
+        long,
+        value_name = "TEXELS",
+        default_value_t = 16,
+        requires = "deepzoom"
+    )]
+    pub tile_halo: u32,
+}
+
+/// Parses a `WIDTHxHEIGHT` size.
+pub fn parse_size(arg: &str) -> anyhow::Result<(u32, u32)> {
+    let (width, height) = arg
+        .split_once('x')
+        .ok_or_else(|| anyhow::anyhow!("Expected WIDTHxHEIGHT, got '{}'", arg))?;
+
+    let size = (width.trim().parse()?, height.trim().parse()?);
+
+    if size.0 == 0 || size.1 == 0 {
+        anyhow::bail!("Size must not be empty, got '{}'", arg);
+    }
+
+    Ok(size)
+}
+
+pub fn parse_offset(arg: &str) -> anyhow::Result<(u32, u32)> {
+    let (x, y) = arg
+        .split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("Expected X,Y, got '{}'", arg))?;
+
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+fn parse_color(arg: &str) -> anyhow::Result<[f32; 4]> {
+    crate::gradient::parse_color(arg)
+}
+
+fn parse_lens_offset(arg: &str) -> anyhow::Result<(f32, f32)> {
+    let (x, y) = arg
+        .split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("Expected X,Y, got '{}'", arg))?;
+
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+fn parse_region(arg: &str) -> anyhow::Result<Bounds> {
+    let (offset, size) = arg
+        .rsplit_once(',')
+        .ok_or_else(|| anyhow::anyhow!("Expected X,Y,WIDTHxHEIGHT, got '{}'", arg))?;
+
+    let (x, y) = parse_offset(offset)?;
+    let (width, height) = parse_size(size)?;
+
+    Ok(Bounds {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_commands_without_an_output() {
+        let cli = Cli::try_parse_from(["wgpu_texture_copy", "adapters"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Adapters)));
+        assert!(cli.process.output.is_none());
+
+        let cli = Cli::try_parse_from(["wgpu_texture_copy", "serve", "--max-jobs", "2"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Serve(_))));
+    }
+
+    #[test]
+    fn requires_the_output_of_a_run() {
+        assert!(Cli::try_parse_from(["wgpu_texture_copy", "in.png"]).is_err());
+
+        let cli = Cli::try_parse_from(["wgpu_texture_copy", "in.png", "-o", "out.png"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.process.output, Some(PathBuf::from("out.png")));
+    }
+
+    #[test]
+    fn gates_sequences_of_whole_images_only() {
+        let run = |extra: &[&str]| {
+            let args = [
+                "wgpu_texture_copy",
+                "in.png",
+                "-o",
+                "out.png",
+                "--frames",
+                "4",
+            ];
+            Cli::try_parse_from(args.iter().chain(extra))
+        };
+
+        assert!(run(&["--skip-unchanged", "2"]).is_ok());
+        assert!(run(&["--skip-unchanged", "2", "--grid", "4x2"]).is_err());
+        assert!(run(&["--skip-unchanged", "2", "--gif"]).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Images of the input and the output together, to review what an operation
+//! did at a glance.
+
+use clap::ValueEnum;
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::trim::Bounds;
+
+/// Divider drawn between the halves of a `slider` comparison.
+const DIVIDER: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// Squares along the shorter side of a `checker` comparison.
+const CHECKER_SQUARES: u32 = 8;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum CompareLayout {
+    /// The original on the left, the processed image on the right.
+    Side,
+    /// The original above the diagonal from the bottom left to the top
+    /// right, the processed image below it.
+    Slider,
+    /// Both interleaved in a checkerboard, the original in the top left
+    /// square.
+    Checker,
+}
+
+/// The original matched to what was read back of an output of
+/// `full_width`x`full_height`: scaled to that size, then cut to `region`.
+pub fn fit_original(
+    original: &RgbaImage,
+    (full_width, full_height): (u32, u32),
+    region: Option<Bounds>,
+) -> RgbaImage {
+    let scaled = if original.dimensions() == (full_width, full_height) {
+        original.clone()
+    } else {
+        imageops::resize(
+            original,
+            full_width,
+            full_height,
+            imageops::FilterType::Triangle,
+        )
+    };
+
+    match region {
+        Some(region) => {
+            imageops::crop_imm(&scaled, region.x, region.y, region.width, region.height).to_image()
+        }
+        None => scaled,
+    }
+}
+
+/// Composes `original` and `processed`, which have the same size.
+pub fn compose(original: &RgbaImage, processed: &RgbaImage, layout: CompareLayout) -> RgbaImage {
+    let (width, height) = processed.dimensions();
+
+    match layout {
+        CompareLayout::Side => {
+            let mut image = RgbaImage::new(width * 2, height);
+            imageops::replace(&mut image, original, 0, 0);
+            imageops::replace(&mut image, processed, width as i64, 0);
+            image
+        }
+        CompareLayout::Slider => {
+            // Texels on the diagonal have a side of zero, the divider covers
+            // those within about a texel of it.
+            let (w, h) = (i64::from(width), i64::from(height));
+            let thickness = w.max(h);
+
+            RgbaImage::from_fn(width, height, |x, y| {
+                let side = i64::from(x) * h + i64::from(y) * w - w * h;
+                if side.abs() < thickness {
+                    DIVIDER
+                } else if side < 0 {
+                    *original.get_pixel(x, y)
+                } else {
+                    *processed.get_pixel(x, y)
+                }
+            })
+        }
+        CompareLayout::Checker => {
+            let square = (width.min(height) / CHECKER_SQUARES).max(1);
+
+            RgbaImage::from_fn(width, height, |x, y| {
+                if (x / square + y / square).is_multiple_of(2) {
+                    *original.get_pixel(x, y)
+                } else {
+                    *processed.get_pixel(x, y)
+                }
+            })
+        }
+    }
+}
@@ -0,0 +1,532 @@
+//! Shell completion scripts, generated from the clap model of the command
+//! line with operation names and parameters taken from [`OPS`].
+
+use clap::{ArgAction, Command, ValueEnum, ValueHint};
+use std::fmt::Write as _;
+
+use crate::ops::OPS;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// A command or subcommand, reached by the subcommand names in `path`.
+struct Node {
+    path: Vec<String>,
+    /// Names and descriptions.
+    subcommands: Vec<(String, String)>,
+    options: Vec<Opt>,
+    /// What the first positional argument takes, if there is one.
+    positional: Option<Values>,
+}
+
+struct Opt {
+    long: Option<String>,
+    short: Option<char>,
+    help: String,
+    repeatable: bool,
+    values: Values,
+}
+
+#[derive(Clone)]
+enum Values {
+    Flag,
+    Choices(Vec<String>),
+    Paths,
+    Any,
+}
+
+impl Node {
+    fn key(&self) -> String {
+        self.path.iter().map(|name| format!("/{}", name)).collect()
+    }
+}
+
+impl Opt {
+    fn flags(&self) -> Vec<String> {
+        let long = self.long.iter().map(|long| format!("--{}", long));
+        let short = self.short.iter().map(|short| format!("-{}", short));
+        long.chain(short).collect()
+    }
+}
+
+/// The completion script of `command` for `shell`.
+pub fn generate(shell: Shell, command: Command) -> String {
+    let mut command = command;
+    // Spreads the global options to every subcommand.
+    command.build();
+
+    let name = command.get_name().to_string();
+    let commands: Vec<_> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    let mut nodes = Vec::new();
+    collect(&command, Vec::new(), &commands, &mut nodes);
+
+    let script = match shell {
+        Shell::Bash => bash(&name, &nodes),
+        Shell::Zsh => zsh(&name, &nodes),
+        Shell::Fish => fish(&name, &nodes),
+        Shell::Powershell => powershell(&name, &nodes),
+    };
+
+    script.expect("Writing to a String doesn't fail")
+}
+
+/// Adds the node of `command` and those of its subcommands, `commands` being
+/// the names of the top-level ones.
+fn collect(command: &Command, path: Vec<String>, commands: &[String], nodes: &mut Vec<Node>) {
+    let subcommands: Vec<_> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .collect();
+
+    nodes.push(Node {
+        path: path.clone(),
+        subcommands: subcommands
+            .iter()
+            .map(|subcommand| {
+                let about = subcommand
+                    .get_about()
+                    .map(first_sentence)
+                    .unwrap_or_default();
+                (subcommand.get_name().to_string(), about)
+            })
+            .collect(),
+        options: command
+            .get_arguments()
+            .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+            .map(|arg| Opt {
+                long: arg.get_long().map(str::to_string),
+                short: arg.get_short(),
+                help: arg.get_help().map(first_sentence).unwrap_or_default(),
+                repeatable: matches!(arg.get_action(), ArgAction::Append | ArgAction::Count),
+                values: values(arg, commands),
+            })
+            .collect(),
+        positional: command
+            .get_positionals()
+            .next()
+            .map(|arg| values(arg, commands)),
+    });
+
+    for subcommand in subcommands {
+        let mut path = path.clone();
+        path.push(subcommand.get_name().to_string());
+        collect(subcommand, path, commands, nodes);
+    }
+}
+
+fn values(arg: &clap::Arg, commands: &[String]) -> Values {
+    if !arg.get_action().takes_values() {
+        return Values::Flag;
+    }
+
+    match arg.get_id().as_str() {
+        "op" => return Values::Choices(OPS.iter().map(|op| op.name.to_string()).collect()),
+        "params" | "animate" => return Values::Choices(param_keys()),
+        // Of `help`.
+        "topic" => {
+            let ops = OPS.iter().map(|op| op.name.to_string());
+            return Values::Choices(commands.iter().cloned().chain(ops).collect());
+        }
+        _ => {}
+    }
+
+    let choices: Vec<_> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if !choices.is_empty() {
+        return Values::Choices(choices);
+    }
+
+    match arg.get_value_hint() {
+        ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath => Values::Paths,
+        _ => Values::Any,
+    }
+}
+
+/// `name=` for every parameter of an operation.
+fn param_keys() -> Vec<String> {
+    let mut keys: Vec<_> = OPS
+        .iter()
+        .flat_map(|op| op.params)
+        .map(|param| format!("{}=", param.name))
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    keys
+}
+
+/// The first sentence of a help text, short enough for a completion menu.
+fn first_sentence(text: &clap::builder::StyledStr) -> String {
+    let text = text.to_string();
+    let end = text
+        .match_indices(". ")
+        .map(|(index, _)| index)
+        .find(|&index| !text[..index].ends_with("e.g") && !text[..index].ends_with("i.e"))
+        .unwrap_or(text.len());
+    let sentence = text[..end].trim();
+
+    sentence.strip_suffix('.').unwrap_or(sentence).to_string()
+}
+
+/// The `/path` of every subcommand, which the scripts follow to find the
+/// command being completed.
+fn transitions(nodes: &[Node]) -> Vec<String> {
+    nodes
+        .iter()
+        .flat_map(|node| {
+            let key = node.key();
+            node.subcommands
+                .iter()
+                .map(move |(name, _)| format!("{}/{}", key, name))
+        })
+        .collect()
+}
+
+fn bash(name: &str, nodes: &[Node]) -> Result<String, std::fmt::Error> {
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut out = String::new();
+
+    writeln!(out, "{}() {{", function)?;
+    writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(out, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(out, "    local cmd=\"\" i")?;
+    writeln!(out, "    for ((i = 1; i < COMP_CWORD; i++)); do")?;
+    writeln!(out, "        case \"$cmd/${{COMP_WORDS[i]}}\" in")?;
+    writeln!(
+        out,
+        "            {}) cmd=\"$cmd/${{COMP_WORDS[i]}}\" ;;",
+        transitions(nodes).join("|")
+    )?;
+    writeln!(out, "        esac")?;
+    writeln!(out, "    done")?;
+    writeln!(out)?;
+    writeln!(out, "    local opts=\"\"")?;
+    writeln!(out, "    case \"$cmd\" in")?;
+
+    for node in nodes {
+        writeln!(out, "        \"{}\")", node.key())?;
+        writeln!(out, "            case \"$prev\" in")?;
+        for opt in &node.options {
+            let action = match &opt.values {
+                Values::Flag => continue,
+                Values::Choices(choices) => {
+                    let nospace = if choices.iter().all(|choice| choice.ends_with('=')) {
+                        "compopt -o nospace; "
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "{}COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+                        nospace,
+                        choices.join(" ")
+                    )
+                }
+                // Left to the default completion of file names.
+                Values::Paths | Values::Any => "return ;;".to_string(),
+            };
+            writeln!(out, "                {}) {}", opt.flags().join("|"), action)?;
+        }
+        writeln!(out, "            esac")?;
+
+        let words: Vec<_> = node
+            .options
+            .iter()
+            .flat_map(Opt::flags)
+            .chain(node.subcommands.iter().map(|(name, _)| name.clone()))
+            .collect();
+        write!(out, "            opts=\"{}", words.join(" "))?;
+        if let Some(Values::Choices(choices)) = &node.positional {
+            write!(out, " {}", choices.join(" "))?;
+        }
+        writeln!(out, "\"")?;
+        writeln!(out, "            ;;")?;
+    }
+
+    writeln!(out, "    esac")?;
+    writeln!(out)?;
+    writeln!(out, "    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "complete -o default -F {} {}", function, name)?;
+
+    Ok(out)
+}
+
+fn zsh(name: &str, nodes: &[Node]) -> Result<String, std::fmt::Error> {
+    let function = format!("_{}", name.replace('-', "_"));
+    let quote = |text: &str| text.replace('\'', "'\\''");
+    let bracket = |text: &str| quote(&text.replace('[', "\\[").replace(']', "\\]"));
+    let mut out = String::new();
+
+    writeln!(out, "#compdef {}", name)?;
+    writeln!(out)?;
+    writeln!(out, "{}() {{", function)?;
+    // `path` is tied to `$PATH` in zsh.
+    writeln!(out, "    local cmd=\"\" word")?;
+    writeln!(out, "    local -i i")?;
+    writeln!(out, "    for ((i = 2; i < CURRENT; i++)); do")?;
+    writeln!(out, "        word=${{words[i]}}")?;
+    writeln!(out, "        case \"$cmd/$word\" in")?;
+    writeln!(
+        out,
+        "            ({}) cmd=\"$cmd/$word\" ;;",
+        transitions(nodes).join("|")
+    )?;
+    writeln!(out, "        esac")?;
+    writeln!(out, "    done")?;
+    writeln!(out)?;
+    writeln!(out, "    case \"$cmd\" in")?;
+
+    for node in nodes {
+        let mut specs = Vec::new();
+        for opt in &node.options {
+            let action = match &opt.values {
+                Values::Flag => String::new(),
+                Values::Choices(choices) => format!(":value:({})", choices.join(" ")),
+                Values::Paths => ":path:_files".to_string(),
+                Values::Any => ":value: ".to_string(),
+            };
+            let repeat = if opt.repeatable { "*" } else { "" };
+
+            for flag in opt.flags() {
+                specs.push(format!(
+                    "'{}{}[{}]{}'",
+                    repeat,
+                    flag,
+                    bracket(&opt.help),
+                    quote(&action)
+                ));
+            }
+        }
+
+        let commands: Vec<_> = node
+            .subcommands
+            .iter()
+            .map(|(name, about)| format!("{}\\:\"{}\"", name, about.replace('"', "\\\"")))
+            .collect();
+        let positional = match (&node.positional, commands.is_empty()) {
+            (None, true) => None,
+            (None, false) => Some(format!("(({}))", commands.join(" "))),
+            (Some(Values::Choices(choices)), _) => Some(format!("({})", choices.join(" "))),
+            (Some(_), true) => Some("_files".to_string()),
+            (Some(_), false) => Some(format!(
+                "_alternative \"commands:command:(({}))\" \"files:file:_files\"",
+                commands.join(" ").replace('"', "\\\"")
+            )),
+        };
+        if let Some(action) = positional {
+            specs.push(format!("'1: :{}'", quote(&action)));
+        }
+
+        writeln!(out, "        (\"{}\")", node.key())?;
+        writeln!(
+            out,
+            "            _arguments -s \\\n                {}",
+            specs.join(" \\\n                ")
+        )?;
+        writeln!(out, "            ;;")?;
+    }
+
+    writeln!(out, "    esac")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "if [ \"$funcstack[1]\" = \"{}\" ]; then", function)?;
+    writeln!(out, "    {} \"$@\"", function)?;
+    writeln!(out, "else")?;
+    writeln!(out, "    compdef {} {}", function, name)?;
+    writeln!(out, "fi")?;
+
+    Ok(out)
+}
+
+fn fish(name: &str, nodes: &[Node]) -> Result<String, std::fmt::Error> {
+    let quote = |text: &str| format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut out = String::new();
+
+    for node in nodes {
+        // Every subcommand leading to the node seen, none leading further.
+        let mut conditions: Vec<_> = node
+            .path
+            .iter()
+            .map(|name| format!("__fish_seen_subcommand_from {}", name))
+            .collect();
+        let children: Vec<_> = node
+            .subcommands
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if !children.is_empty() {
+            conditions.push(format!(
+                "not __fish_seen_subcommand_from {}",
+                children.join(" ")
+            ));
+        }
+        let condition = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" -n {}", quote(&conditions.join("; and ")))
+        };
+
+        for (subcommand, about) in &node.subcommands {
+            writeln!(
+                out,
+                "complete -c {}{} -f -a {} -d {}",
+                name,
+                condition,
+                subcommand,
+                quote(about)
+            )?;
+        }
+
+        if let Some(Values::Choices(choices)) = &node.positional {
+            writeln!(
+                out,
+                "complete -c {}{} -f -a {}",
+                name,
+                condition,
+                quote(&choices.join(" "))
+            )?;
+        }
+
+        for opt in &node.options {
+            let mut line = format!("complete -c {}{}", name, condition);
+            if let Some(long) = &opt.long {
+                write!(line, " -l {}", long)?;
+            }
+            if let Some(short) = opt.short {
+                write!(line, " -s {}", short)?;
+            }
+            match &opt.values {
+                Values::Flag => {}
+                Values::Choices(choices) => write!(line, " -x -a {}", quote(&choices.join(" ")))?,
+                Values::Paths => write!(line, " -r -F")?,
+                Values::Any => write!(line, " -x")?,
+            }
+            if !opt.help.is_empty() {
+                write!(line, " -d {}", quote(&opt.help))?;
+            }
+            writeln!(out, "{}", line)?;
+        }
+    }
+
+    Ok(out)
+}
+
+fn powershell(name: &str, nodes: &[Node]) -> Result<String, std::fmt::Error> {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let list = |words: &[String]| {
+        let words: Vec<_> = words.iter().map(|word| quote(word)).collect();
+        format!("@({})", words.join(", "))
+    };
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{",
+        quote(name)
+    )?;
+    writeln!(
+        out,
+        "    param($wordToComplete, $commandAst, $cursorPosition)"
+    )?;
+    writeln!(out)?;
+
+    // Words offered after each command, and the values of the options
+    // taking any by `command|option`, none for paths and free values so
+    // PowerShell falls back to file names.
+    writeln!(out, "    $words = @{{")?;
+    for node in nodes {
+        let mut words: Vec<_> = node
+            .options
+            .iter()
+            .flat_map(Opt::flags)
+            .chain(node.subcommands.iter().map(|(name, _)| name.clone()))
+            .collect();
+        if let Some(Values::Choices(choices)) = &node.positional {
+            words.extend(choices.iter().cloned());
+        }
+        writeln!(out, "        {} = {}", quote(&node.key()), list(&words))?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "    $subcommands = @{{")?;
+    for node in nodes {
+        let names: Vec<_> = node
+            .subcommands
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        writeln!(out, "        {} = {}", quote(&node.key()), list(&names))?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "    $values = @{{")?;
+    for node in nodes {
+        for opt in &node.options {
+            let choices = match &opt.values {
+                Values::Flag => continue,
+                Values::Choices(choices) => list(choices),
+                Values::Paths | Values::Any => "@()".to_string(),
+            };
+            for flag in opt.flags() {
+                let key = format!("{}|{}", node.key(), flag);
+                writeln!(out, "        {} = {}", quote(&key), choices)?;
+            }
+        }
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    $typed = @($commandAst.CommandElements | Where-Object {{ $_.Extent.EndOffset -le $cursorPosition }} | ForEach-Object {{ $_.ToString() }})"
+    )?;
+    writeln!(out, "    if ($wordToComplete -ne '') {{")?;
+    writeln!(
+        out,
+        "        $typed = @($typed | Select-Object -SkipLast 1)"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    $command = ''")?;
+    writeln!(
+        out,
+        "    foreach ($word in ($typed | Select-Object -Skip 1)) {{"
+    )?;
+    writeln!(
+        out,
+        "        if ($subcommands[$command] -contains $word) {{"
+    )?;
+    writeln!(out, "            $command = \"$command/$word\"")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    $key = \"$command|$($typed[-1])\"")?;
+    writeln!(out, "    if ($values.ContainsKey($key)) {{")?;
+    writeln!(out, "        $candidates = $values[$key]")?;
+    writeln!(out, "    }} else {{")?;
+    writeln!(out, "        $candidates = $words[$command]")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    $candidates | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{"
+    )?;
+    writeln!(
+        out,
+        "        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    Ok(out)
+}
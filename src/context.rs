@@ -0,0 +1,379 @@
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    poll::{Poller, Spin},
+    Error, PipelineCache, ResourcePool, Result,
+};
+
+/// Everything wgpu needs to run work on one GPU. Setting it up takes far
+/// longer than most passes, so it is created once and shared by everything
+/// processed from then on.
+pub struct GpuContext {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    /// Shared, so a [`PollThread`](crate::poll::PollThread) can poll it.
+    pub device: Arc<wgpu::Device>,
+    pub queue: wgpu::Queue,
+    /// Drives the device while readbacks wait, [`Spin`] unless replaced.
+    pub poller: Box<dyn Poller>,
+    /// Pipelines of the kernels run so far, for the images after the first.
+    pub pipelines: PipelineCache,
+    /// Textures and readback buffers done with, for later images.
+    pub pool: ResourcePool,
+    uncaptured: Arc<Mutex<Option<String>>>,
+    lost: Arc<AtomicBool>,
+}
+
+/// What wgpu says about everything done on a lost device.
+const DEVICE_LOST: &str = "device is lost";
+
+/// Which adapter a [`GpuContext`] runs on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AdapterSelector {
+    /// The one wgpu picks.
+    #[default]
+    Default,
+    /// Position in [`adapters`].
+    Index(usize),
+    /// The first whose name contains this, ignoring case.
+    Name(String),
+    /// The first on this backend.
+    Backend(wgpu::Backend),
+}
+
+impl AdapterSelector {
+    fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            Self::Default => index == 0,
+            Self::Index(wanted) => index == *wanted,
+            Self::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+            Self::Backend(backend) => info.backend == *backend,
+        }
+    }
+}
+
+/// Parses an index, a backend such as `vulkan` or `gl`, or else a name.
+impl FromStr for AdapterSelector {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, String> {
+        if value.is_empty() {
+            return Err("Expected an adapter index, name or backend".to_string());
+        }
+
+        if let Ok(index) = value.parse() {
+            return Ok(Self::Index(index));
+        }
+
+        let backend = match value.to_lowercase().as_str() {
+            "vulkan" => Some(wgpu::Backend::Vulkan),
+            "metal" => Some(wgpu::Backend::Metal),
+            "dx12" => Some(wgpu::Backend::Dx12),
+            "dx11" => Some(wgpu::Backend::Dx11),
+            "gl" => Some(wgpu::Backend::Gl),
+            _ => None,
+        };
+
+        Ok(backend.map_or_else(|| Self::Name(value.to_string()), Self::Backend))
+    }
+}
+
+impl fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default adapter"),
+            Self::Index(index) => write!(f, "adapter {}", index),
+            Self::Name(name) => write!(f, "adapter named like '{}'", name),
+            Self::Backend(backend) => write!(f, "adapter on {:?}", backend),
+        }
+    }
+}
+
+/// How a [`GpuContext`] requests its adapter and device.
+#[derive(Clone, Debug)]
+pub struct AdapterOptions {
+    /// Backends to look for adapters on, all of them by default.
+    pub backends: wgpu::Backends,
+    pub selector: AdapterSelector,
+    /// Which GPU [`AdapterSelector::Default`] prefers, when there are several.
+    pub power_preference: wgpu::PowerPreference,
+    /// Only consider software adapters such as llvmpipe or WARP, for
+    /// machines without a GPU.
+    pub force_fallback_adapter: bool,
+    /// Directory to record a wgpu API trace of everything done on the
+    /// device in, for reproducing GPU bugs. Needs the `trace` feature.
+    pub trace: Option<PathBuf>,
+    /// Bytes of push constants kernels can take, see
+    /// [`TextureProcessor::kernel_with_push_constants`](crate::TextureProcessor::kernel_with_push_constants).
+    /// 0, the default, leaves `PUSH_CONSTANTS` off, which many adapters lack.
+    pub push_constant_size: u32,
+}
+
+impl Default for AdapterOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            selector: AdapterSelector::default(),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            trace: None,
+            push_constant_size: 0,
+        }
+    }
+}
+
+impl AdapterOptions {
+    /// An instance on [`AdapterOptions::backends`].
+    pub fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        })
+    }
+
+    fn describe(&self) -> String {
+        match (&self.selector, self.force_fallback_adapter) {
+            (AdapterSelector::Default, true) => "software adapter".to_string(),
+            (selector, true) => format!("software {}", selector),
+            (selector, false) => selector.to_string(),
+        }
+    }
+}
+
+/// Every adapter of `instance`, in the order [`AdapterSelector::Index`]
+/// counts them.
+pub fn adapters(instance: &wgpu::Instance) -> Vec<wgpu::Adapter> {
+    instance.enumerate_adapters(wgpu::Backends::all()).collect()
+}
+
+impl GpuContext {
+    /// Requests the default adapter and a device with downlevel limits.
+    pub async fn new() -> Result<Self> {
+        Self::with_adapter(&AdapterSelector::Default).await
+    }
+
+    /// Like [`GpuContext::new`], on the adapter `selector` picks.
+    pub async fn with_adapter(selector: &AdapterSelector) -> Result<Self> {
+        Self::with_options(&AdapterOptions {
+            selector: selector.clone(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Like [`GpuContext::new`], on the adapter `options` ask for.
+    pub async fn with_options(options: &AdapterOptions) -> Result<Self> {
+        let instance = options.instance();
+
+        let adapter = match &options.selector {
+            AdapterSelector::Default => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: options.power_preference,
+                    force_fallback_adapter: options.force_fallback_adapter,
+                    compatible_surface: None,
+                })
+                .await
+                .ok_or_else(|| {
+                    if options.force_fallback_adapter {
+                        Error::NoMatchingAdapter(options.describe())
+                    } else {
+                        Error::AdapterNotFound
+                    }
+                })?,
+            selector => adapters(&instance)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, adapter)| {
+                    !options.force_fallback_adapter
+                        || adapter.get_info().device_type == wgpu::DeviceType::Cpu
+                })
+                .find(|(index, adapter)| selector.matches(*index, &adapter.get_info()))
+                .map(|(_, adapter)| adapter)
+                .ok_or_else(|| Error::NoMatchingAdapter(options.describe()))?,
+        };
+
+        if let Some(trace) = &options.trace {
+            // wgpu just logs that it can't trace otherwise.
+            if !cfg!(feature = "trace") {
+                return Err(Error::TraceUnsupported);
+            }
+
+            fs::create_dir_all(trace).map_err(|source| Error::Io {
+                path: trace.clone(),
+                source,
+            })?;
+        }
+
+        // Only for profiling passes, read-write storage textures and zeroing
+        // recycled ones, so just where available.
+        let mut features = adapter.features()
+            & (wgpu::Features::TIMESTAMP_QUERY
+                | wgpu::Features::PIPELINE_STATISTICS_QUERY
+                | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                | wgpu::Features::CLEAR_TEXTURE);
+        let mut limits = wgpu::Limits::downlevel_defaults();
+
+        if options.push_constant_size > 0 {
+            if !adapter.features().contains(wgpu::Features::PUSH_CONSTANTS)
+                || adapter.limits().max_push_constant_size < options.push_constant_size
+            {
+                return Err(Error::PushConstantsUnsupported(options.push_constant_size));
+            }
+
+            features |= wgpu::Features::PUSH_CONSTANTS;
+            limits.max_push_constant_size = options.push_constant_size;
+        }
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features,
+                    limits,
+                },
+                options.trace.as_deref(),
+            )
+            .await?;
+
+        let info = adapter.get_info();
+        log::info!("Using {} on {:?}", info.name, info.backend);
+
+        // wgpu panics on errors outside of an error scope by default, keep
+        // the first one for `check` instead.
+        // wgpu 0.16 has no device lost callback, but reports every use of a
+        // lost device here.
+        let uncaptured = Arc::new(Mutex::new(None));
+        let lost = Arc::new(AtomicBool::new(false));
+        device.on_uncaptured_error(Box::new({
+            let uncaptured = uncaptured.clone();
+            let lost = lost.clone();
+            move |err| {
+                log::error!("{}", err);
+                let message = err.to_string();
+                if message.contains(DEVICE_LOST) {
+                    lost.store(true, Ordering::Relaxed);
+                }
+                uncaptured.lock().unwrap().get_or_insert(message);
+            }
+        }));
+
+        Ok(Self {
+            instance,
+            adapter,
+            device: Arc::new(device),
+            queue,
+            poller: Box::new(Spin),
+            pipelines: PipelineCache::default(),
+            pool: ResourcePool::default(),
+            uncaptured,
+            lost,
+        })
+    }
+
+    /// Fails with the first error wgpu reported outside of an error scope
+    /// since the last check, such as a submission it rejected, or with
+    /// [`Error::DeviceLost`] once the device is lost.
+    pub fn check(&self) -> Result<()> {
+        if self.is_lost() {
+            return Err(Error::DeviceLost);
+        }
+
+        match self.uncaptured.lock().unwrap().take() {
+            Some(message) => Err(Error::Validation(message)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether storage textures of `format` can be bound `read_write` on the
+    /// device, which depends on the adapter.
+    pub fn supports_read_write_storage(&self, format: wgpu::TextureFormat) -> bool {
+        // GLES only takes read-write images of single 32-bit channels, even
+        // where wgpu reports more.
+        if self.adapter.get_info().backend == wgpu::Backend::Gl
+            && !matches!(
+                format,
+                wgpu::TextureFormat::R32Float
+                    | wgpu::TextureFormat::R32Uint
+                    | wgpu::TextureFormat::R32Sint
+            )
+        {
+            return false;
+        }
+
+        self.device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            && self
+                .adapter
+                .get_texture_format_features(format)
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+    }
+
+    /// Whether the device was lost. Nothing created from it works anymore, so
+    /// the way on is a new context.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+
+    /// Replaces how readbacks drive the device.
+    pub fn with_poller(mut self, poller: impl Poller + 'static) -> Self {
+        self.poller = Box::new(poller);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(name: &str, backend: wgpu::Backend) -> wgpu::AdapterInfo {
+        wgpu::AdapterInfo {
+            name: name.to_string(),
+            vendor: 0,
+            device: 0,
+            device_type: wgpu::DeviceType::DiscreteGpu,
+            driver: String::new(),
+            driver_info: String::new(),
+            backend,
+        }
+    }
+
+    #[test]
+    fn parses_indices_backends_and_names() {
+        for (value, selector) in [
+            ("0", AdapterSelector::Index(0)),
+            ("12", AdapterSelector::Index(12)),
+            ("vulkan", AdapterSelector::Backend(wgpu::Backend::Vulkan)),
+            ("DX12", AdapterSelector::Backend(wgpu::Backend::Dx12)),
+            ("Gl", AdapterSelector::Backend(wgpu::Backend::Gl)),
+            ("RTX 4090", AdapterSelector::Name("RTX 4090".to_string())),
+            ("-1", AdapterSelector::Name("-1".to_string())),
+        ] {
+            assert_eq!(value.parse::<AdapterSelector>(), Ok(selector), "{}", value);
+        }
+
+        assert!("".parse::<AdapterSelector>().is_err());
+    }
+
+    #[test]
+    fn matches_adapters_by_what_was_asked() {
+        let gpu = adapter("NVIDIA GeForce RTX 4090", wgpu::Backend::Vulkan);
+
+        assert!(AdapterSelector::Default.matches(0, &gpu));
+        assert!(!AdapterSelector::Default.matches(1, &gpu));
+        assert!(AdapterSelector::Index(2).matches(2, &gpu));
+        assert!(AdapterSelector::Name("geforce".to_string()).matches(3, &gpu));
+        assert!(!AdapterSelector::Name("radeon".to_string()).matches(0, &gpu));
+        assert!(AdapterSelector::Backend(wgpu::Backend::Vulkan).matches(1, &gpu));
+        assert!(!AdapterSelector::Backend(wgpu::Backend::Gl).matches(0, &gpu));
+    }
+}
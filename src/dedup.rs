@@ -0,0 +1,218 @@
+//! `--skip-unchanged`: frames of a sequence that hardly differ from the last
+//! one written are told apart on the GPU, before anything is read back, and
+//! left out. A manifest names the file that shows every frame instead.
+
+use anyhow::*;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::diff::TextureDiff;
+
+/// Compares every frame with the last one handed on in full.
+pub struct FrameGate {
+    /// Largest channel difference, in 8-bit steps, of a frame that counts as
+    /// unchanged.
+    threshold: u32,
+    diff: TextureDiff,
+    /// Copy of the last frame handed on in full and its index.
+    previous: wgpu::Texture,
+    previous_frame: Option<usize>,
+}
+
+impl FrameGate {
+    /// Bytes of GPU memory the gate takes for frames of `texture_size`.
+    pub fn texture_bytes(texture_size: wgpu::Extent3d) -> u64 {
+        2 * crate::memory::texture_bytes(texture_size, crate::DATA_PER_PIXEL * crate::U8_SIZE)
+    }
+
+    pub fn new(device: &wgpu::Device, threshold: u32, texture_size: wgpu::Extent3d) -> Self {
+        let previous = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Previous Frame Texture"),
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        });
+
+        Self {
+            threshold,
+            diff: TextureDiff::new(device, texture_size),
+            previous,
+            previous_frame: None,
+        }
+    }
+
+    /// The frame that `output`, frame `frame`, repeats, if it is within the
+    /// threshold of the last one handed on. Otherwise `output` is kept to
+    /// compare the next frames with.
+    pub async fn repeated_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: usize,
+        output: &wgpu::Texture,
+    ) -> Result<Option<usize>> {
+        if let Some(previous_frame) = self.previous_frame {
+            let stats = self
+                .diff
+                .compare(device, queue, output, &self.previous)
+                .await?;
+
+            if stats.max <= self.threshold {
+                return Ok(Some(previous_frame));
+            }
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Previous Frame Encoder"),
+        });
+        encoder.copy_texture_to_texture(
+            output.as_image_copy(),
+            self.previous.as_image_copy(),
+            self.previous.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+
+        self.previous_frame = Some(frame);
+
+        Ok(None)
+    }
+}
+
+/// `<stem>_frames.json` next to `output`, the manifest of the sequence.
+pub fn manifest_path(output: &Path) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    output.with_file_name(format!("{}_frames.json", stem))
+}
+
+/// The file showing every frame of a sequence written with
+/// `--skip-unchanged`.
+#[derive(Serialize)]
+pub struct Manifest {
+    pub threshold: u32,
+    /// Frames written to files of their own.
+    pub written: usize,
+    pub frames: Vec<ManifestFrame>,
+}
+
+#[derive(Serialize)]
+pub struct ManifestFrame {
+    /// File name of the image, next to the manifest.
+    pub file: String,
+    /// The earlier frame whose file this frame repeats, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeats: Option<usize>,
+}
+
+impl Manifest {
+    /// The manifest of `repeats`, the repeated frame of every frame if any,
+    /// written to the numbered `files`.
+    pub fn new(threshold: u32, files: &[PathBuf], repeats: &[Option<usize>]) -> Self {
+        let file_name = |frame: usize| {
+            files[frame]
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        let frames: Vec<_> = repeats
+            .iter()
+            .enumerate()
+            .map(|(frame, &repeats)| ManifestFrame {
+                file: file_name(repeats.unwrap_or(frame)),
+                repeats,
+            })
+            .collect();
+
+        Self {
+            threshold,
+            written: repeats.iter().filter(|repeats| repeats.is_none()).count(),
+            frames,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+
+        fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu_texture_copy::create_input_texture;
+
+    const SIZE: wgpu::Extent3d = wgpu::Extent3d {
+        width: 4,
+        height: 2,
+        depth_or_array_layers: 1,
+    };
+
+    #[test]
+    fn repeats_frames_within_the_threshold_of_the_last_one_written() -> Result<()> {
+        let Some(context) = crate::tests::context() else {
+            return Ok(());
+        };
+        let (device, queue) = (&context.device, &context.queue);
+        let frame = |value: u8| {
+            let mut texels = vec![value; (SIZE.width * SIZE.height * 4) as usize];
+            texels[5] = value / 2;
+            create_input_texture(device, queue, SIZE, &texels)
+        };
+        let mut gate = FrameGate::new(device, 3, SIZE);
+
+        let repeats = futures::executor::block_on(async {
+            let mut repeats = Vec::new();
+            for (index, value) in [100, 102, 104, 106, 120, 120].into_iter().enumerate() {
+                repeats.push(
+                    gate.repeated_frame(device, queue, index, &frame(value))
+                        .await?,
+                );
+            }
+            Ok(repeats)
+        })?;
+
+        // 104 is 4 steps from 100 although only 2 from 102, which was left out.
+        assert_eq!(repeats, [None, Some(0), None, Some(2), None, Some(4)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn names_the_file_of_every_frame() -> Result<()> {
+        let output = Path::new("renders/walk.png");
+        let files: Vec<_> = (0..4)
+            .map(|frame| PathBuf::from(format!("renders/walk_{:04}.png", frame)))
+            .collect();
+        let manifest = Manifest::new(2, &files, &[None, Some(0), Some(0), None]);
+
+        assert_eq!(manifest_path(output), Path::new("renders/walk_frames.json"));
+        assert_eq!(manifest.written, 2);
+        assert_eq!(
+            serde_json::to_value(&manifest)?,
+            serde_json::json!({
+                "threshold": 2,
+                "written": 2,
+                "frames": [
+                    { "file": "walk_0000.png" },
+                    { "file": "walk_0000.png", "repeats": 0 },
+                    { "file": "walk_0000.png", "repeats": 0 },
+                    { "file": "walk_0003.png" },
+                ],
+            })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,542 @@
+//! DeepZoom pyramids, which web viewers such as OpenSeadragon load tile by
+//! tile. `out.dzi` describes the image and `out_files/<level>/<column>_<row>`
+//! hold the tiles of every level, from a single texel at level 0 up to the
+//! full image, each level half the size of the one above rounded up.
+
+use anyhow::*;
+use image::{imageops, RgbaImage};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use wgpu_texture_copy::{check_image_size, create_input_texture, DATA_PER_PIXEL, U8_SIZE};
+
+use crate::{
+    mipmap::{MipGenerator, MipmapSettings},
+    tiled::TiledImage,
+    trim::Bounds,
+};
+
+pub struct DeepZoom {
+    /// Edge of a tile, without the overlap.
+    pub tile_size: u32,
+    /// Texels every tile repeats of its neighbours on each side.
+    pub overlap: u32,
+    /// How the levels below the full image are filtered.
+    pub mipmaps: MipmapSettings,
+}
+
+/// What [`write`] wrote.
+pub struct Pyramid {
+    pub levels: u32,
+    pub tiles: usize,
+}
+
+/// The descriptor of the pyramid written for `output`, `out.dzi` for
+/// `out.png`.
+pub fn descriptor_path(output: &Path) -> PathBuf {
+    output.with_extension("dzi")
+}
+
+/// Writes `image` as a pyramid next to `output`, whose extension gives the
+/// format of the tiles.
+pub async fn write(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &RgbaImage,
+    output: &Path,
+    settings: &DeepZoom,
+) -> Result<Pyramid> {
+    check_tile_size(settings)?;
+
+    let (width, height) = image.dimensions();
+    let max_level = max_level(width, height);
+    let format = tile_format(output);
+    let tiles_dir = tiles_dir(output);
+
+    let below = downsample(device, queue, image, settings.mipmaps).await?;
+
+    let mut tiles = 0;
+    for step in 0..=max_level {
+        let (level_width, level_height) = level_size(width, height, step);
+
+        // The mip chain halves rounding down and ends at 1x1 a level early
+        // for sizes other than powers of two, such levels are fit to size.
+        let mip = match step {
+            0 => image,
+            _ => below
+                .get(step as usize - 1)
+                .or(below.last())
+                .unwrap_or(image),
+        };
+        let fitted;
+        let level = if mip.dimensions() == (level_width, level_height) {
+            mip
+        } else {
+            fitted = imageops::resize(
+                mip,
+                level_width,
+                level_height,
+                imageops::FilterType::Triangle,
+            );
+            &fitted
+        };
+
+        let level_dir = create_level_dir(&tiles_dir, max_level - step)?;
+        tiles += write_tiles(level, &level_dir, &format, settings)?;
+    }
+
+    write_descriptor(output, settings, &format, width, height)?;
+
+    Ok(Pyramid {
+        levels: max_level + 1,
+        tiles,
+    })
+}
+
+/// A pyramid written as its full-size tiles come in, for images that don't
+/// fit in memory whole. The levels below are built from the tiles on disk by
+/// [`PyramidWriter::finish`], averaging every 2x2 texels.
+pub struct PyramidWriter<'a> {
+    output: &'a Path,
+    settings: &'a DeepZoom,
+    width: u32,
+    height: u32,
+    format: String,
+    tiles_dir: PathBuf,
+    tiles: usize,
+}
+
+impl<'a> PyramidWriter<'a> {
+    pub fn new(output: &'a Path, width: u32, height: u32, settings: &'a DeepZoom) -> Result<Self> {
+        check_tile_size(settings)?;
+
+        let tiles_dir = tiles_dir(output);
+        create_level_dir(&tiles_dir, max_level(width, height))?;
+
+        Ok(Self {
+            output,
+            settings,
+            width,
+            height,
+            format: tile_format(output),
+            tiles_dir,
+            tiles: 0,
+        })
+    }
+
+    /// Columns and rows of full-size tiles.
+    pub fn grid(&self) -> (u32, u32) {
+        (
+            self.width.div_ceil(self.settings.tile_size),
+            self.height.div_ceil(self.settings.tile_size),
+        )
+    }
+
+    /// The part of the image the full-size tile at `column`, `row` covers,
+    /// overlap included.
+    pub fn tile_bounds(&self, column: u32, row: u32) -> Bounds {
+        tile_bounds(self.width, self.height, column, row, self.settings)
+    }
+
+    /// Writes the full-size tile at `column`, `row`, of the size of its
+    /// [`PyramidWriter::tile_bounds`].
+    pub fn write_tile(&mut self, column: u32, row: u32, tile: &RgbaImage) -> Result<()> {
+        let level_dir = self
+            .tiles_dir
+            .join(max_level(self.width, self.height).to_string());
+        let path = tile_path(&level_dir, column, row, &self.format);
+
+        tile.save(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        self.tiles += 1;
+
+        Ok(())
+    }
+
+    /// Builds the levels below the full-size one and writes the descriptor.
+    pub fn finish(self) -> Result<Pyramid> {
+        let max_level = max_level(self.width, self.height);
+        let mut tiles = self.tiles;
+
+        for step in 1..=max_level {
+            let (above_width, above_height) = level_size(self.width, self.height, step - 1);
+            let (width, height) = level_size(self.width, self.height, step);
+
+            let mut above = TiledImage::deepzoom_level(
+                self.tiles_dir.join((max_level - step + 1).to_string()),
+                above_width,
+                above_height,
+                self.settings,
+                &self.format,
+            );
+            let level_dir = create_level_dir(&self.tiles_dir, max_level - step)?;
+
+            for row in 0..height.div_ceil(self.settings.tile_size) {
+                for column in 0..width.div_ceil(self.settings.tile_size) {
+                    let bounds = tile_bounds(width, height, column, row, self.settings);
+                    let region = above.read(
+                        bounds.x as i64 * 2,
+                        bounds.y as i64 * 2,
+                        bounds.width * 2,
+                        bounds.height * 2,
+                    )?;
+
+                    let path = tile_path(&level_dir, column, row, &self.format);
+                    halve(&region)
+                        .save(&path)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    tiles += 1;
+                }
+
+                let next_top =
+                    ((row + 1) * self.settings.tile_size).saturating_sub(self.settings.overlap);
+                above.evict_above(next_top as i64 * 2);
+            }
+        }
+
+        write_descriptor(
+            self.output,
+            self.settings,
+            &self.format,
+            self.width,
+            self.height,
+        )?;
+
+        Ok(Pyramid {
+            levels: max_level + 1,
+            tiles,
+        })
+    }
+}
+
+/// Number of the full-size level of a `width`x`height` pyramid, the one
+/// below it being half the size.
+pub fn max_level(width: u32, height: u32) -> u32 {
+    ceil_log2(width.max(height))
+}
+
+/// `out_files` for `out.dzi` or `out.png`.
+pub fn tiles_dir(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!("{}_files", stem))
+}
+
+fn check_tile_size(settings: &DeepZoom) -> Result<()> {
+    if settings.tile_size == 0 {
+        bail!("DeepZoom tiles need a size of at least 1");
+    }
+
+    Ok(())
+}
+
+fn tile_format(output: &Path) -> String {
+    output
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string())
+}
+
+/// Size of the level `step` levels below the full-size one.
+fn level_size(width: u32, height: u32, step: u32) -> (u32, u32) {
+    (width.div_ceil(1 << step), height.div_ceil(1 << step))
+}
+
+fn create_level_dir(tiles_dir: &Path, level: u32) -> Result<PathBuf> {
+    let level_dir = tiles_dir.join(level.to_string());
+    fs::create_dir_all(&level_dir)
+        .with_context(|| format!("Failed to create {}", level_dir.display()))?;
+
+    Ok(level_dir)
+}
+
+fn tile_path(level_dir: &Path, column: u32, row: u32, format: &str) -> PathBuf {
+    level_dir.join(format!("{}_{}.{}", column, row, format))
+}
+
+fn tile_bounds(width: u32, height: u32, column: u32, row: u32, settings: &DeepZoom) -> Bounds {
+    let (tile_size, overlap) = (settings.tile_size, settings.overlap);
+    let (x, y) = (column * tile_size, row * tile_size);
+    let left = x.saturating_sub(overlap);
+    let top = y.saturating_sub(overlap);
+    let right = (x + tile_size + overlap).min(width);
+    let bottom = (y + tile_size + overlap).min(height);
+
+    Bounds {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    }
+}
+
+fn write_descriptor(
+    output: &Path,
+    settings: &DeepZoom,
+    format: &str,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let descriptor = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" ",
+            "TileSize=\"{}\" Overlap=\"{}\" Format=\"{}\">\n",
+            "  <Size Width=\"{}\" Height=\"{}\"/>\n",
+            "</Image>\n"
+        ),
+        settings.tile_size, settings.overlap, format, width, height
+    );
+    let descriptor_path = descriptor_path(output);
+
+    fs::write(&descriptor_path, descriptor)
+        .with_context(|| format!("Failed to write {}", descriptor_path.display()))
+}
+
+/// `image` at half its size, each texel the average of 2x2.
+fn halve(image: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(image.width() / 2, image.height() / 2, |x, y| {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let texel = image.get_pixel(x * 2 + dx, y * 2 + dy);
+            for (sum, channel) in sum.iter_mut().zip(texel.0) {
+                *sum += channel as u32;
+            }
+        }
+
+        image::Rgba(sum.map(|sum| ((sum + 2) / 4) as u8))
+    })
+}
+
+/// The levels below `image`, each half the size of the one before. Levels
+/// too large for a texture of `device` are halved on the CPU until the rest
+/// of the chain can be filtered on the GPU.
+async fn downsample(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &RgbaImage,
+    settings: MipmapSettings,
+) -> Result<Vec<RgbaImage>> {
+    let mut chain = Vec::new();
+
+    loop {
+        let last = chain.last().unwrap_or(image);
+        let (width, height) = last.dimensions();
+
+        if check_image_size(device, width, height, DATA_PER_PIXEL * U8_SIZE).is_ok() {
+            break;
+        }
+
+        let half = imageops::resize(
+            last,
+            width.div_ceil(2),
+            height.div_ceil(2),
+            imageops::FilterType::Triangle,
+        );
+        chain.push(half);
+    }
+
+    let base = chain.last().unwrap_or(image);
+    let size = wgpu::Extent3d {
+        width: base.width(),
+        height: base.height(),
+        depth_or_array_layers: 1,
+    };
+    let texture = create_input_texture(device, queue, size, base.as_raw());
+    let levels = MipGenerator::new(device, settings)?
+        .generate(device, queue, &texture, size)
+        .await?;
+    chain.extend(levels);
+
+    Ok(chain)
+}
+
+/// Cuts `level` into the tiles of its grid, returning how many there are.
+fn write_tiles(level: &RgbaImage, dir: &Path, format: &str, settings: &DeepZoom) -> Result<usize> {
+    let (width, height) = level.dimensions();
+    let (columns, rows) = (
+        width.div_ceil(settings.tile_size),
+        height.div_ceil(settings.tile_size),
+    );
+
+    for column in 0..columns {
+        for row in 0..rows {
+            let bounds = tile_bounds(width, height, column, row, settings);
+
+            let path = tile_path(dir, column, row, format);
+            imageops::crop_imm(level, bounds.x, bounds.y, bounds.width, bounds.height)
+                .to_image()
+                .save(&path)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+
+    Ok((columns * rows) as usize)
+}
+
+/// Smallest `n` with `2^n >= value`.
+fn ceil_log2(value: u32) -> u32 {
+    32 - (value.max(1) - 1).leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mipmap::{FilterSpace, MipFilter},
+        tests::context,
+    };
+    use image::Rgba;
+
+    fn settings(tile_size: u32, overlap: u32) -> DeepZoom {
+        DeepZoom {
+            tile_size,
+            overlap,
+            mipmaps: MipmapSettings {
+                filter: MipFilter::Box,
+                space: FilterSpace::Gamma,
+                alpha_coverage: None,
+                normal_map: false,
+                toksvig: false,
+            },
+        }
+    }
+
+    fn out_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wtc-dzi-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn tiles_of(dir: &Path) -> Vec<String> {
+        let mut tiles: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .flat_map(|level| {
+                let level = level.unwrap().path();
+                let name = level.file_name().unwrap().to_string_lossy().into_owned();
+                fs::read_dir(&level).unwrap().map(move |tile| {
+                    format!("{}/{}", name, tile.unwrap().file_name().to_string_lossy())
+                })
+            })
+            .collect();
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn lays_out_levels_and_overlapping_tiles() {
+        assert_eq!(max_level(1, 1), 0);
+        assert_eq!(max_level(10, 6), 4);
+        assert_eq!(max_level(1024, 3), 10);
+        assert_eq!(max_level(1025, 3), 11);
+        assert_eq!(level_size(10, 6, 1), (5, 3));
+        assert_eq!(level_size(10, 6, 4), (1, 1));
+
+        let settings = settings(4, 1);
+        let bounds = |column, row| {
+            let Bounds {
+                x,
+                y,
+                width,
+                height,
+            } = tile_bounds(10, 6, column, row, &settings);
+            (x, y, width, height)
+        };
+        assert_eq!(bounds(0, 0), (0, 0, 5, 5));
+        assert_eq!(bounds(1, 0), (3, 0, 6, 5));
+        assert_eq!(bounds(2, 1), (7, 3, 3, 3));
+
+        assert_eq!(tiles_dir(Path::new("a/out.png")), Path::new("a/out_files"));
+        assert_eq!(
+            descriptor_path(Path::new("a/out.jpg")),
+            Path::new("a/out.dzi")
+        );
+        assert!(check_tile_size(&DeepZoom {
+            tile_size: 0,
+            ..settings
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn halves_with_rounding() {
+        let image = RgbaImage::from_fn(4, 2, |x, _| Rgba([x as u8, 255, 0, (x * 80) as u8]));
+        let half = halve(&image);
+
+        assert_eq!(half.dimensions(), (2, 1));
+        // Averages of half a step round up: 0.5 to 1 and 2.5 to 3.
+        assert_eq!(half.get_pixel(0, 0), &Rgba([1, 255, 0, 40]));
+        assert_eq!(half.get_pixel(1, 0), &Rgba([3, 255, 0, 200]));
+    }
+
+    #[test]
+    fn streams_the_same_tiles_as_a_whole_image() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        let image = RgbaImage::from_fn(10, 6, |x, y| Rgba([x as u8 * 25, y as u8 * 40, 0, 255]));
+        let settings = settings(4, 1);
+
+        let whole = out_dir("whole").join("out.png");
+        let pyramid = futures::executor::block_on(write(
+            &context.device,
+            &context.queue,
+            &image,
+            &whole,
+            &settings,
+        ))?;
+        // 3x2 tiles at full size, 2x1 at 5x3, then one for each of the rest.
+        assert_eq!((pyramid.levels, pyramid.tiles), (5, 11));
+
+        let streamed = out_dir("streamed").join("out.png");
+        let mut writer = PyramidWriter::new(&streamed, 10, 6, &settings)?;
+        assert_eq!(writer.grid(), (3, 2));
+        for row in 0..2 {
+            for column in 0..3 {
+                let Bounds {
+                    x,
+                    y,
+                    width,
+                    height,
+                } = writer.tile_bounds(column, row);
+                let tile = imageops::crop_imm(&image, x, y, width, height).to_image();
+                writer.write_tile(column, row, &tile)?;
+            }
+        }
+        let pyramid = writer.finish()?;
+        assert_eq!((pyramid.levels, pyramid.tiles), (5, 11));
+
+        assert_eq!(
+            tiles_of(&tiles_dir(&whole)),
+            tiles_of(&tiles_dir(&streamed))
+        );
+        for tile in ["4/1_0.png", "4/2_1.png"] {
+            assert_eq!(
+                image::open(tiles_dir(&whole).join(tile))?.to_rgba8(),
+                image::open(tiles_dir(&streamed).join(tile))?.to_rgba8(),
+            );
+        }
+        assert_eq!(
+            image::open(tiles_dir(&streamed).join("0/0_0.png"))?
+                .to_rgba8()
+                .dimensions(),
+            (1, 1)
+        );
+        assert_eq!(
+            fs::read_to_string(descriptor_path(&streamed))?,
+            fs::read_to_string(descriptor_path(&whole))?
+        );
+        assert!(fs::read_to_string(descriptor_path(&whole))?
+            .contains("TileSize=\"4\" Overlap=\"1\" Format=\"png\""));
+
+        for path in [whole, streamed] {
+            let _ = fs::remove_dir_all(path.parent().unwrap());
+        }
+        Ok(())
+    }
+}
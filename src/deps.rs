@@ -0,0 +1,223 @@
+//! Records of what the outputs of a run were made from, kept with
+//! `--if-exists outdated` so that reruns skip the work whose inputs are
+//! unchanged, like a build system. A record holds the size, modification
+//! time and hash of every input file, and a hash of the settings of the run
+//! with presets, locks and defaults resolved and shaders expanded.
+//!
+//! Files whose size and modification time match the record aren't read
+//! again; ones that were only touched still count as unchanged.
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::sidecar;
+
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    /// Hash of everything about the run besides the contents of the files.
+    pub settings: String,
+    pub files: Vec<FileState>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileState {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch, on platforms that keep them.
+    pub modified: Option<u64>,
+    pub hash: String,
+}
+
+impl FileState {
+    fn new(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let metadata = fs::metadata(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified: modified(&metadata),
+            hash: sidecar::hash(&bytes),
+        })
+    }
+
+    /// Whether the file still has the contents it had.
+    fn is_current(&self, path: &Path) -> bool {
+        let Some(metadata) = fs::metadata(path).ok() else {
+            return false;
+        };
+        if self.path != path || metadata.len() != self.size {
+            return false;
+        }
+        if self.modified.is_some() && modified(&metadata) == self.modified {
+            return true;
+        }
+
+        fs::read(path).is_ok_and(|bytes| sidecar::hash(&bytes) == self.hash)
+    }
+}
+
+impl Record {
+    /// The run with `settings` over `files` as they are now.
+    pub fn new(settings: String, files: &[&Path]) -> Result<Self> {
+        Ok(Self {
+            settings,
+            files: files
+                .iter()
+                .map(|path| FileState::new(path))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// `out.png.deps` for `out.png`.
+    pub fn path(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".deps");
+
+        PathBuf::from(path)
+    }
+
+    /// The record of the run that wrote `output`, if it left a readable one.
+    pub fn load(output: &Path) -> Option<Self> {
+        let path = Self::path(output);
+        let text = fs::read_to_string(&path).ok()?;
+
+        serde_json::from_str(&text)
+            .map_err(|err| log::warn!("Ignoring {}: {}", path.display(), err))
+            .ok()
+    }
+
+    pub fn save(&self, output: &Path) -> Result<()> {
+        let path = Self::path(output);
+        let json = serde_json::to_string_pretty(self)?;
+
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Whether a run with `settings` over `files` would make the same
+    /// outputs as the recorded one.
+    pub fn is_current(&self, settings: &str, files: &[&Path]) -> bool {
+        self.settings == settings
+            && self.files.len() == files.len()
+            && self
+                .files
+                .iter()
+                .zip(files)
+                .all(|(state, path)| state.is_current(path))
+    }
+}
+
+/// Hash of `parts`, the settings of a run in a fixed order.
+pub fn settings_hash(parts: &[String]) -> String {
+    sidecar::hash(parts.join("\0").as_bytes())
+}
+
+fn modified(metadata: &fs::Metadata) -> Option<u64> {
+    let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    since_epoch.as_nanos().try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// An input file with `contents`, and the output it is made into.
+    fn input(test: &str, contents: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("wtc-deps-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("in.png"), contents).unwrap();
+
+        (dir.join("in.png"), dir.join("out.png"))
+    }
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn is_current_until_the_settings_or_files_change() -> Result<()> {
+        let (path, _) = input("settings", "texels");
+        let other = path.with_file_name("second.png");
+        fs::write(&other, "more texels")?;
+        let record = Record::new("blur".to_string(), &[&path])?;
+
+        assert!(record.is_current("blur", &[&path]));
+        assert!(!record.is_current("sharpen", &[&path]));
+        assert!(!record.is_current("blur", &[&other]));
+        assert!(!record.is_current("blur", &[&path, &other]));
+
+        fs::remove_file(&path)?;
+        assert!(!record.is_current("blur", &[&path]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn tells_edits_from_touches() -> Result<()> {
+        let (path, _) = input("touch", "texels");
+        let record = Record::new(String::new(), &[&path])?;
+        let later = SystemTime::now() + Duration::from_secs(60);
+
+        // Other contents of the same size and time are taken on trust.
+        fs::write(&path, "pixels")?;
+        set_modified(
+            &path,
+            SystemTime::UNIX_EPOCH + Duration::from_nanos(record.files[0].modified.unwrap()),
+        );
+        assert!(record.is_current("", &[&path]));
+
+        set_modified(&path, later);
+        assert!(!record.is_current("", &[&path]));
+
+        fs::write(&path, "texels")?;
+        set_modified(&path, later);
+        assert!(record.is_current("", &[&path]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_records_next_to_the_output() -> Result<()> {
+        let (path, output) = input("save", "texels");
+        assert_eq!(Record::path(&output), output.with_file_name("out.png.deps"));
+        assert!(Record::load(&output).is_none());
+
+        Record::new("blur".to_string(), &[&path])?.save(&output)?;
+        let record = Record::load(&output).unwrap();
+        assert!(record.is_current("blur", &[&path]));
+        assert_eq!(record.files[0].size, 6);
+
+        fs::write(Record::path(&output), "{ not json")?;
+        assert!(Record::load(&output).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn hashes_settings_apart_by_their_boundaries() {
+        let hash = |parts: &[&str]| {
+            settings_hash(
+                &parts
+                    .iter()
+                    .map(|part| part.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(hash(&["blur", "sigma=2"]), hash(&["blur", "sigma=2"]));
+        assert_ne!(hash(&["blur", "sigma=2"]), hash(&["blu", "rsigma=2"]));
+        assert_ne!(hash(&["blur"]), hash(&["blur", ""]));
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::*;
+use image::RgbaImage;
+use serde::Serialize;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// How far apart two images are, from the largest channel difference of
+/// every texel in 8-bit steps.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct DiffStats {
+    pub max: u32,
+    pub mean: f32,
+    /// Fraction of texels that differ at all.
+    pub differing: f32,
+}
+
+impl DiffStats {
+    /// Compares two tightly packed RGBA8 buffers of the same size on the
+    /// CPU, the way [`diff`] does.
+    pub fn between(first: &[u8], second: &[u8]) -> Self {
+        let (mut sum, mut max, mut differing) = (0u64, 0u32, 0u32);
+        for (first, second) in first.chunks_exact(4).zip(second.chunks_exact(4)) {
+            let largest = first
+                .iter()
+                .zip(second)
+                .map(|(a, b)| u32::from(a.abs_diff(*b)))
+                .max()
+                .unwrap_or_default();
+
+            sum += u64::from(largest);
+            max = max.max(largest);
+            differing += u32::from(largest > 0);
+        }
+
+        let texels = (first.len() / 4).max(1) as f32;
+
+        Self {
+            max,
+            mean: sum as f32 / texels,
+            differing: differing as f32 / texels,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Difference {
+    /// Per-channel difference, amplified so small ones stay visible.
+    pub image: RgbaImage,
+    pub stats: DiffStats,
+}
+
+/// Compares two images of the same size on the GPU.
+pub async fn diff(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    first: &RgbaImage,
+    second: &RgbaImage,
+) -> Result<Difference> {
+    if first.dimensions() != second.dimensions() {
+        bail!(
+            "Can't compare a {}x{} image with a {}x{} one",
+            first.width(),
+            first.height(),
+            second.width(),
+            second.height()
+        );
+    }
+
+    let texture_size = wgpu::Extent3d {
+        width: first.width(),
+        height: first.height(),
+        depth_or_array_layers: 1,
+    };
+
+    let first_texture = crate::create_input_texture(device, queue, texture_size, first);
+    let second_texture = crate::create_input_texture(device, queue, texture_size, second);
+
+    let pass = TextureDiff::new(device, texture_size);
+    let stats = pass
+        .compare(device, queue, &first_texture, &second_texture)
+        .await?;
+
+    let buffer = crate::read_texture(device, queue, &pass.output_texture, 0, texture_size).await?;
+
+    Ok(Difference {
+        image: RgbaImage::from_raw(texture_size.width, texture_size.height, buffer)
+            .ok_or_else(|| anyhow!("Diff buffer does not match the image size"))?,
+        stats,
+    })
+}
+
+/// The diff pass for textures of one size, set up once to compare many
+/// pairs of them, such as consecutive frames.
+pub struct TextureDiff {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    /// The amplified difference of the last comparison.
+    output_texture: wgpu::Texture,
+    stats_buffer: wgpu::Buffer,
+    texture_size: wgpu::Extent3d,
+}
+
+impl TextureDiff {
+    pub fn new(device: &wgpu::Device, texture_size: wgpu::Extent3d) -> Self {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Diff Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/diff.wgsl"))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Diff Bind Group Layout"),
+            entries: &[
+                crate::input_texture_layout_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                crate::input_texture_layout_entry(3),
+            ],
+        });
+
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Diff Texture"),
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        });
+
+        let stats_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Diff Stats Buffer"),
+            contents: bytemuck::cast_slice(&[0u32; 3]),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Diff Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Diff Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "diff",
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+            output_texture,
+            stats_buffer,
+            texture_size,
+        }
+    }
+
+    /// Compares `first` and `second`, textures of the size the pass was made
+    /// for that can be bound for sampling, reading back only the statistics.
+    pub async fn compare(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        first: &wgpu::Texture,
+        second: &wgpu::Texture,
+    ) -> Result<DiffStats> {
+        let first_view = first.create_view(&wgpu::TextureViewDescriptor::default());
+        let second_view = second.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_view = self
+            .output_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Diff Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&first_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&output_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.stats_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&second_view),
+                },
+            ],
+        });
+
+        queue.write_buffer(&self.stats_buffer, 0, bytemuck::cast_slice(&[0u32; 3]));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Diff Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Diff Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.texture_size.width, self.texture_size.height, 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        let values = crate::read_buffer(device, queue, &self.stats_buffer).await?;
+        let [sum, max, differing]: [u32; 3] = bytemuck::pod_read_unaligned(&values);
+        let texels = (self.texture_size.width * self.texture_size.height) as f32;
+
+        Ok(DiffStats {
+            max,
+            mean: sum as f32 / texels,
+            differing: differing as f32 / texels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+    use image::Rgba;
+
+    #[test]
+    fn measures_the_largest_channel_difference() {
+        let first = [0, 0, 0, 255, 10, 20, 30, 40, 255, 255, 255, 255, 7, 7, 7, 7];
+        let second = [0, 0, 0, 255, 13, 20, 24, 40, 255, 255, 255, 0, 7, 7, 7, 7];
+
+        let stats = DiffStats::between(&first, &second);
+        assert_eq!(stats.max, 255);
+        assert_eq!(stats.mean, (6.0 + 255.0) / 4.0);
+        assert_eq!(stats.differing, 0.5);
+
+        let empty = DiffStats::between(&[], &[]);
+        assert_eq!((empty.max, empty.mean, empty.differing), (0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn compares_on_the_gpu_like_on_the_cpu() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+        let (device, queue) = (&context.device, &context.queue);
+
+        let first = RgbaImage::from_fn(11, 6, |x, y| Rgba([x as u8 * 20, y as u8 * 40, 90, 255]));
+        let mut second = first.clone();
+        second.put_pixel(3, 2, Rgba([0, 80, 90, 255]));
+        second.put_pixel(10, 5, Rgba([200, 200, 91, 128]));
+
+        let difference = futures::executor::block_on(diff(device, queue, &first, &second))?;
+        let expected = DiffStats::between(&first, &second);
+        assert_eq!(difference.stats.max, expected.max);
+        assert!((difference.stats.mean - expected.mean).abs() < 1e-4);
+        assert_eq!(difference.stats.differing, expected.differing);
+
+        assert_eq!(difference.image.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_ne!(difference.image.get_pixel(3, 2), &Rgba([0, 0, 0, 255]));
+
+        let same = futures::executor::block_on(diff(device, queue, &first, &first))?;
+        assert_eq!(same.stats.max, 0);
+
+        let smaller = RgbaImage::new(11, 5);
+        let err = futures::executor::block_on(diff(device, queue, &first, &smaller)).unwrap_err();
+        assert!(
+            err.to_string().contains("11x6 image with a 11x5"),
+            "{}",
+            err
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use image::{GrayImage, ImageBuffer, Luma};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::{pingpong::PingPong, shader};
+
+/// 16-bit grayscale heightmap, white being highest.
+pub type Heightmap = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+pub struct ErosionSettings {
+    pub iterations: u32,
+    /// Water falling on every texel per time unit, in texels of depth.
+    pub rain: f32,
+    /// Height of white in the heightmap, in texels.
+    pub height_scale: f32,
+}
+
+pub struct ErodedTerrain {
+    pub heightmap: Heightmap,
+    /// Water discharge at the end of the simulation, brighter for more water.
+    pub flow: GrayImage,
+    /// Material settled above the original terrain, brighter for more.
+    pub sediment: GrayImage,
+}
+
+/// Layout of `Settings` in `erosion.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    rain: f32,
+    time_step: f32,
+    capacity: f32,
+    dissolving: f32,
+    deposition: f32,
+    evaporation: f32,
+    gravity: f32,
+    min_tilt: f32,
+}
+
+/// Runs hydraulic erosion over a heightmap on the GPU, with rain falling
+/// evenly on every texel and the water kept within the map.
+pub async fn erode(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    heightmap: &Heightmap,
+    settings: &ErosionSettings,
+) -> Result<ErodedTerrain> {
+    if settings.height_scale <= 0.0 {
+        bail!("The height scale needs to be positive");
+    }
+
+    let texture_size = wgpu::Extent3d {
+        width: heightmap.width(),
+        height: heightmap.height(),
+        depth_or_array_layers: 1,
+    };
+
+    let uniforms = Settings {
+        rain: settings.rain,
+        time_step: 0.05,
+        capacity: 0.5,
+        dissolving: 0.5,
+        deposition: 1.0,
+        evaporation: 0.02,
+        gravity: 9.81,
+        min_tilt: 0.05,
+    };
+
+    let shader = shader::preprocess(include_str!("shaders/erosion.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Erosion Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let float_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    };
+    let settings_entry = wgpu::BindGroupLayoutEntry {
+        binding: 6,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let maps_entry = wgpu::BindGroupLayoutEntry {
+        binding: 7,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    // Every pass reads some of the textures the others write, so each gets
+    // a layout of just the bindings it uses.
+    let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries,
+        })
+    };
+
+    let flux_layout = layout(
+        "Erosion Flux Bind Group Layout",
+        &[
+            float_entry(0),
+            float_entry(1),
+            storage_entry(4),
+            settings_entry,
+        ],
+    );
+    let water_layout = layout(
+        "Erosion Water Bind Group Layout",
+        &[
+            float_entry(0),
+            float_entry(1),
+            storage_entry(3),
+            storage_entry(5),
+            settings_entry,
+        ],
+    );
+    let advect_layout = layout(
+        "Erosion Advect Bind Group Layout",
+        &[
+            float_entry(0),
+            float_entry(2),
+            storage_entry(3),
+            settings_entry,
+        ],
+    );
+    let output_layout = layout(
+        "Erosion Output Bind Group Layout",
+        &[float_entry(0), float_entry(2), maps_entry],
+    );
+
+    let float_texture = |label| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        })
+    };
+
+    // Every step goes from the first terrain texture to the second and back,
+    // so unlike the flux they don't need to alternate.
+    let terrain_texture = float_texture("Erosion Terrain Texture");
+    let moved_terrain_texture = float_texture("Erosion Moved Terrain Texture");
+    let velocity_texture = float_texture("Erosion Velocity Texture");
+    let mut flux = PingPong::new(
+        device,
+        "Erosion Flux Texture",
+        texture_size,
+        wgpu::TextureFormat::Rgba32Float,
+    );
+
+    let terrain: Vec<f32> = heightmap
+        .pixels()
+        .flat_map(|pixel| {
+            let height = pixel.0[0] as f32 / u16::MAX as f32 * settings.height_scale;
+            [height, 0.0, 0.0, 0.0]
+        })
+        .collect();
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &terrain_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&terrain),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(16 * texture_size.width),
+            rows_per_image: Some(texture_size.height),
+        },
+        texture_size,
+    );
+
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Erosion Settings Buffer"),
+        contents: bytemuck::bytes_of(&uniforms),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let maps_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Erosion Maps Buffer"),
+        size: 8 * (texture_size.width * texture_size.height) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let terrain_view = terrain_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let moved_terrain_view =
+        moved_terrain_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let velocity_view = velocity_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let view_entry = |binding, view| wgpu::BindGroupEntry {
+        binding,
+        resource: wgpu::BindingResource::TextureView(view),
+    };
+    let settings_binding = wgpu::BindGroupEntry {
+        binding: 6,
+        resource: settings_buffer.as_entire_binding(),
+    };
+
+    let flux_bind_groups = flux.bind_groups(
+        device,
+        &flux_layout,
+        "Erosion Flux Bind Group",
+        (1, 4),
+        &[view_entry(0, &terrain_view), settings_binding.clone()],
+    );
+
+    let water_bind_groups = flux.read_bind_groups(
+        device,
+        &water_layout,
+        "Erosion Water Bind Group",
+        1,
+        &[
+            view_entry(0, &terrain_view),
+            view_entry(3, &moved_terrain_view),
+            view_entry(5, &velocity_view),
+            settings_binding.clone(),
+        ],
+    );
+
+    let advect_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Erosion Advect Bind Group"),
+        layout: &advect_layout,
+        entries: &[
+            view_entry(0, &moved_terrain_view),
+            view_entry(2, &velocity_view),
+            view_entry(3, &terrain_view),
+            settings_binding,
+        ],
+    });
+
+    let output_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Erosion Output Bind Group"),
+        layout: &output_layout,
+        entries: &[
+            view_entry(0, &terrain_view),
+            view_entry(2, &velocity_view),
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: maps_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline = |bind_group_layout, entry_point| {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Erosion Pipeline Layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Erosion Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let flux_pipeline = pipeline(&flux_layout, "flux");
+    let water_pipeline = pipeline(&water_layout, "water");
+    let advect_pipeline = pipeline(&advect_layout, "advect");
+    let output_pipeline = pipeline(&output_layout, "output");
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Erosion Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Erosion Pass"),
+        });
+
+        for _ in 0..settings.iterations {
+            compute_pass.set_pipeline(&flux_pipeline);
+            compute_pass.set_bind_group(0, flux.step(&flux_bind_groups), &[]);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+
+            compute_pass.set_pipeline(&water_pipeline);
+            compute_pass.set_bind_group(0, flux.latest(&water_bind_groups), &[]);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+
+            compute_pass.set_pipeline(&advect_pipeline);
+            compute_pass.set_bind_group(0, &advect_bind_group, &[]);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+        }
+
+        compute_pass.set_pipeline(&output_pipeline);
+        compute_pass.set_bind_group(0, &output_bind_group, &[]);
+        compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes = crate::read_buffer(device, queue, &maps_buffer).await?;
+    let maps: Vec<[f32; 2]> = bytemuck::pod_collect_to_vec(&bytes);
+
+    let heights: Vec<u16> = maps
+        .iter()
+        .map(|[height, _]| {
+            (height / settings.height_scale * u16::MAX as f32)
+                .round()
+                .clamp(0.0, u16::MAX as f32) as u16
+        })
+        .collect();
+
+    let deposits: Vec<f32> = heights
+        .iter()
+        .zip(heightmap.pixels())
+        .map(|(height, original)| (*height as f32 - original.0[0] as f32).max(0.0))
+        .collect();
+    let discharge: Vec<f32> = maps.iter().map(|[_, discharge]| *discharge).collect();
+
+    let (width, height) = heightmap.dimensions();
+
+    Ok(ErodedTerrain {
+        heightmap: Heightmap::from_raw(width, height, heights)
+            .ok_or_else(|| anyhow!("Erosion buffer does not match the heightmap size"))?,
+        flow: normalized(width, height, &discharge),
+        sediment: normalized(width, height, &deposits),
+    })
+}
+
+/// `values` as a grayscale image, scaled so the largest one is white.
+fn normalized(width: u32, height: u32, values: &[f32]) -> GrayImage {
+    let largest = values.iter().copied().fold(0.0, f32::max);
+    let scale = if largest > 0.0 { 255.0 / largest } else { 0.0 };
+
+    GrayImage::from_fn(width, height, |x, y| {
+        Luma([(values[(y * width + x) as usize] * scale).round() as u8])
+    })
+}
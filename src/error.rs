@@ -0,0 +1,154 @@
+use std::{fmt, path::PathBuf};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything the library can fail with.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No adapters are found that suffice all the 'hard' options.")]
+    AdapterNotFound,
+    #[error("Found no {0}")]
+    NoMatchingAdapter(String),
+    #[error("Request device failed: {0}")]
+    DeviceRequest(#[from] wgpu::RequestDeviceError),
+    #[error("Recording a wgpu trace needs the 'trace' feature")]
+    TraceUnsupported,
+    /// The adapter or the device lacks push constants of this many bytes,
+    /// see [`AdapterOptions::push_constant_size`](crate::AdapterOptions::push_constant_size).
+    #[error("No push constants of {0} bytes on this device")]
+    PushConstantsUnsupported(u32),
+    #[error("The kernel takes {expected} bytes of push constants, got {actual}")]
+    PushConstantSize { expected: u32, actual: u32 },
+    /// Parameters don't fit the struct a kernel declares, see
+    /// [`ParamLayout`](crate::params::ParamLayout).
+    #[error("Invalid parameters: {0}")]
+    InvalidParams(String),
+    /// Values for the `override` constants of a kernel don't fit its
+    /// declarations, see [`overrides`](crate::overrides).
+    #[error("Invalid override constants: {0}")]
+    InvalidOverride(String),
+    /// A readback buffer couldn't be mapped, typically as the device is lost.
+    #[error("Couldn't read the buffer back from the GPU.")]
+    MapFailed,
+    /// The device stopped working, such as after a driver reset, and has to
+    /// be recreated along with everything created from it.
+    #[error("The GPU device was lost")]
+    DeviceLost,
+    #[error("Can't read a {actual:?} texture back as {expected:?} texels")]
+    UnsupportedFormat {
+        expected: wgpu::TextureFormat,
+        actual: wgpu::TextureFormat,
+    },
+    #[error("A {width}x{height} RGBA8 image has {expected} bytes, got {actual}")]
+    SizeMismatch {
+        width: u32,
+        height: u32,
+        expected: usize,
+        actual: usize,
+    },
+    /// A caller's buffer can't take the pixels read back into it.
+    #[error("Reading back takes a buffer of {expected} bytes, got {actual}")]
+    OutputTooSmall { expected: usize, actual: usize },
+    #[error("A {width}x{height} image has no texels")]
+    EmptyImage { width: u32, height: u32 },
+    /// An image doesn't fit the textures or buffers of the device.
+    #[error(
+        "A {width}x{height} image exceeds the device limit of {limit}, process it in smaller tiles"
+    )]
+    ImageTooLarge {
+        width: u32,
+        height: u32,
+        limit: SizeLimit,
+    },
+    #[error("Region {width}x{height} at {x},{y} is not within the {texture_width}x{texture_height} texture")]
+    RegionOutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        texture_width: u32,
+        texture_height: u32,
+    },
+    #[error("Row alignment must be a power of two, got {0}")]
+    RowAlignment(u32),
+    #[error("A row stride of {stride} bytes can't hold rows of {row_bytes} bytes")]
+    RowStride { stride: u32, row_bytes: u32 },
+    #[error("Can't process {0} elements at once")]
+    TooManyElements(usize),
+    #[error("{length} elements need {size} bytes, more than the {limit} the device can bind")]
+    BufferTooLarge { length: u32, size: u64, limit: u64 },
+    #[error("Can't dispatch {0} workgroups at once")]
+    TooManyWorkgroups(u32),
+    /// A kernel doesn't compile or doesn't fit its bindings, with the
+    /// validation message of wgpu.
+    #[error("Invalid kernel: {0}")]
+    InvalidKernel(String),
+    /// wgpu rejected a bind group, pass or submission.
+    #[error("wgpu validation failed: {0}")]
+    Validation(String),
+    #[error("Unknown operation '{0}'")]
+    UnknownOp(String),
+    #[error("An operation named '{0}' is registered already")]
+    DuplicateOp(String),
+    #[error("Operation '{op}' takes {expected} inputs, got {actual}")]
+    InputCount {
+        op: String,
+        expected: u32,
+        actual: usize,
+    },
+    #[error("Operation '{op}' has no parameter '{name}'")]
+    UnknownParam { op: String, name: String },
+    #[error("Parameter '{name}' must be within {min}..{max}, got {value}")]
+    ParamOutOfRange {
+        name: String,
+        min: f32,
+        max: f32,
+        value: f32,
+    },
+    #[error("Failed to access {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[cfg(feature = "codecs")]
+    #[error("Failed to decode {}", path.display())]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+    #[cfg(feature = "codecs")]
+    #[error("Failed to encode {}", path.display())]
+    Encode {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+}
+
+impl Error {
+    /// Whether this is, or likely comes from, a lost device. wgpu doesn't
+    /// tell why a map failed, which mostly happens to lost devices.
+    pub fn is_device_lost(&self) -> bool {
+        matches!(self, Self::DeviceLost | Self::MapFailed)
+    }
+}
+
+/// The limit of the device an [`Error::ImageTooLarge`] exceeds.
+#[derive(Clone, Copy, Debug)]
+pub enum SizeLimit {
+    /// `max_texture_dimension_2d`.
+    TextureDimension(u32),
+    /// `max_buffer_size`, for the readback buffer of the image.
+    BufferSize(u64),
+}
+
+impl fmt::Display for SizeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TextureDimension(limit) => write!(f, "{} texels per side", limit),
+            Self::BufferSize(limit) => write!(f, "{} bytes per buffer", limit),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Exit codes scripts can branch on, found from the causes of the error a
+//! run failed with.
+
+use anyhow::Error;
+use std::{fmt, process::ExitCode};
+
+/// Anything else, including mistakes on the command line.
+pub const FAILURE: u8 = 1;
+/// Some inputs of a batch failed, the others were processed.
+pub const PARTIAL_FAILURE: u8 = 2;
+/// No adapter, or none matching `--adapter`, was found.
+pub const NO_ADAPTER: u8 = 3;
+/// wgpu rejected a shader, pipeline or submission.
+pub const VALIDATION: u8 = 4;
+/// A file couldn't be read, decoded, encoded or written.
+pub const IO: u8 = 5;
+
+/// Some inputs of a batch failed, after the rest were processed and
+/// reported.
+#[derive(Debug)]
+pub struct PartialFailure {
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} inputs failed", self.failed, self.total)
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+/// The exit code of a run failing with `err`, from the outermost cause
+/// with one.
+pub fn code(err: &Error) -> ExitCode {
+    let code = err.chain().find_map(|cause| {
+        if cause.is::<PartialFailure>() {
+            return Some(PARTIAL_FAILURE);
+        }
+        if cause.is::<std::io::Error>() || cause.is::<image::ImageError>() {
+            return Some(IO);
+        }
+
+        use wgpu_texture_copy::Error::*;
+        match cause.downcast_ref::<wgpu_texture_copy::Error>()? {
+            AdapterNotFound | NoMatchingAdapter(_) => Some(NO_ADAPTER),
+            Validation(_) | InvalidKernel(_) => Some(VALIDATION),
+            Io { .. } | Decode { .. } | Encode { .. } => Some(IO),
+            _ => None,
+        }
+    });
+
+    ExitCode::from(code.unwrap_or(FAILURE))
+}
@@ -0,0 +1,223 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use image::RgbaImage;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::shader;
+
+/// Geometry of a dual-lens frame: two fisheye image circles side by side,
+/// the front lens on the left and the back lens on the right.
+pub struct DualFisheye {
+    /// Field of view of each lens, in degrees, usually a bit over 180.
+    pub fov: f32,
+    /// Offset of every lens center from the middle of its half of the
+    /// frame, in texels.
+    pub front_offset: (f32, f32),
+    pub back_offset: (f32, f32),
+    /// Image circle radius in texels, by default touching the frame edges.
+    pub radius: Option<f32>,
+}
+
+/// Layout of `Settings` in `fisheye.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    front_center: [f32; 2],
+    back_center: [f32; 2],
+    radius: f32,
+    half_fov: f32,
+    padding: [f32; 2],
+}
+
+/// Reprojects dual-lens frames of one size to equirectangular frames,
+/// keeping the GPU resources around between frames.
+pub struct FisheyeStitcher {
+    input_texture: wgpu::Texture,
+    input_size: wgpu::Extent3d,
+    output_texture: wgpu::Texture,
+    output_size: wgpu::Extent3d,
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl FisheyeStitcher {
+    pub fn new(
+        device: &wgpu::Device,
+        (width, height): (u32, u32),
+        (output_width, output_height): (u32, u32),
+        lenses: &DualFisheye,
+    ) -> Result<Self> {
+        let half_width = width as f32 * 0.5;
+        let radius = lenses
+            .radius
+            .unwrap_or_else(|| (half_width.min(height as f32)) * 0.5);
+
+        let settings = Settings {
+            front_center: [
+                half_width * 0.5 + lenses.front_offset.0,
+                height as f32 * 0.5 + lenses.front_offset.1,
+            ],
+            back_center: [
+                half_width * 1.5 + lenses.back_offset.0,
+                height as f32 * 0.5 + lenses.back_offset.1,
+            ],
+            radius,
+            half_fov: lenses.fov.to_radians() * 0.5,
+            padding: [0.0; 2],
+        };
+
+        let shader = shader::preprocess(include_str!("shaders/fisheye.wgsl"), None)?;
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Fisheye Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fisheye Bind Group Layout"),
+            entries: &[
+                crate::input_texture_layout_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let input_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let output_size = wgpu::Extent3d {
+            width: output_width,
+            height: output_height,
+            depth_or_array_layers: 1,
+        };
+
+        let input_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Fisheye Input Texture"),
+            size: input_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        });
+
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Fisheye Output Texture"),
+            size: output_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        });
+
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fisheye Settings Buffer"),
+            contents: bytemuck::bytes_of(&settings),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fisheye Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&output_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: settings_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Fisheye Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Fisheye Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "equirectangular",
+        });
+
+        Ok(Self {
+            input_texture,
+            input_size,
+            output_texture,
+            output_size,
+            pipeline,
+            bind_group,
+        })
+    }
+
+    pub async fn stitch(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &RgbaImage,
+    ) -> Result<Vec<u8>> {
+        if frame.dimensions() != (self.input_size.width, self.input_size.height) {
+            bail!(
+                "Frame is {}x{}, expected {}x{} like the first one",
+                frame.width(),
+                frame.height(),
+                self.input_size.width,
+                self.input_size.height
+            );
+        }
+
+        crate::write_input_texture(queue, &self.input_texture, self.input_size, frame);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Fisheye Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Fisheye Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.output_size.width, self.output_size.height, 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        Ok(crate::read_texture(device, queue, &self.output_texture, 0, self.output_size).await?)
+    }
+}
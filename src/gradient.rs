@@ -0,0 +1,151 @@
+use anyhow::*;
+use image::RgbaImage;
+use std::str::FromStr;
+
+/// Color gradient for the `gradient-map` operation, written as comma
+/// separated `#rrggbb` or `#rrggbbaa` stops with an optional `@position`
+/// between 0 and 1, e.g. `#102040,#ff8800@0.6,#ffffff`. Stops without a
+/// position are spread evenly between their neighbours.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    stops: Vec<(f32, [f32; 4])>,
+}
+
+impl FromStr for Gradient {
+    type Err = Error;
+
+    fn from_str(arg: &str) -> Result<Self> {
+        let stops = arg.split(',').map(parse_stop).collect::<Result<Vec<_>>>()?;
+
+        if stops.len() < 2 {
+            bail!("A gradient needs at least two stops, got '{}'", arg);
+        }
+
+        let count = stops.len();
+        let mut positions: Vec<Option<f32>> = stops.iter().map(|(position, _)| *position).collect();
+        positions[0].get_or_insert(0.0);
+        positions[count - 1].get_or_insert(1.0);
+
+        // Spread stops without a position evenly between the surrounding ones.
+        let mut previous = 0;
+        for index in 1..count {
+            if let Some(position) = positions[index] {
+                let start = positions[previous].unwrap_or_default();
+                let steps = (index - previous) as f32;
+
+                for (step, between) in (previous + 1..index).enumerate() {
+                    positions[between] =
+                        Some(start + (position - start) * (step + 1) as f32 / steps);
+                }
+
+                previous = index;
+            }
+        }
+
+        let stops: Vec<_> = positions
+            .into_iter()
+            .flatten()
+            .zip(stops.into_iter().map(|(_, color)| color))
+            .collect();
+
+        let ordered = stops.windows(2).all(|pair| pair[0].0 <= pair[1].0);
+        if !ordered
+            || stops
+                .iter()
+                .any(|(position, _)| !(0.0..=1.0).contains(position))
+        {
+            bail!(
+                "Gradient stop positions must increase within 0..1, got '{}'",
+                arg
+            );
+        }
+
+        Ok(Self { stops })
+    }
+}
+
+/// Parses a `#rrggbb[aa][@position]` stop.
+fn parse_stop(stop: &str) -> Result<(Option<f32>, [f32; 4])> {
+    let (color, position) = match stop.split_once('@') {
+        Some((color, position)) => {
+            let position = position
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid stop position in '{}'", stop))?;
+
+            (color, Some(position))
+        }
+        None => (stop, None),
+    };
+
+    Ok((position, parse_color(color.trim())?))
+}
+
+pub fn parse_color(color: &str) -> Result<[f32; 4]> {
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6 || hex.len() == 8)
+        .ok_or_else(|| anyhow!("Expected a #rrggbb or #rrggbbaa color, got '{}'", color))?;
+
+    let mut rgba = [1.0; 4];
+    for (channel, index) in rgba.iter_mut().zip((0..hex.len()).step_by(2)) {
+        let value = u8::from_str_radix(&hex[index..index + 2], 16)
+            .with_context(|| format!("Invalid color '{}'", color))?;
+        *channel = value as f32 / 255.0;
+    }
+
+    Ok(rgba)
+}
+
+impl Gradient {
+    /// Gradient through the texels of the first row of `image`, left to
+    /// right.
+    pub fn from_image(image: &RgbaImage) -> Result<Self> {
+        if image.width() < 2 {
+            bail!("A gradient image needs to be at least two texels wide");
+        }
+
+        let last = (image.width() - 1) as f32;
+        let stops = (0..image.width())
+            .map(|x| {
+                let pixel = image.get_pixel(x, 0);
+                (
+                    x as f32 / last,
+                    pixel.0.map(|channel| channel as f32 / 255.0),
+                )
+            })
+            .collect();
+
+        Ok(Self { stops })
+    }
+
+    /// The gradient sampled at 256 evenly spaced positions as a 256x1 RGBA8
+    /// lookup table.
+    pub fn table(&self) -> Vec<u8> {
+        (0..256)
+            .flat_map(|index| {
+                let t = index as f32 / 255.0;
+
+                let next = self
+                    .stops
+                    .iter()
+                    .position(|(position, _)| *position >= t)
+                    .unwrap_or(self.stops.len() - 1);
+                let (end, end_color) = self.stops[next];
+                let (start, start_color) = self.stops[next.saturating_sub(1)];
+
+                let mix = if end > start {
+                    ((t - start) / (end - start)).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+
+                (0..4).map(move |channel| {
+                    let value =
+                        start_color[channel] + (end_color[channel] - start_color[channel]) * mix;
+                    (value * 255.0).round() as u8
+                })
+            })
+            .collect()
+    }
+}
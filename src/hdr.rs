@@ -0,0 +1,191 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use image::Rgba32FImage;
+use std::{borrow::Cow, path::PathBuf};
+use wgpu::util::DeviceExt;
+
+/// Layout of `Settings` in `hdr.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    exposure: f32,
+}
+
+/// Merges bracketed exposures of the same scene, taken `ev` stops apart
+/// from EV 0, into linear radiance (Debevec-style with an assumed sRGB
+/// response). Inputs are streamed through one texture.
+pub async fn merge(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    paths: &[PathBuf],
+    ev: &[f32],
+) -> Result<Rgba32FImage> {
+    if paths.len() < 3 {
+        bail!(
+            "An HDR merge needs at least three exposures, got {}",
+            paths.len()
+        );
+    }
+    if paths.len() != ev.len() {
+        bail!("Got {} exposures but {} EV values", paths.len(), ev.len());
+    }
+
+    let (width, height) = image::image_dimensions(&paths[0])
+        .with_context(|| format!("Failed to read {}", paths[0].display()))?;
+
+    let texture_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let buffer_size = (width * height) as u64 * 16;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("HDR Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+            crate::shader::preprocess(include_str!("shaders/hdr.wgsl"), None)?.source,
+        )),
+    });
+
+    let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let storage = wgpu::BufferBindingType::Storage { read_only: false };
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("HDR Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            buffer_entry(1, wgpu::BufferBindingType::Uniform),
+            buffer_entry(2, storage),
+            buffer_entry(3, storage),
+        ],
+    });
+
+    let input_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("HDR Input Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("HDR Settings Buffer"),
+        contents: bytemuck::bytes_of(&Settings { exposure: 1.0 }),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let radiance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("HDR Radiance Buffer"),
+        size: buffer_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let fallback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("HDR Fallback Buffer"),
+        size: buffer_size,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("HDR Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: settings_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: radiance_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: fallback_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("HDR Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("HDR Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let run = |pipeline: &wgpu::ComputePipeline| {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("HDR Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("HDR Pass"),
+            });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(width, height, 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+    };
+
+    run(&pipeline("init"));
+
+    let accumulate_pipeline = pipeline("accumulate");
+    for (path, ev) in paths.iter().zip(ev) {
+        let image = crate::load_image(path)?;
+        if image.dimensions() != (width, height) {
+            bail!(
+                "{} is {}x{}, exposures need to match the first one's {}x{}",
+                path.display(),
+                image.width(),
+                image.height(),
+                width,
+                height
+            );
+        }
+
+        let settings = Settings {
+            exposure: ev.exp2(),
+        };
+        queue.write_buffer(&settings_buffer, 0, bytemuck::bytes_of(&settings));
+        crate::write_input_texture(queue, &input_texture, texture_size, &image);
+
+        run(&accumulate_pipeline);
+    }
+
+    run(&pipeline("resolve"));
+
+    let bytes = crate::read_buffer(device, queue, &radiance_buffer).await?;
+
+    Rgba32FImage::from_raw(width, height, bytemuck::pod_collect_to_vec(&bytes))
+        .ok_or_else(|| anyhow!("Radiance buffer does not match the image size"))
+}
@@ -0,0 +1,222 @@
+use anyhow::*;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+const BINS: usize = 256;
+
+/// Counts of every 8-bit value, per RGBA channel.
+pub struct Histogram {
+    pub channels: [[u32; BINS]; 4],
+}
+
+/// Lookup table of an operation, computed from the histograms of its inputs.
+/// Texel `i` of the 256x1 RGBA table holds the value `i` maps to per channel.
+pub type LookupBuilder = fn(&[Histogram]) -> Vec<u8>;
+
+impl Histogram {
+    /// Cumulative distribution of `channel`, normalized to 0..1.
+    fn cdf(&self, channel: usize) -> [f32; BINS] {
+        let bins = &self.channels[channel];
+        let total = bins.iter().sum::<u32>().max(1) as f32;
+
+        let mut cdf = [0.0; BINS];
+        let mut sum = 0;
+        for (value, count) in cdf.iter_mut().zip(bins) {
+            sum += count;
+            *value = sum as f32 / total;
+        }
+
+        cdf
+    }
+}
+
+/// Maps the color channels of the first input so their histograms follow
+/// the second input: every value goes to the lowest reference value whose
+/// cumulative share is at least as large. Alpha is left as is.
+pub fn match_histograms(histograms: &[Histogram]) -> Vec<u8> {
+    let (source, reference) = (&histograms[0], &histograms[1]);
+
+    let mut table = vec![0; BINS * 4];
+
+    for channel in 0..3 {
+        let source_cdf = source.cdf(channel);
+        let reference_cdf = reference.cdf(channel);
+
+        for (value, share) in source_cdf.iter().enumerate() {
+            let matched = reference_cdf
+                .iter()
+                .position(|reference| reference >= share)
+                .unwrap_or(BINS - 1);
+
+            table[value * 4 + channel] = matched as u8;
+        }
+    }
+
+    for value in 0..BINS {
+        table[value * 4 + 3] = value as u8;
+    }
+
+    table
+}
+
+/// Histogram of an RGBA8 texture, counted on the GPU with atomics.
+pub async fn histogram(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    texture_size: wgpu::Extent3d,
+) -> Result<Histogram> {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Histogram Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/histogram.wgsl"))),
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Histogram Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let bins_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Histogram Buffer"),
+        contents: bytemuck::cast_slice(&[0u32; BINS * 4]),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Histogram Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: bins_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Histogram Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Histogram Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: "histogram",
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Histogram Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Histogram Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes = crate::read_buffer(device, queue, &bins_buffer).await?;
+    let counts: Vec<u32> = bytemuck::pod_collect_to_vec(&bytes);
+
+    let mut channels = [[0; BINS]; 4];
+    for (channel, counts) in channels.iter_mut().zip(counts.chunks_exact(BINS)) {
+        channel.copy_from_slice(counts);
+    }
+
+    Ok(Histogram { channels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+
+    /// Histogram of `texels`, counted on the CPU.
+    fn count(texels: &[[u8; 4]]) -> Histogram {
+        let mut channels = [[0; BINS]; 4];
+        for texel in texels {
+            for (channel, value) in channels.iter_mut().zip(texel) {
+                channel[*value as usize] += 1;
+            }
+        }
+
+        Histogram { channels }
+    }
+
+    #[test]
+    fn maps_values_onto_the_reference_distribution() {
+        // A ramp of red matched to a single level, and green and blue
+        // matched to their own distributions.
+        let source: Vec<[u8; 4]> = (0..8).map(|i| [i * 30, i * 30, 7, 128]).collect();
+        let reference: Vec<[u8; 4]> = (0..8).map(|i| [200, i * 30, 7, 0]).collect();
+
+        let table = match_histograms(&[count(&source), count(&reference)]);
+        let lookup = |value: usize, channel: usize| table[value * 4 + channel];
+
+        assert_eq!(lookup(0, 0), 200);
+        assert_eq!(lookup(255, 0), 200);
+        for i in 0..8 {
+            assert_eq!(lookup(i * 30, 1), (i * 30) as u8);
+        }
+        assert_eq!(lookup(7, 2), 7);
+        assert!((0..BINS).all(|value| lookup(value, 3) == value as u8));
+    }
+
+    #[test]
+    fn counts_every_texel_on_the_gpu() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        let (width, height) = (13, 5);
+        let texels: Vec<[u8; 4]> = (0..width * height)
+            .map(|i| [(i * 7) as u8, (i % 3) as u8, 255, (i / 13 * 50) as u8])
+            .collect();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = wgpu_texture_copy::create_input_texture(
+            &context.device,
+            &context.queue,
+            size,
+            bytemuck::cast_slice(&texels),
+        );
+
+        let counted = futures::executor::block_on(histogram(
+            &context.device,
+            &context.queue,
+            &texture,
+            size,
+        ))?;
+        assert_eq!(counted.channels, count(&texels).channels);
+        assert_eq!(counted.channels[2][255], width * height);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,597 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use image::Rgba32FImage;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::shader;
+use wgpu_texture_copy::trim_image_buffer;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Bytes per `FORMAT` texel.
+const TEXEL_SIZE: u32 = 8;
+/// Face size the irradiance convolution samples the environment at, about
+/// the spacing of its sample grid.
+const IRRADIANCE_SOURCE_SIZE: u32 = 32;
+
+pub struct IblSettings {
+    /// Face size of the sharpest specular level, a power of two.
+    pub size: u32,
+    /// Specular mip levels with roughness rising evenly from 0 to 1,
+    /// by default down to 8x8 faces.
+    pub levels: Option<u32>,
+    pub irradiance_size: u32,
+    /// Importance samples per specular texel.
+    pub samples: u32,
+}
+
+/// Prefiltered cubemaps as RGBA16F texels, faces in +x, -x, +y, -y, +z, -z
+/// order.
+pub struct Prefiltered {
+    /// Every specular level, the sharpest first.
+    pub specular: Vec<Vec<u8>>,
+    pub irradiance: Vec<u8>,
+}
+
+/// Layout of `Settings` in `ibl.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    roughness: f32,
+    samples: u32,
+    source_size: f32,
+    source_level: f32,
+    face: u32,
+    _padding: [u32; 3],
+}
+
+/// Uploads an equirectangular environment map as an `Rgba32Float` texture.
+/// Larger ones than the device takes are scaled down, which still leaves far
+/// more detail than prefiltering or projecting them keeps.
+pub fn upload_environment(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    environment: &Rgba32FImage,
+) -> wgpu::Texture {
+    let max_width = device.limits().max_texture_dimension_2d;
+    let scaled;
+    let environment = if environment.width() > max_width || environment.height() > max_width {
+        scaled = image::imageops::resize(
+            environment,
+            max_width,
+            (max_width / 2).max(1),
+            image::imageops::FilterType::Triangle,
+        );
+        &scaled
+    } else {
+        environment
+    };
+
+    device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Environment Texture"),
+            size: wgpu::Extent3d {
+                width: environment.width(),
+                height: environment.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        },
+        bytemuck::cast_slice(environment.as_raw()),
+    )
+}
+
+/// Turns an equirectangular environment map with linear colors into the
+/// GGX-prefiltered specular mip chain and the diffuse irradiance cubemap of
+/// split-sum image-based lighting.
+///
+/// Every pass writes one cube face at a time, and the outputs are kept as
+/// separate 2D textures per face, which every backend can read back.
+pub async fn prefilter(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    environment: &Rgba32FImage,
+    settings: &IblSettings,
+) -> Result<Prefiltered> {
+    if !settings.size.is_power_of_two() {
+        bail!(
+            "The cubemap size needs to be a power of two, got {}",
+            settings.size
+        );
+    }
+
+    let source_levels = settings.size.ilog2() + 1;
+    let levels = settings
+        .levels
+        .unwrap_or_else(|| source_levels.saturating_sub(3).max(1));
+    if !(1..=source_levels).contains(&levels) {
+        bail!(
+            "A {0}x{0} cubemap has between 1 and {1} mip levels, got {2}",
+            settings.size,
+            source_levels,
+            levels
+        );
+    }
+
+    let shader = shader::preprocess(include_str!("shaders/ibl.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("IBL Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let settings_entry = wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let output_entry = wgpu::BindGroupLayoutEntry {
+        binding: 4,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: FORMAT,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    };
+
+    let project_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("IBL Project Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            settings_entry,
+            output_entry,
+        ],
+    });
+    let filter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("IBL Filter Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            settings_entry,
+            output_entry,
+        ],
+    });
+
+    let pipeline = |bind_group_layout, entry_point| {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("IBL Pipeline Layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("IBL Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let project_pipeline = pipeline(&project_layout, "project");
+    let downsample_pipeline = pipeline(&filter_layout, "downsample");
+    let specular_pipeline = pipeline(&filter_layout, "specular");
+    let irradiance_pipeline = pipeline(&filter_layout, "irradiance");
+
+    let environment_texture = upload_environment(device, queue, environment);
+
+    let source_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("IBL Source Texture"),
+        size: wgpu::Extent3d {
+            width: settings.size,
+            height: settings.size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: source_levels,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        view_formats: &[FORMAT],
+    });
+
+    let face_textures = |label, size, mip_level_count| -> Vec<_> {
+        (0..6)
+            .map(|_| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: size,
+                        height: size,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: FORMAT,
+                    usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[FORMAT],
+                })
+            })
+            .collect()
+    };
+
+    let specular_textures = face_textures("IBL Specular Texture", settings.size, levels);
+    let irradiance_textures = face_textures("IBL Irradiance Texture", settings.irradiance_size, 1);
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("IBL Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let environment_view = environment_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // The first `levels` source levels as a cube, so it can be sampled
+    // while the next level is written.
+    let source_view = |levels| {
+        source_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            mip_level_count: Some(levels),
+            ..Default::default()
+        })
+    };
+
+    let output_view = |texture: &wgpu::Texture, level, layer| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    };
+
+    let settings_buffer = |roughness, source_level: u32, face| {
+        let uniforms = Settings {
+            roughness,
+            samples: settings.samples,
+            source_size: settings.size as f32,
+            source_level: source_level as f32,
+            face,
+            _padding: [0; 3],
+        };
+
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("IBL Settings Buffer"),
+            contents: bytemuck::bytes_of(&uniforms),
+            usage: wgpu::BufferUsages::UNIFORM,
+        })
+    };
+
+    let filter_bind_group = |source: &wgpu::TextureView, buffer: wgpu::Buffer, output| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("IBL Filter Bind Group"),
+            layout: &filter_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&output),
+                },
+            ],
+        })
+    };
+
+    // Passes in order, each with its pipeline, a bind group per face and
+    // the face size it writes.
+    let mut passes = Vec::new();
+
+    let project_bind_groups = (0..6)
+        .map(|face| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("IBL Project Bind Group"),
+                layout: &project_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&environment_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: settings_buffer(0.0, 0, face).as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&output_view(
+                            &source_texture,
+                            0,
+                            face,
+                        )),
+                    },
+                ],
+            })
+        })
+        .collect::<Vec<_>>();
+    passes.push((&project_pipeline, project_bind_groups, settings.size));
+
+    for level in 1..source_levels {
+        let source = source_view(level);
+        let bind_groups = (0..6)
+            .map(|face| {
+                filter_bind_group(
+                    &source,
+                    settings_buffer(0.0, level - 1, face),
+                    output_view(&source_texture, level, face),
+                )
+            })
+            .collect();
+        passes.push((&downsample_pipeline, bind_groups, settings.size >> level));
+    }
+
+    let source = source_view(source_levels);
+    for level in 0..levels {
+        let roughness = level as f32 / (levels - 1).max(1) as f32;
+        let bind_groups = specular_textures
+            .iter()
+            .zip(0..)
+            .map(|(texture, face)| {
+                filter_bind_group(
+                    &source,
+                    settings_buffer(roughness, 0, face),
+                    output_view(texture, level, 0),
+                )
+            })
+            .collect();
+        passes.push((&specular_pipeline, bind_groups, settings.size >> level));
+    }
+
+    let irradiance_level = settings
+        .size
+        .ilog2()
+        .saturating_sub(IRRADIANCE_SOURCE_SIZE.ilog2());
+    let bind_groups = irradiance_textures
+        .iter()
+        .zip(0..)
+        .map(|(texture, face)| {
+            filter_bind_group(
+                &source,
+                settings_buffer(1.0, irradiance_level, face),
+                output_view(texture, 0, 0),
+            )
+        })
+        .collect();
+    passes.push((&irradiance_pipeline, bind_groups, settings.irradiance_size));
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("IBL Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("IBL Pass"),
+        });
+
+        for (pipeline, bind_groups, size) in &passes {
+            compute_pass.set_pipeline(pipeline);
+            for bind_group in bind_groups {
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                compute_pass.dispatch_workgroups(*size, *size, 1);
+            }
+        }
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let mut specular = Vec::new();
+    for level in 0..levels {
+        specular.push(read_faces(device, queue, &specular_textures, level).await?);
+    }
+
+    Ok(Prefiltered {
+        specular,
+        irradiance: read_faces(device, queue, &irradiance_textures, 0).await?,
+    })
+}
+
+/// Integrates the split-sum BRDF lookup table: for N.V rising along the
+/// columns and roughness down the rows, the scale and bias applied to the
+/// Fresnel reflectance at normal incidence, row by row.
+pub async fn integrate_brdf(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: u32,
+    samples: u32,
+) -> Result<Vec<[f32; 2]>> {
+    let shader = shader::preprocess(include_str!("shaders/ibl.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("BRDF Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("BRDF Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("BRDF Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("BRDF Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: "brdf",
+    });
+
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("BRDF Settings Buffer"),
+        contents: bytemuck::bytes_of(&Settings {
+            samples,
+            ..Zeroable::zeroed()
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let lut_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("BRDF Lookup Buffer"),
+        size: 8 * (size * size) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("BRDF Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: settings_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: lut_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("BRDF Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("BRDF Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(size, size, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes = crate::read_buffer(device, queue, &lut_buffer).await?;
+
+    Ok(bytemuck::pod_collect_to_vec(&bytes))
+}
+
+/// One level of the six face textures of a cubemap, tightly packed.
+async fn read_faces(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    faces: &[wgpu::Texture],
+    level: u32,
+) -> Result<Vec<u8>> {
+    let size = (faces[0].width() >> level).max(1);
+    let align_width = crate::align_up(size * TEXEL_SIZE, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let face_size = (align_width * size) as wgpu::BufferAddress;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("IBL Level Buffer"),
+        size: face_size * faces.len() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("IBL Readback Encoder"),
+    });
+
+    for (texture, face) in faces.iter().zip(0..) {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: face_size * face,
+                    bytes_per_row: Some(align_width),
+                    rows_per_image: Some(size),
+                },
+            },
+            wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes = crate::read_buffer(device, queue, &buffer).await?;
+
+    Ok(trim_image_buffer(
+        size * TEXEL_SIZE,
+        size * faces.len() as u32,
+        &bytes,
+    ))
+}
@@ -0,0 +1,165 @@
+use anyhow::*;
+use image::Rgba32FImage;
+use std::borrow::Cow;
+
+use wgpu_texture_copy::{
+    scan::{Scan, ScanKind, ScanLayout},
+    GpuContext,
+};
+
+/// Bytes per texel of a summed-area table, four `u32` channel sums.
+const TEXEL_SIZE: u64 = 16;
+
+/// Summed-area table of an RGBA8 texture: texel `(x, y)` holds the sums of
+/// the 8-bit channel values of all texels up to and including it, as a
+/// row-major `array<vec4<u32>>`. Sums wrap around, which keeps the box sums
+/// taken from four of them exact.
+pub async fn integral_image(
+    context: &GpuContext,
+    texture: &wgpu::Texture,
+    texture_size: wgpu::Extent3d,
+) -> Result<wgpu::Buffer> {
+    let (device, queue) = (&*context.device, &context.queue);
+    let wgpu::Extent3d { width, height, .. } = texture_size;
+
+    let size = width as u64 * height as u64 * TEXEL_SIZE;
+    let limit = device.limits().max_storage_buffer_binding_size as u64;
+    if size > limit {
+        bail!(
+            "A summed-area table of {}x{} texels needs {} bytes, more than the {} the device can bind",
+            width,
+            height,
+            size,
+            limit
+        );
+    }
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Integral Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/integral.wgsl"))),
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Integral Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let sums_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Integral Buffer"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Integral Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: sums_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Integral Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Integral Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: "load",
+    });
+
+    let scan = Scan::<u32>::new(device, &context.pipelines);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Integral Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Integral Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(width, height, 1);
+    }
+
+    // Every channel of every row, then every channel of every column.
+    scan.encode(
+        device,
+        &mut encoder,
+        &sums_buffer,
+        ScanLayout {
+            length: width,
+            element_stride: 4,
+            offset: 0,
+            inner: 4,
+            inner_stride: 1,
+            outer_stride: width * 4,
+            segments: height * 4,
+        },
+        ScanKind::Inclusive,
+    )?;
+    scan.encode(
+        device,
+        &mut encoder,
+        &sums_buffer,
+        ScanLayout {
+            length: height,
+            element_stride: width * 4,
+            offset: 0,
+            inner: width * 4,
+            inner_stride: 1,
+            outer_stride: 0,
+            segments: width * 4,
+        },
+        ScanKind::Inclusive,
+    )?;
+
+    queue.submit(Some(encoder.finish()));
+
+    Ok(sums_buffer)
+}
+
+/// Reads a table from [`integral_image`] back as float channel sums in
+/// units of fully saturated texels.
+pub async fn read_integral_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    texture_size: wgpu::Extent3d,
+) -> Result<Rgba32FImage> {
+    let bytes = crate::read_buffer(device, queue, buffer).await?;
+    let sums: Vec<u32> = bytemuck::pod_collect_to_vec(&bytes);
+
+    Rgba32FImage::from_raw(
+        texture_size.width,
+        texture_size.height,
+        sums.into_iter().map(|sum| sum as f32 / 255.0).collect(),
+    )
+    .ok_or_else(|| anyhow!("Integral buffer does not match the image size"))
+}
@@ -0,0 +1,62 @@
+//! Compute shader invocations of a frame, from pipeline statistics queries.
+
+use anyhow::*;
+
+pub struct InvocationCounter {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+}
+
+impl InvocationCounter {
+    /// A counter, or `None` when `device` has no pipeline statistics queries.
+    pub fn new(device: &wgpu::Device) -> Option<Self> {
+        if !device
+            .features()
+            .contains(wgpu::Features::PIPELINE_STATISTICS_QUERY)
+        {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Pipeline Statistics Query Set"),
+            ty: wgpu::QueryType::PipelineStatistics(
+                wgpu::PipelineStatisticsTypes::COMPUTE_SHADER_INVOCATIONS,
+            ),
+            count: 1,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pipeline Statistics Buffer"),
+            size: wgpu::QUERY_SIZE as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+        })
+    }
+
+    /// Starts counting the dispatches that follow in `compute_pass`.
+    pub fn begin<'a>(&'a self, compute_pass: &mut wgpu::ComputePass<'a>) {
+        compute_pass.begin_pipeline_statistics_query(&self.query_set, 0);
+    }
+
+    pub fn end(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.end_pipeline_statistics_query();
+    }
+
+    /// Records copying the count into a buffer `read` can map, after the
+    /// compute pass.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..1, &self.resolve_buffer, 0);
+    }
+
+    /// Invocations of the frame submitted last.
+    pub async fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<u64> {
+        let bytes = crate::read_buffer(device, queue, &self.resolve_buffer).await?;
+
+        Ok(bytemuck::pod_read_unaligned(&bytes[..8]))
+    }
+}
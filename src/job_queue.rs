@@ -0,0 +1,324 @@
+//! Jobs of `serve` waiting for the GPU. The one of the highest priority is
+//! taken first, and among equals the one that came in first. The running
+//! jobs and the last finished ones are kept for `status`. Priorities are
+//! clamped to [`MAX_PRIORITY`] either way, so no client can put its jobs
+//! out of reach of the others.
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    net::IpAddr,
+    sync::{Condvar, Mutex},
+    time::Instant,
+};
+
+/// Finished jobs kept for `status`.
+const FINISHED_KEPT: usize = 100;
+
+/// Highest priority a job runs at, and the negated lowest.
+pub const MAX_PRIORITY: i32 = 10;
+
+/// A job as `status` shows it. The client, command line and error are only
+/// shown to the client of the job.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub id: u64,
+    pub client: Option<IpAddr>,
+    pub priority: i32,
+    pub args: Vec<String>,
+    pub state: JobState,
+    /// Seconds the job ran for, once it finished.
+    pub seconds: Option<f64>,
+    /// Why the job failed.
+    pub error: Option<String>,
+}
+
+impl JobStatus {
+    /// The status without what only its client may see.
+    fn redacted(&self) -> Self {
+        Self {
+            client: None,
+            args: Vec::new(),
+            error: None,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+impl JobState {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Finished => "finished",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+struct State<T> {
+    next_id: u64,
+    queued: Vec<(JobStatus, T)>,
+    running: Vec<(JobStatus, Instant)>,
+    /// Newest first.
+    finished: VecDeque<JobStatus>,
+    closed: bool,
+}
+
+pub struct JobQueue<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+    /// Most jobs one client may have queued or running at once.
+    quota: Option<u32>,
+}
+
+impl<T> JobQueue<T> {
+    pub fn new(quota: Option<u32>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                next_id: 1,
+                queued: Vec::new(),
+                running: Vec::new(),
+                finished: VecDeque::new(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            quota,
+        }
+    }
+
+    /// Queues `job` of `client` at `priority` clamped to [`MAX_PRIORITY`],
+    /// returning its id. Fails when the client has its quota of jobs queued
+    /// or running already, or the queue is closed.
+    pub fn push(&self, client: IpAddr, priority: i32, args: Vec<String>, job: T) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            bail!("The server is shutting down");
+        }
+        if let Some(quota) = self.quota {
+            let pending = state
+                .queued
+                .iter()
+                .map(|(status, _)| status)
+                .chain(state.running.iter().map(|(status, _)| status))
+                .filter(|status| status.client == Some(client))
+                .count();
+            if pending >= quota as usize {
+                bail!(
+                    "{} has {} jobs queued or running already, the most the server takes",
+                    client,
+                    pending
+                );
+            }
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let status = JobStatus {
+            id,
+            client: Some(client),
+            priority: priority.clamp(-MAX_PRIORITY, MAX_PRIORITY),
+            args,
+            state: JobState::Queued,
+            seconds: None,
+            error: None,
+        };
+        state.queued.push((status, job));
+        self.ready.notify_one();
+
+        Ok(id)
+    }
+
+    /// The next job to run, waiting for one to come in. `None` once the
+    /// queue is closed.
+    pub fn pop(&self) -> Option<(u64, T)> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(index) = next_index(&state.queued) {
+                let (mut status, job) = state.queued.remove(index);
+                let id = status.id;
+                status.state = JobState::Running;
+                state.running.push((status, Instant::now()));
+
+                return Some((id, job));
+            }
+
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    /// Records that job `id` is done, failed with `error` if given.
+    pub fn finish(&self, id: u64, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(index) = state.running.iter().position(|(status, _)| status.id == id) else {
+            return;
+        };
+
+        let (mut status, started) = state.running.remove(index);
+        status.state = match error {
+            Some(_) => JobState::Failed,
+            None => JobState::Finished,
+        };
+        status.seconds = Some(started.elapsed().as_secs_f64());
+        status.error = error;
+
+        state.finished.push_front(status);
+        state.finished.truncate(FINISHED_KEPT);
+    }
+
+    /// Stops handing out jobs, returning the ones still queued.
+    pub fn close(&self) -> Vec<T> {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        self.ready.notify_all();
+
+        state.queued.drain(..).map(|(_, job)| job).collect()
+    }
+
+    /// Jobs queued and running.
+    pub fn counts(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+
+        (state.queued.len(), state.running.len())
+    }
+
+    /// The queued jobs in the order they will run, then the running ones and
+    /// the finished ones, newest first, as `viewer` may see them.
+    pub fn statuses(&self, viewer: IpAddr) -> Vec<JobStatus> {
+        let state = self.state.lock().unwrap();
+
+        let mut queued: Vec<_> = state.queued.iter().map(|(status, _)| status).collect();
+        queued.sort_by_key(|status| (std::cmp::Reverse(status.priority), status.id));
+
+        queued
+            .into_iter()
+            .chain(state.running.iter().map(|(status, _)| status))
+            .chain(&state.finished)
+            .map(|status| match status.client == Some(viewer) {
+                true => status.clone(),
+                false => status.redacted(),
+            })
+            .collect()
+    }
+}
+
+/// Index of the job of the highest priority, the earliest among equals.
+fn next_index<T>(queued: &[(JobStatus, T)]) -> Option<usize> {
+    queued
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, (status, _))| (std::cmp::Reverse(status.priority), status.id))
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const ALICE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const BOB: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    fn push(queue: &JobQueue<&'static str>, client: IpAddr, priority: i32, job: &'static str) {
+        queue
+            .push(client, priority, vec![job.to_string()], job)
+            .unwrap();
+    }
+
+    #[test]
+    fn runs_higher_priorities_first_then_in_order() {
+        let queue = JobQueue::new(None);
+        push(&queue, ALICE, 0, "first");
+        push(&queue, BOB, 5, "urgent");
+        push(&queue, ALICE, -1, "background");
+        push(&queue, BOB, 0, "second");
+
+        let order: Vec<_> = queue
+            .statuses(ALICE)
+            .iter()
+            .map(|status| status.id)
+            .collect();
+        assert_eq!(order, [2, 1, 4, 3]);
+
+        let popped: Vec<_> = (0..4).map(|_| queue.pop().unwrap().1).collect();
+        assert_eq!(popped, ["urgent", "first", "second", "background"]);
+    }
+
+    #[test]
+    fn clamps_priorities() {
+        let queue = JobQueue::new(None);
+        push(&queue, ALICE, i32::MAX, "high");
+        push(&queue, BOB, MAX_PRIORITY, "also high");
+        push(&queue, BOB, i32::MIN, "low");
+
+        let priorities: Vec<_> = queue
+            .statuses(ALICE)
+            .iter()
+            .map(|status| status.priority)
+            .collect();
+        assert_eq!(priorities, [MAX_PRIORITY, MAX_PRIORITY, -MAX_PRIORITY]);
+        assert_eq!(queue.pop().unwrap().1, "high");
+    }
+
+    #[test]
+    fn turns_down_jobs_over_the_quota_until_one_finishes() {
+        let queue = JobQueue::new(Some(2));
+        push(&queue, ALICE, 0, "a");
+        push(&queue, ALICE, 0, "b");
+        assert!(queue.push(ALICE, 0, Vec::new(), "c").is_err());
+        push(&queue, BOB, 0, "d");
+
+        // Running jobs count against the quota as well.
+        let (id, _) = queue.pop().unwrap();
+        assert!(queue.push(ALICE, 0, Vec::new(), "c").is_err());
+
+        queue.finish(id, Some("failed".to_string()));
+        push(&queue, ALICE, 0, "c");
+        assert_eq!(queue.counts(), (3, 0));
+    }
+
+    #[test]
+    fn shows_other_clients_only_what_their_jobs_are_at() {
+        let queue = JobQueue::new(None);
+        push(&queue, ALICE, 0, "mine");
+        push(&queue, BOB, 0, "theirs");
+        let (id, _) = queue.pop().unwrap();
+        queue.finish(id, Some("Failed to read /home/alice/in.png".to_string()));
+
+        let statuses = queue.statuses(BOB);
+        assert_eq!(statuses[0].client, Some(BOB));
+        assert_eq!(statuses[0].args, ["theirs"]);
+
+        let other = &statuses[1];
+        assert_eq!((other.client, other.state), (None, JobState::Failed));
+        assert!(other.args.is_empty() && other.error.is_none());
+
+        assert_eq!(queue.statuses(ALICE)[1].args, ["mine"]);
+    }
+
+    #[test]
+    fn hands_back_the_queued_jobs_once_closed() {
+        let queue = JobQueue::new(None);
+        push(&queue, ALICE, 0, "running");
+        push(&queue, ALICE, 0, "queued");
+        queue.pop().unwrap();
+
+        assert_eq!(queue.close(), ["queued"]);
+        assert!(queue.pop().is_none());
+        assert!(queue.push(ALICE, 0, Vec::new(), "late").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use serde::Serialize;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+pub const MAX_CLUSTERS: u32 = 64;
+
+const ITERATIONS: u32 = 16;
+/// Texels looked at to pick the starting centroids.
+const INIT_SAMPLES: usize = 4096;
+/// 64-bit words per cluster in the sums buffer of `kmeans.wgsl`.
+const SUM_WORDS: usize = 12;
+/// Offset of the centroid positions in the lookup table.
+const POSITIONS: usize = 128;
+
+/// Mean color and position of the texels in one cluster.
+#[derive(Clone, Copy, Serialize)]
+pub struct Centroid {
+    pub color: [u8; 3],
+    pub x: f32,
+    pub y: f32,
+    pub texels: u32,
+}
+
+/// Layout of `Centroid` in `kmeans.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuCentroid {
+    color: [f32; 4],
+    position: [f32; 2],
+    texels: u32,
+    padding: u32,
+}
+
+/// Layout of `Settings` in `kmeans.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    clusters: u32,
+    spatial: f32,
+}
+
+/// Runs k-means over the RGB colors of `texture`, whose texels are `buffer`,
+/// optionally together with their normalized position weighted by `spatial`.
+/// The centroids start at luminance quantiles, so results are deterministic.
+pub async fn cluster(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    buffer: &[u8],
+    texture_size: wgpu::Extent3d,
+    clusters: u32,
+    spatial: f32,
+) -> Result<Vec<Centroid>> {
+    if !(1..=MAX_CLUSTERS).contains(&clusters) {
+        bail!(
+            "Cluster count must be within 1..{}, got {}",
+            MAX_CLUSTERS,
+            clusters
+        );
+    }
+
+    let initial = initial_centroids(buffer, texture_size, clusters as usize);
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("K-Means Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/kmeans.wgsl"))),
+    });
+
+    let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let storage = wgpu::BufferBindingType::Storage { read_only: false };
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("K-Means Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            buffer_entry(1, wgpu::BufferBindingType::Uniform),
+            buffer_entry(2, storage),
+            buffer_entry(3, storage),
+        ],
+    });
+
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("K-Means Settings Buffer"),
+        contents: bytemuck::bytes_of(&Settings { clusters, spatial }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let centroids_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("K-Means Centroids Buffer"),
+        contents: bytemuck::cast_slice(&initial),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+
+    let sums_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("K-Means Sums Buffer"),
+        contents: bytemuck::cast_slice(&vec![0u32; clusters as usize * SUM_WORDS]),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("K-Means Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: settings_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: centroids_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: sums_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("K-Means Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("K-Means Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let assign_pipeline = pipeline("assign");
+    let update_pipeline = pipeline("update");
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("K-Means Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("K-Means Pass"),
+        });
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+
+        for _ in 0..ITERATIONS {
+            compute_pass.set_pipeline(&assign_pipeline);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+            compute_pass.set_pipeline(&update_pipeline);
+            compute_pass.dispatch_workgroups(clusters, 1, 1);
+        }
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes = crate::read_buffer(device, queue, &centroids_buffer).await?;
+    let centroids: Vec<GpuCentroid> = bytemuck::pod_collect_to_vec(&bytes);
+
+    Ok(centroids
+        .iter()
+        .map(|centroid| Centroid {
+            color: [0, 1, 2].map(|channel| (centroid.color[channel] * 255.0).round() as u8),
+            x: centroid.position[0] * texture_size.width as f32,
+            y: centroid.position[1] * texture_size.height as f32,
+            texels: centroid.texels,
+        })
+        .collect())
+}
+
+/// Spreads the starting centroids over the luminance range of a sample of
+/// the texels.
+fn initial_centroids(buffer: &[u8], size: wgpu::Extent3d, clusters: usize) -> Vec<GpuCentroid> {
+    let texels = (size.width * size.height) as usize;
+    let stride = (texels / INIT_SAMPLES).max(1);
+
+    let mut samples: Vec<(f32, usize)> = (0..texels)
+        .step_by(stride)
+        .map(|index| {
+            let texel = &buffer[index * 4..index * 4 + 3];
+            let luminance =
+                0.2126 * texel[0] as f32 + 0.7152 * texel[1] as f32 + 0.0722 * texel[2] as f32;
+            (luminance, index)
+        })
+        .collect();
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    (0..clusters)
+        .map(|cluster| {
+            let (_, index) = samples[(cluster * 2 + 1) * samples.len() / (clusters * 2)];
+            let texel = &buffer[index * 4..index * 4 + 4];
+            let (x, y) = (index as u32 % size.width, index as u32 / size.width);
+
+            GpuCentroid {
+                color: [texel[0], texel[1], texel[2], 255].map(|value| value as f32 / 255.0),
+                position: [
+                    (x as f32 + 0.5) / size.width as f32,
+                    (y as f32 + 0.5) / size.height as f32,
+                ],
+                texels: 0,
+                padding: 0,
+            }
+        })
+        .collect()
+}
+
+/// Lookup table of the `segment` operation: the cluster colors from texel 0
+/// and their normalized positions as 16-bit values from texel
+/// [`POSITIONS`].
+pub fn table(centroids: &[Centroid], size: wgpu::Extent3d) -> Vec<u8> {
+    let mut table = vec![0; 256 * 4];
+
+    for (index, centroid) in centroids.iter().enumerate() {
+        table[index * 4..index * 4 + 3].copy_from_slice(&centroid.color);
+        table[index * 4 + 3] = 255;
+
+        let x = (centroid.x / size.width as f32 * 65535.0).round() as u16;
+        let y = (centroid.y / size.height as f32 * 65535.0).round() as u16;
+        let position = (POSITIONS + index) * 4;
+        table[position..position + 2].copy_from_slice(&x.to_be_bytes());
+        table[position + 2..position + 4].copy_from_slice(&y.to_be_bytes());
+    }
+
+    table
+}
@@ -0,0 +1,147 @@
+use anyhow::*;
+use std::{fs, path::Path};
+
+const IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+
+/// Uncompressed formats the KTX2 writer knows the data format descriptor of.
+#[derive(Clone, Copy)]
+pub enum Format {
+    Rgba16Float,
+    Rg32Float,
+}
+
+impl Format {
+    fn vk_format(self) -> u32 {
+        match self {
+            // VK_FORMAT_R16G16B16A16_SFLOAT
+            Self::Rgba16Float => 97,
+            // VK_FORMAT_R32G32_SFLOAT
+            Self::Rg32Float => 103,
+        }
+    }
+
+    /// Bytes per channel and channel ids (red 0, green 1, blue 2, alpha 15).
+    fn channels(self) -> (u32, &'static [u8]) {
+        match self {
+            Self::Rgba16Float => (2, &[0, 1, 2, 15]),
+            Self::Rg32Float => (4, &[0, 1]),
+        }
+    }
+
+    fn texel_size(self) -> u32 {
+        let (size, channels) = self.channels();
+        size * channels.len() as u32
+    }
+}
+
+/// Writes a 2D texture, or a cubemap when `faces` is 6, as KTX2. Every entry
+/// of `levels` holds one mip level from the largest down, its faces one
+/// after the other in +x, -x, +y, -y, +z, -z order, tightly packed.
+pub fn write(
+    path: &Path,
+    format: Format,
+    (width, height): (u32, u32),
+    faces: u32,
+    levels: &[Vec<u8>],
+) -> Result<()> {
+    for (level, data) in levels.iter().enumerate() {
+        let level_width = (width >> level).max(1);
+        let level_height = (height >> level).max(1);
+        let expected = (level_width * level_height * faces * format.texel_size()) as usize;
+        if data.len() != expected {
+            bail!(
+                "Mip level {} holds {} bytes instead of {}",
+                level,
+                data.len(),
+                expected
+            );
+        }
+    }
+
+    let descriptor = data_format_descriptor(format);
+
+    let level_index_start = 80;
+    let descriptor_offset = level_index_start + 24 * levels.len();
+    let mut offset = descriptor_offset + descriptor.len();
+
+    // Levels are stored from the smallest up, each aligned to its texels.
+    let alignment = (format.texel_size() as usize).max(4);
+    let mut placements = vec![(0, 0); levels.len()];
+    for (level, data) in levels.iter().enumerate().rev() {
+        offset = offset.div_ceil(alignment) * alignment;
+        placements[level] = (offset, data.len());
+        offset += data.len();
+    }
+
+    let mut file = Vec::with_capacity(offset);
+    file.extend(IDENTIFIER);
+    for value in [
+        format.vk_format(),
+        format.channels().0,
+        width,
+        height,
+        0,
+        0,
+        faces,
+        levels.len() as u32,
+        0,
+    ] {
+        file.extend(value.to_le_bytes());
+    }
+
+    // Data format descriptor, with no key/value or supercompression data.
+    file.extend((descriptor_offset as u32).to_le_bytes());
+    file.extend((descriptor.len() as u32).to_le_bytes());
+    file.extend([0u8; 8]);
+    file.extend([0u8; 16]);
+
+    for (start, length) in &placements {
+        file.extend((*start as u64).to_le_bytes());
+        file.extend((*length as u64).to_le_bytes());
+        file.extend((*length as u64).to_le_bytes());
+    }
+
+    file.extend(&descriptor);
+
+    for (level, data) in levels.iter().enumerate().rev() {
+        file.resize(placements[level].0, 0);
+        file.extend(data);
+    }
+
+    fs::write(path, file).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Basic data format descriptor of a linear, float `format`.
+fn data_format_descriptor(format: Format) -> Vec<u8> {
+    let (channel_size, channels) = format.channels();
+    let block_size = 24 + 16 * channels.len() as u32;
+
+    let mut descriptor = Vec::new();
+    descriptor.extend((4 + block_size).to_le_bytes());
+    // Khronos vendor, basic descriptor type, version 2.
+    descriptor.extend(0u32.to_le_bytes());
+    descriptor.extend(2u16.to_le_bytes());
+    descriptor.extend((block_size as u16).to_le_bytes());
+    // RGBSDA color model, BT.709 primaries, linear transfer, straight alpha.
+    descriptor.extend([1, 1, 1, 0]);
+    // A single texel per block.
+    descriptor.extend([0; 4]);
+    // Bytes in the first plane.
+    descriptor.extend([format.texel_size() as u8, 0, 0, 0, 0, 0, 0, 0]);
+
+    for (index, channel) in channels.iter().enumerate() {
+        let bits = channel_size * 8;
+        descriptor.extend(((index as u32 * bits) as u16).to_le_bytes());
+        descriptor.push((bits - 1) as u8);
+        // Float and signed flags.
+        descriptor.push(channel | 0x80 | 0x40);
+        descriptor.extend([0; 4]);
+        // Float channels span -1..1.
+        descriptor.extend((-1.0f32).to_bits().to_le_bytes());
+        descriptor.extend(1.0f32.to_bits().to_le_bytes());
+    }
+
+    descriptor
+}
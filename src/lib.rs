@@ -0,0 +1,1011 @@
+//! GPU building blocks of the texture tool that are useful on their own.
+//! [`TextureProcessor`] runs compute kernels over RGBA8 image buffers, from
+//! device setup to readback. Its async methods never block the thread, so
+//! they can be awaited from any runtime; the `_blocking` ones are for callers
+//! outside of one.
+
+mod context;
+mod error;
+mod map;
+pub mod op;
+pub mod overrides;
+pub mod params;
+mod pipeline_cache;
+pub mod poll;
+mod processor;
+mod resource_pool;
+mod row_packer;
+pub mod scan;
+mod texel;
+mod upload;
+
+use std::{borrow::Cow, sync::Arc};
+use wgpu::{Device, Queue};
+
+use poll::{Poller, Spin};
+
+pub use context::{adapters, AdapterOptions, AdapterSelector, GpuContext};
+pub use error::{Error, Result, SizeLimit};
+pub use map::map_buffer;
+pub use pipeline_cache::PipelineCache;
+pub use processor::{
+    create_input_texture, input_texture_descriptor, input_texture_layout_entry,
+    output_texture_descriptor, output_texture_layout_entry, read_write_texture_layout_entry,
+    write_input_texture, Kernel, TextureProcessor, COPY_SHADER,
+};
+pub use resource_pool::{PooledBuffer, PooledTexture, ResourcePool, DEFAULT_POOL_BUDGET};
+pub use row_packer::RowPacker;
+#[cfg(feature = "codecs")]
+pub use texel::TexelImage;
+pub use texel::{Rgba16, Rgba32F, Rgba8, Texel};
+#[cfg(feature = "codecs")]
+pub use upload::upload_file;
+pub use upload::{upload_mapped, SourceLayout};
+
+/// Bytes per RGBA8 texel.
+pub const DATA_PER_PIXEL: u32 = 4;
+pub const U8_SIZE: u32 = std::mem::size_of::<u8>() as u32;
+
+/// Rounds `num` up to a multiple of `align`, a power of two.
+pub fn align_up(num: u32, align: u32) -> u32 {
+    (num + align - 1) & !(align - 1)
+}
+
+/// Workgroup size attribute of kernels dispatched in workgroups of a size
+/// picked at run time, see [`expand_workgroup_size`].
+pub const WORKGROUP_SIZE_ATTRIBUTE: &str = "@workgroup_size(WORKGROUP_SIZE)";
+
+/// What [`WORKGROUP_SIZE_ATTRIBUTE`] stands for unless told otherwise.
+pub const DEFAULT_WORKGROUP_SIZE: [u32; 2] = [8, 8];
+
+/// `shader` with its [`WORKGROUP_SIZE_ATTRIBUTE`]s declaring `width` by
+/// `height` invocations.
+pub fn expand_workgroup_size(shader: &str, [width, height]: [u32; 2]) -> Cow<'_, str> {
+    match shader.contains(WORKGROUP_SIZE_ATTRIBUTE) {
+        true => Cow::Owned(shader.replace(
+            WORKGROUP_SIZE_ATTRIBUTE,
+            &format!("@workgroup_size({}, {})", width, height),
+        )),
+        false => Cow::Borrowed(shader),
+    }
+}
+
+/// Workgroup size `entry_point` of the WGSL in `shader` declares. A shader
+/// that doesn't parse counts as 1x1x1, leaving wgpu to report it when the
+/// pipeline is made.
+pub fn workgroup_size(shader: &str, entry_point: &str) -> [u32; 3] {
+    naga::front::wgsl::parse_str(shader)
+        .ok()
+        .and_then(|module| {
+            module
+                .entry_points
+                .into_iter()
+                .find(|entry| entry.name == entry_point)
+        })
+        .map_or([1, 1, 1], |entry| entry.workgroup_size)
+}
+
+/// Workgroups of `workgroup_size` to dispatch along x and y for one
+/// invocation per texel of a `width`x`height` output. Kernels check their
+/// coordinates against the output, as the last workgroups overhang it.
+pub fn workgroup_count(width: u32, height: u32, workgroup_size: [u32; 3]) -> (u32, u32) {
+    (
+        width.div_ceil(workgroup_size[0]),
+        height.div_ceil(workgroup_size[1]),
+    )
+}
+
+/// Copies `source`, which needs `COPY_SRC` usage, into a readback buffer and
+/// returns its contents.
+pub async fn read_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    source: &wgpu::Buffer,
+) -> Result<Vec<u8>> {
+    read_buffer_polled(device, queue, source, &Spin).await
+}
+
+/// Like [`read_buffer`], driving the device with `poller`.
+pub async fn read_buffer_polled(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    source: &wgpu::Buffer,
+    poller: &dyn Poller,
+) -> Result<Vec<u8>> {
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: source.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+
+    encoder.copy_buffer_to_buffer(source, 0, &readback_buffer, 0, source.size());
+
+    queue.submit(Some(encoder.finish()));
+
+    let slice = readback_buffer.slice(..);
+
+    if map_for_reading(device, poller, slice).await.is_ok() {
+        let buffer = slice.get_mapped_range().to_vec();
+
+        readback_buffer.unmap();
+
+        Ok(buffer)
+    } else {
+        Err(Error::MapFailed)
+    }
+}
+
+/// Maps `slice` for reading, driving the device with `poller` until done.
+/// Unless that is [`poll::Wait`], the thread is never blocked, so it can be
+/// awaited on any executor, a tokio runtime included.
+async fn map_for_reading(
+    device: &wgpu::Device,
+    poller: &dyn Poller,
+    slice: wgpu::BufferSlice<'_>,
+) -> Result<()> {
+    let (sender, mut receiver) = futures::channel::oneshot::channel();
+
+    slice.map_async(wgpu::MapMode::Read, move |v| {
+        let _ = sender.send(v);
+    });
+
+    loop {
+        let result = match receiver.try_recv() {
+            Ok(Some(result)) => result,
+            Ok(None) if poller.poll(device) => {
+                yield_now().await;
+                continue;
+            }
+            Ok(None) => receiver.await.map_err(|_| Error::MapFailed)?,
+            Err(_) => return Err(Error::MapFailed),
+        };
+
+        return result.map_err(|_| Error::MapFailed);
+    }
+}
+
+/// Returns to the executor once, waking the task again right away.
+async fn yield_now() {
+    let mut yielded = false;
+
+    futures::future::poll_fn(|cx| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Requests the default adapter and a device with downlevel limits, see
+/// [`GpuContext`] to keep the instance and adapter too. The device is shared
+/// like the one of a context, so a [`PollThread`](poll::PollThread) can poll
+/// it.
+pub async fn get_device_and_queue() -> Result<(Arc<Device>, Queue)> {
+    let context = GpuContext::new().await?;
+
+    Ok((context.device, context.queue))
+}
+
+/// Distance between the starts of consecutive rows of read back pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowStride {
+    /// Rows follow each other without padding.
+    Packed,
+    /// Rows are padded to a multiple of this many bytes, a power of two,
+    /// such as 256 to upload them elsewhere as they are.
+    Aligned(u32),
+    /// Rows start this many bytes apart, at least the bytes of a row.
+    Bytes(u32),
+}
+
+impl RowStride {
+    /// Bytes per row for rows of `row_bytes` bytes of pixels.
+    pub fn bytes(self, row_bytes: u32) -> Result<u32> {
+        match self {
+            Self::Packed => Ok(row_bytes),
+            Self::Aligned(align) if align.is_power_of_two() => row_bytes
+                .checked_next_multiple_of(align)
+                .ok_or(Error::RowAlignment(align)),
+            Self::Aligned(align) => Err(Error::RowAlignment(align)),
+            Self::Bytes(stride) if stride >= row_bytes => Ok(stride),
+            Self::Bytes(stride) => Err(Error::RowStride { stride, row_bytes }),
+        }
+    }
+}
+
+/// Bytes of output from which rows are copied on all cores, with the
+/// `parallel` feature. Smaller images aren't worth handing out.
+const PARALLEL_RESTRIDE_BYTES: usize = 4 << 20;
+
+/// Drops the row padding of a buffer holding `height` rows of `width` bytes
+/// each, laid out with the same stride.
+pub fn trim_image_buffer(width: u32, height: u32, buffer: &[u8]) -> Vec<u8> {
+    restride_rows(width, height, width, buffer)
+}
+
+/// Copies the `height` rows of `width` bytes of a buffer laid out with any
+/// stride into rows `stride` bytes apart, padded with zeros. Fails unless
+/// `stride` and the rows of `buffer` hold `width` bytes.
+pub fn restride_image_buffer(
+    width: u32,
+    height: u32,
+    stride: u32,
+    buffer: &[u8],
+) -> Result<Vec<u8>> {
+    if stride < width {
+        return Err(Error::RowStride {
+            stride,
+            row_bytes: width,
+        });
+    }
+    if height > 0 && buffer.len() / (height as usize) < width as usize {
+        return Err(Error::RowStride {
+            stride: (buffer.len() / height as usize) as u32,
+            row_bytes: width,
+        });
+    }
+
+    Ok(restride_rows(width, height, stride, buffer))
+}
+
+/// [`restride_image_buffer`] of a `stride` and `buffer` known to hold the
+/// rows.
+fn restride_rows(width: u32, height: u32, stride: u32, buffer: &[u8]) -> Vec<u8> {
+    if height == 0 {
+        return Vec::new();
+    }
+
+    let align_width = buffer.len() / height as usize;
+    let (row_bytes, stride) = (width as usize, stride as usize);
+
+    if align_width == stride {
+        return buffer.to_vec();
+    }
+
+    // Packed rows are appended as they are, without zeroing the output first.
+    if stride == row_bytes && !parallel_restride(stride * height as usize) {
+        let mut output = Vec::with_capacity(stride * height as usize);
+        for source in buffer.chunks(align_width).take(height as usize) {
+            output.extend_from_slice(&source[..row_bytes]);
+        }
+
+        return output;
+    }
+
+    let mut output = vec![0; stride * height as usize];
+    restride_into(width, height, stride as u32, buffer, &mut output);
+
+    output
+}
+
+/// Like [`restride_image_buffer`], writing into the start of `output`,
+/// which holds the `height` rows `stride` bytes apart. The padding of the
+/// rows in `output` is left as it is.
+fn restride_into(width: u32, height: u32, stride: u32, buffer: &[u8], output: &mut [u8]) {
+    if height == 0 {
+        return;
+    }
+
+    let align_width = buffer.len() / height as usize;
+    let (width, stride, height) = (width as usize, stride as usize, height as usize);
+    let output = &mut output[..stride * height];
+
+    if align_width == stride {
+        return output.copy_from_slice(&buffer[..output.len()]);
+    }
+
+    #[cfg(feature = "parallel")]
+    if parallel_restride(output.len()) {
+        use rayon::prelude::*;
+
+        return output
+            .par_chunks_mut(stride)
+            .zip(buffer.par_chunks(align_width))
+            .for_each(|(row, source)| row[..width].copy_from_slice(&source[..width]));
+    }
+
+    for (row, source) in output.chunks_mut(stride).zip(buffer.chunks(align_width)) {
+        row[..width].copy_from_slice(&source[..width]);
+    }
+}
+
+/// Whether to copy the rows of `bytes` of output on all cores.
+fn parallel_restride(bytes: usize) -> bool {
+    cfg!(feature = "parallel") && bytes >= PARALLEL_RESTRIDE_BYTES
+}
+
+/// Fails unless `output` holds `height` rows `stride` bytes apart.
+fn check_output_len(height: u32, stride: u32, output: &[u8]) -> Result<()> {
+    let expected = stride as usize * height as usize;
+
+    if output.len() < expected {
+        return Err(Error::OutputTooSmall {
+            expected,
+            actual: output.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Maps a readback buffer of `width`x`height` RGBA8 texels with padded rows
+/// and returns the tightly packed pixels. A lost device fails the map, see
+/// [`Error::is_device_lost`].
+pub async fn view_into_buffer(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    raw_buffer: &wgpu::Buffer,
+) -> Result<Vec<u8>> {
+    view_into_buffer_with_stride(device, width, height, raw_buffer, RowStride::Packed).await
+}
+
+/// Like [`view_into_buffer`], with the rows of the returned pixels laid out
+/// `stride` apart.
+pub async fn view_into_buffer_with_stride(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    raw_buffer: &wgpu::Buffer,
+    stride: RowStride,
+) -> Result<Vec<u8>> {
+    read_rows(
+        device,
+        &Spin,
+        DATA_PER_PIXEL * width,
+        height,
+        raw_buffer,
+        stride,
+    )
+    .await
+}
+
+/// Like [`view_into_buffer`], writing the pixels into the start of `output`
+/// instead of a fresh `Vec`, such as the frame buffer of an encoder. Fails
+/// with [`Error::OutputTooSmall`] unless `output` holds all of them.
+pub async fn view_into_slice(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    raw_buffer: &wgpu::Buffer,
+    output: &mut [u8],
+) -> Result<()> {
+    view_into_slice_with_stride(device, width, height, raw_buffer, output, RowStride::Packed).await
+}
+
+/// Like [`view_into_slice`], with the rows of the pixels laid out `stride`
+/// apart in `output`. The padding between them is left as it is.
+pub async fn view_into_slice_with_stride(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    raw_buffer: &wgpu::Buffer,
+    output: &mut [u8],
+    stride: RowStride,
+) -> Result<()> {
+    read_rows_into(
+        device,
+        &Spin,
+        DATA_PER_PIXEL * width,
+        height,
+        raw_buffer,
+        output,
+        stride,
+    )
+    .await
+}
+
+/// Maps a readback buffer of `width`x`height` RGBA8 texels and hands
+/// `on_rows` the tightly packed pixels `rows_per_chunk` rows at a time,
+/// along with the index of the first of them, so the whole image never has
+/// to be on the host at once. The last chunk may be shorter. Rows already
+/// packed in the buffer are handed out straight from the mapped range, and
+/// padded ones are packed in a buffer of one chunk.
+pub async fn view_rows(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    raw_buffer: &wgpu::Buffer,
+    rows_per_chunk: u32,
+    on_rows: impl FnMut(u32, &[u8]),
+) -> Result<()> {
+    map_rows(device, &Spin, raw_buffer, |view| {
+        visit_rows(
+            DATA_PER_PIXEL * width,
+            height,
+            view,
+            rows_per_chunk,
+            on_rows,
+        )
+    })
+    .await
+}
+
+/// Hands `on_rows` the `height` rows of `row_bytes` bytes of `buffer`
+/// packed, `rows_per_chunk` at a time.
+fn visit_rows(
+    row_bytes: u32,
+    height: u32,
+    buffer: &[u8],
+    rows_per_chunk: u32,
+    mut on_rows: impl FnMut(u32, &[u8]),
+) {
+    if height == 0 {
+        return;
+    }
+
+    let align_width = buffer.len() / height as usize;
+    let row_bytes = row_bytes as usize;
+    let rows_per_chunk = rows_per_chunk.clamp(1, height);
+    let mut chunk = Vec::new();
+
+    for first in (0..height).step_by(rows_per_chunk as usize) {
+        let rows = rows_per_chunk.min(height - first) as usize;
+        let source = &buffer[first as usize * align_width..][..rows * align_width];
+
+        if align_width == row_bytes {
+            on_rows(first, source);
+        } else {
+            chunk.clear();
+            for row in source.chunks(align_width) {
+                chunk.extend_from_slice(&row[..row_bytes]);
+            }
+            on_rows(first, &chunk);
+        }
+    }
+}
+
+/// Maps a readback buffer of `height` padded rows of `row_bytes` bytes each.
+async fn read_rows(
+    device: &wgpu::Device,
+    poller: &dyn Poller,
+    row_bytes: u32,
+    height: u32,
+    raw_buffer: &wgpu::Buffer,
+    stride: RowStride,
+) -> Result<Vec<u8>> {
+    let stride = stride.bytes(row_bytes)?;
+
+    map_rows(device, poller, raw_buffer, |view| {
+        restride_rows(row_bytes, height, stride, view)
+    })
+    .await
+}
+
+/// Like [`read_rows`], writing the rows into `output`.
+async fn read_rows_into(
+    device: &wgpu::Device,
+    poller: &dyn Poller,
+    row_bytes: u32,
+    height: u32,
+    raw_buffer: &wgpu::Buffer,
+    output: &mut [u8],
+    stride: RowStride,
+) -> Result<()> {
+    let stride = stride.bytes(row_bytes)?;
+    check_output_len(height, stride, output)?;
+
+    map_rows(device, poller, raw_buffer, |view| {
+        restride_into(row_bytes, height, stride, view, output)
+    })
+    .await
+}
+
+/// Maps all of `raw_buffer` for `read`, unmapping it again after.
+async fn map_rows<T>(
+    device: &wgpu::Device,
+    poller: &dyn Poller,
+    raw_buffer: &wgpu::Buffer,
+    read: impl FnOnce(&[u8]) -> T,
+) -> Result<T> {
+    let slice = raw_buffer.slice(..);
+
+    if map_for_reading(device, poller, slice).await.is_ok() {
+        let buffer_view = slice.get_mapped_range();
+
+        let result = read(&buffer_view);
+
+        drop(buffer_view);
+        raw_buffer.unmap();
+
+        Ok(result)
+    } else {
+        Err(Error::MapFailed)
+    }
+}
+
+/// Copies one mip level of an RGBA8 texture into a fresh readback buffer and
+/// returns its tightly packed pixels. `texture_size` is the size of that level.
+pub async fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    texture_size: wgpu::Extent3d,
+) -> Result<Vec<u8>> {
+    read_texture_with_stride(
+        device,
+        queue,
+        texture,
+        mip_level,
+        texture_size,
+        RowStride::Packed,
+    )
+    .await
+}
+
+/// Like [`read_texture`], with the rows of the returned pixels laid out
+/// `stride` apart.
+pub async fn read_texture_with_stride(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    texture_size: wgpu::Extent3d,
+    stride: RowStride,
+) -> Result<Vec<u8>> {
+    read_texels(
+        device,
+        queue,
+        texture,
+        mip_level,
+        wgpu::Origin3d::ZERO,
+        texture_size,
+        DATA_PER_PIXEL,
+        stride,
+        &ResourcePool::with_budget(0),
+        &Spin,
+    )
+    .await
+}
+
+/// Reads back only the `width`x`height` texels at `x`, `y` of the base level
+/// of an RGBA8 texture, which transfers just the crop instead of the whole
+/// texture.
+pub async fn read_region(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>> {
+    check_region(texture.size(), x, y, width, height)?;
+
+    read_texels(
+        device,
+        queue,
+        texture,
+        0,
+        wgpu::Origin3d { x, y, z: 0 },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        DATA_PER_PIXEL,
+        RowStride::Packed,
+        &ResourcePool::with_budget(0),
+        &Spin,
+    )
+    .await
+}
+
+/// Fails unless the `width`x`height` rectangle at `x`, `y` is non-empty and
+/// lies within `size`.
+pub fn check_region(size: wgpu::Extent3d, x: u32, y: u32, width: u32, height: u32) -> Result<()> {
+    let fits = |start: u32, length: u32, limit: u32| {
+        length > 0 && start.checked_add(length).is_some_and(|end| end <= limit)
+    };
+
+    if !fits(x, width, size.width) || !fits(y, height, size.height) {
+        return Err(Error::RegionOutOfBounds {
+            x,
+            y,
+            width,
+            height,
+            texture_width: size.width,
+            texture_height: size.height,
+        });
+    }
+
+    Ok(())
+}
+
+/// Fails unless `width`x`height` textures with texels of `texel_bytes` bytes,
+/// and the buffers they are read back through, have texels and fit the
+/// limits of `device`.
+pub fn check_image_size(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    texel_bytes: u32,
+) -> Result<()> {
+    let limits = device.limits();
+    let too_large = |limit| Error::ImageTooLarge {
+        width,
+        height,
+        limit,
+    };
+
+    if width == 0 || height == 0 {
+        return Err(Error::EmptyImage { width, height });
+    }
+    if width > limits.max_texture_dimension_2d || height > limits.max_texture_dimension_2d {
+        return Err(too_large(SizeLimit::TextureDimension(
+            limits.max_texture_dimension_2d,
+        )));
+    }
+
+    let row_bytes = padded_row_bytes(width, texel_bytes);
+    if row_bytes.saturating_mul(height as u64) > limits.max_buffer_size {
+        return Err(too_large(SizeLimit::BufferSize(limits.max_buffer_size)));
+    }
+
+    Ok(())
+}
+
+/// Bytes of a row of `width` texels of `texel_bytes` bytes each, padded for
+/// copies between textures and buffers.
+fn padded_row_bytes(width: u32, texel_bytes: u32) -> u64 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64;
+
+    (width as u64 * texel_bytes as u64).div_ceil(align) * align
+}
+
+/// Copies texels of `texel_bytes` bytes each into a readback buffer of
+/// `pool` and returns them with rows `stride` apart, driving the device with
+/// `poller`. A pool with no budget keeps nothing, for one-off readbacks.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn read_texels(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    origin: wgpu::Origin3d,
+    texture_size: wgpu::Extent3d,
+    texel_bytes: u32,
+    stride: RowStride,
+    pool: &ResourcePool,
+    poller: &dyn Poller,
+) -> Result<Vec<u8>> {
+    let output_buffer = copy_texels(
+        device,
+        queue,
+        pool,
+        texture,
+        mip_level,
+        origin,
+        texture_size,
+        texel_bytes,
+    )?;
+
+    read_rows(
+        device,
+        poller,
+        texture_size.width * texel_bytes,
+        texture_size.height,
+        &output_buffer,
+        stride,
+    )
+    .await
+}
+
+/// Like [`read_texels`], writing the texels into `output`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn read_texels_into(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    origin: wgpu::Origin3d,
+    texture_size: wgpu::Extent3d,
+    texel_bytes: u32,
+    output: &mut [u8],
+    stride: RowStride,
+    pool: &ResourcePool,
+    poller: &dyn Poller,
+) -> Result<()> {
+    let row_bytes = texture_size.width * texel_bytes;
+    check_output_len(texture_size.height, stride.bytes(row_bytes)?, output)?;
+
+    let output_buffer = copy_texels(
+        device,
+        queue,
+        pool,
+        texture,
+        mip_level,
+        origin,
+        texture_size,
+        texel_bytes,
+    )?;
+
+    read_rows_into(
+        device,
+        poller,
+        row_bytes,
+        texture_size.height,
+        &output_buffer,
+        output,
+        stride,
+    )
+    .await
+}
+
+/// Like [`read_texels`], handing the packed texels to `on_rows`
+/// `rows_per_chunk` rows at a time, see [`view_rows`].
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn read_texel_rows(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    texture_size: wgpu::Extent3d,
+    texel_bytes: u32,
+    rows_per_chunk: u32,
+    pool: &ResourcePool,
+    poller: &dyn Poller,
+    on_rows: impl FnMut(u32, &[u8]),
+) -> Result<()> {
+    let output_buffer = copy_texels(
+        device,
+        queue,
+        pool,
+        texture,
+        0,
+        wgpu::Origin3d::ZERO,
+        texture_size,
+        texel_bytes,
+    )?;
+
+    map_rows(device, poller, &output_buffer, |view| {
+        visit_rows(
+            texture_size.width * texel_bytes,
+            texture_size.height,
+            view,
+            rows_per_chunk,
+            on_rows,
+        )
+    })
+    .await
+}
+
+/// Submits a copy of texels of `texel_bytes` bytes each into a readback
+/// buffer of `pool` with padded rows, unless [`check_image_size`] fails.
+#[allow(clippy::too_many_arguments)]
+fn copy_texels(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pool: &ResourcePool,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    origin: wgpu::Origin3d,
+    texture_size: wgpu::Extent3d,
+    texel_bytes: u32,
+) -> Result<PooledBuffer> {
+    check_image_size(device, texture_size.width, texture_size.height, texel_bytes)?;
+
+    // Fits a `u32` as the buffer fits the device.
+    let align_width = padded_row_bytes(texture_size.width, texel_bytes);
+
+    let output_buffer = pool.buffer(
+        device,
+        &wgpu::BufferDescriptor {
+            label: Some("Buffer"),
+            size: align_width * texture_size.height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        },
+    );
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level,
+            origin,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &output_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(align_width as u32),
+                rows_per_image: Some(texture_size.height),
+            },
+        },
+        texture_size,
+    );
+
+    queue.submit(Some(encoder.finish()));
+
+    Ok(output_buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A context on the default adapter for the tests that run on the GPU,
+    /// which skip themselves on machines without one.
+    pub(crate) fn context() -> Option<GpuContext> {
+        let context = futures::executor::block_on(GpuContext::new());
+        if let Err(err) = &context {
+            eprintln!("Skipping a GPU test: {}", err);
+        }
+
+        context.ok()
+    }
+
+    /// `height` rows of `width` bytes counting up from 1, padded with 0xff to
+    /// `stride`.
+    fn padded_rows(width: usize, height: usize, stride: usize) -> Vec<u8> {
+        let mut buffer = vec![0xff; stride * height];
+        for (index, row) in buffer.chunks_mut(stride).enumerate() {
+            for (x, byte) in row[..width].iter_mut().enumerate() {
+                *byte = (index * width + x + 1) as u8;
+            }
+        }
+        buffer
+    }
+
+    #[test]
+    fn expands_the_workgroup_size() {
+        assert_eq!(
+            expand_workgroup_size(COPY_SHADER, [16, 4])
+                .matches("@workgroup_size(16, 4)")
+                .count(),
+            1
+        );
+        assert_eq!(workgroup_size(COPY_SHADER, "basic"), [1, 1, 1]);
+        assert_eq!(
+            workgroup_size(&expand_workgroup_size(COPY_SHADER, [16, 4]), "basic"),
+            [16, 4, 1]
+        );
+
+        let fixed = "@compute @workgroup_size(2) fn main() {}";
+        assert!(matches!(
+            expand_workgroup_size(fixed, [16, 4]),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn row_stride_bytes() {
+        assert_eq!(RowStride::Packed.bytes(12).unwrap(), 12);
+        assert_eq!(RowStride::Aligned(256).bytes(12).unwrap(), 256);
+        assert_eq!(RowStride::Aligned(4).bytes(12).unwrap(), 12);
+        assert_eq!(RowStride::Aligned(256).bytes(0).unwrap(), 0);
+        assert_eq!(RowStride::Bytes(16).bytes(12).unwrap(), 16);
+
+        assert!(matches!(
+            RowStride::Aligned(24).bytes(12),
+            Err(Error::RowAlignment(24))
+        ));
+        assert!(matches!(
+            RowStride::Aligned(1 << 31).bytes(u32::MAX),
+            Err(Error::RowAlignment(_))
+        ));
+        assert!(matches!(
+            RowStride::Bytes(8).bytes(12),
+            Err(Error::RowStride {
+                stride: 8,
+                row_bytes: 12
+            })
+        ));
+    }
+
+    #[test]
+    fn trims_padding() {
+        let buffer = padded_rows(3, 2, 8);
+
+        assert_eq!(trim_image_buffer(3, 2, &buffer), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn restrides_to_wider_rows_padded_with_zeros() {
+        let buffer = padded_rows(3, 2, 4);
+
+        assert_eq!(
+            restride_image_buffer(3, 2, 5, &buffer).unwrap(),
+            [1, 2, 3, 0, 0, 4, 5, 6, 0, 0]
+        );
+    }
+
+    #[test]
+    fn refuses_strides_shorter_than_the_rows() {
+        let buffer = padded_rows(3, 2, 4);
+
+        assert!(matches!(
+            restride_image_buffer(3, 2, 2, &buffer),
+            Err(Error::RowStride {
+                stride: 2,
+                row_bytes: 3
+            })
+        ));
+        assert!(matches!(
+            restride_image_buffer(5, 2, 8, &buffer),
+            Err(Error::RowStride {
+                stride: 4,
+                row_bytes: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn keeps_rows_of_the_same_stride() {
+        let buffer = padded_rows(3, 2, 4);
+
+        assert_eq!(restride_image_buffer(3, 2, 4, &buffer).unwrap(), buffer);
+    }
+
+    #[test]
+    fn restrides_nothing_of_zero_rows() {
+        assert!(restride_image_buffer(4, 0, 4, &[]).unwrap().is_empty());
+        assert!(trim_image_buffer(0, 0, &[]).is_empty());
+
+        let mut output = [7; 4];
+        restride_into(4, 0, 4, &[], &mut output);
+        assert_eq!(output, [7; 4]);
+    }
+
+    #[test]
+    fn visits_rows_in_chunks() {
+        let buffer = padded_rows(2, 5, 4);
+        let mut chunks = Vec::new();
+
+        visit_rows(2, 5, &buffer, 2, |first, rows| {
+            chunks.push((first, rows.to_vec()))
+        });
+
+        assert_eq!(
+            chunks,
+            [
+                (0, vec![1, 2, 3, 4]),
+                (2, vec![5, 6, 7, 8]),
+                (4, vec![9, 10]),
+            ]
+        );
+    }
+
+    #[test]
+    fn visits_packed_rows_at_least_one_at_a_time() {
+        let buffer = padded_rows(2, 3, 2);
+        let mut firsts = Vec::new();
+
+        visit_rows(2, 3, &buffer, 0, |first, rows| {
+            assert_eq!(rows.len(), 2);
+            firsts.push(first);
+        });
+
+        assert_eq!(firsts, [0, 1, 2]);
+    }
+
+    #[test]
+    fn visits_no_rows_of_an_empty_image() {
+        visit_rows(4, 0, &[], 8, |_, _| panic!("An empty image has no rows"));
+    }
+
+    #[test]
+    fn pads_rows_without_overflowing() {
+        assert_eq!(padded_row_bytes(1, 4), 256);
+        assert_eq!(padded_row_bytes(64, 4), 256);
+        assert_eq!(padded_row_bytes(65, 4), 512);
+        assert_eq!(
+            padded_row_bytes(u32::MAX, 16),
+            (u32::MAX as u64 * 16).div_ceil(256) * 256
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Job locks written with `--emit-lock` and replayed with `--from-lock`: the
+//! kernel of a run as it was resolved, down to the WGSL of the operation and
+//! of every file it includes and the value of every parameter, defaults
+//! included. Replaying a lock cooks the same output after presets, defaults
+//! or the bundled shaders change.
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{
+    animation::Animation,
+    cli::Args,
+    ops::{self, OpSpec},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Lock {
+    /// Version of the tool that wrote the lock.
+    pub version: String,
+    pub op: String,
+    pub entry_point: String,
+    pub inputs: u32,
+    /// WGSL of the operation, its includes not yet expanded.
+    pub shader: String,
+    /// Contents of every file the shader includes, by its path from the
+    /// directory of the shader, or `lib:<name>` for the bundled ones.
+    pub includes: BTreeMap<String, String>,
+    /// Every parameter of a built-in operation, or the ones given to the
+    /// parameter struct of a `--shader`.
+    pub params: BTreeMap<String, f32>,
+    /// Parameters interpolated across the frames, as `key=start..end`.
+    #[serde(default)]
+    pub animate: Vec<String>,
+    #[serde(default)]
+    pub constants: BTreeMap<String, f32>,
+    pub iterations: Option<u32>,
+    pub seed: u32,
+    pub workgroup_size: [u32; 2],
+    pub strict_math: bool,
+}
+
+impl Lock {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lock {}", path.display()))?;
+        let lock: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse lock {}", path.display()))?;
+
+        if lock.version != env!("CARGO_PKG_VERSION") {
+            log::warn!(
+                "{} was written by version {}, this is {}",
+                path.display(),
+                lock.version,
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+
+        Ok(lock)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+
+        fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Whether the lock holds a `--shader` kernel rather than a built-in
+    /// operation.
+    pub fn is_custom(&self) -> bool {
+        self.op == ops::CUSTOM
+    }
+
+    /// The operation of the lock, compiled from the locked WGSL.
+    pub fn op(&self) -> Result<OpSpec<'_>> {
+        if self.is_custom() {
+            return Ok(ops::custom(&self.shader, &self.entry_point, self.inputs));
+        }
+
+        let spec = ops::find(&self.op)?;
+        if spec.entry_point != self.entry_point || spec.inputs != self.inputs {
+            bail!(
+                "Operation '{}' has changed its entry point or inputs since the lock",
+                self.op
+            );
+        }
+
+        Ok(ops::with_shader(spec, &self.shader))
+    }
+
+    /// Replaces the settings of `args` with the ones of the lock.
+    pub fn apply(&self, args: &mut Args) -> Result<()> {
+        args.entry_point = self.entry_point.clone();
+        args.params = self
+            .params
+            .iter()
+            .map(|(key, &value)| (key.clone(), value))
+            .collect();
+        args.clusters = None;
+        args.animate = self
+            .animate
+            .iter()
+            .map(|animation| animation.parse())
+            .collect::<Result<Vec<Animation>>>()?;
+        args.constants = self
+            .constants
+            .iter()
+            .map(|(key, &value)| (key.clone(), value))
+            .collect();
+        args.iterations = self.iterations;
+        args.seed = self.seed;
+        args.workgroup_size = self.workgroup_size.into();
+        args.strict_math = self.strict_math;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+
+    fn blur_lock() -> Lock {
+        Lock {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            op: "blur".to_string(),
+            entry_point: "blur".to_string(),
+            inputs: 1,
+            shader: "// blur as it was\n".to_string(),
+            includes: BTreeMap::from([("lib:common.wgsl".to_string(), "// helpers\n".to_string())]),
+            params: BTreeMap::from([("sigma".to_string(), 2.5)]),
+            animate: vec!["sigma=1..4".to_string()],
+            constants: BTreeMap::from([("RADIUS".to_string(), 3.0)]),
+            iterations: Some(2),
+            seed: 9,
+            workgroup_size: [16, 4],
+            strict_math: true,
+        }
+    }
+
+    #[test]
+    fn loads_what_it_saves() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("wtc-lock-{}.json", std::process::id()));
+        blur_lock().save(&path)?;
+        let lock = Lock::load(&path)?;
+        fs::remove_file(&path)?;
+
+        assert_eq!(
+            serde_json::to_value(&lock)?,
+            serde_json::to_value(blur_lock())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn compiles_the_locked_shader() -> Result<()> {
+        let lock = blur_lock();
+        let op = lock.op()?;
+        assert_eq!((op.name, op.shader), ("blur", "// blur as it was\n"));
+
+        let custom = Lock {
+            op: ops::CUSTOM.to_string(),
+            entry_point: "main".to_string(),
+            inputs: 2,
+            ..blur_lock()
+        };
+        let op = custom.op()?;
+        assert_eq!((op.entry_point, op.inputs), ("main", 2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_operations_that_changed_since() {
+        for lock in [
+            Lock {
+                entry_point: "box_blur".to_string(),
+                ..blur_lock()
+            },
+            Lock {
+                inputs: 2,
+                ..blur_lock()
+            },
+        ] {
+            let Err(err) = lock.op() else {
+                panic!("{} took the changed operation", lock.entry_point);
+            };
+            assert!(err
+                .to_string()
+                .contains("has changed its entry point or inputs"));
+        }
+        let unknown = Lock {
+            op: "sharpen-more".to_string(),
+            ..blur_lock()
+        };
+        assert!(unknown.op().is_err());
+    }
+
+    #[test]
+    fn replaces_the_settings_of_a_run() -> Result<()> {
+        let args: Vec<String> = ["in.png", "-o", "out.png", "--seed", "1", "--clusters", "4"]
+            .map(String::from)
+            .into();
+        let mut args = Cli::parse_run(&args, |_| unreachable!())?;
+        blur_lock().apply(&mut args)?;
+
+        assert_eq!(args.params, [("sigma".to_string(), 2.5)]);
+        assert_eq!(args.constants, [("RADIUS".to_string(), 3.0)]);
+        assert_eq!(args.animate.len(), 1);
+        assert_eq!(args.clusters, None);
+        assert_eq!((args.iterations, args.seed), (Some(2), 9));
+        assert_eq!(args.workgroup_size, (16, 4));
+        assert!(args.strict_math);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_the_lock_it_cannot_parse() {
+        let path = std::env::temp_dir().join(format!("wtc-lock-{}-bad.json", std::process::id()));
+        fs::write(&path, "{\"version\": 1}").unwrap();
+        let err = Lock::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Failed to parse lock {}", path.display())
+        );
+    }
+}
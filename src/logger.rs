@@ -0,0 +1,84 @@
+//! Prints log records to stderr, filtered by `RUST_LOG` the way env_logger
+//! reads it: a default level such as `info`, and `target=level` entries for
+//! the modules whose target starts with `target`, e.g. `warn,wgpu_core=info`.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::env;
+
+/// Level of the records printed when `RUST_LOG` doesn't say.
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Error;
+
+struct Logger {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Logger {
+    fn from_env() -> Self {
+        let mut logger = Self {
+            default: DEFAULT_LEVEL,
+            targets: Vec::new(),
+        };
+
+        for directive in env::var("RUST_LOG").unwrap_or_default().split(',') {
+            let directive = directive.trim();
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Ok(level) = level.parse() {
+                        logger.targets.push((target.to_string(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        logger.default = level;
+                    }
+                }
+            }
+        }
+
+        // The longest matching target wins.
+        logger
+            .targets
+            .sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+
+        logger
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, configured from `RUST_LOG`.
+pub fn init() {
+    let logger = Logger::from_env();
+    let max_level = logger.max_level();
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
@@ -1,275 +1,3439 @@
+mod animation;
+mod ascii;
+mod audit;
+mod blue_noise;
+mod cli;
+mod compare;
+mod completions;
+mod dedup;
+mod deepzoom;
+mod deps;
+mod diff;
+mod erosion;
+mod exit;
+mod fisheye;
+mod gradient;
+mod hdr;
+mod histogram;
+mod ibl;
+mod integral;
+mod invocations;
+mod job_queue;
+mod kmeans;
+mod ktx2;
+mod lock;
+mod logger;
+mod memory;
+mod metrics;
+mod mipmap;
+mod montage;
+mod ops;
+mod output;
+mod pack;
+mod panorama;
+mod pingpong;
+mod poisson;
+mod preset;
+mod preview;
+mod reference;
+mod remote;
+mod report;
+mod resources;
+mod seam;
+mod sh;
+mod shader;
+mod sidecar;
+mod slic;
+mod sprite;
+mod stack;
+mod thumbnail;
+mod tiled;
+mod timing;
+mod trim;
+mod uniforms;
+
+use animation::SequenceWriter;
 use anyhow::*;
-use image::io::Reader;
-use std::{borrow::Cow, fs::File, io::BufReader};
-use wgpu::{Device, Queue};
+use clap::CommandFactory;
+use cli::Command;
+use dedup::FrameGate;
+use exit::PartialFailure;
+use gradient::Gradient;
+use image::{io::Reader, RgbaImage};
+use mipmap::{FilterSpace, MipFilter, MipGenerator, MipmapSettings};
+use ops::{Lookup, OpSpec, OPS};
+use output::IfExists;
+use preset::Preset;
+use report::{InvocationEntry, Report, SrgbCheckEntry, TimingEntry, TrimEntry, VerifyEntry};
+use resources::{BundledTextures, ShaderParams, BUNDLED_TEXTURES_GROUP};
+use sprite::{Grid, SheetLayout};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use uniforms::Globals;
+use wgpu::util::DeviceExt;
+use wgpu_texture_copy::{
+    adapters, align_up, check_image_size, check_region, create_input_texture,
+    input_texture_descriptor, input_texture_layout_entry,
+    op::EXTRA_INPUT_BINDING,
+    output_texture_descriptor, output_texture_layout_entry,
+    params::{params_layout_entry, ParamLayout, PARAMS_GROUP},
+    read_buffer, read_region, read_texture, read_write_texture_layout_entry, upload_file,
+    view_into_buffer, view_into_slice, workgroup_count, workgroup_size, write_input_texture,
+    AdapterOptions, AdapterSelector, GpuContext, PooledBuffer, PooledTexture, RowPacker,
+    DATA_PER_PIXEL, U8_SIZE,
+};
+
+/// Set by `--quiet`, which leaves only errors on the terminal.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// `println!`, unless `--quiet` is given.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// GPU resources for running one operation over one image size. Everything is
+/// kept alive so further frames or sprite cells only need to rewrite the
+/// globals uniform and the input textures.
+struct Computation {
+    /// Empty when `in_place`.
+    input_textures: Vec<PooledTexture>,
+    input_size: wgpu::Extent3d,
+    pipeline: Arc<wgpu::ComputePipeline>,
+    bind_group: wgpu::BindGroup,
+    /// Further dispatches when iterating: from the output into
+    /// `iteration_texture` and back again.
+    iteration: Option<Iteration>,
+    bundled_textures: Option<BundledTextures>,
+    shader_params: Option<ShaderParams>,
+    globals_buffer: wgpu::Buffer,
+    output_texture: PooledTexture,
+    /// Readback buffers, more than one when the output is read back in bands
+    /// so that the next bands are copied while one is drained.
+    output_buffers: Vec<PooledBuffer>,
+    /// Further outputs of the operation, read back on their own.
+    extra_output_textures: Vec<PooledTexture>,
+    texture_size: wgpu::Extent3d,
+    /// Workgroups along x and y covering the output, the same for every
+    /// entry point of the operation.
+    workgroups: (u32, u32),
+    /// Part of the output copied into `output_buffers`, all of it by default.
+    read_origin: wgpu::Origin3d,
+    read_size: wgpu::Extent3d,
+    align_width: u32,
+    /// Rows every one of `output_buffers` holds, fewer than `read_size` when
+    /// a buffer for all of them would exceed the memory budget, the buffer
+    /// size limit of the device or `READBACK_BAND_BYTES`.
+    band_rows: u32,
+    /// Packs the padded rows of the read region on the GPU, so that
+    /// `output_buffers` holds them without padding.
+    row_packer: Option<RowPacker>,
+    /// Clusters found for the lookup table of segmenting operations.
+    clusters: Vec<kmeans::Centroid>,
+    /// Times the stages of every submission, with `--gpu-timings`.
+    timer: Option<timing::GpuTimer>,
+    /// Counts the invocations of every compute pass, with `--pipeline-stats`.
+    invocations: Option<invocations::InvocationCounter>,
+    /// The input is uploaded into `output_texture`, which the operation reads
+    /// and writes, with `--in-place`.
+    in_place: bool,
+}
+
+/// Ping-pong state of an operation dispatched several times per frame.
+/// Lookup and summed-area tables stay those of the original input.
+struct Iteration {
+    /// Passes after the first, with `step_pipeline` or else the pipeline of
+    /// the first pass.
+    steps: u32,
+    step_pipeline: Option<Arc<wgpu::ComputePipeline>>,
+    /// One more pass at the end, for simulations.
+    resolve_pipeline: Option<Arc<wgpu::ComputePipeline>>,
+    texture: PooledTexture,
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Iteration {
+    /// Dispatches in a frame, the first pass included.
+    fn passes(&self) -> u32 {
+        1 + self.steps + self.resolve_pipeline.is_some() as u32
+    }
+}
+
+impl Computation {
+    fn upload(&self, queue: &wgpu::Queue, inputs: &[&[u8]]) {
+        if self.in_place {
+            write_input_texture(queue, &self.output_texture, self.input_size, inputs[0]);
+        }
+        for (texture, buffer) in self.input_textures.iter().zip(inputs) {
+            write_input_texture(queue, texture, self.input_size, buffer);
+        }
+    }
+
+    fn submit(&self, device: &wgpu::Device, queue: &wgpu::Queue, globals: &Globals) {
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(globals));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder"),
+        });
+
+        if let Some(timer) = &self.timer {
+            timer.mark(&mut encoder, 0);
+        }
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+            });
+            if let Some(invocations) = &self.invocations {
+                invocations.begin(&mut compute_pass);
+            }
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            if let Some(bundled_textures) = &self.bundled_textures {
+                compute_pass.set_bind_group(
+                    BUNDLED_TEXTURES_GROUP,
+                    &bundled_textures.bind_group,
+                    &[],
+                );
+            }
+            if let Some(shader_params) = &self.shader_params {
+                if self.bundled_textures.is_none() {
+                    compute_pass.set_bind_group(
+                        BUNDLED_TEXTURES_GROUP,
+                        &shader_params.empty_bind_group,
+                        &[],
+                    );
+                }
+                compute_pass.set_bind_group(PARAMS_GROUP, &shader_params.bind_group, &[]);
+            }
+            compute_pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, 1);
+
+            if let Some(iteration) = &self.iteration {
+                compute_pass
+                    .set_pipeline(iteration.step_pipeline.as_ref().unwrap_or(&self.pipeline));
+
+                for pass in 1..iteration.passes() {
+                    if pass == iteration.steps + 1 {
+                        if let Some(resolve_pipeline) = &iteration.resolve_pipeline {
+                            compute_pass.set_pipeline(resolve_pipeline);
+                        }
+                    }
+
+                    let bind_group = &iteration.bind_groups[(pass as usize - 1) % 2];
+                    compute_pass.set_bind_group(0, bind_group, &[]);
+                    compute_pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, 1);
+                }
+            }
+
+            if let Some(invocations) = &self.invocations {
+                invocations.end(&mut compute_pass);
+            }
+        }
+
+        if let Some(invocations) = &self.invocations {
+            invocations.resolve(&mut encoder);
+        }
+
+        // An even number of passes leaves the result in the other texture.
+        if let Some(iteration) = self.iteration.as_ref().filter(|it| it.passes() % 2 == 0) {
+            encoder.copy_texture_to_texture(
+                iteration.texture.as_image_copy(),
+                self.output_texture.as_image_copy(),
+                self.texture_size,
+            );
+        }
+
+        // Bands are copied one at a time when reading back.
+        if self.band_rows == self.read_size.height {
+            if let Some(timer) = &self.timer {
+                timer.mark(&mut encoder, 1);
+            }
+
+            self.copy_rows(&mut encoder, 0, &self.output_buffers[0]);
+
+            if let Some(timer) = &self.timer {
+                timer.mark(&mut encoder, 2);
+                timer.resolve(&mut encoder);
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Records copying `band_rows` rows of the read region from `row` on into
+    /// `buffer`.
+    fn copy_rows(&self, encoder: &mut wgpu::CommandEncoder, row: u32, buffer: &wgpu::Buffer) {
+        if let Some(row_packer) = &self.row_packer {
+            return row_packer.record(encoder, buffer);
+        }
+
+        let image_texture = wgpu::ImageCopyTextureBase {
+            texture: &*self.output_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                y: self.read_origin.y + row,
+                ..self.read_origin
+            },
+            aspect: wgpu::TextureAspect::All,
+        };
+
+        let image_buffer = wgpu::ImageCopyBuffer {
+            buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.align_width),
+                rows_per_image: Some(self.band_rows),
+            },
+        };
+
+        encoder.copy_texture_to_buffer(
+            image_texture,
+            image_buffer,
+            wgpu::Extent3d {
+                height: self.band_rows,
+                ..self.read_size
+            },
+        );
+    }
+
+    /// The read region of the output submitted last, as tightly packed
+    /// RGBA8 texels.
+    async fn read_output(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<u8>> {
+        let (width, height) = (self.read_size.width, self.read_size.height);
+
+        if self.band_rows == height {
+            return Ok(view_into_buffer(device, width, height, &self.output_buffers[0]).await?);
+        }
+
+        let row_bytes = (width * DATA_PER_PIXEL * U8_SIZE) as usize;
+        let mut buffer = vec![0; row_bytes * height as usize];
+
+        // The last band ends at the bottom, reading some rows over again,
+        // so every band is read straight into its place.
+        let starts: Vec<_> = (0..height)
+            .step_by(self.band_rows as usize)
+            .map(|row| row.min(height - self.band_rows))
+            .collect();
+
+        let copy_band = |band: usize| {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Band Command Encoder"),
+            });
+            let output_buffer = &self.output_buffers[band % self.output_buffers.len()];
+            self.copy_rows(&mut encoder, starts[band], output_buffer);
+            queue.submit(Some(encoder.finish()));
+        };
+
+        // Every buffer of the ring takes a band ahead, and gets the band after
+        // the last one once it is drained.
+        for band in 0..self.output_buffers.len().min(starts.len()) {
+            copy_band(band);
+        }
+
+        for (band, &start) in starts.iter().enumerate() {
+            let output_buffer = &self.output_buffers[band % self.output_buffers.len()];
+            let rows = &mut buffer[start as usize * row_bytes..];
+            view_into_slice(device, width, self.band_rows, output_buffer, rows).await?;
+
+            if band + self.output_buffers.len() < starts.len() {
+                copy_band(band + self.output_buffers.len());
+            }
+        }
+
+        Ok(buffer)
+    }
+}
+
+/// Fails for workgroups larger than `device` runs.
+fn check_workgroup_size(device: &wgpu::Device, [width, height, depth]: [u32; 3]) -> Result<()> {
+    let limits = device.limits();
+
+    if width > limits.max_compute_workgroup_size_x
+        || height > limits.max_compute_workgroup_size_y
+        || width * height * depth > limits.max_compute_invocations_per_workgroup
+    {
+        bail!(
+            "A {}x{} workgroup exceeds the device limits of {}x{} and {} invocations",
+            width,
+            height,
+            limits.max_compute_workgroup_size_x,
+            limits.max_compute_workgroup_size_y,
+            limits.max_compute_invocations_per_workgroup
+        );
+    }
+
+    Ok(())
+}
+
+/// Largest readback buffer, beyond which the output is read back in bands
+/// rather than mapping one buffer the size of a gigapixel image.
+const READBACK_BAND_BYTES: u64 = 256 << 20;
+
+/// Buffers the bands of a readback go through in turn.
+const READBACK_BUFFERS: u32 = 3;
+
+/// The WGSL of `op` with the settings of `options` applied, as its pipelines
+/// are created from it.
+fn prepare_shader(op: &OpSpec<'_>, options: &RunOptions) -> Result<shader::Shader> {
+    let mut shader = match (options.in_place, &options.locked_includes) {
+        (Some(format), includes) => shader::preprocess_in_place(
+            op.shader,
+            options.shader_dir.as_deref(),
+            includes.as_ref(),
+            format,
+        )?,
+        (None, Some(includes)) => shader::preprocess_locked(op.shader, includes)?,
+        (None, None) => shader::preprocess(op.shader, options.shader_dir.as_deref())?,
+    };
+    shader.set_workgroup_size(options.workgroup_size);
+    shader.specialize(&options.constants)?;
+    if options.strict_math {
+        if !op.strict_math {
+            bail!(
+                "Operation '{}' doesn't round its sums under --strict-math, only {} do",
+                op.name,
+                ops::strict_math_ops().join(", ")
+            );
+        }
+        shader.set_strict_math();
+    }
+    if options.in_place.is_some() && !shader.includes(shader::POINTWISE_INCLUDE) {
+        bail!(
+            "Operation '{}' reads more than the texel it writes, so --in-place can't run it",
+            op.name
+        );
+    }
 
-const DATA_PER_PIXEL: u32 = 4;
-const U8_SIZE: u32 = std::mem::size_of::<u8>() as u32;
+    Ok(shader)
+}
+
+/// Format of the single texture `--in-place` runs on: RGBA8 where the
+/// adapter reads and writes it as storage, or else 32-bit texels holding
+/// the same bytes.
+fn in_place_format(context: &GpuContext, args: &cli::Args) -> Result<Option<wgpu::TextureFormat>> {
+    if !args.in_place {
+        return Ok(None);
+    }
+
+    [
+        wgpu::TextureFormat::Rgba8Unorm,
+        wgpu::TextureFormat::R32Uint,
+    ]
+    .into_iter()
+    .find(|&format| context.supports_read_write_storage(format))
+    .map(Some)
+    .ok_or_else(|| anyhow!("--in-place needs an adapter with read-write storage textures"))
+}
 
-fn align_up(num: u32, align: u32) -> u32 {
-    (num + align - 1) & !(align - 1)
+/// Zeroes `texture`, an output of `op`, if it was recycled and
+/// `op` may leave texels of it unwritten: the built-in kernels write every
+/// texel of their outputs, a `--shader` may well not.
+fn clear_recycled(context: &GpuContext, op: &OpSpec<'_>, texture: &PooledTexture) {
+    if op.name == ops::CUSTOM && texture.is_recycled() {
+        context
+            .pool
+            .zero_texture(&context.device, &context.queue, texture);
+    }
 }
 
-fn compute_and_get_texture(
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
+/// Uploads `inputs` (all `width`x`height` RGBA8), after the input already
+/// decoded into a texture in `options` if there is one, and runs `op` over
+/// them. The output, and with it the dispatch, has the size given in
+/// `globals`; only the region in `options` of it is read back when given.
+#[allow(clippy::too_many_arguments)]
+async fn compute_and_get_texture(
+    context: &GpuContext,
     width: u32,
     height: u32,
-    buffer: &[u8],
-) -> wgpu::Buffer {
+    inputs: &[&[u8]],
+    op: &OpSpec<'_>,
+    globals: &Globals,
+    options: &RunOptions,
+) -> Result<Computation> {
+    let (device, queue) = (&*context.device, &context.queue);
+    let (pipelines, pool) = (&context.pipelines, &context.pool);
+
+    let input_count = options.input_texture.is_some() as usize + inputs.len();
+    if input_count != op.inputs as usize {
+        bail!(
+            "Operation '{}' takes {} input(s), got {}",
+            op.name,
+            op.inputs,
+            input_count
+        );
+    }
+
+    let [output_width, output_height] = globals.size;
+
+    // Textures too large for the device fail deep inside wgpu otherwise.
+    check_image_size(device, width, height, DATA_PER_PIXEL * U8_SIZE)?;
+    check_image_size(
+        device,
+        output_width,
+        output_height,
+        DATA_PER_PIXEL * U8_SIZE,
+    )?;
+
+    let texture_size = wgpu::Extent3d {
+        width: output_width,
+        height: output_height,
+        depth_or_array_layers: 1,
+    };
+
+    let (read_origin, read_size) = match &options.region {
+        Some(region) => {
+            check_region(
+                texture_size,
+                region.x,
+                region.y,
+                region.width,
+                region.height,
+            )?;
+
+            (
+                wgpu::Origin3d {
+                    x: region.x,
+                    y: region.y,
+                    z: 0,
+                },
+                wgpu::Extent3d {
+                    width: region.width,
+                    height: region.height,
+                    depth_or_array_layers: 1,
+                },
+            )
+        }
+        None => (wgpu::Origin3d::ZERO, texture_size),
+    };
+
     let align_width = align_up(
-        width * DATA_PER_PIXEL * U8_SIZE,
+        read_size.width * DATA_PER_PIXEL * U8_SIZE,
         wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
     ) / U8_SIZE;
 
-    let texture_size = wgpu::Extent3d {
+    let input_size = wgpu::Extent3d {
         width,
         height,
         depth_or_array_layers: 1,
     };
 
-    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Shader Module"),
-        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/compute.wgsl"))),
-    });
+    let shader = prepare_shader(op, options)?;
 
-    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("Bind Group Layout"),
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    multisampled: false,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::StorageTexture {
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    format: wgpu::TextureFormat::Rgba8Unorm,
-                    access: wgpu::StorageTextureAccess::WriteOnly,
-                },
-                count: None,
+    if let Some(format) = options.in_place {
+        if inputs.len() != 1 || !op.outputs.is_empty() || texture_size != input_size {
+            bail!("--in-place needs an operation with one input and output of the same size");
+        }
+        if op.simulation.is_some() || options.iterations.is_some() {
+            bail!("--in-place runs a single pass, without --iterations");
+        }
+        if format != wgpu::TextureFormat::Rgba8Unorm
+            && (options.trim_threshold.is_some()
+                || options.mipmaps.is_some()
+                || options.verify_srgb.is_some()
+                || options.skip_unchanged.is_some())
+        {
+            bail!("On this adapter --in-place packs the output into 32-bit texels, which trimming, mipmaps, --verify-srgb and --skip-unchanged can't read");
+        }
+    }
+    // The format of the output, which takes the input too when in place.
+    let output_format = options.in_place.unwrap_or(wgpu::TextureFormat::Rgba8Unorm);
+
+    let declared_size = workgroup_size(&shader.source, op.entry_point);
+    check_workgroup_size(device, declared_size)?;
+    if let Some(simulation) = &op.simulation {
+        if [simulation.seed, simulation.resolve]
+            .iter()
+            .any(|entry_point| workgroup_size(&shader.source, entry_point) != declared_size)
+        {
+            bail!(
+                "The entry points of operation '{}' declare different workgroup sizes",
+                op.name
+            );
+        }
+    }
+
+    let workgroups = workgroup_count(texture_size.width, texture_size.height, declared_size);
+    let max_workgroups = device.limits().max_compute_workgroups_per_dimension;
+    if workgroups.0 > max_workgroups || workgroups.1 > max_workgroups {
+        bail!(
+            "Covering a {}x{} output takes {}x{} workgroups, more than the device limit of {}",
+            texture_size.width,
+            texture_size.height,
+            workgroups.0,
+            workgroups.1,
+            max_workgroups
+        );
+    }
+
+    let bundled_textures = if BundledTextures::used_by(&shader) {
+        Some(BundledTextures::new(device, queue, pipelines)?)
+    } else {
+        None
+    };
+    let shader_params = options
+        .shader_params
+        .as_deref()
+        .map(|contents| ShaderParams::new(device, pipelines, contents));
+
+    // Report mistakes in shaders loaded from disk as errors rather than
+    // panicking.
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    // The layout of the operations of the library, with the globals for the
+    // parameters, and the bindings of the tool after it.
+    let mut layout_entries = wgpu_texture_copy::op::layout_entries(input_count as u32);
+    if options.in_place.is_some() {
+        // In place, the output is the input too and there is nothing at 0.
+        layout_entries.splice(0..2, [read_write_texture_layout_entry(1, output_format)]);
+    }
+    let lookup_binding = EXTRA_INPUT_BINDING + input_count as u32 - 1;
+    if op.lookup.is_some() {
+        layout_entries.push(input_texture_layout_entry(lookup_binding));
+    }
+    let integral_binding = lookup_binding + op.lookup.is_some() as u32;
+    if op.integral {
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: integral_binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
             },
-        ],
-    });
+            count: None,
+        });
+    }
+    let extra_output_binding = integral_binding + op.integral as u32;
+    layout_entries.extend(
+        (extra_output_binding..)
+            .take(op.outputs.len())
+            .map(output_texture_layout_entry),
+    );
 
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("Texture"),
-        size: texture_size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    let bind_group_layout =
+        pipelines.bind_group_layout(device, "Bind Group Layout", &layout_entries);
+
+    let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Globals Buffer"),
+        contents: bytemuck::bytes_of(globals),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
-    queue.write_texture(
-        wgpu::ImageCopyTexture {
-            texture: &texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-            aspect: wgpu::TextureAspect::All,
-        },
-        buffer,
-        wgpu::ImageDataLayout {
-            offset: 0,
-            bytes_per_row: Some(DATA_PER_PIXEL * width),
-            rows_per_image: Some(height),
-        },
-        texture_size,
-    );
+    // The output texture takes the input in place.
+    let separate_inputs = match options.in_place {
+        Some(_) => &inputs[..0],
+        None => inputs,
+    };
+
+    let memory = &options.memory;
+    memory.allocate("The globals", std::mem::size_of::<Globals>() as u64)?;
+    memory.allocate(
+        "The input textures",
+        (options.input_texture.is_some() as u64 + separate_inputs.len() as u64)
+            * memory::texture_bytes(input_size, DATA_PER_PIXEL * U8_SIZE),
+    )?;
+
+    let input_textures: Vec<_> = separate_inputs
+        .iter()
+        .map(|buffer| {
+            let texture = pool.texture(device, &input_texture_descriptor(input_size));
+            write_input_texture(queue, &texture, input_size, buffer);
+
+            texture
+        })
+        .collect();
+
+    let sources: Vec<&wgpu::Texture> = options
+        .input_texture
+        .iter()
+        .chain(input_textures.iter().map(|texture| &**texture))
+        .collect();
+    let input_views: Vec<_> = sources
+        .iter()
+        .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        .collect();
+
+    let mut clusters = Vec::new();
+
+    let lookup_table = match &op.lookup {
+        Some(Lookup::Histograms(build)) => {
+            let mut histograms = Vec::with_capacity(sources.len());
+            for texture in &sources {
+                histograms.push(histogram::histogram(device, queue, texture, input_size).await?);
+            }
+
+            Some(build(&histograms))
+        }
+        Some(Lookup::Gradient) => Some(
+            options
+                .gradient
+                .as_ref()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Operation '{}' needs --gradient or --gradient-image",
+                        op.name
+                    )
+                })?
+                .table(),
+        ),
+        Some(Lookup::Clusters) => {
+            // In place the input only reaches the GPU in the output texture,
+            // which isn't made yet, so clustering gets a texture of its own.
+            let scratch = match sources.first() {
+                Some(_) => None,
+                None => {
+                    memory.allocate(
+                        "The clustered input",
+                        memory::texture_bytes(input_size, DATA_PER_PIXEL * U8_SIZE),
+                    )?;
+                    let texture = pool.texture(device, &input_texture_descriptor(input_size));
+                    write_input_texture(queue, &texture, input_size, inputs[0]);
+
+                    Some(texture)
+                }
+            };
+            let [count, spatial, ..] = globals.params[0];
+            clusters = kmeans::cluster(
+                device,
+                queue,
+                scratch.as_deref().unwrap_or_else(|| sources[0]),
+                inputs[0],
+                input_size,
+                count as u32,
+                spatial,
+            )
+            .await?;
+
+            Some(kmeans::table(&clusters, input_size))
+        }
+        None => None,
+    };
+
+    if let Some(table) = &lookup_table {
+        memory.allocate("The lookup table", table.len() as u64)?;
+    }
 
-    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("Output Texture"),
-        size: texture_size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
-        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
-        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    let lookup_view = lookup_table.map(|table| {
+        let lookup_size = wgpu::Extent3d {
+            width: 256,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+        let texture = create_input_texture(device, queue, lookup_size, &table);
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
     });
 
-    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let integral_buffer = if op.integral {
+        let buffer = integral::integral_image(context, sources[0], input_size).await?;
+        memory.allocate("The summed-area table", buffer.size())?;
+
+        Some(buffer)
+    } else {
+        None
+    };
+
+    memory.allocate(
+        "The output textures",
+        (1 + op.outputs.len() as u64)
+            * memory::texture_bytes(texture_size, DATA_PER_PIXEL * U8_SIZE),
+    )?;
+
+    let output_texture = pool.texture(
+        device,
+        &output_texture_descriptor(texture_size, output_format),
+    );
 
+    if options.in_place.is_some() {
+        write_input_texture(queue, &output_texture, input_size, inputs[0]);
+    } else {
+        clear_recycled(context, op, &output_texture);
+    }
     let output_texture_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
+    let extra_output_textures: Vec<_> = op
+        .outputs
+        .iter()
+        .map(|_| {
+            pool.texture(
+                device,
+                &wgpu::TextureDescriptor {
+                    label: Some("Extra Output Texture"),
+                    ..output_texture_descriptor(texture_size, wgpu::TextureFormat::Rgba8Unorm)
+                },
+            )
+        })
+        .collect();
+
+    for texture in &extra_output_textures {
+        clear_recycled(context, op, texture);
+    }
+    let extra_output_views: Vec<_> = extra_output_textures
+        .iter()
+        .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+        .collect();
+
+    let mut entries = Vec::new();
+    if let Some(view) = input_views.first() {
+        entries.push(wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(view),
+        });
+    }
+    entries.extend([
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&output_texture_view),
+        },
+        wgpu::BindGroupEntry {
+            binding: 2,
+            resource: globals_buffer.as_entire_binding(),
+        },
+    ]);
+    entries.extend(
+        input_views
+            .iter()
+            .skip(1)
+            .zip(EXTRA_INPUT_BINDING..)
+            .map(|(view, binding)| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            }),
+    );
+    if let Some(lookup_view) = &lookup_view {
+        entries.push(wgpu::BindGroupEntry {
+            binding: lookup_binding,
+            resource: wgpu::BindingResource::TextureView(lookup_view),
+        });
+    }
+    if let Some(integral_buffer) = &integral_buffer {
+        entries.push(wgpu::BindGroupEntry {
+            binding: integral_binding,
+            resource: integral_buffer.as_entire_binding(),
+        });
+    }
+    entries.extend(
+        extra_output_views
+            .iter()
+            .zip(extra_output_binding..)
+            .map(|(view, binding)| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            }),
+    );
+
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: Some("Bind Group"),
         layout: &bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&texture_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::TextureView(&output_texture_view),
-            },
-        ],
+        entries: &entries,
     });
 
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Pipeline Layout"),
-        bind_group_layouts: &[&bind_group_layout],
-        push_constant_ranges: &[],
-    });
+    let bundled_entries = BundledTextures::layout_entries();
+    let params_entries = [params_layout_entry()];
+    let mut bind_group_layouts: Vec<&[wgpu::BindGroupLayoutEntry]> = vec![&layout_entries];
+    if bundled_textures.is_some() {
+        bind_group_layouts.push(&bundled_entries);
+    }
+    if shader_params.is_some() {
+        if bundled_textures.is_none() {
+            bind_group_layouts.push(&[]);
+        }
+        bind_group_layouts.push(&params_entries);
+    }
 
-    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("Compute Pipeline"),
-        layout: Some(&pipeline_layout),
-        module: &shader_module,
-        entry_point: "basic",
-    });
+    // Created on the first image, and taken from the cache of the context
+    // for the ones after it.
+    let create_pipeline = |entry_point| {
+        pipelines.compute_pipeline(device, &shader.source, entry_point, &bind_group_layouts)
+    };
 
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("Command Encoder"),
-    });
+    let pipeline = create_pipeline(
+        op.simulation
+            .as_ref()
+            .map_or(op.entry_point, |simulation| simulation.seed),
+    );
 
-    {
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass"),
-        });
-        compute_pass.set_pipeline(&pipeline);
-        compute_pass.set_bind_group(0, &bind_group, &[]);
-        compute_pass.dispatch_workgroups(width, height, 1);
+    // Passes after the first: the remaining iterations, or for simulations
+    // every step and then the one resolving the state.
+    let iteration_passes = match &op.simulation {
+        Some(simulation) => Some((
+            options.iterations.unwrap_or(simulation.iterations),
+            Some(create_pipeline(op.entry_point)),
+            Some(create_pipeline(simulation.resolve)),
+        )),
+        None => options
+            .iterations
+            .filter(|&iterations| iterations > 1)
+            .map(|iterations| (iterations - 1, None, None)),
+    };
+
+    if let Some(err) = device.pop_error_scope().await {
+        pipelines.forget(&shader.source);
+        return Err(wgpu_texture_copy::Error::Validation(err.to_string()))
+            .with_context(|| format!("Invalid shader for operation '{}'", op.name));
     }
 
-    let image_texture = wgpu::ImageCopyTextureBase {
-        texture: &output_texture,
-        mip_level: 0,
-        origin: wgpu::Origin3d::ZERO,
-        aspect: wgpu::TextureAspect::All,
-    };
+    let iteration = if let Some((steps, step_pipeline, resolve_pipeline)) = iteration_passes {
+        if texture_size != input_size {
+            bail!("Iterating needs an output the size of the input");
+        }
 
-    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Buffer"),
-        size: (align_width * height) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+        memory.allocate(
+            "The iteration texture",
+            memory::texture_bytes(texture_size, DATA_PER_PIXEL * U8_SIZE),
+        )?;
 
-    let image_buffer = wgpu::ImageCopyBuffer {
-        buffer: &output_buffer,
-        layout: wgpu::ImageDataLayout {
-            offset: 0,
-            bytes_per_row: Some(align_width),
-            rows_per_image: Some(height),
-        },
+        let texture = pool.texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Iteration Texture"),
+                ..output_texture_descriptor(texture_size, wgpu::TextureFormat::Rgba8Unorm)
+            },
+        );
+        clear_recycled(context, op, &texture);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // The same entries as the first pass, with the primary input and the
+        // output swapped for the two textures in turn.
+        let create_bind_group = |read, write| {
+            let mut entries = entries.clone();
+            entries[0].resource = wgpu::BindingResource::TextureView(read);
+            entries[1].resource = wgpu::BindingResource::TextureView(write);
+
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Iteration Bind Group"),
+                layout: &bind_group_layout,
+                entries: &entries,
+            })
+        };
+
+        Some(Iteration {
+            steps,
+            step_pipeline,
+            resolve_pipeline,
+            bind_groups: [
+                create_bind_group(&output_texture_view, &view),
+                create_bind_group(&view, &output_texture_view),
+            ],
+            texture,
+        })
+    } else {
+        None
     };
 
-    encoder.copy_texture_to_buffer(image_texture, image_buffer, texture_size);
+    // Read the output back in bands of rows when a buffer for all of them
+    // doesn't fit, through a ring of buffers so the GPU copies the next
+    // bands while one is drained.
+    let max_band_rows = (device.limits().max_buffer_size.min(READBACK_BAND_BYTES)
+        / align_width as u64)
+        .max(1) as u32;
+    let (band_rows, band_buffers) = match memory.available() {
+        Some(available) if available < align_width as u64 * read_size.height as u64 => {
+            let rows = (available / align_width as u64) as u32;
+            if rows == 0 {
+                bail!(
+                    "The memory budget leaves no room to read back a row of {}",
+                    memory::format_bytes(align_width as u64)
+                );
+            }
+            if options.gpu_timings {
+                bail!(
+                    "--gpu-timings can't time a readback split into bands, raise --memory-budget"
+                );
+            }
 
-    queue.submit(Some(encoder.finish()));
+            let buffers = READBACK_BUFFERS.min(rows);
+            let band_rows = (rows / buffers).min(max_band_rows);
+            status!(
+                "Reading the output back {} rows at a time to stay within the memory budget",
+                band_rows
+            );
+            (band_rows, buffers)
+        }
+        _ if read_size.height > max_band_rows => {
+            if options.gpu_timings {
+                bail!("--gpu-timings can't time a readback split into bands");
+            }
 
-    output_buffer
-}
+            status!(
+                "Reading the output back {} rows at a time, as a buffer for all of them would be too large",
+                max_band_rows
+            );
+            (max_band_rows, READBACK_BUFFERS)
+        }
+        _ => (read_size.height, 1),
+    };
+    let band_buffers = band_buffers.min(read_size.height.div_ceil(band_rows));
 
-async fn get_device_and_queue() -> Result<(Device, Queue)> {
-    let instance = wgpu::Instance::default();
+    // Rows padded for the copy are packed on the GPU instead when the output
+    // is read back at once, so mapping it needs no copy row by row.
+    let packed_bytes = RowPacker::packed_bytes(read_size);
+    let row_packer = (band_rows == read_size.height
+        && output_format == wgpu::TextureFormat::Rgba8Unorm
+        && align_width != read_size.width * DATA_PER_PIXEL * U8_SIZE
+        && RowPacker::fits(device, read_size)
+        && memory
+            .available()
+            .is_none_or(|available| available >= 2 * packed_bytes))
+    .then(|| {
+        RowPacker::new(
+            device,
+            &context.pipelines,
+            &output_texture,
+            read_origin,
+            read_size,
+        )
+    });
 
-    if let Some(adapter) = instance
-        .request_adapter(&wgpu::RequestAdapterOptions::default())
-        .await
-    {
-        adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_defaults(),
+    let readback_bytes = match &row_packer {
+        Some(_) => {
+            memory.allocate("The packed rows", packed_bytes)?;
+            packed_bytes
+        }
+        None => align_width as u64 * band_rows as u64,
+    };
+    memory.allocate("The readback buffers", readback_bytes * band_buffers as u64)?;
+
+    let output_buffers = (0..band_buffers)
+        .map(|_| {
+            pool.buffer(
+                device,
+                &wgpu::BufferDescriptor {
+                    label: Some("Buffer"),
+                    size: readback_bytes,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
                 },
-                None,
             )
-            .await
-            .map_err(|err| anyhow!("Request device failed: {}", err))
+        })
+        .collect();
+
+    let timer = if options.gpu_timings {
+        Some(
+            timing::GpuTimer::new(device, queue)
+                .ok_or_else(|| anyhow!("The adapter has no timestamp queries for --gpu-timings"))?,
+        )
     } else {
-        bail!("No adapters are found that suffice all the 'hard' options.")
-    }
-}
+        None
+    };
+
+    let invocations = if options.pipeline_stats {
+        Some(invocations::InvocationCounter::new(device).ok_or_else(|| {
+            anyhow!("The adapter has no pipeline statistics queries for --pipeline-stats")
+        })?)
+    } else {
+        None
+    };
 
-async fn manipulate_buffer(width: u32, height: u32, buffer: &[u8]) -> Result<Vec<u8>> {
-    let (device, queue) = get_device_and_queue().await?;
+    let computation = Computation {
+        input_textures,
+        input_size,
+        pipeline,
+        bind_group,
+        iteration,
+        bundled_textures,
+        shader_params,
+        globals_buffer,
+        output_texture,
+        output_buffers,
+        extra_output_textures,
+        texture_size,
+        workgroups,
+        read_origin,
+        read_size,
+        align_width,
+        band_rows,
+        row_packer,
+        clusters,
+        timer,
+        invocations,
+        in_place: options.in_place.is_some(),
+    };
 
-    let buffer = compute_and_get_texture(&device, &queue, width, height, buffer);
+    computation.submit(device, queue, globals);
 
-    view_into_buffer(&device, width, height, &buffer).await
+    Ok(computation)
 }
 
-fn run() -> Result<()> {
-    let file = File::open("data/test.png")?;
-    let reader = BufReader::new(file);
+/// A read back result of one dispatch.
+#[derive(Default)]
+struct Output {
+    frame: usize,
+    cell: usize,
+    /// The earlier frame this one repeats with `--skip-unchanged`, which
+    /// leaves everything else empty.
+    repeats: Option<usize>,
+    buffer: Vec<u8>,
+    /// Bounding box of the visible texels, when trimming was requested.
+    alpha_bounds: Option<trim::Bounds>,
+    /// Mip levels below the output, when requested.
+    mip_levels: Vec<RgbaImage>,
+    /// Gamma against linear space comparison of the mip levels, when
+    /// requested.
+    srgb_checks: Vec<mipmap::SrgbCheck>,
+    /// Further outputs of the operation, in declaration order.
+    extra_outputs: Vec<Vec<u8>>,
+    /// Time the GPU took, when requested and supported.
+    gpu_timings: Option<timing::GpuTimings>,
+    /// Compute shader invocations of every pass of the frame, and the texels
+    /// they were dispatched for.
+    invocations: Option<(u64, u64)>,
+}
 
-    let reader = Reader::new(reader).with_guessed_format()?;
+/// Settings of a run beyond the operation and its inputs.
+struct RunOptions {
+    /// Gradient of operations that map colors through one.
+    gradient: Option<Gradient>,
+    /// Compute the bounds of the texels whose alpha exceeds this.
+    trim_threshold: Option<f32>,
+    /// Generate the mip chain of the output.
+    mipmaps: Option<MipmapSettings>,
+    /// Compare gamma and linear space mips built with this filter.
+    verify_srgb: Option<MipFilter>,
+    /// Read back only this part of the output.
+    region: Option<trim::Bounds>,
+    /// Dispatches per frame, every one after the first reading the output of
+    /// the one before; steps of simulations.
+    iterations: Option<u32>,
+    /// Where the shader of the operation was loaded from, if from disk, to
+    /// resolve its includes against.
+    shader_dir: Option<PathBuf>,
+    /// Includes of the shader of the operation from `--from-lock`, in place
+    /// of the files and the bundled library.
+    locked_includes: Option<BTreeMap<String, String>>,
+    /// Contents of the parameter struct the shader of the operation declares,
+    /// for `--shader` kernels with one.
+    shader_params: Option<Vec<u8>>,
+    /// Values of the `override` constants of the shader by name or id.
+    constants: Vec<(String, f32)>,
+    /// Workgroup size of the built-in operations.
+    workgroup_size: [u32; 2],
+    /// Round the products and sums of the weighted sums of the operations
+    /// on their own, for bit-stable outputs.
+    strict_math: bool,
+    /// Time the compute pass and the readback copy with timestamp queries.
+    gpu_timings: bool,
+    /// Count the compute shader invocations with pipeline statistics queries.
+    pipeline_stats: bool,
+    /// GPU memory the operation allocates, within `--memory-budget`.
+    memory: memory::MemoryTracker,
+    /// Hand on frames within this many 8-bit steps of the last one in full
+    /// as repeats of it, without reading them back.
+    skip_unchanged: Option<u32>,
+    /// Run the operation on a single read-write texture of this format, see
+    /// `--in-place`.
+    in_place: Option<wgpu::TextureFormat>,
+    /// The first input, decoded straight into a texture by [`upload_file`]
+    /// when nothing reads its texels on the CPU. The cells then hold the
+    /// further inputs alone, and there is a single one of them.
+    input_texture: Option<wgpu::Texture>,
+}
+
+/// Runs `op` once per entry in `frames` and, within each frame, once per set
+/// of `width`x`height` inputs in `cells`, handing every read back result to
+/// `on_output`. Returns the clusters of segmenting operations.
+#[allow(clippy::too_many_arguments)]
+async fn manipulate_buffer(
+    context: &GpuContext,
+    width: u32,
+    height: u32,
+    cells: &[Vec<&[u8]>],
+    op: &OpSpec<'_>,
+    frames: &[Globals],
+    options: &RunOptions,
+    mut on_output: impl FnMut(Output) -> Result<()>,
+) -> Result<Vec<kmeans::Centroid>> {
+    let (device, queue) = (&context.device, &context.queue);
 
-    let image = reader.decode()?;
+    let computation =
+        compute_and_get_texture(context, width, height, &cells[0], op, &frames[0], options).await?;
 
-    let width = image.width();
-    let height = image.height();
-    let mut buffer = image.into_bytes();
+    let mip_generator = options
+        .mipmaps
+        .map(|settings| MipGenerator::new(device, settings))
+        .transpose()?;
 
-    let buffer = futures::executor::block_on(manipulate_buffer(width, height, &mut buffer))?;
+    let mut gate = match options.skip_unchanged {
+        Some(threshold) => {
+            options.memory.allocate(
+                "The frame comparison textures",
+                FrameGate::texture_bytes(computation.texture_size),
+            )?;
+            Some(FrameGate::new(device, threshold, computation.texture_size))
+        }
+        None => None,
+    };
 
-    image::save_buffer(
-        "data/out.png",
-        &buffer,
-        width,
-        height,
-        image::ColorType::Rgba8,
-    )?;
+    for (frame, globals) in frames.iter().enumerate() {
+        for (cell, inputs) in cells.iter().enumerate() {
+            if frame > 0 || cell > 0 {
+                // In place, the last dispatch overwrote the input.
+                if cells.len() > 1 || computation.in_place {
+                    computation.upload(queue, inputs);
+                }
 
-    Ok(())
-}
+                computation.submit(device, queue, globals);
+            }
+
+            let repeats = match &mut gate {
+                Some(gate) => {
+                    gate.repeated_frame(device, queue, frame, &computation.output_texture)
+                        .await?
+                }
+                None => None,
+            };
+            if repeats.is_some() {
+                on_output(Output {
+                    frame,
+                    cell,
+                    repeats,
+                    ..Output::default()
+                })?;
+                continue;
+            }
+
+            let alpha_bounds = match options.trim_threshold {
+                Some(threshold) => trim::alpha_bounds(
+                    device,
+                    queue,
+                    &computation.output_texture,
+                    computation.texture_size,
+                    threshold,
+                )
+                .await?
+                // Keep a single transparent texel for fully transparent images.
+                .or(Some(trim::Bounds {
+                    x: 0,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                })),
+                None => None,
+            };
+
+            let mip_levels = match &mip_generator {
+                Some(generator) => {
+                    generator
+                        .generate(
+                            device,
+                            queue,
+                            &computation.output_texture,
+                            computation.texture_size,
+                        )
+                        .await?
+                }
+                None => Vec::new(),
+            };
+
+            let srgb_checks = match options.verify_srgb {
+                Some(filter) => {
+                    mipmap::verify_srgb(
+                        device,
+                        queue,
+                        &computation.output_texture,
+                        computation.texture_size,
+                        filter,
+                    )
+                    .await?
+                }
+                None => Vec::new(),
+            };
+
+            let buffer = computation.read_output(device, queue).await?;
+
+            let gpu_timings = match &computation.timer {
+                Some(timer) => Some(timer.read(device, queue).await?),
+                None => None,
+            };
+
+            let invocations = match &computation.invocations {
+                Some(invocations) => {
+                    let passes = computation.iteration.as_ref().map_or(1, Iteration::passes);
+                    let texels = computation.texture_size.width as u64
+                        * computation.texture_size.height as u64
+                        * passes as u64;
 
-fn trim_image_buffer(width: u32, height: u32, buffer: &[u8]) -> Vec<u8> {
-    let mut output = Vec::with_capacity((width * height) as usize);
+                    Some((invocations.read(device, queue).await?, texels))
+                }
+                None => None,
+            };
 
-    let align_width = buffer.len() / height as usize;
+            // Each further output is read back through a buffer of its own.
+            let extra_readback = align_up(
+                computation.read_size.width * DATA_PER_PIXEL * U8_SIZE,
+                wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+            ) as u64
+                * computation.read_size.height as u64;
 
-    for i in 0..height as usize {
-        for j in 0..width as usize {
-            output.push(buffer[i * align_width + j]);
+            let mut extra_outputs = Vec::with_capacity(computation.extra_output_textures.len());
+            for texture in &computation.extra_output_textures {
+                options
+                    .memory
+                    .allocate("Reading back a further output", extra_readback)?;
+                extra_outputs.push(
+                    read_region(
+                        device,
+                        queue,
+                        texture,
+                        computation.read_origin.x,
+                        computation.read_origin.y,
+                        computation.read_size.width,
+                        computation.read_size.height,
+                    )
+                    .await?,
+                );
+                options.memory.free(extra_readback);
+            }
+
+            on_output(Output {
+                frame,
+                cell,
+                repeats: None,
+                buffer,
+                alpha_bounds,
+                mip_levels,
+                srgb_checks,
+                extra_outputs,
+                gpu_timings,
+                invocations,
+            })?;
         }
     }
 
-    output
+    Ok(computation.clusters)
 }
 
-async fn view_into_buffer(
-    device: &wgpu::Device,
-    width: u32,
-    height: u32,
-    raw_buffer: &wgpu::Buffer,
-) -> Result<Vec<u8>> {
-    let slice = raw_buffer.slice(..);
+/// File for the further output `name` of `frame`, next to `output`:
+/// `out_mask.png`, or `out_0003_mask.png` within a sequence.
+fn extra_output_path(output: &Path, name: &str, frame: usize, frames: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string());
 
-    let (sender, receiver) = futures::channel::oneshot::channel();
+    let file_name = if frames > 1 {
+        format!("{}_{:04}_{}.{}", stem, frame, name, extension)
+    } else {
+        format!("{}_{}.{}", stem, name, extension)
+    };
 
-    slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    output.with_file_name(file_name)
+}
 
-    device.poll(wgpu::Maintain::Wait);
+fn load_image(path: &Path) -> Result<RgbaImage> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let reader = BufReader::new(file);
 
-    if let std::result::Result::Ok(_) = receiver.await {
-        let buffer_view = slice.get_mapped_range();
+    let reader = Reader::new(reader).with_guessed_format()?;
 
-        let buffer = trim_image_buffer(DATA_PER_PIXEL * width, height, &buffer_view);
+    Ok(reader.decode()?.into_rgba8())
+}
 
-        drop(buffer_view);
-        raw_buffer.unmap();
+fn run(cli: cli::Cli) -> Result<()> {
+    if cli.quiet {
+        QUIET.store(true, Ordering::Relaxed);
+        log::set_max_level(log::max_level().min(log::LevelFilter::Error));
+    }
+    if let Some(threads) = cli.threads {
+        thumbnail::set_threads(threads.get());
+        #[cfg(feature = "parallel")]
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.get())
+            .build_global()?;
+    }
 
-        Ok(buffer)
-    } else {
-        bail!("Couldn't run compute on the GPU.")
+    // Listings and submissions, which don't need a GPU.
+    match &cli.command {
+        Some(Command::Adapters) => {
+            list_adapters(&cli.adapter_options());
+            return Ok(());
+        }
+        Some(Command::ListEffects(args)) => return list_effects(args),
+        Some(Command::Completions(args)) => {
+            print!("{}", completions::generate(args.shell, cli::Cli::command()));
+            return Ok(());
+        }
+        Some(Command::Help(args)) => return print_help(args),
+        Some(Command::Submit(args)) => return remote::submit(args),
+        Some(Command::Status(args)) => return remote::status(args),
+        _ => {}
     }
-}
 
-fn main() {
-    run().unwrap();
+    let mut gpu = Gpu::new(cli.adapter_options(), cli.retries)?;
+
+    // Batches of images run every image again on its own, keeping the ones
+    // written already.
+    match cli.command.clone() {
+        Some(Command::Thumbnail(args)) => make_thumbnails(&mut gpu, args),
+        Some(Command::Fisheye(args)) => unwrap_fisheye(&mut gpu, args),
+        _ => gpu.run(|context| run_command(context, &cli)),
+    }
+}
+
+/// The GPU context of a run, made again when its device is lost.
+struct Gpu {
+    context: GpuContext,
+    options: AdapterOptions,
+    /// How often to run an image again on a new context, see `--retries`.
+    retries: u32,
+}
+
+impl Gpu {
+    fn new(options: AdapterOptions, retries: u32) -> Result<Self> {
+        let context = futures::executor::block_on(GpuContext::with_options(&options))?;
+
+        Ok(Self {
+            context,
+            options,
+            retries,
+        })
+    }
+
+    /// Runs `image` and fails with the errors wgpu reported outside of an
+    /// error scope meanwhile. Where the device is lost on the way, `image`
+    /// runs again on a new context, up to `retries` times.
+    fn run<T>(&mut self, mut image: impl FnMut(&GpuContext) -> Result<T>) -> Result<T> {
+        let mut retries = 0;
+        loop {
+            let result = image(&self.context).and_then(|value| {
+                self.context.check()?;
+                Ok(value)
+            });
+
+            match result {
+                Err(err)
+                    if retries < self.retries
+                        && (self.context.is_lost() || is_device_lost(&err)) =>
+                {
+                    retries += 1;
+                    log::warn!(
+                        "{:#}, retrying with a new device ({} of {})",
+                        err,
+                        retries,
+                        self.retries
+                    );
+                    self.context =
+                        futures::executor::block_on(GpuContext::with_options(&self.options))?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// What `make` made on the device of `context`, kept in `made`, or made
+/// again where that device has been replaced by a new context since.
+fn made_on<'a, T>(
+    made: &'a mut Option<(Arc<wgpu::Device>, T)>,
+    context: &GpuContext,
+    make: impl FnOnce(&wgpu::Device) -> Result<T>,
+) -> Result<&'a T> {
+    let current = matches!(made, Some((device, _)) if Arc::ptr_eq(device, &context.device));
+    if !current {
+        *made = None;
+    }
+
+    match made {
+        Some((_, value)) => Ok(value),
+        None => Ok(&made
+            .insert((context.device.clone(), make(&context.device)?))
+            .1),
+    }
+}
+
+/// Whether `err` comes from the device getting lost, after which running
+/// again on a new one may well succeed.
+fn is_device_lost(err: &Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<wgpu_texture_copy::Error>()
+            .is_some_and(wgpu_texture_copy::Error::is_device_lost)
+    })
+}
+
+fn run_command(context: &GpuContext, cli: &cli::Cli) -> Result<()> {
+    let result = match cli.command.clone() {
+        Some(Command::Assemble(args)) => assemble_sheet(context, args),
+        Some(Command::Montage(args)) => make_montage(context, args),
+        Some(Command::Audit(args)) => audit_textures(context, args),
+        Some(Command::Clone(args)) => clone_patch(context, args),
+        Some(Command::Inpaint(args)) => inpaint_holes(context, args),
+        Some(Command::Superpixels(args)) => segment_superpixels(context, args),
+        Some(Command::Stack(args)) => stack_images(context, args),
+        Some(Command::HdrMerge(args)) => merge_exposures(context, args),
+        Some(Command::Panorama(args)) => stitch_panorama(context, args),
+        Some(Command::Integral(args)) => write_integral_image(context, args),
+        Some(Command::Erode(args)) => erode_heightmap(context, args),
+        Some(Command::Ibl(args)) => prefilter_environment(context, args),
+        Some(Command::Sh(args)) => project_spherical_harmonics(context, args),
+        Some(Command::Generate(args)) => match args.texture {
+            cli::Generated::BrdfLut(args) => generate_brdf_lut(context, args),
+            cli::Generated::BlueNoise(args) => generate_blue_noise(context, args),
+        },
+        Some(Command::Serve(args)) => remote::serve(context, &args, &cli.cache_dir()),
+        Some(
+            Command::Submit(_)
+            | Command::Status(_)
+            | Command::Adapters
+            | Command::ListEffects(_)
+            | Command::Completions(_)
+            | Command::Help(_),
+        ) => {
+            unreachable!("Listings and submissions run without a context")
+        }
+        Some(Command::Thumbnail(_) | Command::Fisheye(_)) => {
+            unreachable!("Batches run their images on their own")
+        }
+        None => process(context, cli.process.clone()),
+    };
+
+    result
+}
+
+fn list_adapters(options: &AdapterOptions) {
+    let instance = options.instance();
+
+    for (index, adapter) in adapters(&instance).iter().enumerate() {
+        let info = adapter.get_info();
+        println!(
+            "{}: {} ({:?}, {:?})",
+            index, info.name, info.backend, info.device_type
+        );
+    }
+}
+
+fn list_effects(args: &cli::ListEffectsArgs) -> Result<()> {
+    let effects = ops::effects();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&effects)?);
+        return Ok(());
+    }
+
+    for effect in effects {
+        let inputs = match effect.inputs {
+            1 => "1 input".to_string(),
+            inputs => format!("{} inputs", inputs),
+        };
+        let resizable = if effect.resizable { ", resizable" } else { "" };
+        println!("{} ({}{})", effect.name, inputs, resizable);
+
+        for param in effect.params {
+            println!(
+                "  {:<12} {} {}..{}, default {}",
+                param.name, param.kind, param.min, param.max, param.default
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn print_help(args: &cli::HelpArgs) -> Result<()> {
+    let mut command = cli::Cli::command();
+    // Gives subcommands their full name in the usage.
+    command.build();
+
+    let Some(topic) = &args.topic else {
+        return Ok(command.print_long_help()?);
+    };
+
+    if let Some(subcommand) = command.find_subcommand_mut(topic) {
+        return Ok(subcommand.print_long_help()?);
+    }
+
+    match OPS.iter().find(|op| op.name == topic) {
+        Some(op) => {
+            print!("{}", ops::help_page(op, command.get_name()));
+            Ok(())
+        }
+        None => bail!(
+            "No command or operation named '{}', see --help and list-effects",
+            topic
+        ),
+    }
+}
+
+fn clone_patch(context: &GpuContext, args: cli::CloneArgs) -> Result<()> {
+    let destination = load_image(&args.destination)?;
+    let source = load_image(&args.source)?;
+    let mask = args
+        .mask
+        .as_deref()
+        .map(|path| load_image(path).map(|mask| image::imageops::grayscale(&mask)))
+        .transpose()?;
+
+    let settings = poisson::CloneSettings {
+        mask: mask.as_ref(),
+        offset: args.offset,
+        iterations: args.iterations,
+    };
+
+    let output = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        poisson::seamless_clone(device, queue, &destination, &source, &settings).await
+    })?;
+
+    output.save(&args.output)?;
+
+    Ok(())
+}
+
+fn inpaint_holes(context: &GpuContext, args: cli::InpaintArgs) -> Result<()> {
+    let input = load_image(&args.input)?;
+    let mask = image::imageops::grayscale(&load_image(&args.mask)?);
+
+    let output = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        poisson::inpaint(device, queue, &input, &mask, args.iterations).await
+    })?;
+
+    output.save(&args.output)?;
+
+    Ok(())
+}
+
+fn segment_superpixels(context: &GpuContext, args: cli::SuperpixelArgs) -> Result<()> {
+    let input = load_image(&args.input)?;
+
+    let settings = slic::SlicSettings {
+        spacing: args.spacing,
+        compactness: args.compactness,
+        iterations: args.iterations,
+        labels: args.labels,
+    };
+
+    let output = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        slic::superpixels(device, queue, &input, &settings).await
+    })?;
+
+    output.save(&args.output)?;
+
+    Ok(())
+}
+
+fn stack_images(context: &GpuContext, args: cli::StackArgs) -> Result<()> {
+    let output = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        stack::stack(device, queue, &args.inputs, args.mode).await
+    })?;
+
+    output.save(&args.output)?;
+
+    Ok(())
+}
+
+fn merge_exposures(context: &GpuContext, args: cli::HdrMergeArgs) -> Result<()> {
+    let output = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        hdr::merge(device, queue, &args.inputs, &args.ev).await
+    })?;
+
+    image::DynamicImage::ImageRgba32F(output).save(&args.output)?;
+
+    Ok(())
+}
+
+fn stitch_panorama(context: &GpuContext, args: cli::PanoramaArgs) -> Result<()> {
+    let tiles = args
+        .tiles
+        .iter()
+        .map(|path| load_image(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let settings = panorama::PanoramaSettings {
+        yaw: args.yaw,
+        fov: args.fov,
+        projection: args.projection,
+        levels: args.levels,
+    };
+
+    let output = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        panorama::stitch(device, queue, &tiles, &settings).await
+    })?;
+
+    output.save(&args.output)?;
+
+    Ok(())
+}
+
+fn write_integral_image(context: &GpuContext, args: cli::IntegralArgs) -> Result<()> {
+    let input = load_image(&args.input)?;
+
+    let output = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        let texture_size = wgpu::Extent3d {
+            width: input.width(),
+            height: input.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture = create_input_texture(device, queue, texture_size, &input);
+
+        let buffer = integral::integral_image(context, &texture, texture_size).await?;
+        integral::read_integral_image(device, queue, &buffer, texture_size).await
+    })?;
+
+    image::DynamicImage::ImageRgba32F(output).save(&args.output)?;
+
+    Ok(())
+}
+
+fn erode_heightmap(context: &GpuContext, args: cli::ErodeArgs) -> Result<()> {
+    let heightmap = image::open(&args.input)
+        .with_context(|| format!("Failed to open {}", args.input.display()))?
+        .into_luma16();
+
+    let settings = erosion::ErosionSettings {
+        iterations: args.iterations,
+        rain: args.rain,
+        height_scale: args.height_scale,
+    };
+
+    let terrain = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        erosion::erode(device, queue, &heightmap, &settings).await
+    })?;
+
+    terrain.heightmap.save(&args.output)?;
+
+    if let Some(path) = &args.flow {
+        terrain.flow.save(path)?;
+    }
+    if let Some(path) = &args.sediment {
+        terrain.sediment.save(path)?;
+    }
+
+    Ok(())
+}
+
+/// Loads an environment map with linear colors, taking those of formats
+/// without float texels as sRGB.
+fn load_environment(path: &Path) -> Result<image::Rgba32FImage> {
+    let image = image::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let linear = matches!(
+        image,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    );
+
+    let mut environment = image.into_rgba32f();
+    if !linear {
+        for pixel in environment.pixels_mut() {
+            for channel in &mut pixel.0[..3] {
+                *channel = if *channel <= 0.04045 {
+                    *channel / 12.92
+                } else {
+                    ((*channel + 0.055) / 1.055).powf(2.4)
+                };
+            }
+        }
+    }
+
+    Ok(environment)
+}
+
+fn prefilter_environment(context: &GpuContext, args: cli::IblArgs) -> Result<()> {
+    let environment = load_environment(&args.input)?;
+
+    let settings = ibl::IblSettings {
+        size: args.size,
+        levels: args.levels,
+        irradiance_size: args.irradiance_size,
+        samples: args.samples,
+    };
+
+    let prefiltered = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        ibl::prefilter(device, queue, &environment, &settings).await
+    })?;
+
+    let format = ktx2::Format::Rgba16Float;
+    ktx2::write(
+        &args.output,
+        format,
+        (args.size, args.size),
+        6,
+        &prefiltered.specular,
+    )?;
+    let irradiance = args
+        .irradiance
+        .clone()
+        .unwrap_or_else(|| extra_output_path(&args.output, "irradiance", 0, 1));
+    ktx2::write(
+        &irradiance,
+        format,
+        (args.irradiance_size, args.irradiance_size),
+        6,
+        &[prefiltered.irradiance],
+    )?;
+
+    Ok(())
+}
+
+fn project_spherical_harmonics(context: &GpuContext, args: cli::ShArgs) -> Result<()> {
+    let environment = load_environment(&args.input)?;
+
+    let coefficients = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        let texture = ibl::upload_environment(device, queue, &environment);
+        sh::project(device, queue, &texture).await
+    })?;
+
+    let harmonics = sh::SphericalHarmonics::new(&coefficients, args.order, args.irradiance);
+    fs::write(&args.output, serde_json::to_string_pretty(&harmonics)?)
+        .with_context(|| format!("Failed to write {}", args.output.display()))?;
+
+    Ok(())
+}
+
+fn generate_brdf_lut(context: &GpuContext, args: cli::BrdfLutArgs) -> Result<()> {
+    let lut = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        ibl::integrate_brdf(device, queue, args.size, args.samples).await
+    })?;
+
+    let is_ktx2 = args
+        .output
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ktx2"));
+    if is_ktx2 {
+        return ktx2::write(
+            &args.output,
+            ktx2::Format::Rg32Float,
+            (args.size, args.size),
+            1,
+            &[bytemuck::cast_slice(&lut).to_vec()],
+        );
+    }
+
+    let texels = lut
+        .iter()
+        .flat_map(|&[scale, bias]| [scale, bias, 0.0])
+        .collect();
+    let image = image::DynamicImage::ImageRgb32F(
+        image::Rgb32FImage::from_raw(args.size, args.size, texels).unwrap(),
+    );
+
+    match image::ImageFormat::from_path(&args.output)? {
+        image::ImageFormat::OpenExr | image::ImageFormat::Hdr => image.save(&args.output)?,
+        _ => image::DynamicImage::ImageRgb16(image.into_rgb16()).save(&args.output)?,
+    }
+
+    Ok(())
+}
+
+fn generate_blue_noise(context: &GpuContext, args: cli::BlueNoiseArgs) -> Result<()> {
+    let channels = usize::from(args.channels);
+    let patterns = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        let mut patterns = Vec::with_capacity(channels);
+        for channel in 0..args.channels {
+            let seed = args.seed.wrapping_add(channel.into());
+            patterns.push(blue_noise::generate(device, queue, args.size, seed).await?);
+        }
+
+        Ok(patterns)
+    })?;
+
+    // Spreads the ranks evenly over the 256 levels.
+    let texel_count = u64::from(args.size * args.size);
+    let texels = (0..patterns[0].len())
+        .flat_map(|index| patterns.iter().map(move |ranks| ranks[index]))
+        .map(|rank| (u64::from(rank) * 256 / texel_count) as u8)
+        .collect();
+
+    let image = match channels {
+        1 => image::DynamicImage::ImageLuma8(
+            image::GrayImage::from_raw(args.size, args.size, texels).unwrap(),
+        ),
+        2 => image::DynamicImage::ImageLumaA8(
+            image::GrayAlphaImage::from_raw(args.size, args.size, texels).unwrap(),
+        ),
+        3 => image::DynamicImage::ImageRgb8(
+            image::RgbImage::from_raw(args.size, args.size, texels).unwrap(),
+        ),
+        _ => image::DynamicImage::ImageRgba8(
+            image::RgbaImage::from_raw(args.size, args.size, texels).unwrap(),
+        ),
+    };
+    image.save(&args.output)?;
+
+    Ok(())
+}
+
+fn unwrap_fisheye(gpu: &mut Gpu, args: cli::FisheyeArgs) -> Result<()> {
+    let mut paths = args.frames;
+    sprite::sort_numbered(&mut paths);
+
+    let (width, height) = image::image_dimensions(&paths[0])
+        .with_context(|| format!("Failed to read {}", paths[0].display()))?;
+    let (output_width, output_height) = args.size.unwrap_or((width, (width / 2).max(1)));
+
+    let lenses = fisheye::DualFisheye {
+        fov: args.fov,
+        front_offset: args.front_offset,
+        back_offset: args.back_offset,
+        radius: args.radius,
+    };
+
+    let mut writer = if paths.len() == 1 {
+        SequenceWriter::single(&args.output)
+    } else if args.gif {
+        SequenceWriter::gif(&args.output, args.fps)?
+    } else {
+        SequenceWriter::images(&args.output, paths.len() as u32)
+    };
+
+    let mut stitcher = None;
+    for (index, path) in paths.iter().enumerate() {
+        let frame = load_image(path)?;
+        let buffer = gpu.run(|context| {
+            let stitcher = made_on(&mut stitcher, context, |device| {
+                fisheye::FisheyeStitcher::new(
+                    device,
+                    (width, height),
+                    (output_width, output_height),
+                    &lenses,
+                )
+            })?;
+
+            futures::executor::block_on(stitcher.stitch(&context.device, &context.queue, &frame))
+        })?;
+        writer.write(index, output_width, output_height, buffer)?;
+    }
+
+    Ok(())
+}
+
+fn assemble_sheet(context: &GpuContext, args: cli::AssembleArgs) -> Result<()> {
+    let mut paths = args.frames;
+    sprite::sort_numbered(&mut paths);
+
+    let frames = paths
+        .iter()
+        .map(|path| load_image(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let layout = SheetLayout {
+        columns: args.columns,
+        padding: args.padding,
+        power_of_two: args.power_of_two,
+    };
+
+    let sheet = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        sprite::assemble(device, queue, &frames, &layout).await
+    })?;
+
+    sheet.save(&args.output)?;
+
+    Ok(())
+}
+
+fn make_montage(context: &GpuContext, args: cli::MontageArgs) -> Result<()> {
+    let layout = montage::MontageLayout {
+        columns: args.columns,
+        cell: args.cell,
+        padding: args.padding,
+        label: args.label,
+        label_scale: args.label_scale,
+        label_color: args.label_color,
+        background: args.background,
+    };
+
+    let (sheet, failed) = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        montage::compose(device, queue, &args.inputs, &layout).await
+    })?;
+
+    for (path, err) in &failed {
+        eprintln!("{}: {:#}", path.display(), err);
+    }
+
+    sheet.save(&args.output)?;
+
+    if !failed.is_empty() {
+        return Err(PartialFailure {
+            failed: failed.len(),
+            total: args.inputs.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn audit_textures(context: &GpuContext, args: cli::AuditArgs) -> Result<()> {
+    let report = futures::executor::block_on(async {
+        let (device, queue) = (&context.device, &context.queue);
+
+        audit::audit(device, queue, &args.inputs).await
+    })?;
+
+    for failure in &report.failed {
+        eprintln!("{}: {}", failure.path, failure.error);
+    }
+    if !quiet() {
+        report.print();
+    }
+
+    if let Some(report_path) = &args.report {
+        fs::write(report_path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("Failed to write {}", report_path.display()))?;
+    }
+
+    if !report.failed.is_empty() {
+        return Err(PartialFailure {
+            failed: report.failed.len(),
+            total: args.inputs.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+fn make_thumbnails(gpu: &mut Gpu, args: cli::ThumbnailArgs) -> Result<()> {
+    let outputs: Vec<_> = args
+        .inputs
+        .iter()
+        .map(|input| {
+            let stem = input.file_stem().unwrap_or(input.as_os_str());
+            args.output
+                .join(stem)
+                .with_extension(args.format.extension())
+        })
+        .collect();
+
+    let mut inputs_by_output = HashMap::new();
+    for (input, output) in args.inputs.iter().zip(&outputs) {
+        if let Some(other) = inputs_by_output.insert(output, input) {
+            bail!(
+                "{} and {} would both be written to {}",
+                other.display(),
+                input.display(),
+                output.display()
+            );
+        }
+    }
+
+    fs::create_dir_all(&args.output)
+        .with_context(|| format!("Failed to create {}", args.output.display()))?;
+
+    let limits = gpu.context.device.limits();
+    let limit = limits.max_texture_dimension_2d;
+    let batch = args.batch.min(limits.max_texture_array_layers) as usize;
+    let mut thumbnailer = None;
+    let mut failed = 0;
+    let mut written = 0;
+
+    for (paths, outputs) in args.inputs.chunks(batch).zip(outputs.chunks(batch)) {
+        let decoded =
+            thumbnail::parallel_map(paths, |path| thumbnail::decode(path, args.max, limit));
+
+        // Inputs that fail to decode are left out of the batch.
+        let mut images = Vec::with_capacity(paths.len());
+        let mut written_to = Vec::with_capacity(paths.len());
+        for ((path, output), image) in paths.iter().zip(outputs).zip(decoded) {
+            match image {
+                std::result::Result::Ok(image) => {
+                    images.push(image);
+                    written_to.push(output);
+                }
+                Err(err) => {
+                    eprintln!("{}: {:#}", path.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+        if images.is_empty() {
+            continue;
+        }
+
+        // A lost device shrinks the batch again, on a new one.
+        let thumbnails = gpu.run(|context| {
+            let thumbnailer = made_on(&mut thumbnailer, context, thumbnail::Thumbnailer::new)?;
+
+            futures::executor::block_on(thumbnailer.shrink(
+                &context.device,
+                &context.queue,
+                &images,
+                args.max,
+            ))
+        })?;
+        drop(images);
+
+        let work: Vec<_> = thumbnails.iter().zip(written_to).collect();
+        let encoded = thumbnail::parallel_map(&work, |(thumbnail, output)| {
+            thumbnail::encode(thumbnail, output, args.format, args.quality)
+        });
+        for ((_, output), result) in work.iter().zip(encoded) {
+            match result {
+                std::result::Result::Ok(()) => written += 1,
+                Err(err) => {
+                    eprintln!("{}: {:#}", output.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if !quiet() {
+        println!(
+            "{} thumbnails written to {}",
+            written,
+            args.output.display()
+        );
+    }
+
+    if failed > 0 {
+        return Err(PartialFailure {
+            failed,
+            total: args.inputs.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Loads the input and, for operations taking two, the `second` one resized
+/// to match it.
+fn load_inputs(op: &OpSpec<'_>, path: &Path, second: Option<&Path>) -> Result<Vec<RgbaImage>> {
+    let image = load_image(path)?;
+    let (width, height) = image.dimensions();
+
+    let mut images = vec![image];
+
+    if op.inputs > 1 {
+        let path = second.ok_or_else(|| anyhow!("Operation '{}' needs a second input", op.name))?;
+
+        let mut second = load_image(path)?;
+        if second.dimensions() != (width, height) {
+            second = image::imageops::resize(
+                &second,
+                width,
+                height,
+                image::imageops::FilterType::Triangle,
+            );
+        }
+
+        images.push(second);
+    }
+
+    Ok(images)
+}
+
+/// Contents of the parameter struct the `--shader` of `args`, or the one of
+/// `lock`, declares, with `overrides` by name, see [`ParamLayout::encode`].
+/// `None` for the built-in operations and shaders declaring none.
+fn shader_params(
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    lock: Option<&lock::Lock>,
+    overrides: &[(String, f32)],
+) -> Result<Option<Vec<u8>>> {
+    let mut shader = match (lock, &args.shader) {
+        (Some(lock), _) if lock.is_custom() => {
+            shader::preprocess_locked(op.shader, &lock.includes)?
+        }
+        (None, Some(path)) => shader::preprocess(op.shader, path.parent())?,
+        _ => return Ok(None),
+    };
+    shader.set_workgroup_size([args.workgroup_size.0, args.workgroup_size.1]);
+    shader.specialize(&args.constants)?;
+
+    let Some(layout) = ParamLayout::reflect(&shader.source)
+        .with_context(|| format!("Invalid shader for operation '{}'", op.name))?
+    else {
+        return Ok(None);
+    };
+
+    let values: Vec<_> = overrides
+        .iter()
+        .map(|(key, value)| (key.as_str(), *value))
+        .collect();
+
+    Ok(Some(layout.encode(&values)?))
+}
+
+fn process(context: &GpuContext, mut args: cli::Args) -> Result<()> {
+    let lock = args
+        .from_lock
+        .as_deref()
+        .map(lock::Lock::load)
+        .transpose()?;
+    if let Some(lock) = &lock {
+        lock.apply(&mut args)?;
+    }
+
+    let (source, entry_point);
+    let op = match (&lock, &args.shader) {
+        (Some(lock), _) => lock.op()?,
+        (None, Some(path)) => {
+            source = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            entry_point = args.entry_point.clone();
+            let inputs = 1 + args.second.is_some() as u32;
+
+            ops::custom(&source, &entry_point, inputs)
+        }
+        (None, None) => *ops::find(&args.op)?,
+    };
+    let op = &op;
+    let mut overrides = args.params.clone();
+    if let Some(clusters) = args.clusters {
+        if op.name != "segment" {
+            bail!("--clusters only applies to the 'segment' operation");
+        }
+        overrides.push(("clusters".to_string(), clusters as f32));
+    }
+
+    // A `--shader` declaring a parameter struct takes the overrides there.
+    let shader_params = shader_params(&args, op, lock.as_ref(), &overrides)?;
+    let params = match shader_params {
+        Some(_) => op.resolve_params(&[])?,
+        None => op.resolve_params(&overrides)?,
+    };
+
+    if let Some(path) = &args.save_preset {
+        Preset::from_args(&args, &overrides).save(path)?;
+        status!("Saved the preset to {}", path.display());
+    }
+
+    let frame_params = animation::frame_params(op, params, &args.animate, args.frames)?;
+
+    let output_path = args.output.as_deref().context("No --output to write to")?;
+    let output_path = output::resolve_path(output_path, args.format)?;
+    let output_path = output_path.as_path();
+
+    if args.deepzoom && frame_params.len() > 1 {
+        bail!("--deepzoom writes a single frame");
+    }
+    if args.skip_unchanged.is_some() && frame_params.len() < 2 {
+        bail!("--skip-unchanged needs a sequence of more than one frame");
+    }
+
+    // The manifest of a gated sequence stands for the frames, of which only
+    // some are written.
+    let targets = if args.deepzoom {
+        vec![deepzoom::descriptor_path(output_path)]
+    } else if args.skip_unchanged.is_some() {
+        vec![dedup::manifest_path(output_path)]
+    } else {
+        SequenceWriter::paths(output_path, frame_params.len() as u32, args.gif)
+    };
+    if matches!(args.if_exists, IfExists::Outdated)
+        && is_up_to_date(
+            &args,
+            op,
+            lock.as_ref(),
+            &frame_params,
+            output_path,
+            &targets,
+        )?
+    {
+        status!("Skipping, nothing the outputs were made from has changed");
+        return Ok(());
+    }
+    if !output::should_write(&targets, args.if_exists)? {
+        status!("Skipping, every output exists already");
+        return Ok(());
+    }
+
+    if let Some(input_path) = &args.input {
+        let max_dimension = context.device.limits().max_texture_dimension_2d;
+
+        if let Some(source) = tiled::TiledImage::open(input_path, max_dimension)? {
+            return process_tiled(
+                context,
+                &args,
+                op,
+                lock.as_ref(),
+                &frame_params[0],
+                output_path,
+                source,
+            );
+        }
+    }
+
+    // Nothing reads the texels of a single input on the CPU unless it is cut
+    // into cells, retargeted, clustered, compared or checked, or in place
+    // uploaded again for every frame.
+    let decode_to_texture = op.inputs == 1
+        && args.grid.is_none()
+        && args.resize_content_aware.is_none()
+        && args.ascii_text.is_none()
+        && args.compare_output.is_none()
+        && !args.verify
+        && !args.in_place
+        && !matches!(op.lookup, Some(Lookup::Clusters));
+
+    let mut input_texture = None;
+    let (input, images) = match (&args.pack, &args.input) {
+        (Some(pack), _) if op.name == "pack" => (pack.describe(), pack.load()?),
+        (Some(_), _) => bail!("--pack only applies to the 'pack' operation"),
+        (None, _) if op.name == "pack" => {
+            bail!("Operation 'pack' needs a --pack channel mapping")
+        }
+        (None, Some(input_path)) if decode_to_texture => {
+            input_texture = Some(upload_file(&context.device, &context.queue, input_path)?);
+            (input_path.display().to_string(), Vec::new())
+        }
+        (None, input_path) => {
+            let input_path = input_path.as_deref().context("No input image given")?;
+            (
+                input_path.display().to_string(),
+                load_inputs(op, input_path, args.second.as_deref())?,
+            )
+        }
+    };
+
+    let images = match args.resize_content_aware {
+        Some(_) if images.len() > 1 => {
+            bail!("--resize-content-aware needs an operation with a single input")
+        }
+        Some((width, height)) => futures::executor::block_on(async {
+            let (device, queue) = (&context.device, &context.queue);
+
+            let retargeted = seam::retarget(device, queue, &images[0], width, height).await?;
+            Ok(vec![retargeted])
+        })?,
+        None => images,
+    };
+
+    let (width, height) = match &input_texture {
+        Some(texture) => (texture.width(), texture.height()),
+        None => images[0].dimensions(),
+    };
+
+    let grid = args.grid.unwrap_or(Grid::SINGLE);
+    let (cell_width, cell_height) = grid.cell_size(width, height)?;
+
+    // Processing each cell on its own keeps neighbourhood effects from
+    // sampling across cell borders.
+    let sliced = images
+        .iter()
+        .map(|image| grid.slice(image))
+        .collect::<Result<Vec<_>>>()?;
+
+    let cells: Vec<Vec<&[u8]>> = (0..grid.cells() as usize)
+        .map(|cell| {
+            sliced
+                .iter()
+                .map(|cells| cells[cell].as_raw().as_slice())
+                .collect()
+        })
+        .collect();
+
+    if let Some(text_path) = &args.ascii_text {
+        if op.name != "ascii" {
+            bail!("--ascii-text only applies to the 'ascii' operation");
+        }
+        if cells.len() > 1 || frame_params.len() > 1 {
+            bail!("--ascii-text needs a single image and frame");
+        }
+
+        let cell = frame_params[0][op.param_index("cell").unwrap_or_default()];
+        ascii::save_text(&images[0], cell as u32, text_path)?;
+    }
+
+    if (op.lookup.is_some() || !op.outputs.is_empty()) && cells.len() > 1 {
+        bail!("Operation '{}' can't be used with a sprite sheet", op.name);
+    }
+
+    let (output_width, output_height) = match args.size {
+        Some(size) if op.resizable => size,
+        Some(_) => bail!("Operation '{}' doesn't support --size", op.name),
+        None => (cell_width, cell_height),
+    };
+
+    let frames: Vec<_> = frame_params
+        .iter()
+        .enumerate()
+        .map(|(frame, params)| {
+            Globals::new(output_width, output_height, args.seed, frame as u32, params)
+        })
+        .collect();
+
+    let mut writer = if frames.len() == 1 {
+        SequenceWriter::single(output_path)
+    } else if args.gif {
+        SequenceWriter::gif(output_path, args.fps)?
+    } else {
+        SequenceWriter::images(output_path, args.frames)
+    };
+
+    // What is read back of every cell, all of it unless a region is given.
+    let (cell_output_width, cell_output_height) = args
+        .out_region
+        .map_or((output_width, output_height), |region| {
+            (region.width, region.height)
+        });
+
+    let sheet_width = cell_output_width * grid.columns;
+    let sheet_height = cell_output_height * grid.rows;
+
+    let mut sheet = RgbaImage::new(sheet_width, sheet_height);
+    let mut trimmed = Vec::new();
+    // The finished image, kept to cut into a pyramid with --deepzoom.
+    let mut pyramid_image = None;
+
+    let mipmaps = if args.mipmaps || args.preserve_alpha_coverage.is_some() || args.normal_map {
+        if cells.len() > 1 {
+            bail!("Mipmaps can't be generated for a sprite sheet");
+        }
+        if args.trim_alpha {
+            bail!("Mipmaps can't be generated for a trimmed output");
+        }
+        if args.strict_math {
+            bail!("Mipmaps are filtered without --strict-math, so they can't be generated with it");
+        }
+        if let Some(cutoff) = args.preserve_alpha_coverage {
+            if !(0.0..1.0).contains(&cutoff) {
+                bail!("Alpha coverage cutoff must be within 0..1, got {}", cutoff);
+            }
+        }
+        if args.normal_map && matches!(args.mip_space, FilterSpace::Linear) {
+            bail!("Normal maps are filtered as stored, --mip-space linear doesn't apply");
+        }
+        if args.toksvig && args.preserve_alpha_coverage.is_some() {
+            bail!("--toksvig stores roughness in alpha, which can't also be coverage corrected");
+        }
+
+        Some(MipmapSettings {
+            filter: args.mip_filter,
+            space: args.mip_space,
+            alpha_coverage: args.preserve_alpha_coverage,
+            normal_map: args.normal_map,
+            toksvig: args.toksvig,
+        })
+    } else {
+        None
+    };
+
+    if args.verify_srgb && cells.len() > 1 {
+        bail!("--verify-srgb can't be used with a sprite sheet");
+    }
+
+    if args.preview_3d.is_some() && cells.len() > 1 {
+        bail!("--preview-3d can't be used with a sprite sheet");
+    }
+
+    if args.compare_output.is_some() && cells.len() > 1 {
+        bail!("--compare-output can't be used with a sprite sheet");
+    }
+
+    if args.skip_unchanged.is_some()
+        && (cells.len() > 1
+            || args.export_cells
+            || args.trim_alpha
+            || mipmaps.is_some()
+            || args.verify
+            || args.verify_srgb
+            || args.preview_3d.is_some()
+            || args.compare_output.is_some()
+            || !op.outputs.is_empty())
+    {
+        bail!("--skip-unchanged leaves out whole frames, which sprite sheets, trimming, mipmaps, checks, previews and further outputs don't allow");
+    }
+
+    if args.out_region.is_some() {
+        if cells.len() > 1 {
+            bail!("--out-region can't be used with a sprite sheet");
+        }
+        if args.trim_alpha || mipmaps.is_some() {
+            bail!("--out-region can't be combined with trimming or mipmaps");
+        }
+    }
+
+    if args.verify {
+        if !reference::OPERATIONS.contains(&op.name) {
+            bail!(
+                "Operation '{}' has no CPU reference, --verify supports {}",
+                op.name,
+                reference::OPERATIONS.join(", ")
+            );
+        }
+        if args.out_region.is_some() || args.iterations.is_some() {
+            bail!("--verify checks a single full pass, without --out-region or --iterations");
+        }
+    }
+
+    let options = RunOptions {
+        gradient: load_gradient(&args)?,
+        trim_threshold: args.trim_alpha.then_some(args.trim_threshold),
+        mipmaps,
+        verify_srgb: args.verify_srgb.then_some(args.mip_filter),
+        region: args.out_region,
+        iterations: args.iterations,
+        shader_dir: args
+            .shader
+            .as_deref()
+            .map(|path| path.parent().map(Path::to_path_buf).unwrap_or_default()),
+        locked_includes: lock.as_ref().map(|lock| lock.includes.clone()),
+        shader_params,
+        constants: args.constants.clone(),
+        workgroup_size: [args.workgroup_size.0, args.workgroup_size.1],
+        strict_math: args.strict_math,
+        gpu_timings: args.gpu_timings,
+        pipeline_stats: args.pipeline_stats,
+        memory: memory::MemoryTracker::new(args.memory_budget),
+        skip_unchanged: args.skip_unchanged,
+        in_place: in_place_format(context, &args)?,
+        input_texture,
+    };
+    emit_lock(&args, op, &params, &options)?;
+
+    let mut srgb_check = Vec::new();
+    let mut verification = Vec::new();
+    let mut gpu_timings = Vec::new();
+    let mut invocations = Vec::new();
+    let mut previewed = Vec::new();
+    // The frame every frame repeats with --skip-unchanged, if any.
+    let mut repeats = Vec::new();
+
+    // The input as it lines up with the output, for --compare-output.
+    let compared = args.compare_output.map(|layout| {
+        let original =
+            compare::fit_original(&images[0], (output_width, output_height), args.out_region);
+        (layout, original)
+    });
+
+    let clusters = futures::executor::block_on(manipulate_buffer(
+        context,
+        cell_width,
+        cell_height,
+        &cells,
+        op,
+        &frames,
+        &options,
+        |output| {
+            // Don't write what a rejected pass left in the output.
+            context.check()?;
+
+            if args.skip_unchanged.is_some() {
+                repeats.push(output.repeats);
+            }
+            if let Some(frame) = output.repeats {
+                status!(
+                    "frame {}: unchanged from frame {}, skipped",
+                    output.frame,
+                    frame
+                );
+                return Ok(());
+            }
+
+            for (level, image) in output.mip_levels.iter().enumerate() {
+                image.save(mipmap::level_path(
+                    output_path,
+                    "mip",
+                    output.frame,
+                    frames.len(),
+                    level + 1,
+                ))?;
+            }
+
+            for (level, check) in (1..).zip(&output.srgb_checks) {
+                let stats = check.difference.stats;
+                let (width, height) = check.gamma.dimensions();
+
+                status!(
+                    "mip {} ({}x{}): max difference {}, mean {:.2}, {:.1}% of texels differ",
+                    level,
+                    width,
+                    height,
+                    stats.max,
+                    stats.mean,
+                    stats.differing * 100.0
+                );
+
+                check.comparison().save(mipmap::level_path(
+                    output_path,
+                    "srgb_check",
+                    output.frame,
+                    frames.len(),
+                    level,
+                ))?;
+
+                srgb_check.push(SrgbCheckEntry {
+                    frame: output.frame,
+                    level,
+                    width,
+                    height,
+                    stats,
+                });
+            }
+
+            if args.verify {
+                let expected = reference::render(
+                    op,
+                    cell_width,
+                    cell_height,
+                    &cells[output.cell],
+                    &frame_params[output.frame],
+                    options.gradient.as_ref(),
+                )?;
+                let stats = diff::DiffStats::between(&output.buffer, &expected);
+
+                status!(
+                    "frame {}, cell {}: max deviation from the CPU {}, mean {:.3}, {:.1}% of texels differ",
+                    output.frame,
+                    output.cell,
+                    stats.max,
+                    stats.mean,
+                    stats.differing * 100.0
+                );
+
+                verification.push(VerifyEntry {
+                    frame: output.frame,
+                    cell: output.cell,
+                    stats,
+                });
+            }
+
+            if let Some(timings) = output.gpu_timings {
+                status!(
+                    "frame {}, cell {}: compute {:.3} ms, copy {:.3} ms on the GPU",
+                    output.frame,
+                    output.cell,
+                    timings.compute,
+                    timings.copy
+                );
+
+                gpu_timings.push(TimingEntry {
+                    frame: output.frame,
+                    cell: output.cell,
+                    compute_ms: timings.compute,
+                    copy_ms: timings.copy,
+                });
+            }
+
+            if let Some((count, texels)) = output.invocations {
+                // One invocation per texel and pass covers the image exactly.
+                status!(
+                    "frame {}, cell {}: {} compute shader invocations for {} texels, {:.2} per texel",
+                    output.frame,
+                    output.cell,
+                    count,
+                    texels,
+                    count as f64 / texels.max(1) as f64
+                );
+
+                invocations.push(InvocationEntry {
+                    frame: output.frame,
+                    cell: output.cell,
+                    invocations: count,
+                    texels,
+                });
+            }
+
+            for (name, buffer) in op.outputs.iter().zip(&output.extra_outputs) {
+                image::save_buffer(
+                    extra_output_path(output_path, name, output.frame, frames.len()),
+                    buffer,
+                    cell_output_width,
+                    cell_output_height,
+                    image::ColorType::Rgba8,
+                )?;
+            }
+
+            let cell_image =
+                RgbaImage::from_raw(cell_output_width, cell_output_height, output.buffer)
+                    .ok_or_else(|| anyhow!("Output buffer does not match the cell size"))?;
+
+            if args.preview_3d.is_some() {
+                previewed.push((output.frame, cell_image.clone()));
+            }
+
+            if let Some((layout, original)) = &compared {
+                compare::compose(original, &cell_image, *layout).save(extra_output_path(
+                    output_path,
+                    "compare",
+                    output.frame,
+                    frames.len(),
+                ))?;
+            }
+
+            let trimmed_cell = output.alpha_bounds.map(|bounds| {
+                trimmed.push(TrimEntry {
+                    frame: output.frame,
+                    cell: output.cell,
+                    source_width: output_width,
+                    source_height: output_height,
+                    bounds,
+                });
+
+                image::imageops::crop_imm(
+                    &cell_image,
+                    bounds.x,
+                    bounds.y,
+                    bounds.width,
+                    bounds.height,
+                )
+                .to_image()
+            });
+
+            if args.export_cells {
+                let cell_path =
+                    sprite::cell_path(output_path, output.frame, frames.len(), output.cell);
+                trimmed_cell
+                    .as_ref()
+                    .unwrap_or(&cell_image)
+                    .save(cell_path)?;
+            }
+
+            // A sprite sheet keeps its grid; only a single image is cropped.
+            if let (Some(trimmed_cell), 1) = (trimmed_cell, cells.len()) {
+                if args.deepzoom {
+                    pyramid_image = Some(trimmed_cell);
+                    return Ok(());
+                }
+
+                return writer.write(
+                    output.frame,
+                    trimmed_cell.width(),
+                    trimmed_cell.height(),
+                    trimmed_cell.into_raw(),
+                );
+            }
+
+            let (x, y) = grid.cell_origin(output.cell as u32, output_width, output_height);
+            image::imageops::replace(&mut sheet, &cell_image, x as i64, y as i64);
+
+            if output.cell + 1 == cells.len() {
+                if args.deepzoom {
+                    pyramid_image = Some(std::mem::take(&mut sheet));
+                } else {
+                    writer.write(
+                        output.frame,
+                        sheet_width,
+                        sheet_height,
+                        sheet.as_raw().clone(),
+                    )?;
+                }
+            }
+
+            Ok(())
+        },
+    ))?;
+
+    if let Some(threshold) = args.skip_unchanged {
+        let files = SequenceWriter::paths(output_path, frames.len() as u32, false);
+        let manifest = dedup::Manifest::new(threshold, &files, &repeats);
+        let manifest_path = dedup::manifest_path(output_path);
+        manifest.save(&manifest_path)?;
+
+        status!(
+            "Wrote {} of {} frames, {} names the file of every one",
+            manifest.written,
+            frames.len(),
+            manifest_path.display()
+        );
+    }
+
+    if let Some(image) = &pyramid_image {
+        let pyramid = futures::executor::block_on(deepzoom::write(
+            &context.device,
+            &context.queue,
+            image,
+            output_path,
+            &deepzoom_settings(&args),
+        ))?;
+
+        status!(
+            "Wrote a DeepZoom pyramid of {} levels and {} tiles to {}",
+            pyramid.levels,
+            pyramid.tiles,
+            deepzoom::descriptor_path(output_path).display()
+        );
+    }
+
+    if let Some(mesh) = &args.preview_3d {
+        let geometry = mesh.load()?;
+
+        futures::executor::block_on(async {
+            let (device, queue) = (&context.device, &context.queue);
+
+            for (frame, image) in &previewed {
+                preview::render(device, queue, image, &geometry, args.preview_size)
+                    .await?
+                    .save(extra_output_path(
+                        output_path,
+                        "preview",
+                        *frame,
+                        frames.len(),
+                    ))?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    if let Some(report_path) = &args.report {
+        Report {
+            input,
+            output: output_path.display().to_string(),
+            operation: op.name.to_string(),
+            width: sheet_width,
+            height: sheet_height,
+            frames: frames.len(),
+            trimmed,
+            srgb_check,
+            clusters,
+            verification,
+            gpu_timings,
+            invocations,
+            peak_gpu_memory: options.memory.peak(),
+            memory_budget: args.memory_budget,
+        }
+        .save(report_path)?;
+    }
+
+    if args.sidecar {
+        write_sidecars(
+            context,
+            &args,
+            op,
+            &params,
+            &options,
+            &targets,
+            frames.len(),
+        )?;
+    }
+    if matches!(args.if_exists, IfExists::Outdated) {
+        record_dependencies(&args, op, lock.as_ref(), &frame_params, output_path)?;
+    }
+
+    Ok(())
+}
+
+/// The parameters of `op` by name with the values of `params`. Those of a
+/// `--shader` are only known by the names given.
+fn param_values(
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    params: &[f32; ops::MAX_PARAMS],
+) -> BTreeMap<String, f32> {
+    match op.name {
+        ops::CUSTOM => args.params.iter().cloned().collect(),
+        _ => op
+            .params
+            .iter()
+            .zip(params)
+            .map(|(spec, &value)| (spec.name.to_string(), value))
+            .collect(),
+    }
+}
+
+/// Writes the `--emit-lock` of a run of `op` with `params`, its shader
+/// resolved as `options` have it.
+fn emit_lock(
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    params: &[f32; ops::MAX_PARAMS],
+    options: &RunOptions,
+) -> Result<()> {
+    let Some(path) = &args.emit_lock else {
+        return Ok(());
+    };
+
+    lock::Lock {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        op: op.name.to_string(),
+        entry_point: op.entry_point.to_string(),
+        inputs: op.inputs,
+        shader: op.shader.to_string(),
+        includes: prepare_shader(op, options)?.includes,
+        params: param_values(args, op, params),
+        animate: args.animate.iter().map(ToString::to_string).collect(),
+        constants: options.constants.iter().cloned().collect(),
+        iterations: options.iterations,
+        seed: args.seed,
+        workgroup_size: options.workgroup_size,
+        strict_math: options.strict_math,
+    }
+    .save(path)?;
+    status!("Saved the lock to {}", path.display());
+
+    Ok(())
+}
+
+/// The files a run of `args` reads, by what they are read as.
+fn input_files(args: &cli::Args) -> Vec<(String, &Path)> {
+    let mut files = Vec::new();
+    if let Some(path) = &args.input {
+        files.push(("input".to_string(), path.as_path()));
+    }
+    if let Some(pack) = &args.pack {
+        for (channel, path) in pack.sources() {
+            files.push((format!("pack-{}", channel), path));
+        }
+    }
+    if let Some(path) = &args.second {
+        files.push(("second".to_string(), path.as_path()));
+    }
+    if let Some(path) = &args.gradient_image {
+        files.push(("gradient".to_string(), path.as_path()));
+    }
+
+    files
+}
+
+/// Hash of the settings of a run of `op` with the parameters of `frames`,
+/// for its dependency record: the options of `args` that change what is
+/// written, and what presets, locks, defaults and includes resolve them to.
+/// Options that only verify, time or report the run are left out, and so is
+/// the order they were given in.
+fn run_settings(
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    lock: Option<&lock::Lock>,
+    frames: &[[f32; ops::MAX_PARAMS]],
+) -> Result<String> {
+    let shader = match lock {
+        Some(lock) => shader::preprocess_locked(op.shader, &lock.includes)?,
+        None => shader::preprocess(op.shader, args.shader.as_deref().and_then(Path::parent))?,
+    };
+
+    let parts = vec![
+        env!("CARGO_PKG_VERSION").to_string(),
+        op.name.to_string(),
+        op.entry_point.to_string(),
+        shader.source,
+        format!("{:?}", frames),
+        format!("{:?}", args.params),
+        format!("{:?}", args.constants),
+        format!("{:?} {}", args.iterations, args.seed),
+        format!("{:?} {:?}", args.format, args.pack),
+        format!("{:?} {:?}", args.gradient, args.ascii_text),
+        format!("{:?} {:?}", args.size, args.resize_content_aware),
+        format!("{:?} {:?}", args.clusters, args.out_region),
+        format!("{:?} {} {}", args.grid, args.frames, args.export_cells),
+        format!("{} {}", args.trim_alpha, args.trim_threshold),
+        format!(
+            "{} {:?} {} {} {:?} {:?}",
+            args.mipmaps,
+            args.preserve_alpha_coverage,
+            args.normal_map,
+            args.toksvig,
+            args.mip_filter,
+            args.mip_space
+        ),
+        format!(
+            "{:?} {} {}",
+            args.workgroup_size, args.strict_math, args.in_place
+        ),
+        format!(
+            "{:?} {:?} {} {} {:?}",
+            args.preview_size, args.compare_output, args.gif, args.fps, args.skip_unchanged
+        ),
+        format!(
+            "{} {} {} {}",
+            args.deepzoom, args.tile_size, args.tile_overlap, args.tile_halo
+        ),
+        args.sidecar.to_string(),
+    ];
+
+    Ok(deps::settings_hash(&parts))
+}
+
+/// Whether every one of `targets` is there and was made, according to the
+/// dependency record of `output_path`, by the same run from the same files.
+fn is_up_to_date(
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    lock: Option<&lock::Lock>,
+    frames: &[[f32; ops::MAX_PARAMS]],
+    output_path: &Path,
+    targets: &[PathBuf],
+) -> Result<bool> {
+    if !targets.iter().all(|path| path.exists()) {
+        return Ok(false);
+    }
+    let Some(record) = deps::Record::load(output_path) else {
+        return Ok(false);
+    };
+
+    let files: Vec<_> = input_files(args)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+
+    Ok(record.is_current(&run_settings(args, op, lock, frames)?, &files))
+}
+
+/// Writes the dependency record of `output_path` for `--if-exists outdated`.
+fn record_dependencies(
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    lock: Option<&lock::Lock>,
+    frames: &[[f32; ops::MAX_PARAMS]],
+    output_path: &Path,
+) -> Result<()> {
+    let files: Vec<_> = input_files(args)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+
+    deps::Record::new(run_settings(args, op, lock, frames)?, &files)?.save(output_path)
+}
+
+/// Writes the `--sidecar` of every one of `targets`, the outputs of `frames`
+/// frames of `op` with `params` before any animation.
+fn write_sidecars(
+    context: &GpuContext,
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    params: &[f32; ops::MAX_PARAMS],
+    options: &RunOptions,
+    targets: &[PathBuf],
+    frames: usize,
+) -> Result<()> {
+    let inputs = input_files(args)
+        .into_iter()
+        .map(|(role, path)| sidecar::Input::new(role, path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let operation = |name: &str, shader: &str, params: BTreeMap<String, f32>| sidecar::Operation {
+        name: name.to_string(),
+        shader_hash: sidecar::hash(shader.as_bytes()),
+        entry_point: None,
+        params,
+        animate: Vec::new(),
+        constants: BTreeMap::new(),
+        iterations: None,
+    };
+
+    let mut operations = Vec::new();
+    if let Some((width, height)) = args.resize_content_aware {
+        operations.push(operation(
+            "resize-content-aware",
+            &shader::preprocess(seam::SHADER, None)?.source,
+            BTreeMap::from([
+                ("width".to_string(), width as f32),
+                ("height".to_string(), height as f32),
+            ]),
+        ));
+    }
+
+    operations.push(sidecar::Operation {
+        entry_point: Some(op.entry_point.to_string()),
+        animate: args.animate.iter().map(ToString::to_string).collect(),
+        constants: options.constants.iter().cloned().collect(),
+        iterations: options.iterations,
+        ..operation(
+            op.name,
+            &prepare_shader(op, options)?.source,
+            param_values(args, op, params),
+        )
+    });
+
+    if let Some(threshold) = options.trim_threshold {
+        operations.push(operation(
+            "trim-alpha",
+            trim::SHADER,
+            BTreeMap::from([("threshold".to_string(), threshold)]),
+        ));
+    }
+
+    let mut sidecar = sidecar::Sidecar {
+        version: env!("CARGO_PKG_VERSION"),
+        output: String::new(),
+        frame: None,
+        frames,
+        inputs,
+        operations,
+        adapter: sidecar::Adapter::new(&context.adapter.get_info()),
+    };
+
+    for (index, target) in targets.iter().enumerate() {
+        sidecar.output = target.display().to_string();
+        sidecar.frame = (targets.len() > 1).then_some(index);
+        sidecar.save(target)?;
+    }
+
+    Ok(())
+}
+
+fn load_gradient(args: &cli::Args) -> Result<Option<Gradient>> {
+    Ok(match (&args.gradient, &args.gradient_image) {
+        (Some(gradient), _) => Some(gradient.clone()),
+        (None, Some(path)) => Some(Gradient::from_image(&load_image(path)?)?),
+        (None, None) => None,
+    })
+}
+
+fn deepzoom_settings(args: &cli::Args) -> deepzoom::DeepZoom {
+    deepzoom::DeepZoom {
+        tile_size: args.tile_size,
+        overlap: args.tile_overlap,
+        mipmaps: MipmapSettings {
+            filter: args.mip_filter,
+            space: args.mip_space,
+            alpha_coverage: None,
+            normal_map: false,
+            toksvig: false,
+        },
+    }
+}
+
+/// Runs `op` over `source` a tile of the output pyramid at a time, each with
+/// `--tile-halo` texels of its neighbours around it, so that only a row of
+/// tiles is ever in memory.
+fn process_tiled(
+    context: &GpuContext,
+    args: &cli::Args,
+    op: &OpSpec<'_>,
+    lock: Option<&lock::Lock>,
+    params: &[f32; ops::MAX_PARAMS],
+    output_path: &Path,
+    mut source: tiled::TiledImage,
+) -> Result<()> {
+    if !args.deepzoom {
+        bail!("A tiled input is processed into a DeepZoom pyramid, pass --deepzoom");
+    }
+    // A gradient maps every texel alike, other lookup tables are built from
+    // the whole image.
+    let whole_image_lookup = matches!(op.lookup, Some(Lookup::Histograms(_) | Lookup::Clusters));
+    if op.inputs > 1 || whole_image_lookup || !op.outputs.is_empty() {
+        bail!("Operation '{}' can't be used with a tiled input", op.name);
+    }
+    if args.grid.is_some()
+        || args.size.is_some()
+        || args.out_region.is_some()
+        || args.resize_content_aware.is_some()
+    {
+        bail!("A tiled input is processed at its own size, without --grid, --size, --out-region or --resize-content-aware");
+    }
+    if args.trim_alpha
+        || args.mipmaps
+        || args.preserve_alpha_coverage.is_some()
+        || args.normal_map
+        || args.verify
+        || args.verify_srgb
+        || args.preview_3d.is_some()
+        || args.compare_output.is_some()
+        || args.ascii_text.is_some()
+        || args.gpu_timings
+        || args.pipeline_stats
+    {
+        bail!("Trimming, mipmaps, checks, previews and GPU statistics need the whole image, which a tiled input is never read as");
+    }
+
+    let (width, height) = source.dimensions();
+    let settings = deepzoom_settings(args);
+    let mut writer = deepzoom::PyramidWriter::new(output_path, width, height, &settings)?;
+    let (columns, rows) = writer.grid();
+
+    // Every tile is processed within a region of the same size, so that the
+    // tiles of a row share one set of textures.
+    let margin = args.tile_overlap + args.tile_halo;
+    let span = args.tile_size + 2 * margin;
+    let frames = [Globals::new(span, span, args.seed, 0, params)];
+
+    let options = RunOptions {
+        gradient: load_gradient(args)?,
+        trim_threshold: None,
+        mipmaps: None,
+        verify_srgb: None,
+        region: None,
+        iterations: args.iterations,
+        shader_dir: args
+            .shader
+            .as_deref()
+            .map(|path| path.parent().map(Path::to_path_buf).unwrap_or_default()),
+        locked_includes: lock.map(|lock| lock.includes.clone()),
+        shader_params: shader_params(args, op, lock, &args.params)?,
+        constants: args.constants.clone(),
+        workgroup_size: [args.workgroup_size.0, args.workgroup_size.1],
+        strict_math: args.strict_math,
+        gpu_timings: false,
+        pipeline_stats: false,
+        memory: memory::MemoryTracker::new(args.memory_budget),
+        skip_unchanged: None,
+        in_place: in_place_format(context, args)?,
+        input_texture: None,
+    };
+    emit_lock(args, op, params, &options)?;
+
+    let origin = |index: u32| index as i64 * args.tile_size as i64 - margin as i64;
+
+    for row in 0..rows {
+        let regions = (0..columns)
+            .map(|column| source.read(origin(column), origin(row), span, span))
+            .collect::<Result<Vec<_>>>()?;
+        source.evict_above(origin(row + 1));
+
+        let cells: Vec<Vec<&[u8]>> = regions
+            .iter()
+            .map(|region| vec![region.as_raw().as_slice()])
+            .collect();
+
+        futures::executor::block_on(manipulate_buffer(
+            context,
+            span,
+            span,
+            &cells,
+            op,
+            &frames,
+            &options,
+            |output| {
+                context.check()?;
+
+                let column = output.cell as u32;
+                let bounds = writer.tile_bounds(column, row);
+                let region = RgbaImage::from_raw(span, span, output.buffer)
+                    .ok_or_else(|| anyhow!("Output buffer does not match the tile size"))?;
+
+                let tile = image::imageops::crop_imm(
+                    &region,
+                    (bounds.x as i64 - origin(column)) as u32,
+                    (bounds.y as i64 - origin(row)) as u32,
+                    bounds.width,
+                    bounds.height,
+                )
+                .to_image();
+
+                writer.write_tile(column, row, &tile)
+            },
+        ))?;
+    }
+
+    let pyramid = writer.finish()?;
+
+    status!(
+        "Wrote a DeepZoom pyramid of {} levels and {} tiles to {}",
+        pyramid.levels,
+        pyramid.tiles,
+        deepzoom::descriptor_path(output_path).display()
+    );
+
+    if let Some(report_path) = &args.report {
+        Report {
+            input: args
+                .input
+                .as_deref()
+                .unwrap_or(Path::new(""))
+                .display()
+                .to_string(),
+            output: output_path.display().to_string(),
+            operation: op.name.to_string(),
+            width,
+            height,
+            frames: 1,
+            trimmed: Vec::new(),
+            srgb_check: Vec::new(),
+            clusters: Vec::new(),
+            verification: Vec::new(),
+            gpu_timings: Vec::new(),
+            invocations: Vec::new(),
+            peak_gpu_memory: options.memory.peak(),
+            memory_budget: args.memory_budget,
+        }
+        .save(report_path)?;
+    }
+
+    if args.sidecar {
+        let targets = [deepzoom::descriptor_path(output_path)];
+        write_sidecars(context, args, op, params, &options, &targets, 1)?;
+    }
+    if matches!(args.if_exists, IfExists::Outdated) {
+        record_dependencies(args, op, lock, std::slice::from_ref(params), output_path)?;
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    logger::init();
+
+    if let Err(err) = cli::Cli::parse_with_preset().and_then(run) {
+        eprintln!("Error: {:?}", err);
+        return exit::code(&err);
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A context on the default adapter for the tests that run on the GPU,
+    /// which skip themselves on machines without one.
+    pub(crate) fn context() -> Option<GpuContext> {
+        let context = futures::executor::block_on(GpuContext::new());
+        if let Err(err) = &context {
+            eprintln!("Skipping a GPU test: {}", err);
+        }
+
+        context.ok()
+    }
+
+    fn settings(args: &[&str]) -> Result<String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let args = cli::Cli::parse_run(&args, |_| unreachable!())?;
+        let op = ops::find(&args.op)?;
+
+        run_settings(&args, op, None, &[op.resolve_params(&args.params)?])
+    }
+
+    fn strict_options() -> RunOptions {
+        RunOptions {
+            gradient: None,
+            trim_threshold: None,
+            mipmaps: None,
+            verify_srgb: None,
+            region: None,
+            iterations: None,
+            shader_dir: None,
+            locked_includes: None,
+            shader_params: None,
+            constants: Vec::new(),
+            workgroup_size: [8, 8],
+            strict_math: true,
+            gpu_timings: false,
+            pipeline_stats: false,
+            memory: memory::MemoryTracker::new(None),
+            skip_unchanged: None,
+            in_place: None,
+            input_texture: None,
+        }
+    }
+
+    #[test]
+    fn compiles_strict_math_into_the_operations_covering_it() -> Result<()> {
+        let options = strict_options();
+
+        let covered = ops::strict_math_ops();
+        assert_eq!(
+            covered,
+            ["blur", "morph", "alpha-bleed", "kuwahara", "ascii"]
+        );
+        for name in covered {
+            let op = ops::find(name)?;
+            assert!(op.shader.contains("strict"), "{}", name);
+            let source = prepare_shader(op, &options)?.source;
+            assert!(
+                source.contains("const STRICT_MATH: bool = true;"),
+                "{}",
+                name
+            );
+            assert!(
+                !source.contains("const STRICT_MATH: bool = false;"),
+                "{}",
+                name
+            );
+        }
+
+        let err = prepare_shader(ops::find("copy")?, &options).unwrap_err();
+        assert!(err.to_string().contains("only blur, morph"), "{}", err);
+
+        // Shaders of their own call the helpers themselves.
+        let custom = ops::custom(include_str!("shaders/blur.wgsl"), "blur", 1);
+        assert!(prepare_shader(&custom, &options)?
+            .source
+            .contains("const STRICT_MATH: bool = true;"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn hashes_the_options_of_a_run_in_any_order() -> Result<()> {
+        let run = settings(&[
+            "in.png",
+            "-o",
+            "out.png",
+            "--op",
+            "blur",
+            "--seed",
+            "3",
+            "--mipmaps",
+        ])?;
+
+        assert_eq!(
+            settings(&[
+                "--mipmaps",
+                "--seed",
+                "3",
+                "-o",
+                "out.png",
+                "--op",
+                "blur",
+                "in.png"
+            ])?,
+            run
+        );
+        assert_eq!(
+            settings(&[
+                "in.png",
+                "-o",
+                "out.png",
+                "--op",
+                "blur",
+                "--seed",
+                "3",
+                "--mipmaps",
+                "--verify",
+                "--gpu-timings"
+            ])?,
+            run
+        );
+        assert_ne!(
+            settings(&[
+                "in.png",
+                "-o",
+                "out.png",
+                "--op",
+                "blur",
+                "--seed",
+                "4",
+                "--mipmaps"
+            ])?,
+            run
+        );
+        assert_ne!(
+            settings(&["in.png", "-o", "out.png", "--op", "blur", "--seed", "3"])?,
+            run
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,272 @@
+use wgpu::util::DeviceExt;
+
+use crate::{expand_workgroup_size, workgroup_size, Error, GpuContext, Result};
+
+/// Most workgroups dispatched along one dimension.
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// Invocations `@workgroup_size(WORKGROUP_SIZE)` declares in a kernel.
+const MAP_WORKGROUP_SIZE: u32 = 64;
+
+const MAP_PRELUDE: &str = include_str!("shaders/map_prelude.wgsl");
+
+/// Runs the WGSL kernel `shader` once for every element of `input` and
+/// returns what it wrote to `output`, which has the same length.
+///
+/// The kernel defines `@compute fn map(...)` and sees the bindings of
+/// `shaders/map_prelude.wgsl`: `input`, `output`, `params` (never empty; a
+/// single zero when none are given) and `element_index`. Enough workgroups
+/// of the size it declares are dispatched to cover the input, with
+/// `@workgroup_size(WORKGROUP_SIZE)` standing for 64 invocations along x,
+/// so the ones past its end need to return early:
+///
+/// ```wgsl
+/// @compute @workgroup_size(WORKGROUP_SIZE)
+/// fn map(@builtin(global_invocation_id) global_id: vec3<u32>) {
+///   let index = element_index(global_id);
+///   if index < arrayLength(&input) {
+///     output[index] = input[index] * params[0];
+///   }
+/// }
+/// ```
+///
+/// Pipelines are kept in the [`PipelineCache`](crate::PipelineCache) of
+/// `context`, so mapping more buffers with the same kernel compiles it once.
+pub async fn map_buffer(
+    context: &GpuContext,
+    input: &[f32],
+    shader: &str,
+    params: &[f32],
+) -> Result<Vec<f32>> {
+    if input.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (device, queue, pipelines) = (&context.device, &context.queue, &context.pipelines);
+
+    let length = u32::try_from(input.len()).map_err(|_| Error::TooManyElements(input.len()))?;
+    let size = std::mem::size_of_val(input) as u64;
+    let limit = device.limits().max_storage_buffer_binding_size as u64;
+    if size > limit {
+        return Err(Error::BufferTooLarge {
+            length,
+            size,
+            limit,
+        });
+    }
+
+    let shader = expand_workgroup_size(shader, [MAP_WORKGROUP_SIZE, 1]);
+    let (source, [width, height, _]) = map_source(&shader);
+
+    // Elements of a row of invocations, past which the dispatch wraps.
+    let columns = MAX_WORKGROUPS * width;
+    let rows = length.div_ceil(columns);
+    let workgroups = (length.min(columns).div_ceil(width), rows.div_ceil(height));
+
+    let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let entries = [
+        storage_entry(0, true),
+        storage_entry(1, false),
+        storage_entry(2, true),
+    ];
+
+    // Report mistakes in the kernel as errors rather than panicking.
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    let bind_group_layout = pipelines.bind_group_layout(device, "Map Bind Group Layout", &entries);
+    let pipeline = pipelines.compute_pipeline(device, &source, "map", &[&entries]);
+
+    if let Some(err) = device.pop_error_scope().await {
+        pipelines.forget(&source);
+        return Err(Error::InvalidKernel(err.to_string()));
+    }
+
+    let input_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Map Input Buffer"),
+        contents: bytemuck::cast_slice(input),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Map Output Buffer"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let params = if params.is_empty() { &[0.0] } else { params };
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Map Params Buffer"),
+        contents: bytemuck::cast_slice(params),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Map Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: input_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: output_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Map Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Map Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+
+    log::debug!(
+        "Mapping {} elements in {}x{} workgroups of {}x{}",
+        length,
+        workgroups.0,
+        workgroups.1,
+        width,
+        height
+    );
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes =
+        crate::read_buffer_polled(device, queue, &output_buffer, context.poller.as_ref()).await?;
+
+    context.check()?;
+
+    Ok(bytemuck::pod_collect_to_vec(&bytes))
+}
+
+/// The WGSL of the kernel `shader` after the prelude, and the workgroup size
+/// its `map` declares. A row of the dispatch holds `MAP_COLUMNS` elements,
+/// as many workgroups as can be dispatched along x.
+fn map_source(shader: &str) -> (String, [u32; 3]) {
+    let source = |columns: u32| {
+        format!(
+            "const MAP_COLUMNS: u32 = {}u;\n\n{}\n{}",
+            columns, MAP_PRELUDE, shader
+        )
+    };
+    let size = workgroup_size(&source(0), "map").map(|length| length.max(1));
+
+    (source(MAX_WORKGROUPS * size[0]), size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+
+    const SCALE: &str = "
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn map(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = element_index(global_id);
+  if index < arrayLength(&input) {
+    output[index] = input[index] * params[0];
+  }
+}
+";
+
+    fn map(context: &GpuContext, input: &[f32], shader: &str, params: &[f32]) -> Result<Vec<f32>> {
+        futures::executor::block_on(map_buffer(context, input, shader, params))
+    }
+
+    #[test]
+    fn scales_every_element() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        assert!(map(&context, &[], SCALE, &[2.0])?.is_empty());
+        assert_eq!(
+            map(&context, &[1.0, -2.0, 0.5], SCALE, &[3.0])?,
+            [3.0, -6.0, 1.5]
+        );
+        // Without parameters, `params[0]` is zero.
+        assert_eq!(map(&context, &[4.0; 5], SCALE, &[])?, [0.0; 5]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wraps_long_inputs_into_rows() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        // Past one row of workgroups of one invocation, and of 64.
+        let input: Vec<f32> = (0..65535 * 64 + 100)
+            .map(|index| (index % 1000) as f32)
+            .collect();
+        for shader in [
+            SCALE.to_string(),
+            SCALE.replace("WORKGROUP_SIZE", "1"),
+            SCALE.replace("WORKGROUP_SIZE", "8, 4"),
+        ] {
+            let output = map(&context, &input[..70_000], &shader, &[2.0])?;
+            assert!(output
+                .iter()
+                .zip(&input)
+                .all(|(out, input)| *out == input * 2.0));
+        }
+        let output = map(&context, &input, SCALE, &[0.5])?;
+        assert!(output
+            .iter()
+            .zip(&input)
+            .all(|(out, input)| *out == input * 0.5));
+        assert_eq!(output.len(), input.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sizes_rows_by_the_workgroups_declared() {
+        let (source, size) = map_source(&expand_workgroup_size(SCALE, [MAP_WORKGROUP_SIZE, 1]));
+        assert_eq!(size, [64, 1, 1]);
+        // The same for any length, so the pipeline is too.
+        assert!(source.starts_with("const MAP_COLUMNS: u32 = 4194240u;"));
+
+        let tiled = SCALE.replace("WORKGROUP_SIZE", "8, 4");
+        assert_eq!(map_source(&tiled).1, [8, 4, 1]);
+        assert!(map_source(&tiled)
+            .0
+            .starts_with("const MAP_COLUMNS: u32 = 524280u;"));
+    }
+
+    #[test]
+    fn reports_kernels_that_do_not_compile() {
+        let Some(context) = context() else {
+            return;
+        };
+
+        // Also the second time, from a cache that forgot the invalid one.
+        for _ in 0..2 {
+            let err = map(&context, &[1.0], "fn map() {", &[]).unwrap_err();
+            assert!(matches!(err, Error::InvalidKernel(_)), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! GPU memory held by the textures and buffers of an operation, checked
+//! against `--memory-budget`.
+
+use anyhow::*;
+use std::cell::Cell;
+
+const UNITS: [(&str, u64); 7] = [
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+    ("GB", 1_000_000_000),
+    ("MB", 1_000_000),
+    ("KB", 1_000),
+    ("B", 1),
+];
+
+thread_local! {
+    /// Largest peak of the trackers dropped on this thread since the last
+    /// [`take_peak`].
+    static DROPPED_PEAK: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Bytes allocated so far and at the peak of a run, within an optional
+/// budget.
+#[derive(Default)]
+pub struct MemoryTracker {
+    budget: Option<u64>,
+    current: Cell<u64>,
+    peak: Cell<u64>,
+}
+
+impl MemoryTracker {
+    pub fn new(budget: Option<u64>) -> Self {
+        Self {
+            budget,
+            current: Cell::new(0),
+            peak: Cell::new(0),
+        }
+    }
+
+    /// Bytes that fit under the budget, if there is one.
+    pub fn available(&self) -> Option<u64> {
+        self.budget
+            .map(|budget| budget.saturating_sub(self.current.get()))
+    }
+
+    /// Records `bytes` allocated for `what`, failing if they don't fit.
+    pub fn allocate(&self, what: &str, bytes: u64) -> Result<()> {
+        let current = self.current.get() + bytes;
+
+        if let Some(budget) = self.budget.filter(|&budget| current > budget) {
+            bail!(
+                "{} need {} of GPU memory, beyond the budget of {} with {} in use",
+                what,
+                format_bytes(bytes),
+                format_bytes(budget),
+                format_bytes(self.current.get())
+            );
+        }
+
+        self.current.set(current);
+        self.peak.set(self.peak.get().max(current));
+
+        Ok(())
+    }
+
+    pub fn free(&self, bytes: u64) {
+        self.current.set(self.current.get().saturating_sub(bytes));
+    }
+
+    pub fn peak(&self) -> u64 {
+        self.peak.get()
+    }
+}
+
+impl Drop for MemoryTracker {
+    fn drop(&mut self) {
+        DROPPED_PEAK.with(|peak| peak.set(peak.get().max(self.peak.get())));
+    }
+}
+
+/// The GPU memory the runs on this thread took at most since the last call,
+/// for the metrics of `serve`.
+pub fn take_peak() -> u64 {
+    DROPPED_PEAK.with(|peak| peak.replace(0))
+}
+
+/// Bytes of a texture of `size` with `texel_bytes` per texel.
+pub fn texture_bytes(size: wgpu::Extent3d, texel_bytes: u32) -> u64 {
+    size.width as u64 * size.height as u64 * size.depth_or_array_layers as u64 * texel_bytes as u64
+}
+
+/// Parses an amount such as `2GiB`, `512MB` or `1048576`.
+pub fn parse_bytes(arg: &str) -> Result<u64> {
+    let arg = arg.trim();
+    let (number, unit) = UNITS
+        .iter()
+        .find_map(|&(suffix, unit)| Some((arg.strip_suffix(suffix)?, unit)))
+        .unwrap_or((arg, 1));
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite() && *number >= 0.0)
+        .ok_or_else(|| anyhow!("Expected an amount such as 2GiB, got '{}'", arg))?;
+
+    Ok((number * unit as f64) as u64)
+}
+
+/// `bytes` in the largest binary unit below them, e.g. `1.50 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    UNITS[..3]
+        .iter()
+        .find(|&&(_, unit)| bytes >= unit)
+        .map(|&(suffix, unit)| format!("{:.2} {}", bytes as f64 / unit as f64, suffix))
+        .unwrap_or_else(|| format!("{} B", bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_formats_amounts() -> Result<()> {
+        assert_eq!(parse_bytes("2GiB")?, 2 << 30);
+        assert_eq!(parse_bytes(" 1.5 MiB")?, 3 << 19);
+        assert_eq!(parse_bytes("512MB")?, 512_000_000);
+        assert_eq!(parse_bytes("1048576")?, 1 << 20);
+        assert_eq!(parse_bytes("10B")?, 10);
+        for arg in ["", "GiB", "-1KB", "lots", "inf"] {
+            assert!(parse_bytes(arg).is_err(), "{}", arg);
+        }
+
+        assert_eq!(format_bytes(3 << 29), "1.50 GiB");
+        assert_eq!(format_bytes(1 << 10), "1.00 KiB");
+        assert_eq!(format_bytes(1023), "1023 B");
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_allocations_beyond_the_budget() -> Result<()> {
+        let tracker = MemoryTracker::new(Some(1000));
+        tracker.allocate("The input", 600)?;
+        assert_eq!(tracker.available(), Some(400));
+
+        let err = tracker.allocate("The output", 500).unwrap_err();
+        assert!(
+            err.to_string().starts_with("The output need 500 B"),
+            "{}",
+            err
+        );
+        assert_eq!(tracker.available(), Some(400));
+
+        tracker.free(600);
+        tracker.allocate("The output", 500)?;
+        assert_eq!(tracker.peak(), 600);
+
+        let unlimited = MemoryTracker::new(None);
+        unlimited.allocate("Everything", u64::MAX / 2)?;
+        assert_eq!(unlimited.available(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_largest_peak_of_dropped_trackers() -> Result<()> {
+        take_peak();
+        for bytes in [300, 9000, 40] {
+            MemoryTracker::new(None).allocate("A texture", bytes)?;
+        }
+
+        assert_eq!(take_peak(), 9000);
+        assert_eq!(take_peak(), 0);
+        assert_eq!(
+            texture_bytes(
+                wgpu::Extent3d {
+                    width: 4,
+                    height: 3,
+                    depth_or_array_layers: 2,
+                },
+                16
+            ),
+            384
+        );
+
+        Ok(())
+    }
+}
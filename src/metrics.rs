@@ -0,0 +1,229 @@
+//! Prometheus metrics of `serve`, in the text exposition format at
+//! `/metrics` of the `--metrics` address: jobs by outcome, the queue, the
+//! latency of every stage of a job and the GPU memory the jobs took.
+
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Largest request head read, which is plenty for a scrape.
+const MAX_REQUEST_BYTES: usize = 8 << 10;
+
+#[derive(Clone, Copy)]
+pub enum Outcome {
+    Finished,
+    Failed,
+    /// Turned down before queueing, such as over the quota.
+    Rejected,
+}
+
+const OUTCOMES: [&str; 3] = ["finished", "failed", "rejected"];
+
+/// Parts of a job, in the order it goes through them.
+#[derive(Clone, Copy)]
+pub enum Stage {
+    /// Reading the job and its files from the client.
+    Receive,
+    /// Waiting for the GPU.
+    Queue,
+    Run,
+    /// Sending the outputs back.
+    Send,
+}
+
+const STAGES: [&str; 4] = ["receive", "queue", "run", "send"];
+
+#[derive(Clone, Copy, Default)]
+struct Histogram {
+    /// Observations up to every bucket bound, not yet cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| value <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Default)]
+struct State {
+    jobs: [u64; OUTCOMES.len()],
+    stages: [Histogram; STAGES.len()],
+    last_job_memory: u64,
+    max_job_memory: u64,
+    pool_free_bytes: u64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    pub fn count_job(&self, outcome: Outcome) {
+        self.state.lock().unwrap().jobs[outcome as usize] += 1;
+    }
+
+    pub fn observe(&self, stage: Stage, duration: Duration) {
+        self.state.lock().unwrap().stages[stage as usize].observe(duration.as_secs_f64());
+    }
+
+    /// Records the GPU memory a job took at its peak, and the bytes of free
+    /// textures and buffers the context keeps after it.
+    pub fn record_memory(&self, job_peak: u64, pool_free_bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.last_job_memory = job_peak;
+        state.max_job_memory = state.max_job_memory.max(job_peak);
+        state.pool_free_bytes = pool_free_bytes;
+    }
+
+    /// The metrics in the text exposition format, with the jobs `queued`
+    /// and `running` now.
+    pub fn render(&self, queued: usize, running: usize) -> String {
+        let state = self.state.lock().unwrap();
+        let mut text = String::new();
+
+        header(&mut text, "wtc_jobs_total", "counter", "Jobs by outcome.");
+        for (outcome, count) in OUTCOMES.iter().zip(state.jobs) {
+            let _ = writeln!(text, "wtc_jobs_total{{outcome=\"{}\"}} {}", outcome, count);
+        }
+
+        let gauges = [
+            (
+                "wtc_jobs_queued",
+                "Jobs waiting for the GPU.",
+                queued as u64,
+            ),
+            (
+                "wtc_jobs_running",
+                "Jobs running on the GPU.",
+                running as u64,
+            ),
+            (
+                "wtc_last_job_gpu_memory_bytes",
+                "GPU memory the last job took at its peak.",
+                state.last_job_memory,
+            ),
+            (
+                "wtc_max_job_gpu_memory_bytes",
+                "GPU memory the largest job took at its peak.",
+                state.max_job_memory,
+            ),
+            (
+                "wtc_pool_free_bytes",
+                "Free textures and buffers kept for the next jobs.",
+                state.pool_free_bytes,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            header(&mut text, name, "gauge", help);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+
+        header(
+            &mut text,
+            "wtc_job_stage_seconds",
+            "histogram",
+            "Time jobs spent in every stage.",
+        );
+        for (stage, histogram) in STAGES.iter().zip(&state.stages) {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    text,
+                    "wtc_job_stage_seconds_bucket{{stage=\"{}\",le=\"{}\"}} {}",
+                    stage, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                text,
+                "wtc_job_stage_seconds_bucket{{stage=\"{}\",le=\"+Inf\"}} {}",
+                stage, histogram.count
+            );
+            let _ = writeln!(
+                text,
+                "wtc_job_stage_seconds_sum{{stage=\"{}\"}} {}",
+                stage, histogram.sum
+            );
+            let _ = writeln!(
+                text,
+                "wtc_job_stage_seconds_count{{stage=\"{}\"}} {}",
+                stage, histogram.count
+            );
+        }
+
+        text
+    }
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// Answers scrapes on `listener` with `render` until `stopped` is set and a
+/// connection wakes it up.
+pub fn serve_metrics(listener: TcpListener, stopped: &AtomicBool, render: impl Fn() -> String) {
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let result = stream.and_then(|stream| answer(stream, &render));
+        if let Err(err) = result {
+            log::warn!("Failed to answer a metrics request: {}", err);
+        }
+    }
+}
+
+fn answer(mut stream: TcpStream, render: impl Fn() -> String) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_REQUEST_BYTES {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+
+    let request_line = head.split(|&byte| byte == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|&byte| byte == b' ');
+    let (method, path) = (parts.next(), parts.next());
+
+    let (status, body) = match (method, path) {
+        (Some(b"GET"), Some(b"/metrics")) => ("200 OK", render()),
+        (Some(b"GET"), _) => ("404 Not Found", "Metrics are at /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,640 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use clap::ValueEnum;
+use image::RgbaImage;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+use wgpu::util::DeviceExt;
+
+use crate::diff::{self, Difference};
+
+const HISTOGRAM_BINS: usize = 256;
+
+/// Downsampling kernel between mip levels.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum MipFilter {
+    /// Average of the 2x2 texels below.
+    Box,
+    /// Kaiser-windowed sinc, sharper than box with little ringing.
+    Kaiser,
+    /// Lanczos-3, the sharpest, may ring around hard edges.
+    Lanczos,
+}
+
+/// Space the color channels are averaged in. Alpha is always filtered as is.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum FilterSpace {
+    /// Filter the stored sRGB values directly.
+    Gamma,
+    /// Decode sRGB before filtering, which keeps mips from darkening.
+    Linear,
+}
+
+/// How the mip chain below the base level is produced.
+#[derive(Clone, Copy)]
+pub struct MipmapSettings {
+    pub filter: MipFilter,
+    pub space: FilterSpace,
+    /// Alpha-test cutoff whose coverage (fraction of texels above it) every
+    /// level should keep, as for alpha-tested foliage.
+    pub alpha_coverage: Option<f32>,
+    /// Treat RGB as a tangent-space normal and renormalize it per level.
+    pub normal_map: bool,
+    /// With `normal_map`, widen the roughness stored in alpha by the variance
+    /// of the filtered normals (Toksvig).
+    pub toksvig: bool,
+}
+
+impl MipmapSettings {
+    /// Whether levels need a pass after filtering, which then writes into a
+    /// second texture so that its effect doesn't compound down the chain.
+    fn finalizes(&self) -> bool {
+        self.alpha_coverage.is_some() || self.normal_map
+    }
+}
+
+pub fn level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// File for mip `level` of `frame`, next to `output`: `out_mip1.png`, or
+/// `out_0003_mip1.png` within a sequence, with `kind` in place of `mip`.
+pub fn level_path(output: &Path, kind: &str, frame: usize, frames: usize, level: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string());
+
+    let name = if frames > 1 {
+        format!("{}_{:04}_{}{}.{}", stem, frame, kind, level, extension)
+    } else {
+        format!("{}_{}{}.{}", stem, kind, level, extension)
+    };
+
+    output.with_file_name(name)
+}
+
+/// One mip level filtered on the stored values and in linear space.
+pub struct SrgbCheck {
+    pub gamma: RgbaImage,
+    pub linear: RgbaImage,
+    pub difference: Difference,
+}
+
+/// Builds the mip chain of `source` both in gamma and in linear space and
+/// compares every level, showing how far gamma-space mips are off.
+pub async fn verify_srgb(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    source: &wgpu::Texture,
+    size: wgpu::Extent3d,
+    filter: MipFilter,
+) -> Result<Vec<SrgbCheck>> {
+    let generator = |space| {
+        MipGenerator::new(
+            device,
+            MipmapSettings {
+                filter,
+                space,
+                alpha_coverage: None,
+                normal_map: false,
+                toksvig: false,
+            },
+        )
+    };
+
+    let gamma = generator(FilterSpace::Gamma)?
+        .generate(device, queue, source, size)
+        .await?;
+    let linear = generator(FilterSpace::Linear)?
+        .generate(device, queue, source, size)
+        .await?;
+
+    let mut checks = Vec::with_capacity(gamma.len());
+
+    for (gamma, linear) in gamma.into_iter().zip(linear) {
+        let difference = diff::diff(device, queue, &gamma, &linear).await?;
+
+        checks.push(SrgbCheck {
+            gamma,
+            linear,
+            difference,
+        });
+    }
+
+    Ok(checks)
+}
+
+impl SrgbCheck {
+    /// The gamma level, the linear level and their difference side by side.
+    pub fn comparison(&self) -> RgbaImage {
+        let (width, height) = self.gamma.dimensions();
+        let mut image = RgbaImage::new(width * 3, height);
+
+        for (index, part) in [&self.gamma, &self.linear, &self.difference.image]
+            .into_iter()
+            .enumerate()
+        {
+            image::imageops::replace(&mut image, part, (index as u32 * width) as i64, 0);
+        }
+
+        image
+    }
+}
+
+/// Layout of `Settings` in `mipmap.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PassSettings {
+    alpha_scale: f32,
+    filter_type: u32,
+    linear: u32,
+    normal_mode: u32,
+}
+
+fn level_size(size: wgpu::Extent3d, level: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width: (size.width >> level).max(1),
+        height: (size.height >> level).max(1),
+        depth_or_array_layers: 1,
+    }
+}
+
+pub struct MipGenerator {
+    bind_group_layout: wgpu::BindGroupLayout,
+    downsample: wgpu::ComputePipeline,
+    alpha_histogram: wgpu::ComputePipeline,
+    finalize: wgpu::ComputePipeline,
+    settings: MipmapSettings,
+}
+
+impl MipGenerator {
+    pub fn new(device: &wgpu::Device, settings: MipmapSettings) -> Result<Self> {
+        let shader = crate::shader::preprocess(include_str!("shaders/mipmap.wgsl"), None)?;
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Mipmap Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mipmap Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mipmap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Mipmap Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point,
+            })
+        };
+
+        Ok(Self {
+            downsample: pipeline("downsample"),
+            alpha_histogram: pipeline("alpha_histogram"),
+            finalize: pipeline("finalize"),
+            bind_group_layout,
+            settings,
+        })
+    }
+
+    /// Builds the mip chain of an RGBA8 `source` texture (which needs
+    /// `TEXTURE_BINDING`) and returns every level below the base one.
+    pub async fn generate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::Texture,
+        size: wgpu::Extent3d,
+    ) -> Result<Vec<RgbaImage>> {
+        let levels = level_count(size.width, size.height);
+
+        // Every level is a texture of its own rather than a mip of a shared
+        // one, as the GL backend ignores the base level of a view when
+        // loading from it.
+        let create_levels = |label| -> Vec<wgpu::Texture> {
+            (1..levels)
+                .map(|level| {
+                    device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size: level_size(size, level),
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        usage: wgpu::TextureUsages::STORAGE_BINDING
+                            | wgpu::TextureUsages::TEXTURE_BINDING
+                            | wgpu::TextureUsages::COPY_SRC,
+                        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+                    })
+                })
+                .collect()
+        };
+
+        // Levels are always filtered from the raw chain, which keeps the
+        // shortened normals that Toksvig needs.
+        let raw_levels = create_levels("Mip Chain Texture");
+        let chain: Vec<_> = std::iter::once(source).chain(&raw_levels).collect();
+        let finalized = self
+            .settings
+            .finalizes()
+            .then(|| create_levels("Finalized Mip Texture"));
+
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Alpha Histogram Buffer"),
+            size: (HISTOGRAM_BINS * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let reference_coverage = match self.settings.alpha_coverage {
+            Some(cutoff) => {
+                let histogram = self
+                    .histogram(device, queue, source, size, &histogram_buffer)
+                    .await?;
+
+                Some(coverage(&histogram, cutoff))
+            }
+            None => None,
+        };
+
+        for level in 1..levels as usize {
+            let level_size = level_size(size, level as u32);
+
+            self.dispatch(
+                device,
+                queue,
+                &self.downsample,
+                &view(chain[level - 1]),
+                &view(chain[level]),
+                &histogram_buffer,
+                1.0,
+                level_size,
+            );
+
+            if let Some(finalized) = &finalized {
+                let alpha_scale = match (self.settings.alpha_coverage, reference_coverage) {
+                    (Some(cutoff), Some(reference)) => {
+                        let histogram = self
+                            .histogram(device, queue, chain[level], level_size, &histogram_buffer)
+                            .await?;
+
+                        coverage_scale(&histogram, cutoff, reference)
+                    }
+                    _ => 1.0,
+                };
+
+                self.dispatch(
+                    device,
+                    queue,
+                    &self.finalize,
+                    &view(chain[level]),
+                    &view(&finalized[level - 1]),
+                    &histogram_buffer,
+                    alpha_scale,
+                    level_size,
+                );
+            }
+        }
+
+        let output = match &finalized {
+            Some(finalized) => finalized.iter().collect(),
+            None => chain[1..].to_vec(),
+        };
+
+        let mut images = Vec::with_capacity(output.len());
+
+        for (level, texture) in (1..).zip(output) {
+            let level_size = level_size(size, level);
+            let buffer = crate::read_texture(device, queue, texture, 0, level_size).await?;
+
+            images.push(
+                RgbaImage::from_raw(level_size.width, level_size.height, buffer)
+                    .ok_or_else(|| anyhow!("Mip level {} has an unexpected size", level))?,
+            );
+        }
+
+        Ok(images)
+    }
+
+    async fn histogram(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        level_size: wgpu::Extent3d,
+        histogram_buffer: &wgpu::Buffer,
+    ) -> Result<Vec<u32>> {
+        queue.write_buffer(histogram_buffer, 0, &[0; HISTOGRAM_BINS * 4]);
+
+        // The destination binding is unused by the histogram pass but has to
+        // be bound to something other than the source.
+        let scratch = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Histogram Scratch Texture"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        });
+
+        self.dispatch(
+            device,
+            queue,
+            &self.alpha_histogram,
+            &view(texture),
+            &view(&scratch),
+            histogram_buffer,
+            1.0,
+            level_size,
+        );
+
+        let bytes = crate::read_buffer(device, queue, histogram_buffer).await?;
+
+        Ok(bytemuck::pod_collect_to_vec(&bytes))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn dispatch(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline: &wgpu::ComputePipeline,
+        source: &wgpu::TextureView,
+        destination: &wgpu::TextureView,
+        histogram_buffer: &wgpu::Buffer,
+        alpha_scale: f32,
+        size: wgpu::Extent3d,
+    ) {
+        let settings = PassSettings {
+            alpha_scale,
+            filter_type: self.settings.filter as u32,
+            linear: matches!(self.settings.space, FilterSpace::Linear) as u32,
+            normal_mode: match (self.settings.normal_map, self.settings.toksvig) {
+                (false, _) => 0,
+                (true, false) => 1,
+                (true, true) => 2,
+            },
+        };
+
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mipmap Settings Buffer"),
+            contents: bytemuck::bytes_of(&settings),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Mipmap Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(destination),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: settings_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Mipmap Pass"),
+            });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(size.width, size.height, 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+fn view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Fraction of texels whose alpha is above `cutoff`.
+fn coverage(histogram: &[u32], cutoff: f32) -> f32 {
+    let total: u32 = histogram.iter().sum();
+    let covered: u32 = histogram
+        .iter()
+        .enumerate()
+        .filter(|(bin, _)| *bin as f32 / 255.0 > cutoff)
+        .map(|(_, count)| count)
+        .sum();
+
+    covered as f32 / total.max(1) as f32
+}
+
+/// Alpha scale that brings the coverage of a level as close as possible to
+/// `reference`. Of the scales that put the same texels above `cutoff`, the one
+/// closest to 1 is used so levels that already match are left alone.
+fn coverage_scale(histogram: &[u32], cutoff: f32, reference: f32) -> f32 {
+    let total: u32 = histogram.iter().sum();
+    let target = reference * total as f32;
+
+    // Lowest bin to keep above the cutoff.
+    let mut lowest_covered = None;
+    let mut covered = 0;
+    for (bin, count) in histogram.iter().enumerate().rev() {
+        if *count == 0 {
+            continue;
+        }
+
+        let with_bin = covered + count;
+        if with_bin as f32 >= target {
+            if lowest_covered.is_none() || with_bin as f32 - target < target - covered as f32 {
+                lowest_covered = Some(bin);
+            }
+            break;
+        }
+
+        covered = with_bin;
+        lowest_covered = Some(bin);
+    }
+
+    let Some(bin) = lowest_covered else {
+        return 1.0;
+    };
+
+    // Thresholds sit half a bin away to stay clear of rounding.
+    let lowest = cutoff / ((bin as f32 - 0.5).max(0.5) / 255.0);
+    let highest = histogram[..bin]
+        .iter()
+        .rposition(|count| *count > 0)
+        .map_or(f32::INFINITY, |below| {
+            cutoff / ((below as f32 + 0.5) / 255.0)
+        });
+
+    1.0_f32.max(lowest).min(highest.max(lowest)).min(255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+    use image::Rgba;
+
+    #[test]
+    fn names_and_counts_levels() {
+        assert_eq!(level_count(1, 1), 1);
+        assert_eq!(level_count(0, 0), 1);
+        assert_eq!(level_count(256, 3), 9);
+        assert_eq!(level_count(257, 3), 9);
+        assert_eq!(level_size(wgpu::Extent3d::default(), 0).width, 1);
+
+        let output = Path::new("out/tree.png");
+        assert_eq!(
+            level_path(output, "mip", 0, 1, 2),
+            Path::new("out/tree_mip2.png")
+        );
+        assert_eq!(
+            level_path(Path::new("leaf"), "srgb", 7, 12, 1),
+            Path::new("leaf_0007_srgb1.png")
+        );
+    }
+
+    #[test]
+    fn scales_alpha_back_to_the_coverage_of_the_base() {
+        // Half the texels at 100, half at 200.
+        let mut histogram = [0; HISTOGRAM_BINS];
+        histogram[100] = 8;
+        histogram[200] = 8;
+
+        assert_eq!(coverage(&histogram, 0.5), 0.5);
+        assert_eq!(coverage(&histogram, 0.1), 1.0);
+        assert_eq!(coverage(&[0; HISTOGRAM_BINS], 0.5), 0.0);
+        // Already at the reference coverage.
+        assert_eq!(coverage_scale(&histogram, 0.5, 0.5), 1.0);
+
+        // Keeping every texel above the cutoff lifts 100 past 128.
+        let scale = coverage_scale(&histogram, 0.5, 1.0);
+        assert!(100.0 * scale / 255.0 > 0.5, "{}", scale);
+        // Nothing to keep.
+        assert_eq!(coverage_scale(&histogram, 0.5, 0.0), 1.0);
+    }
+
+    #[test]
+    fn averages_boxes_of_texels() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+        let (device, queue) = (&context.device, &context.queue);
+
+        #[rustfmt::skip]
+        let gray = [
+            10, 20, 200, 200,
+            30, 40, 100, 100,
+        ];
+        let image = RgbaImage::from_fn(4, 2, |x, y| {
+            let value = gray[(y * 4 + x) as usize];
+            Rgba([value, value, value, 255])
+        });
+        let size = wgpu::Extent3d {
+            width: 4,
+            height: 2,
+            depth_or_array_layers: 1,
+        };
+        let texture = wgpu_texture_copy::create_input_texture(device, queue, size, &image);
+
+        let generator = MipGenerator::new(
+            device,
+            MipmapSettings {
+                filter: MipFilter::Box,
+                space: FilterSpace::Gamma,
+                alpha_coverage: None,
+                normal_map: false,
+                toksvig: false,
+            },
+        )?;
+        let levels =
+            futures::executor::block_on(generator.generate(device, queue, &texture, size))?;
+
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].dimensions(), (2, 1));
+        assert_eq!(levels[1].dimensions(), (1, 1));
+        assert_eq!(levels[0].get_pixel(0, 0), &Rgba([25, 25, 25, 255]));
+        assert_eq!(levels[0].get_pixel(1, 0), &Rgba([150, 150, 150, 255]));
+        let last = levels[1].get_pixel(0, 0)[0];
+        assert!((87..=88).contains(&last), "{}", last);
+
+        Ok(())
+    }
+}
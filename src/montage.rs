@@ -0,0 +1,366 @@
+//! Contact sheets: many inputs shrunk into a grid of equal cells on the GPU,
+//! with an optional label under each drawn from a bitmap font.
+
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use clap::ValueEnum;
+use image::RgbaImage;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+use wgpu::util::DeviceExt;
+
+use crate::{resources, shader, thumbnail};
+
+/// Workgroup size of `montage.wgsl`.
+const WORKGROUP_SIZE: [u32; 3] = [8, 8, 1];
+
+/// Texels of a glyph cell of the font, spacing included.
+const GLYPH_CELL: (u32, u32) = (6, 8);
+
+/// Printable ASCII characters the font has, from the space on.
+const FONT_CHARACTERS: std::ops::RangeInclusive<u32> = 32..=126;
+
+/// Most characters of a label, as many as `Settings.text` holds.
+const MAX_LABEL_LENGTH: usize = 64;
+
+/// Cells decoded and drawn at once, which bounds the inputs in memory.
+const BATCH: usize = 64;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MontageLabel {
+    /// The file name of the input.
+    Filename,
+    /// The position of the input on the sheet, from 1.
+    Index,
+}
+
+pub struct MontageLayout {
+    pub columns: u32,
+    /// Width and height of a cell, which images are shrunk to fit.
+    pub cell: u32,
+    pub padding: u32,
+    pub label: Option<MontageLabel>,
+    /// Texels per font texel.
+    pub label_scale: u32,
+    pub label_color: [f32; 4],
+    pub background: [f32; 4],
+}
+
+impl MontageLayout {
+    /// Height of the strip under a cell taking its label.
+    fn label_height(&self) -> u32 {
+        match self.label {
+            Some(_) => (GLYPH_CELL.1 + 2) * self.label_scale,
+            None => 0,
+        }
+    }
+
+    /// Top left of cell `index` in the sheet.
+    fn slot_origin(&self, index: u32) -> (u32, u32) {
+        let (column, row) = (index % self.columns, index / self.columns);
+
+        (
+            self.padding + column * (self.cell + self.padding),
+            self.padding + row * (self.cell + self.label_height() + self.padding),
+        )
+    }
+
+    /// Size of a sheet of `count` cells.
+    fn sheet_size(&self, count: u32) -> (u32, u32) {
+        let columns = self.columns.min(count);
+        let rows = count.div_ceil(self.columns);
+
+        (
+            columns * self.cell + (columns + 1) * self.padding,
+            rows * (self.cell + self.label_height()) + (rows + 1) * self.padding,
+        )
+    }
+
+    fn label_text(&self, path: &Path, index: usize) -> Option<String> {
+        Some(match self.label? {
+            MontageLabel::Filename => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            MontageLabel::Index => (index + 1).to_string(),
+        })
+    }
+}
+
+/// Layout of `Settings` in `montage.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    slot_origin: [u32; 2],
+    slot_size: [u32; 2],
+    image_origin: [u32; 2],
+    image_size: [u32; 2],
+    label_origin: [u32; 2],
+    label_scale: u32,
+    label_length: u32,
+    label_color: [f32; 4],
+    text: [[u32; 4]; MAX_LABEL_LENGTH / 4],
+}
+
+/// Character codes of `text` in the font, at most `max_length` of them, the
+/// end replaced by `...` when it doesn't fit. Characters the font lacks
+/// become `?`.
+fn label_codes(text: &str, max_length: usize) -> Vec<u32> {
+    let mut codes: Vec<u32> = text
+        .chars()
+        .map(|character| match character as u32 {
+            code if FONT_CHARACTERS.contains(&code) => code,
+            _ => '?' as u32,
+        })
+        .collect();
+
+    if codes.len() > max_length {
+        codes.truncate(max_length.saturating_sub(3));
+        codes.resize(max_length, '.' as u32);
+    }
+
+    codes
+}
+
+/// Lays out the images at `paths` into a contact sheet as `layout` says,
+/// row by row from the top left. Inputs that fail to load leave their cell
+/// empty and are returned with the error.
+pub async fn compose(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    paths: &[PathBuf],
+    layout: &MontageLayout,
+) -> Result<(RgbaImage, Vec<(PathBuf, Error)>)> {
+    if paths.is_empty() {
+        bail!("No images to lay out");
+    }
+
+    let (width, height) = layout.sheet_size(paths.len() as u32);
+    let limit = device.limits().max_texture_dimension_2d;
+    if width > limit || height > limit {
+        bail!(
+            "A {}x{} sheet exceeds the device limit of {} texels a side, use fewer inputs or smaller cells",
+            width,
+            height,
+            limit
+        );
+    }
+
+    let shader = shader::preprocess(include_str!("shaders/montage.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Montage Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Montage Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            crate::output_texture_layout_entry(1),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            crate::input_texture_layout_entry(3),
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Montage Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Montage Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: "cell",
+    });
+
+    let font = resources::decode_luma(resources::FONT_PNG)?;
+    let font_view = resources::create_r8_texture(
+        device,
+        queue,
+        "Font Texture",
+        font.width(),
+        font.height(),
+        font.as_raw(),
+    );
+
+    let sheet_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let sheet = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Montage Sheet Texture"),
+        size: sheet_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+    let sheet_view = sheet.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // Cells only write their image and the ink of their label, so the rest of
+    // the sheet keeps the background it is cleared to first.
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Montage Encoder"),
+    });
+    {
+        let [r, g, b, a] = layout.background.map(f64::from);
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Montage Clear Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &sheet_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color { r, g, b, a }),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+    }
+    queue.submit(Some(encoder.finish()));
+
+    let label_height = layout.label_height();
+    let max_label_length = (((layout.cell + layout.label_scale)
+        / (GLYPH_CELL.0 * layout.label_scale)) as usize)
+        .min(MAX_LABEL_LENGTH);
+    let mut failed = Vec::new();
+
+    for (batch, batch_paths) in paths.chunks(BATCH).enumerate() {
+        let images = thumbnail::parallel_map(batch_paths, |path| {
+            thumbnail::decode(path, layout.cell, limit)
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Montage Encoder"),
+        });
+
+        // Keep the inputs alive until the passes are submitted.
+        let mut inputs = Vec::with_capacity(images.len());
+
+        for (offset, (path, image)) in batch_paths.iter().zip(images).enumerate() {
+            let index = batch * BATCH + offset;
+            let slot_origin = layout.slot_origin(index as u32);
+
+            // A cell that failed to load still gets its label, over no image.
+            let (image, size) = match image {
+                Result::Ok(image) => {
+                    let size = thumbnail::fit(image.width(), image.height(), layout.cell);
+                    (image, size)
+                }
+                Err(err) => {
+                    failed.push((path.clone(), err));
+                    (RgbaImage::new(1, 1), (0, 0))
+                }
+            };
+
+            let codes = match layout.label_text(path, index) {
+                Some(text) => label_codes(&text, max_label_length),
+                None => Vec::new(),
+            };
+            let mut text = [[0; 4]; MAX_LABEL_LENGTH / 4];
+            for (index, &code) in codes.iter().enumerate() {
+                text[index / 4][index % 4] = code;
+            }
+            let text_width =
+                (codes.len() as u32 * GLYPH_CELL.0).saturating_sub(1) * layout.label_scale;
+
+            let settings = Settings {
+                slot_origin: [slot_origin.0, slot_origin.1],
+                slot_size: [layout.cell, layout.cell + label_height],
+                image_origin: [
+                    slot_origin.0 + (layout.cell - size.0) / 2,
+                    slot_origin.1 + (layout.cell - size.1) / 2,
+                ],
+                image_size: [size.0, size.1],
+                label_origin: [
+                    slot_origin.0 + layout.cell.saturating_sub(text_width) / 2,
+                    slot_origin.1 + layout.cell + layout.label_scale,
+                ],
+                label_scale: layout.label_scale,
+                label_length: codes.len() as u32,
+                label_color: layout.label_color,
+                text,
+            };
+
+            let input_size = wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            };
+            let input = crate::create_input_texture(device, queue, input_size, image.as_raw());
+            let input_view = input.create_view(&wgpu::TextureViewDescriptor::default());
+
+            let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Montage Settings Buffer"),
+                contents: bytemuck::bytes_of(&settings),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Montage Bind Group"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&input_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&sheet_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: settings_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&font_view),
+                    },
+                ],
+            });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Montage Pass"),
+                });
+                compute_pass.set_pipeline(&pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                let (x, y) = crate::workgroup_count(
+                    settings.slot_size[0],
+                    settings.slot_size[1],
+                    WORKGROUP_SIZE,
+                );
+                compute_pass.dispatch_workgroups(x, y, 1);
+            }
+
+            inputs.push(input);
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+
+    let buffer = crate::read_texture(device, queue, &sheet, 0, sheet_size).await?;
+    let sheet = RgbaImage::from_raw(width, height, buffer)
+        .ok_or_else(|| anyhow!("Montage buffer does not match {}x{}", width, height))?;
+
+    Ok((sheet, failed))
+}
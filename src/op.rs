@@ -0,0 +1,253 @@
+//! Operations a [`TextureProcessor`](crate::TextureProcessor) can apply by
+//! name, and the [`OpRegistry`] holding them.
+//!
+//! An [`Op`] describes its parameters and builds its own bind group and
+//! pass, so effects with bindings of their own plug in like the built-in
+//! ones. Most only need a shader, see [`ShaderOp`].
+
+use std::borrow::Cow;
+
+use crate::{input_texture_layout_entry, output_texture_layout_entry, Error, Result};
+
+/// Number of `f32` parameters in the parameter block of an [`Op`].
+pub const MAX_PARAMS: usize = 16;
+
+/// Binding of the second input of the default layout, the first is at 0.
+pub const EXTRA_INPUT_BINDING: u32 = 3;
+
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub default: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// What an [`Op`] binds for one run.
+pub struct OpResources<'a> {
+    pub inputs: &'a [wgpu::TextureView],
+    pub output: &'a wgpu::TextureView,
+    /// The parameters, [`MAX_PARAMS`] `f32` values as `array<vec4<f32>, 4>`.
+    pub params: &'a wgpu::Buffer,
+}
+
+/// The default layout of an [`Op`] with `inputs` inputs, which the built-in
+/// operations of the tool extend with bindings of their own.
+pub fn layout_entries(inputs: u32) -> Vec<wgpu::BindGroupLayoutEntry> {
+    let mut entries = vec![
+        input_texture_layout_entry(0),
+        output_texture_layout_entry(1),
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+    entries.extend(
+        (EXTRA_INPUT_BINDING..EXTRA_INPUT_BINDING + inputs.saturating_sub(1))
+            .map(input_texture_layout_entry),
+    );
+
+    entries
+}
+
+/// An operation over one or more RGBA8 inputs of the same size, writing an
+/// RGBA8 output of that size.
+///
+/// The default layout binds the first input at 0 as a `texture_2d<f32>`, the
+/// output at 1 as a `texture_storage_2d<rgba8unorm, write>`, the parameters
+/// at 2 as a uniform `array<vec4<f32>, 4>` and further inputs from 3 on.
+pub trait Op: Send + Sync {
+    /// Name to look the operation up by, see [`OpRegistry::get`].
+    fn name(&self) -> &str;
+
+    fn shader(&self) -> Cow<'_, str>;
+
+    fn entry_point(&self) -> &str;
+
+    fn inputs(&self) -> u32 {
+        1
+    }
+
+    /// Parameters in the order the shader reads them.
+    fn params(&self) -> &[ParamSpec] {
+        &[]
+    }
+
+    fn layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        layout_entries(self.inputs())
+    }
+
+    /// Binds `resources` following [`Op::layout_entries`].
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        resources: &OpResources,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&resources.inputs[0]),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(resources.output),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: resources.params.as_entire_binding(),
+            },
+        ];
+        entries.extend(resources.inputs[1..].iter().zip(EXTRA_INPUT_BINDING..).map(
+            |(view, binding)| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+        ));
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Op Bind Group"),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    /// Records the dispatches into `compute_pass`, which has the pipeline
+    /// and bind group set already. One invocation per output texel by
+    /// default, in the workgroups of `workgroup_size` the compiled entry
+    /// point declares.
+    fn encode(
+        &self,
+        compute_pass: &mut wgpu::ComputePass,
+        output_size: wgpu::Extent3d,
+        workgroup_size: [u32; 3],
+    ) {
+        let (x, y) = crate::workgroup_count(output_size.width, output_size.height, workgroup_size);
+        compute_pass.dispatch_workgroups(x, y, 1);
+    }
+}
+
+/// An [`Op`] that is just a shader using the default layout.
+pub struct ShaderOp {
+    pub name: String,
+    pub shader: String,
+    pub entry_point: String,
+    pub inputs: u32,
+    pub params: Vec<ParamSpec>,
+}
+
+impl Op for ShaderOp {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn shader(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.shader)
+    }
+
+    fn entry_point(&self) -> &str {
+        &self.entry_point
+    }
+
+    fn inputs(&self) -> u32 {
+        self.inputs
+    }
+
+    fn params(&self) -> &[ParamSpec] {
+        &self.params
+    }
+}
+
+/// Operations by name.
+pub struct OpRegistry {
+    ops: Vec<Box<dyn Op>>,
+}
+
+impl OpRegistry {
+    /// A registry holding the built-in `copy`.
+    pub fn new() -> Self {
+        Self {
+            ops: vec![Box::new(ShaderOp {
+                name: "copy".to_string(),
+                shader: crate::COPY_SHADER.to_string(),
+                entry_point: "basic".to_string(),
+                inputs: 1,
+                params: Vec::new(),
+            })],
+        }
+    }
+
+    /// Adds `op`, failing if an operation of its name is there already.
+    pub fn register(&mut self, op: impl Op + 'static) -> Result<()> {
+        if self
+            .ops
+            .iter()
+            .any(|registered| registered.name() == op.name())
+        {
+            return Err(Error::DuplicateOp(op.name().to_string()));
+        }
+
+        self.ops.push(Box::new(op));
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn Op> {
+        self.ops
+            .iter()
+            .find(|op| op.name() == name)
+            .map(|op| op.as_ref())
+            .ok_or_else(|| Error::UnknownOp(name.to_string()))
+    }
+
+    /// Every operation, in the order they were registered.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Op> {
+        self.ops.iter().map(|op| op.as_ref())
+    }
+}
+
+impl Default for OpRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The parameter block of `op`: the defaults with `overrides` by name on top.
+pub(crate) fn param_block(op: &dyn Op, overrides: &[(&str, f32)]) -> Result<[f32; MAX_PARAMS]> {
+    let specs = op.params();
+    let mut block = [0.0; MAX_PARAMS];
+
+    for (value, spec) in block.iter_mut().zip(specs) {
+        *value = spec.default;
+    }
+
+    for &(name, value) in overrides {
+        let index = specs
+            .iter()
+            .position(|spec| spec.name == name)
+            .filter(|&index| index < MAX_PARAMS)
+            .ok_or_else(|| Error::UnknownParam {
+                op: op.name().to_string(),
+                name: name.to_string(),
+            })?;
+        let spec = &specs[index];
+
+        if value < spec.min || value > spec.max {
+            return Err(Error::ParamOutOfRange {
+                name: name.to_string(),
+                min: spec.min,
+                max: spec.max,
+                value,
+            });
+        }
+
+        block[index] = value;
+    }
+
+    Ok(block)
+}
@@ -0,0 +1,963 @@
+use anyhow::*;
+use serde::Serialize;
+
+use crate::{
+    histogram::{self, LookupBuilder},
+    kmeans::MAX_CLUSTERS,
+};
+
+/// Number of `f32` parameters that fit in the globals uniform block.
+pub const MAX_PARAMS: usize = 16;
+
+pub use wgpu_texture_copy::op::ParamSpec;
+
+/// A built-in single-pass operation. Parameters are handed to the shader in
+/// declaration order through `param(index)`.
+///
+/// Inputs after the first are bound from binding 3 onwards. Resizable
+/// operations write an output of the size requested with `--size` and should
+/// query input dimensions with `textureDimensions`. A parameter named
+/// [`PROGRESS_PARAM`] is swept across its range when rendering a sequence
+/// unless it is animated explicitly. The built-in operations are
+/// `OpSpec<'static>`, ones compiled from WGSL loaded at run time borrow it.
+#[derive(Clone, Copy)]
+pub struct OpSpec<'a> {
+    pub name: &'static str,
+    pub shader: &'a str,
+    pub entry_point: &'a str,
+    pub inputs: u32,
+    pub resizable: bool,
+    /// A 256x1 lookup table bound after the last input.
+    pub lookup: Option<Lookup>,
+    /// The summed-area table of the first input as a read-only storage
+    /// buffer, see [`crate::integral::integral_image`], bound after the
+    /// lookup table.
+    pub integral: bool,
+    /// Its weighted sums go through the `strict` helpers of `globals.wgsl`,
+    /// so `--strict-math` can run it.
+    pub strict_math: bool,
+    /// Names of further outputs, written to storage textures bound after the
+    /// summed-area table in this order and saved next to the main output as
+    /// `<stem>_<name>.png`. At most three, with the main output.
+    pub outputs: &'static [&'static str],
+    /// Run as a simulation over a state of its own, see [`Simulation`].
+    pub simulation: Option<Simulation>,
+    pub params: &'static [ParamSpec],
+}
+
+/// Entry points of an operation that evolves a state over many passes, which
+/// ping-pong like `--iterations`: `seed` turns the input into the initial
+/// state, the operation's entry point advances it by one step per iteration
+/// and `resolve` turns the final state into the output.
+#[derive(Clone, Copy)]
+pub struct Simulation {
+    pub seed: &'static str,
+    pub resolve: &'static str,
+    /// Steps when `--iterations` isn't given.
+    pub iterations: u32,
+}
+
+/// Where the lookup table of an operation comes from.
+#[derive(Clone, Copy)]
+pub enum Lookup {
+    /// Built from the histograms of the inputs.
+    Histograms(LookupBuilder),
+    /// A color gradient given on the command line.
+    Gradient,
+    /// k-means clusters of the first input, see [`crate::kmeans::table`].
+    /// The cluster count and the spatial weight are the first two
+    /// parameters.
+    Clusters,
+}
+
+pub const PROGRESS_PARAM: &str = "progress";
+
+const PROGRESS: ParamSpec = ParamSpec {
+    name: PROGRESS_PARAM,
+    default: 0.5,
+    min: 0.0,
+    max: 1.0,
+};
+
+/// Height of white above black in heightmaps, in texels.
+const HEIGHT: ParamSpec = ParamSpec {
+    name: "height",
+    default: 64.0,
+    min: 0.0,
+    max: 65536.0,
+};
+
+const SOFTNESS: ParamSpec = ParamSpec {
+    name: "softness",
+    default: 0.1,
+    min: 0.0,
+    max: 1.0,
+};
+
+pub const OPS: &[OpSpec<'static>] = &[
+    OpSpec {
+        name: "copy",
+        shader: concat!(
+            "#include \"pointwise.wgsl\"\n",
+            include_str!("shaders/compute.wgsl")
+        ),
+        entry_point: "basic",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[],
+    },
+    OpSpec {
+        name: "blur",
+        shader: include_str!("shaders/blur.wgsl"),
+        entry_point: "blur",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: true,
+        outputs: &[],
+        simulation: None,
+        params: &[ParamSpec {
+            name: "sigma",
+            default: 2.0,
+            min: 0.0,
+            max: 20.0,
+        }],
+    },
+    OpSpec {
+        name: "crossfade",
+        shader: include_str!("shaders/transitions.wgsl"),
+        entry_point: "crossfade",
+        inputs: 2,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[PROGRESS],
+    },
+    OpSpec {
+        name: "wipe",
+        shader: include_str!("shaders/transitions.wgsl"),
+        entry_point: "wipe",
+        inputs: 2,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            PROGRESS,
+            ParamSpec {
+                name: "angle",
+                default: 0.0,
+                min: -360.0,
+                max: 360.0,
+            },
+            SOFTNESS,
+        ],
+    },
+    OpSpec {
+        name: "dissolve",
+        shader: include_str!("shaders/transitions.wgsl"),
+        entry_point: "dissolve",
+        inputs: 2,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[PROGRESS, SOFTNESS],
+    },
+    OpSpec {
+        name: "morph",
+        shader: include_str!("shaders/transitions.wgsl"),
+        entry_point: "morph",
+        inputs: 2,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: true,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            PROGRESS,
+            ParamSpec {
+                name: "radius",
+                default: 3.0,
+                min: 1.0,
+                max: 16.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "nine-slice",
+        shader: include_str!("shaders/nine_slice.wgsl"),
+        entry_point: "nine_slice",
+        inputs: 1,
+        resizable: true,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            BORDER_LEFT,
+            BORDER_RIGHT,
+            BORDER_TOP,
+            BORDER_BOTTOM,
+            ParamSpec {
+                name: "tile",
+                default: 0.0,
+                min: 0.0,
+                max: 1.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "alpha-bleed",
+        shader: include_str!("shaders/alpha_bleed.wgsl"),
+        entry_point: "alpha_bleed",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: true,
+        outputs: &[],
+        simulation: None,
+        params: &[ParamSpec {
+            name: "radius",
+            default: 8.0,
+            min: 1.0,
+            max: 64.0,
+        }],
+    },
+    OpSpec {
+        name: "pack",
+        shader: include_str!("shaders/pack.wgsl"),
+        entry_point: "pack",
+        inputs: 4,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[],
+    },
+    OpSpec {
+        name: "histogram-match",
+        shader: include_str!("shaders/histogram_match.wgsl"),
+        entry_point: "histogram_match",
+        inputs: 2,
+        resizable: false,
+        lookup: Some(Lookup::Histograms(histogram::match_histograms)),
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[],
+    },
+    OpSpec {
+        name: "gradient-map",
+        shader: include_str!("shaders/gradient_map.wgsl"),
+        entry_point: "gradient_map",
+        inputs: 1,
+        resizable: false,
+        lookup: Some(Lookup::Gradient),
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[],
+    },
+    OpSpec {
+        name: "halftone",
+        shader: include_str!("shaders/stylize.wgsl"),
+        entry_point: "halftone",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "size",
+                default: 6.0,
+                min: 2.0,
+                max: 64.0,
+            },
+            ParamSpec {
+                name: "angle",
+                default: 45.0,
+                min: -360.0,
+                max: 360.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "crosshatch",
+        shader: include_str!("shaders/crosshatch.wgsl"),
+        entry_point: "crosshatch",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "spacing",
+                default: 6.0,
+                min: 2.0,
+                max: 64.0,
+            },
+            ParamSpec {
+                name: "width",
+                default: 1.0,
+                min: 0.5,
+                max: 16.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "kuwahara",
+        shader: include_str!("shaders/kuwahara.wgsl"),
+        entry_point: "kuwahara",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: true,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "radius",
+                default: 4.0,
+                min: 1.0,
+                max: 16.0,
+            },
+            ParamSpec {
+                name: "sectors",
+                default: 8.0,
+                min: 4.0,
+                max: 8.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "pixel-sort",
+        shader: include_str!("shaders/pixel_sort.wgsl"),
+        entry_point: "pixel_sort",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "threshold",
+                default: 0.25,
+                min: 0.0,
+                max: 1.0,
+            },
+            ParamSpec {
+                name: "vertical",
+                default: 0.0,
+                min: 0.0,
+                max: 1.0,
+            },
+            ParamSpec {
+                name: "length",
+                default: 64.0,
+                min: 2.0,
+                max: 256.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "channel-shift",
+        shader: include_str!("shaders/glitch.wgsl"),
+        entry_point: "channel_shift",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[ParamSpec {
+            name: "amount",
+            default: 4.0,
+            min: 0.0,
+            max: 64.0,
+        }],
+    },
+    OpSpec {
+        name: "block-displace",
+        shader: include_str!("shaders/glitch.wgsl"),
+        entry_point: "block_displace",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "block",
+                default: 16.0,
+                min: 2.0,
+                max: 256.0,
+            },
+            ParamSpec {
+                name: "strength",
+                default: 16.0,
+                min: 0.0,
+                max: 256.0,
+            },
+            ParamSpec {
+                name: "probability",
+                default: 0.2,
+                min: 0.0,
+                max: 1.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "scanlines",
+        shader: include_str!("shaders/glitch.wgsl"),
+        entry_point: "scanlines",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "intensity",
+                default: 0.3,
+                min: 0.0,
+                max: 1.0,
+            },
+            ParamSpec {
+                name: "noise",
+                default: 0.2,
+                min: 0.0,
+                max: 1.0,
+            },
+            ParamSpec {
+                name: "spacing",
+                default: 2.0,
+                min: 1.0,
+                max: 16.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "segment",
+        shader: include_str!("shaders/segment.wgsl"),
+        entry_point: "segment",
+        inputs: 1,
+        resizable: false,
+        lookup: Some(Lookup::Clusters),
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "clusters",
+                default: 8.0,
+                min: 1.0,
+                max: MAX_CLUSTERS as f32,
+            },
+            ParamSpec {
+                name: "spatial",
+                default: 0.0,
+                min: 0.0,
+                max: 4.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "ascii",
+        shader: include_str!("shaders/ascii.wgsl"),
+        entry_point: "ascii",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: true,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "cell",
+                default: 8.0,
+                min: 2.0,
+                max: 64.0,
+            },
+            ParamSpec {
+                name: "color",
+                default: 0.0,
+                min: 0.0,
+                max: 1.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "box-blur",
+        shader: include_str!("shaders/box_filter.wgsl"),
+        entry_point: "box_blur",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: true,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[ParamSpec {
+            name: "radius",
+            default: 8.0,
+            min: 0.0,
+            max: 4096.0,
+        }],
+    },
+    OpSpec {
+        name: "adaptive-threshold",
+        shader: include_str!("shaders/box_filter.wgsl"),
+        entry_point: "adaptive_threshold",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: true,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "radius",
+                default: 16.0,
+                min: 1.0,
+                max: 4096.0,
+            },
+            ParamSpec {
+                name: "offset",
+                default: 0.05,
+                min: -1.0,
+                max: 1.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "chroma-key",
+        shader: include_str!("shaders/chroma_key.wgsl"),
+        entry_point: "chroma_key",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &["mask", "edges"],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "hue",
+                default: 120.0,
+                min: 0.0,
+                max: 360.0,
+            },
+            ParamSpec {
+                name: "threshold",
+                default: 0.15,
+                min: 0.0,
+                max: 1.0,
+            },
+            ParamSpec {
+                name: "softness",
+                default: 0.1,
+                min: 0.0,
+                max: 1.0,
+            },
+        ],
+    },
+    OpSpec {
+        name: "reaction-diffusion",
+        shader: include_str!("shaders/reaction_diffusion.wgsl"),
+        entry_point: "reaction_diffusion",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: Some(Simulation {
+            seed: "seed",
+            resolve: "resolve",
+            iterations: 2000,
+        }),
+        params: &[
+            ParamSpec {
+                name: "feed",
+                default: 0.055,
+                min: 0.0,
+                max: 0.1,
+            },
+            ParamSpec {
+                name: "kill",
+                default: 0.062,
+                min: 0.0,
+                max: 0.1,
+            },
+        ],
+    },
+    OpSpec {
+        name: "slope",
+        shader: include_str!("shaders/terrain.wgsl"),
+        entry_point: "slope",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[HEIGHT],
+    },
+    OpSpec {
+        name: "aspect",
+        shader: include_str!("shaders/terrain.wgsl"),
+        entry_point: "aspect",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[],
+    },
+    OpSpec {
+        name: "curvature",
+        shader: include_str!("shaders/terrain.wgsl"),
+        entry_point: "curvature",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        simulation: None,
+        params: &[HEIGHT],
+    },
+    OpSpec {
+        name: "flow-accumulation",
+        shader: include_str!("shaders/terrain.wgsl"),
+        entry_point: "flow_accumulation",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &[],
+        // Counts travel one texel per step, so the default covers drainage
+        // paths across most images.
+        simulation: Some(Simulation {
+            seed: "flow_seed",
+            resolve: "flow_resolve",
+            iterations: 1024,
+        }),
+        params: &[],
+    },
+    OpSpec {
+        name: "cavity",
+        shader: include_str!("shaders/cavity.wgsl"),
+        entry_point: "cavity",
+        inputs: 1,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: false,
+        outputs: &["cavity", "edges"],
+        simulation: None,
+        params: &[
+            ParamSpec {
+                name: "radius",
+                default: 1.0,
+                min: 1.0,
+                max: 64.0,
+            },
+            ParamSpec {
+                name: "strength",
+                default: 4.0,
+                min: 0.0,
+                max: 1000.0,
+            },
+            ParamSpec {
+                name: "directx",
+                default: 0.0,
+                min: 0.0,
+                max: 1.0,
+            },
+        ],
+    },
+];
+
+const fn border(name: &'static str) -> ParamSpec {
+    ParamSpec {
+        name,
+        default: 8.0,
+        min: 0.0,
+        max: 4096.0,
+    }
+}
+
+const BORDER_LEFT: ParamSpec = border("left");
+const BORDER_RIGHT: ParamSpec = border("right");
+const BORDER_TOP: ParamSpec = border("top");
+const BORDER_BOTTOM: ParamSpec = border("bottom");
+
+pub fn find(name: &str) -> Result<&'static OpSpec<'static>> {
+    OPS.iter().find(|op| op.name == name).ok_or_else(|| {
+        let names: Vec<_> = OPS.iter().map(|op| op.name).collect();
+        anyhow!(
+            "Unknown operation '{}', expected one of: {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+/// What `list-effects` shows of an operation.
+#[derive(Serialize)]
+pub struct EffectInfo {
+    pub name: &'static str,
+    pub inputs: u32,
+    pub resizable: bool,
+    pub params: Vec<ParamInfo>,
+}
+
+#[derive(Serialize)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    /// Always `float`, parameters are handed to shaders as `f32`.
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+/// Every built-in operation with its parameter schema.
+pub fn effects() -> Vec<EffectInfo> {
+    OPS.iter()
+        .map(|op| EffectInfo {
+            name: op.name,
+            inputs: op.inputs,
+            resizable: op.resizable,
+            params: op
+                .params
+                .iter()
+                .map(|param| ParamInfo {
+                    name: param.name,
+                    kind: "float",
+                    min: param.min,
+                    max: param.max,
+                    default: param.default,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// The `help` page of `op`, put together from its spec.
+pub fn help_page(op: &OpSpec<'_>, program: &str) -> String {
+    // `pack` gathers its inputs itself, see `--pack`.
+    let mut usage = match op.name {
+        "pack" => format!("{} --op pack --pack <CHANNEL=PATH,...>", program),
+        _ => format!("{} <INPUT> --op {}", program, op.name),
+    };
+    if op.inputs == 2 {
+        usage.push_str(" --second <PATH>");
+    }
+    if !op.params.is_empty() {
+        usage.push_str(" [--param NAME=VALUE]...");
+    }
+
+    let mut page = format!(
+        "{}, an operation over {} input{}\n\nUsage: {}\n",
+        op.name,
+        op.inputs,
+        if op.inputs == 1 { "" } else { "s" },
+        usage
+    );
+
+    if !op.params.is_empty() {
+        page.push_str("\nParameters:\n");
+        for param in op.params {
+            page.push_str(&format!(
+                "  {:<12} {}..{}, default {}\n",
+                param.name, param.min, param.max, param.default
+            ));
+        }
+    }
+
+    let mut notes = Vec::new();
+    if op.resizable {
+        notes.push("Writes an output of --size rather than the size of the input.".to_string());
+    }
+    match &op.lookup {
+        Some(Lookup::Histograms(_)) => {
+            notes.push("Builds a lookup table from the histograms of its inputs.".to_string())
+        }
+        Some(Lookup::Gradient) => {
+            notes.push("Maps colors through --gradient or --gradient-image.".to_string())
+        }
+        Some(Lookup::Clusters) => {
+            notes.push("Clusters the colors of the input, see --clusters.".to_string())
+        }
+        None => {}
+    }
+    if !op.outputs.is_empty() {
+        let outputs: Vec<_> = op
+            .outputs
+            .iter()
+            .map(|output| format!("<name>_{}", output))
+            .collect();
+        notes.push(format!("Also writes {}.", outputs.join(", ")));
+    }
+    if let Some(simulation) = &op.simulation {
+        notes.push(format!(
+            "Simulates {} steps unless --iterations says otherwise.",
+            simulation.iterations
+        ));
+    }
+
+    if !notes.is_empty() {
+        page.push('\n');
+        for note in notes {
+            page.push_str(&note);
+            page.push('\n');
+        }
+    }
+
+    page
+}
+
+/// Names of the built-in operations [`OpSpec::strict_math`] holds for.
+pub fn strict_math_ops() -> Vec<&'static str> {
+    OPS.iter()
+        .filter(|op| op.strict_math)
+        .map(|op| op.name)
+        .collect()
+}
+
+/// Name of the operations of [`custom`].
+pub const CUSTOM: &str = "custom";
+
+/// Operation running `entry_point` of a shader loaded at run time, bound
+/// like the built-in ones and taking a second input if `inputs` is 2.
+pub fn custom<'a>(source: &'a str, entry_point: &'a str, inputs: u32) -> OpSpec<'a> {
+    OpSpec {
+        name: CUSTOM,
+        shader: source,
+        entry_point,
+        inputs,
+        resizable: false,
+        lookup: None,
+        integral: false,
+        strict_math: true,
+        outputs: &[],
+        simulation: None,
+        params: &[],
+    }
+}
+
+/// `spec` compiled from `source` instead of its own shader.
+pub fn with_shader<'a>(spec: &OpSpec<'a>, source: &'a str) -> OpSpec<'a> {
+    OpSpec {
+        shader: source,
+        ..*spec
+    }
+}
+
+impl OpSpec<'_> {
+    /// Index of the parameter addressed by `key`, which is either the bare
+    /// parameter name or qualified with the operation name (`blur-sigma`).
+    pub fn param_index(&self, key: &str) -> Option<usize> {
+        let name = key
+            .strip_prefix(self.name)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .unwrap_or(key);
+
+        self.params.iter().position(|param| param.name == name)
+    }
+
+    /// Parameter values with `overrides` applied on top of the defaults.
+    pub fn resolve_params(&self, overrides: &[(String, f32)]) -> Result<[f32; MAX_PARAMS]> {
+        let mut values = [0.0; MAX_PARAMS];
+
+        for (value, param) in values.iter_mut().zip(self.params) {
+            *value = param.default;
+        }
+
+        for (key, value) in overrides {
+            let index = self
+                .param_index(key)
+                .ok_or_else(|| anyhow!("Operation '{}' has no parameter '{}'", self.name, key))?;
+            self.check_param(index, *value)?;
+
+            values[index] = *value;
+        }
+
+        Ok(values)
+    }
+
+    /// Fails unless `value` is within the range of parameter `index`.
+    pub fn check_param(&self, index: usize, value: f32) -> Result<()> {
+        let param = &self.params[index];
+
+        if value < param.min || value > param.max {
+            bail!(
+                "Parameter '{}' must be within {}..{}, got {}",
+                param.name,
+                param.min,
+                param.max,
+                value
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a `key=value` parameter assignment.
+pub fn parse_param(arg: &str) -> Result<(String, f32)> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected key=value, got '{}'", arg))?;
+
+    let value = value
+        .parse()
+        .with_context(|| format!("Invalid value for parameter '{}'", key))?;
+
+    Ok((key.to_string(), value))
+}
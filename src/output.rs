@@ -0,0 +1,75 @@
+use anyhow::*;
+use clap::ValueEnum;
+use image::ImageFormat;
+use std::path::{Path, PathBuf};
+
+/// Image format of the output, which otherwise follows its extension.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    Tiff,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Bmp => "bmp",
+            Self::Tga => "tga",
+            Self::Tiff => "tiff",
+        }
+    }
+}
+
+/// What to do when a file the run would write is already there.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum IfExists {
+    /// Replace it.
+    Overwrite,
+    /// Stop before processing anything.
+    Fail,
+    /// Leave it and skip the run, if every output is there already.
+    Skip,
+    /// Leave it and skip the run, if every output is there already and
+    /// nothing it was made from has changed since.
+    Outdated,
+}
+
+/// The output path with the extension of `format`, or as given if its
+/// extension names a format the output can be written in.
+pub fn resolve_path(path: &Path, format: Option<OutputFormat>) -> Result<PathBuf> {
+    if let Some(format) = format {
+        return Ok(path.with_extension(format.extension()));
+    }
+
+    if ImageFormat::from_path(path).is_err() {
+        bail!(
+            "Can't tell an image format from the extension of {}, pass --format",
+            path.display()
+        );
+    }
+
+    Ok(path.to_path_buf())
+}
+
+/// Whether to go ahead writing `paths`, failing if one of them exists and
+/// mustn't be overwritten.
+pub fn should_write(paths: &[PathBuf], if_exists: IfExists) -> Result<bool> {
+    match if_exists {
+        IfExists::Overwrite => Ok(true),
+        IfExists::Fail => match paths.iter().find(|path| path.exists()) {
+            Some(path) => bail!(
+                "{} already exists, pass --if-exists overwrite to replace it",
+                path.display()
+            ),
+            None => Ok(true),
+        },
+        IfExists::Skip => Ok(!paths.iter().all(|path| path.exists())),
+        // Whether anything changed is up to the dependency record.
+        IfExists::Outdated => Ok(true),
+    }
+}
@@ -0,0 +1,301 @@
+//! WGSL `override` constants, such as a kernel radius or a channel count,
+//! specialized when a kernel is compiled:
+//!
+//! ```wgsl
+//! @id(0) override RADIUS: i32 = 4;
+//! override STRENGTH: f32;
+//! ```
+//!
+//! wgpu doesn't take their values along with the pipeline yet, so
+//! [`specialize`] turns the declarations into `const` ones before the shader
+//! is compiled. Every declaration is on a line of its own.
+
+use std::borrow::Cow;
+
+use crate::{Error, Result};
+
+const OVERRIDE_KEYWORD: &str = "override";
+
+/// An `override` declaration of a shader.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Override {
+    pub name: String,
+    /// The number of its `@id` attribute, if any.
+    pub id: Option<u32>,
+    /// `f32`, `i32`, `u32` or `bool`.
+    pub ty: String,
+    /// The expression it defaults to, if any.
+    pub default: Option<String>,
+}
+
+/// The `override` declarations of the WGSL in `shader`, in order.
+pub fn declared(shader: &str) -> Result<Vec<Override>> {
+    shader
+        .lines()
+        .filter_map(|line| parse_declaration(line).transpose())
+        .collect()
+}
+
+/// `shader` with every `override` declaration turned into a `const` one,
+/// set to the value of `constants` either its name or the number of its
+/// `@id` maps to, or else to its default.
+pub fn specialize<'a>(shader: &'a str, constants: &[(&str, f64)]) -> Result<Cow<'a, str>> {
+    let declarations = declared(shader)?;
+
+    if let Some(&(key, _)) = constants.iter().find(|&&(key, _)| {
+        !declarations
+            .iter()
+            .any(|declaration| declaration.matches(key))
+    }) {
+        return Err(Error::InvalidOverride(format!(
+            "the shader declares no override '{}'",
+            key
+        )));
+    }
+
+    if declarations.is_empty() {
+        return Ok(Cow::Borrowed(shader));
+    }
+
+    let mut output = String::with_capacity(shader.len());
+    for line in shader.lines() {
+        match parse_declaration(line)? {
+            Some(declaration) => {
+                let value = match constants
+                    .iter()
+                    .rev()
+                    .find(|&&(key, _)| declaration.matches(key))
+                {
+                    Some(&(_, value)) => literal(&declaration, value)?,
+                    None => declaration.default.clone().ok_or_else(|| {
+                        Error::InvalidOverride(format!(
+                            "'{}' has no default and no value",
+                            declaration.name
+                        ))
+                    })?,
+                };
+                let indent = &line[..line.len() - line.trim_start().len()];
+
+                output.push_str(&format!(
+                    "{}const {}: {} = {};",
+                    indent, declaration.name, declaration.ty, value
+                ));
+            }
+            None => output.push_str(line),
+        }
+        output.push('\n');
+    }
+
+    Ok(Cow::Owned(output))
+}
+
+impl Override {
+    fn matches(&self, key: &str) -> bool {
+        self.name == key || self.id.is_some_and(|id| id.to_string() == key)
+    }
+}
+
+/// The declaration on `line`, `[@id(<n>)] override <name>: <type> [= <default>];`.
+fn parse_declaration(line: &str) -> Result<Option<Override>> {
+    let mut rest = line.trim();
+    let malformed = || Error::InvalidOverride(format!("can't read '{}'", line.trim()));
+
+    let mut id = None;
+    if let Some(attribute) = rest.strip_prefix("@id") {
+        let attribute = attribute.trim_start();
+        let Some((number, after)) = attribute
+            .strip_prefix('(')
+            .and_then(|attribute| attribute.split_once(')'))
+        else {
+            return Ok(None);
+        };
+
+        id = Some(number.trim().parse().map_err(|_| malformed())?);
+        rest = after.trim_start();
+    }
+
+    let Some(declaration) = rest.strip_prefix(OVERRIDE_KEYWORD) else {
+        return Ok(None);
+    };
+    if !declaration.starts_with(char::is_whitespace) {
+        return Ok(None);
+    }
+
+    let declaration = declaration.trim().strip_suffix(';').ok_or_else(malformed)?;
+    let (declaration, default) = match declaration.split_once('=') {
+        Some((declaration, default)) => (declaration, Some(default.trim().to_string())),
+        None => (declaration, None),
+    };
+    let (name, ty) = declaration
+        .split_once(':')
+        .ok_or_else(|| Error::InvalidOverride(format!("'{}' needs a type", declaration.trim())))?;
+
+    let ty = ty.trim();
+    if !matches!(ty, "f32" | "i32" | "u32" | "bool") {
+        return Err(Error::InvalidOverride(format!(
+            "'{}' is a {}, only f32, i32, u32 and bool can be specialized",
+            name.trim(),
+            ty
+        )));
+    }
+
+    Ok(Some(Override {
+        name: name.trim().to_string(),
+        id,
+        ty: ty.to_string(),
+        default,
+    }))
+}
+
+/// `value` as a WGSL literal of the type of `declaration`. Integers only
+/// take whole numbers within their range, booleans are true unless 0.
+fn literal(declaration: &Override, value: f64) -> Result<String> {
+    let integral = |min: f64, max: f64| {
+        if value.fract() != 0.0 || value < min || value > max {
+            Err(Error::InvalidOverride(format!(
+                "'{}' takes whole numbers within {}..={}, got {}",
+                declaration.name, min, max, value
+            )))
+        } else {
+            Ok(())
+        }
+    };
+
+    Ok(match declaration.ty.as_str() {
+        "f32" if !(value as f32).is_finite() => {
+            return Err(Error::InvalidOverride(format!(
+                "'{}' takes finite numbers, got {}",
+                declaration.name, value
+            )))
+        }
+        "f32" => format!("{:?}", value as f32),
+        "i32" => {
+            integral(i32::MIN as f64, i32::MAX as f64)?;
+            format!("{}", value as i32)
+        }
+        "u32" => {
+            integral(0.0, u32::MAX as f64)?;
+            format!("{}u", value as u32)
+        }
+        _ => (value != 0.0).to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "\
+@id(0) override RADIUS: i32 = 4;
+override STRENGTH: f32;
+  @id(7) override CHANNELS: u32 = 3u;
+override INVERT: bool = false;
+let overridden = 1;
+@compute @workgroup_size(8, 8)
+fn main() {}
+";
+
+    fn message(err: Error) -> String {
+        match err {
+            Error::InvalidOverride(message) => message,
+            err => panic!("not an override error: {}", err),
+        }
+    }
+
+    #[test]
+    fn reads_the_declarations() -> Result<()> {
+        let declarations = declared(SHADER)?;
+
+        assert_eq!(
+            declarations[0],
+            Override {
+                name: "RADIUS".to_string(),
+                id: Some(0),
+                ty: "i32".to_string(),
+                default: Some("4".to_string()),
+            }
+        );
+        assert_eq!(declarations[1].default, None);
+        assert_eq!(
+            declarations
+                .iter()
+                .map(|declaration| declaration.name.as_str())
+                .collect::<Vec<_>>(),
+            ["RADIUS", "STRENGTH", "CHANNELS", "INVERT"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn sets_constants_by_name_or_id() -> Result<()> {
+        let specialized = specialize(
+            SHADER,
+            &[
+                ("STRENGTH", 0.5),
+                ("7", 4.0),
+                ("RADIUS", 2.0),
+                ("0", -3.0),
+                ("INVERT", 1.0),
+            ],
+        )?;
+
+        assert_eq!(
+            specialized,
+            "\
+const RADIUS: i32 = -3;
+const STRENGTH: f32 = 0.5;
+  const CHANNELS: u32 = 4u;
+const INVERT: bool = true;
+let overridden = 1;
+@compute @workgroup_size(8, 8)
+fn main() {}
+"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_defaults_and_shaders_without_overrides() -> Result<()> {
+        let specialized = specialize(SHADER, &[("STRENGTH", 2.0)])?;
+        assert!(specialized.contains("const RADIUS: i32 = 4;"));
+        assert!(specialized.contains("const STRENGTH: f32 = 2.0;"));
+        assert!(specialized.contains("const CHANNELS: u32 = 3u;"));
+
+        let plain = "fn main() {}\n";
+        assert!(matches!(specialize(plain, &[])?, Cow::Borrowed(_)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_values_that_do_not_fit() {
+        let cases: [(&[(&str, f64)], &str); 6] = [
+            (&[("WIDTH", 1.0)], "declares no override 'WIDTH'"),
+            (&[], "'STRENGTH' has no default"),
+            (&[("STRENGTH", 1.0), ("RADIUS", 1.5)], "whole numbers"),
+            (&[("STRENGTH", 1.0), ("CHANNELS", -1.0)], "whole numbers"),
+            (&[("STRENGTH", 1.0), ("RADIUS", 3e9)], "whole numbers"),
+            (&[("STRENGTH", f64::INFINITY)], "finite numbers"),
+        ];
+
+        for (constants, expected) in cases {
+            let message = message(specialize(SHADER, constants).unwrap_err());
+            assert!(message.contains(expected), "{:?}: {}", constants, message);
+        }
+    }
+
+    #[test]
+    fn rejects_declarations_it_cannot_specialize() {
+        for (line, expected) in [
+            ("override SIZE: vec2<f32>;", "only f32, i32, u32 and bool"),
+            ("override SIZE = 4;", "needs a type"),
+            ("override SIZE: u32 = 4u", "can't read"),
+            ("@id(x) override SIZE: u32;", "can't read"),
+        ] {
+            let message = message(declared(line).unwrap_err());
+            assert!(message.contains(expected), "{}: {}", line, message);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::*;
+use image::{imageops, Rgba, RgbaImage};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const CHANNELS: [char; 4] = ['r', 'g', 'b', 'a'];
+
+/// Grayscale image for every output channel of the `pack` operation, written
+/// as `r=roughness.png,g=metal.png,b=ao.png` on the command line.
+#[derive(Clone, Debug)]
+pub struct PackSpec {
+    channels: [Option<PathBuf>; 4],
+}
+
+impl FromStr for PackSpec {
+    type Err = Error;
+
+    fn from_str(arg: &str) -> Result<Self> {
+        let mut channels: [Option<PathBuf>; 4] = Default::default();
+
+        for mapping in arg.split(',') {
+            let (channel, path) = mapping
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected channel=path, got '{}'", mapping))?;
+
+            let index = match channel.trim() {
+                "r" => 0,
+                "g" => 1,
+                "b" => 2,
+                "a" => 3,
+                _ => bail!("Unknown channel '{}', expected r, g, b or a", channel),
+            };
+
+            if channels[index].replace(PathBuf::from(path)).is_some() {
+                bail!("Channel '{}' is mapped more than once", channel);
+            }
+        }
+
+        Ok(Self { channels })
+    }
+}
+
+impl PackSpec {
+    /// Loads the mapped images in channel order, resized to the first one.
+    /// Unmapped color channels are black and an unmapped alpha is opaque.
+    pub fn load(&self) -> Result<Vec<RgbaImage>> {
+        let mut loaded = self
+            .channels
+            .iter()
+            .map(|path| path.as_deref().map(crate::load_image).transpose())
+            .collect::<Result<Vec<_>>>()?;
+
+        let (width, height) = loaded
+            .iter()
+            .flatten()
+            .next()
+            .map(|image| image.dimensions())
+            .ok_or_else(|| anyhow!("No channel is mapped to an input"))?;
+
+        let images = loaded
+            .iter_mut()
+            .zip(CHANNELS)
+            .map(|(image, channel)| match image.take() {
+                Some(image) if image.dimensions() == (width, height) => image,
+                Some(image) => {
+                    imageops::resize(&image, width, height, imageops::FilterType::Triangle)
+                }
+                None if channel == 'a' => RgbaImage::from_pixel(width, height, Rgba([255; 4])),
+                None => RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255])),
+            })
+            .collect();
+
+        Ok(images)
+    }
+
+    /// The channels and the files they are taken from.
+    pub fn sources(&self) -> impl Iterator<Item = (char, &Path)> {
+        self.channels
+            .iter()
+            .zip(CHANNELS)
+            .filter_map(|(path, channel)| Some((channel, path.as_deref()?)))
+    }
+
+    /// The channels and their files, to point them elsewhere.
+    pub fn sources_mut(&mut self) -> impl Iterator<Item = (char, &mut PathBuf)> {
+        self.channels
+            .iter_mut()
+            .zip(CHANNELS)
+            .filter_map(|(path, channel)| Some((channel, path.as_mut()?)))
+    }
+
+    /// The mapped files, for the report.
+    pub fn describe(&self) -> String {
+        self.sources()
+            .map(|(channel, path)| format!("{}={}", channel, path.display()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_channel_mappings() -> Result<()> {
+        let spec: PackSpec = "b=ao.png, a=mask.png,r=rough.png".parse()?;
+        let sources: Vec<_> = spec.sources().collect();
+        assert_eq!(
+            sources,
+            [
+                ('r', Path::new("rough.png")),
+                ('b', Path::new("ao.png")),
+                ('a', Path::new("mask.png")),
+            ]
+        );
+        assert_eq!(spec.describe(), "r=rough.png,b=ao.png,a=mask.png");
+
+        for (arg, error) in [
+            ("r=a.png,r=b.png", "mapped more than once"),
+            ("x=a.png", "Unknown channel 'x'"),
+            ("r:a.png", "Expected channel=path"),
+        ] {
+            let err = arg.parse::<PackSpec>().unwrap_err();
+            assert!(err.to_string().contains(error), "{}: {}", arg, err);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn fills_and_resizes_the_channels() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("wtc-pack-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let (green, blue) = (dir.join("green.png"), dir.join("blue.png"));
+        RgbaImage::from_pixel(6, 4, Rgba([90, 90, 90, 255])).save(&green)?;
+        RgbaImage::from_pixel(3, 2, Rgba([200, 200, 200, 255])).save(&blue)?;
+
+        let mut spec: PackSpec = format!("g={},b={}", green.display(), blue.display()).parse()?;
+        let images = spec.load()?;
+
+        assert_eq!(images.len(), 4);
+        assert!(images.iter().all(|image| image.dimensions() == (6, 4)));
+        assert_eq!(images[0].get_pixel(5, 3), &Rgba([0, 0, 0, 255]));
+        assert_eq!(images[1].get_pixel(0, 0), &Rgba([90, 90, 90, 255]));
+        assert_eq!(images[2].get_pixel(5, 3), &Rgba([200, 200, 200, 255]));
+        assert_eq!(images[3].get_pixel(2, 1), &Rgba([255; 4]));
+
+        for (_, path) in spec.sources_mut() {
+            *path = dir.join("missing.png");
+        }
+        assert!(spec.load().is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use clap::ValueEnum;
+use image::RgbaImage;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::shader;
+
+/// Size of `Texel` in `panorama.wgsl`.
+const TEXEL_SIZE: u64 = 48;
+
+/// Surface the tiles are projected onto.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Projection {
+    /// Keeps vertical lines straight, for panoramas with a modest vertical
+    /// field of view.
+    Cylindrical,
+    /// Equirectangular, evenly spaced in latitude.
+    Spherical,
+}
+
+pub struct PanoramaSettings {
+    /// Horizontal direction of every tile, in degrees.
+    pub yaw: Vec<f32>,
+    /// Horizontal field of view of the tiles, in degrees.
+    pub fov: f32,
+    pub projection: Projection,
+    /// Bands of the multi-band blend.
+    pub levels: u32,
+}
+
+/// Layout of `Tile` in `panorama.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct TileSettings {
+    yaw: f32,
+    focal: f32,
+    origin: f32,
+    projection: u32,
+}
+
+/// Layout of `Level` in `panorama.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LevelSettings {
+    size: [u32; 2],
+    offset: u32,
+    top: u32,
+    fine_size: [u32; 2],
+    fine_offset: u32,
+    coarse_offset: u32,
+    coarse_size: [u32; 2],
+    padding: [u32; 2],
+}
+
+/// Stitches pre-aligned tiles taken from one spot, turned by `yaw`, into a
+/// cylindrical or spherical panorama. Every panorama texel is taken from
+/// the tile it is farthest inside of, and the seams between tiles are
+/// hidden by blending Laplacian pyramid bands with correspondingly blurred
+/// masks (Burt-Adelson).
+pub async fn stitch(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    tiles: &[RgbaImage],
+    settings: &PanoramaSettings,
+) -> Result<RgbaImage> {
+    if tiles.len() != settings.yaw.len() {
+        bail!(
+            "Got {} tiles but {} yaw angles",
+            tiles.len(),
+            settings.yaw.len()
+        );
+    }
+
+    let (tile_width, tile_height) = tiles[0].dimensions();
+    if tiles
+        .iter()
+        .any(|tile| tile.dimensions() != (tile_width, tile_height))
+    {
+        bail!("Panorama tiles need to be the same size");
+    }
+
+    let fov = settings.fov.to_radians();
+    let focal = tile_width as f32 * 0.5 / (fov * 0.5).tan();
+
+    let (min_yaw, max_yaw) = settings
+        .yaw
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), yaw| {
+            (min.min(yaw.to_radians()), max.max(yaw.to_radians()))
+        });
+    let origin = min_yaw - fov * 0.5;
+
+    let half_height = tile_height as f32 * 0.5 / focal;
+    let vertical_extent = match settings.projection {
+        Projection::Cylindrical => half_height,
+        Projection::Spherical => half_height.atan(),
+    };
+
+    let width = (focal * (max_yaw - min_yaw + fov)).ceil() as u32;
+    let height = (2.0 * focal * vertical_extent).ceil() as u32;
+
+    let mut sizes = vec![(width, height)];
+    while sizes.len() < settings.levels as usize {
+        let (width, height) = sizes[sizes.len() - 1];
+        if width < 4 || height < 4 {
+            break;
+        }
+        sizes.push((width.div_ceil(2), height.div_ceil(2)));
+    }
+
+    let offsets: Vec<u32> = sizes
+        .iter()
+        .scan(0, |offset, (width, height)| {
+            let start = *offset;
+            *offset += width * height;
+            Some(start)
+        })
+        .collect();
+    let texels: u64 = sizes
+        .iter()
+        .map(|(width, height)| (width * height) as u64)
+        .sum();
+
+    if texels * TEXEL_SIZE > device.limits().max_storage_buffer_binding_size as u64 {
+        bail!(
+            "A {}x{} panorama needs more GPU memory for blending than a buffer may hold",
+            width,
+            height
+        );
+    }
+
+    let shader = shader::preprocess(include_str!("shaders/panorama.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Panorama Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    let tile_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Panorama Tile Bind Group Layout"),
+        entries: &[crate::input_texture_layout_entry(0), uniform_entry(1)],
+    });
+
+    let level_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Panorama Level Bind Group Layout"),
+        entries: &[uniform_entry(0)],
+    });
+
+    let canvas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Panorama Canvas Bind Group Layout"),
+        entries: &[
+            storage_entry(0),
+            storage_entry(1),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let tile_size = wgpu::Extent3d {
+        width: tile_width,
+        height: tile_height,
+        depth_or_array_layers: 1,
+    };
+
+    let projection = match settings.projection {
+        Projection::Cylindrical => 0,
+        Projection::Spherical => 1,
+    };
+
+    let tile_textures: Vec<_> = tiles
+        .iter()
+        .map(|tile| crate::create_input_texture(device, queue, tile_size, tile))
+        .collect();
+
+    let tile_bind_groups: Vec<_> = tile_textures
+        .iter()
+        .zip(&settings.yaw)
+        .map(|(texture, yaw)| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Panorama Tile Buffer"),
+                contents: bytemuck::bytes_of(&TileSettings {
+                    yaw: yaw.to_radians(),
+                    focal,
+                    origin,
+                    projection,
+                }),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Panorama Tile Bind Group"),
+                layout: &tile_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        })
+        .collect();
+
+    let level_bind_groups: Vec<_> = (0..sizes.len())
+        .map(|level| {
+            let fine = level.saturating_sub(1);
+            let coarse = (level + 1).min(sizes.len() - 1);
+
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Panorama Level Buffer"),
+                contents: bytemuck::bytes_of(&LevelSettings {
+                    size: [sizes[level].0, sizes[level].1],
+                    offset: offsets[level],
+                    top: (level + 1 == sizes.len()) as u32,
+                    fine_size: [sizes[fine].0, sizes[fine].1],
+                    fine_offset: offsets[fine],
+                    coarse_offset: offsets[coarse],
+                    coarse_size: [sizes[coarse].0, sizes[coarse].1],
+                    padding: [0; 2],
+                }),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Panorama Level Bind Group"),
+                layout: &level_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            })
+        })
+        .collect();
+
+    let texture_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Panorama Output Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+
+    let pyramid_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Panorama Pyramid Buffer"),
+        size: texels * TEXEL_SIZE,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    let best_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Panorama Weight Buffer"),
+        size: (width * height) as u64 * 4,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let canvas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Panorama Canvas Bind Group"),
+        layout: &canvas_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: pyramid_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: best_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&output_view),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Panorama Pipeline Layout"),
+        bind_group_layouts: &[&tile_layout, &level_layout, &canvas_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Panorama Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let weigh_pipeline = pipeline("weigh");
+    let warp_pipeline = pipeline("warp");
+    let reduce_pipeline = pipeline("reduce");
+    let accumulate_pipeline = pipeline("accumulate");
+    let collapse_pipeline = pipeline("collapse");
+    let output_pipeline = pipeline("output");
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Panorama Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Panorama Pass"),
+        });
+        compute_pass.set_bind_group(2, &canvas_bind_group, &[]);
+
+        let mut dispatch = |tile: usize, pipeline, level: usize| {
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &tile_bind_groups[tile], &[]);
+            compute_pass.set_bind_group(1, &level_bind_groups[level], &[]);
+            compute_pass.dispatch_workgroups(sizes[level].0, sizes[level].1, 1);
+        };
+
+        // The seam masks need the weights of all tiles, and every tile its
+        // mask before its bands can be blended.
+        for tile in 0..tiles.len() {
+            dispatch(tile, &weigh_pipeline, 0);
+        }
+
+        for tile in 0..tiles.len() {
+            dispatch(tile, &warp_pipeline, 0);
+            for level in 1..sizes.len() {
+                dispatch(tile, &reduce_pipeline, level);
+            }
+            for level in 0..sizes.len() {
+                dispatch(tile, &accumulate_pipeline, level);
+            }
+        }
+
+        // The remaining passes don't read a tile.
+        for level in (0..sizes.len()).rev() {
+            dispatch(0, &collapse_pipeline, level);
+        }
+        dispatch(0, &output_pipeline, 0);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let buffer = crate::read_texture(device, queue, &output_texture, 0, texture_size).await?;
+
+    RgbaImage::from_raw(width, height, buffer)
+        .ok_or_else(|| anyhow!("Panorama buffer does not match the image size"))
+}
@@ -0,0 +1,265 @@
+//! Parameters of kernels of one's own, passed in a uniform buffer. A kernel
+//! declares them as a struct of scalars and vectors at [`PARAMS_GROUP`],
+//! [`PARAMS_BINDING`]:
+//!
+//! ```wgsl
+//! struct Params {
+//!     strength: f32,
+//!     tint: vec3<f32>,
+//! }
+//!
+//! @group(2) @binding(0)
+//! var<uniform> params: Params;
+//! ```
+//!
+//! [`ParamLayout`] reads the offsets and types of the members from the WGSL
+//! and lays out values by name, so the caller needn't match the padding
+//! rules of uniform buffers. Group 1 stays free for other resources.
+
+use serde::Serialize;
+
+use crate::{Error, Result};
+
+/// Bind group of the parameter struct.
+pub const PARAMS_GROUP: u32 = 2;
+/// Binding of the parameter struct within [`PARAMS_GROUP`].
+pub const PARAMS_BINDING: u32 = 0;
+
+/// Component type of a parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scalar {
+    F32,
+    I32,
+    U32,
+}
+
+/// A member of the parameter struct.
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: String,
+    /// Bytes from the start of the struct.
+    pub offset: u32,
+    pub scalar: Scalar,
+    /// 1 for a scalar, 2 to 4 for a vector.
+    pub components: u32,
+}
+
+/// The parameter struct a shader declares, see the [module](self) docs.
+#[derive(Clone, Debug)]
+pub struct ParamLayout {
+    fields: Vec<Field>,
+    size: u32,
+}
+
+impl ParamLayout {
+    /// The parameter struct of the WGSL in `shader`, `None` if it declares
+    /// none.
+    pub fn reflect(shader: &str) -> Result<Option<Self>> {
+        let module = naga::front::wgsl::parse_str(shader)
+            .map_err(|err| Error::InvalidKernel(err.emit_to_string(shader)))?;
+
+        let Some(var) = module.global_variables.iter().find_map(|(_, var)| {
+            var.binding
+                .as_ref()
+                .filter(|binding| {
+                    (binding.group, binding.binding) == (PARAMS_GROUP, PARAMS_BINDING)
+                })
+                .map(|_| var)
+        }) else {
+            return Ok(None);
+        };
+
+        let naga::TypeInner::Struct { members, span } = &module.types[var.ty].inner else {
+            return Err(Error::InvalidParams(
+                "the parameters need to be declared as a struct".to_string(),
+            ));
+        };
+        if var.space != naga::AddressSpace::Uniform {
+            return Err(Error::InvalidParams(
+                "the parameters need to be declared as var<uniform>".to_string(),
+            ));
+        }
+
+        let fields = members
+            .iter()
+            .map(|member| {
+                let name = member.name.clone().unwrap_or_default();
+                let (kind, width, components) = match module.types[member.ty].inner {
+                    naga::TypeInner::Scalar { kind, width } => (kind, width, 1),
+                    naga::TypeInner::Vector { size, kind, width } => (kind, width, size as u32),
+                    _ => (naga::ScalarKind::Bool, 0, 0),
+                };
+                let scalar = match (kind, width) {
+                    (naga::ScalarKind::Float, 4) => Scalar::F32,
+                    (naga::ScalarKind::Sint, 4) => Scalar::I32,
+                    (naga::ScalarKind::Uint, 4) => Scalar::U32,
+                    _ => {
+                        return Err(Error::InvalidParams(format!(
+                            "'{}' isn't a scalar or vector of f32, i32 or u32",
+                            name
+                        )))
+                    }
+                };
+
+                Ok(Field {
+                    name,
+                    offset: member.offset,
+                    scalar,
+                    components,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Some(Self {
+            fields,
+            size: *span,
+        }))
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Bytes of the uniform buffer.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The buffer contents for `values` by name, zero for the fields they
+    /// leave out. A name such as `tint.y` sets one component of a vector,
+    /// a bare `tint` all of them.
+    pub fn encode(&self, values: &[(&str, f32)]) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; self.size as usize];
+
+        for &(key, value) in values {
+            let (name, component) = match key.split_once('.') {
+                Some((name, component)) => (name, Some(component)),
+                None => (key, None),
+            };
+            let field = self.field(name)?;
+
+            let components = match component {
+                None => 0..field.components,
+                Some(component) => {
+                    let index = ["x", "y", "z", "w"]
+                        .iter()
+                        .position(|&axis| axis == component)
+                        .map(|index| index as u32)
+                        .filter(|&index| index < field.components)
+                        .ok_or_else(|| {
+                            Error::InvalidParams(format!(
+                                "'{}' has no component '{}'",
+                                name, component
+                            ))
+                        })?;
+                    index..index + 1
+                }
+            };
+
+            for index in components {
+                write_component(&mut bytes, field, index, value as f64, key)?;
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// The buffer contents for `value`, a struct or map whose fields are
+    /// named like those of the shader, numbers for scalars and arrays of
+    /// them for vectors. Fields it leaves out are zero.
+    pub fn encode_serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        let value =
+            serde_json::to_value(value).map_err(|err| Error::InvalidParams(err.to_string()))?;
+        let serde_json::Value::Object(values) = value else {
+            return Err(Error::InvalidParams(
+                "parameters need to serialize as a struct or map".to_string(),
+            ));
+        };
+
+        let mut bytes = vec![0; self.size as usize];
+
+        for (name, value) in &values {
+            let field = self.field(name)?;
+            let components = match value {
+                serde_json::Value::Array(components) => components.iter().collect(),
+                value => vec![value],
+            };
+            if components.len() != field.components as usize {
+                return Err(Error::InvalidParams(format!(
+                    "'{}' has {} components, got {}",
+                    name,
+                    field.components,
+                    components.len()
+                )));
+            }
+
+            for (index, component) in components.into_iter().enumerate() {
+                let number = component.as_f64().ok_or_else(|| {
+                    Error::InvalidParams(format!("'{}' needs numbers, got {}", name, component))
+                })?;
+                write_component(&mut bytes, field, index as u32, number, name)?;
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn field(&self, name: &str) -> Result<&Field> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| Error::InvalidParams(format!("the shader declares no '{}'", name)))
+    }
+}
+
+/// Layout entry of the parameter buffer at [`PARAMS_BINDING`].
+pub fn params_layout_entry() -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: PARAMS_BINDING,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Writes `value` as component `index` of `field`, which integer fields
+/// only take whole and within their range.
+fn write_component(
+    bytes: &mut [u8],
+    field: &Field,
+    index: u32,
+    value: f64,
+    key: &str,
+) -> Result<()> {
+    let integral = |min: f64, max: f64| {
+        if value.fract() != 0.0 || value < min || value > max {
+            Err(Error::InvalidParams(format!(
+                "'{}' takes whole numbers within {}..={}, got {}",
+                key, min, max, value
+            )))
+        } else {
+            Ok(())
+        }
+    };
+
+    let component = match field.scalar {
+        Scalar::F32 => (value as f32).to_le_bytes(),
+        Scalar::I32 => {
+            integral(i32::MIN as f64, i32::MAX as f64)?;
+            (value as i32).to_le_bytes()
+        }
+        Scalar::U32 => {
+            integral(0.0, u32::MAX as f64)?;
+            (value as u32).to_le_bytes()
+        }
+    };
+
+    let start = (field.offset + index * 4) as usize;
+    bytes[start..start + 4].copy_from_slice(&component);
+
+    Ok(())
+}
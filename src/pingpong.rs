@@ -0,0 +1,130 @@
+/// Two equally sized textures that iterative passes alternate between, every
+/// pass reading the result of the one before and writing the other texture.
+pub struct PingPong {
+    textures: [wgpu::Texture; 2],
+    size: wgpu::Extent3d,
+    /// Index of the texture holding the latest result.
+    current: usize,
+}
+
+impl PingPong {
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let create = || {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[format],
+            })
+        };
+
+        Self {
+            textures: [create(), create()],
+            size,
+            // Nothing has been written yet, so the first pass may read either.
+            current: 1,
+        }
+    }
+
+    /// Starts over from RGBA8 texels, as if a pass had produced them.
+    pub fn upload(&mut self, queue: &wgpu::Queue, buffer: &[u8]) {
+        self.current = 0;
+        crate::write_input_texture(queue, &self.textures[0], self.size, buffer);
+    }
+
+    /// The texture holding the latest result, e.g. to read it back.
+    pub fn latest_texture(&self) -> &wgpu::Texture {
+        &self.textures[self.current]
+    }
+
+    /// Bind groups for both directions, made of `entries` plus the texture
+    /// read at `read_binding` and the one written at `write_binding`. Hand
+    /// them to [`PingPong::step`] and [`PingPong::latest`].
+    pub fn bind_groups(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        label: &str,
+        (read_binding, write_binding): (u32, u32),
+        entries: &[wgpu::BindGroupEntry],
+    ) -> [wgpu::BindGroup; 2] {
+        let views = self
+            .textures
+            .each_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        [0, 1].map(|read| {
+            let mut entries = entries.to_vec();
+            entries.push(wgpu::BindGroupEntry {
+                binding: read_binding,
+                resource: wgpu::BindingResource::TextureView(&views[read]),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: write_binding,
+                resource: wgpu::BindingResource::TextureView(&views[1 - read]),
+            });
+
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries: &entries,
+            })
+        })
+    }
+
+    /// Bind groups made of `entries` plus either texture at `read_binding`,
+    /// for passes of another layout that only read the latest result. Hand
+    /// them to [`PingPong::latest`].
+    pub fn read_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        label: &str,
+        read_binding: u32,
+        entries: &[wgpu::BindGroupEntry],
+    ) -> [wgpu::BindGroup; 2] {
+        let views = self
+            .textures
+            .each_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        [0, 1].map(|read| {
+            let mut entries = entries.to_vec();
+            entries.push(wgpu::BindGroupEntry {
+                binding: read_binding,
+                resource: wgpu::BindingResource::TextureView(&views[read]),
+            });
+
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries: &entries,
+            })
+        })
+    }
+
+    /// Bind group for a pass producing a new result, which becomes the
+    /// latest one.
+    pub fn step<'a>(&mut self, bind_groups: &'a [wgpu::BindGroup; 2]) -> &'a wgpu::BindGroup {
+        let bind_group = &bind_groups[self.current];
+        self.current = 1 - self.current;
+        bind_group
+    }
+
+    /// Bind group for a pass that only reads the latest result.
+    pub fn latest<'a>(&self, bind_groups: &'a [wgpu::BindGroup; 2]) -> &'a wgpu::BindGroup {
+        &bind_groups[self.current]
+    }
+}
@@ -0,0 +1,242 @@
+//! Shader modules, bind group layouts and compute pipelines kept for as long
+//! as a [`GpuContext`](crate::GpuContext), so that running a kernel over more
+//! images only creates the textures, buffers and bind groups of each.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+/// What a compute pipeline is created from. The formats of the textures
+/// it writes are part of the entries of its layouts.
+#[derive(PartialEq, Eq, Hash)]
+struct PipelineKey {
+    shader: u64,
+    entry_point: String,
+    layouts: Vec<Vec<wgpu::BindGroupLayoutEntry>>,
+    push_constant_size: u32,
+}
+
+#[derive(Default)]
+pub struct PipelineCache {
+    modules: Mutex<HashMap<u64, Arc<wgpu::ShaderModule>>>,
+    layouts: Mutex<HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>>,
+    pipelines: Mutex<HashMap<PipelineKey, Arc<wgpu::ComputePipeline>>>,
+}
+
+impl PipelineCache {
+    /// The module compiled from the WGSL in `source`.
+    pub fn shader_module(&self, device: &wgpu::Device, source: &str) -> Arc<wgpu::ShaderModule> {
+        self.modules
+            .lock()
+            .unwrap()
+            .entry(hash(source))
+            .or_insert_with(|| {
+                Arc::new(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("Shader Module"),
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                }))
+            })
+            .clone()
+    }
+
+    /// The layout of `entries`, one and the same for everything binding
+    /// them, so bind groups fit every pipeline laid out alike. `label` names
+    /// it when it is first created.
+    pub fn bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Arc<wgpu::BindGroupLayout> {
+        self.layouts
+            .lock()
+            .unwrap()
+            .entry(entries.to_vec())
+            .or_insert_with(|| {
+                Arc::new(
+                    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: Some(label),
+                        entries,
+                    }),
+                )
+            })
+            .clone()
+    }
+
+    /// The pipeline running `entry_point` of the WGSL in `source`, with the
+    /// bind groups laid out as `layouts` in group order.
+    pub fn compute_pipeline(
+        &self,
+        device: &wgpu::Device,
+        source: &str,
+        entry_point: &str,
+        layouts: &[&[wgpu::BindGroupLayoutEntry]],
+    ) -> Arc<wgpu::ComputePipeline> {
+        self.compute_pipeline_with_push_constants(device, source, entry_point, layouts, 0)
+    }
+
+    /// Like [`PipelineCache::compute_pipeline`], for a shader taking
+    /// `push_constant_size` bytes of push constants, none for 0.
+    pub fn compute_pipeline_with_push_constants(
+        &self,
+        device: &wgpu::Device,
+        source: &str,
+        entry_point: &str,
+        layouts: &[&[wgpu::BindGroupLayoutEntry]],
+        push_constant_size: u32,
+    ) -> Arc<wgpu::ComputePipeline> {
+        let key = PipelineKey {
+            shader: hash(source),
+            entry_point: entry_point.to_string(),
+            layouts: layouts.iter().map(|entries| entries.to_vec()).collect(),
+            push_constant_size,
+        };
+
+        if let Some(pipeline) = self.pipelines.lock().unwrap().get(&key) {
+            return pipeline.clone();
+        }
+
+        log::debug!("Creating the pipeline of '{}'", entry_point);
+
+        let module = self.shader_module(device, source);
+        let bind_group_layouts: Vec<_> = layouts
+            .iter()
+            .map(|entries| self.bind_group_layout(device, "Bind Group Layout", entries))
+            .collect();
+        let bind_group_layouts: Vec<_> = bind_group_layouts.iter().map(Arc::as_ref).collect();
+
+        let push_constant_range = wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::COMPUTE,
+            range: 0..push_constant_size,
+        };
+        let push_constant_ranges = match push_constant_size {
+            0 => &[],
+            _ => std::slice::from_ref(&push_constant_range),
+        };
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges,
+        });
+        let pipeline = Arc::new(
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+            }),
+        );
+
+        self.pipelines.lock().unwrap().insert(key, pipeline.clone());
+
+        pipeline
+    }
+
+    /// Drops the module and pipelines of `source`. wgpu hands out invalid
+    /// ones for shaders failing validation, which mustn't be reused.
+    pub fn forget(&self, source: &str) {
+        let shader = hash(source);
+
+        self.modules.lock().unwrap().remove(&shader);
+        self.pipelines
+            .lock()
+            .unwrap()
+            .retain(|key, _| key.shader != shader);
+    }
+}
+
+fn hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+
+    const SOURCE: &str = "
+@group(0) @binding(0) var<storage, read_write> data: array<u32>;
+
+@compute @workgroup_size(1)
+fn double(@builtin(global_invocation_id) id: vec3<u32>) {
+  data[id.x] *= 2u;
+}
+
+@compute @workgroup_size(1)
+fn clear(@builtin(global_invocation_id) id: vec3<u32>) {
+  data[id.x] = 0u;
+}
+";
+
+    fn entries(read_only: bool) -> [wgpu::BindGroupLayoutEntry; 1] {
+        [wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }]
+    }
+
+    #[test]
+    fn creates_every_pipeline_once() {
+        let Some(context) = context() else {
+            return;
+        };
+        let (device, cache) = (&context.device, PipelineCache::default());
+        let layout = entries(false);
+
+        let double = cache.compute_pipeline(device, SOURCE, "double", &[&layout]);
+        assert!(Arc::ptr_eq(
+            &double,
+            &cache.compute_pipeline(device, SOURCE, "double", &[&layout])
+        ));
+        let clear = cache.compute_pipeline(device, SOURCE, "clear", &[&layout]);
+        assert!(!Arc::ptr_eq(&double, &clear));
+
+        // One module for both entry points, one layout for both pipelines.
+        assert_eq!(cache.modules.lock().unwrap().len(), 1);
+        assert!(Arc::ptr_eq(
+            &cache.shader_module(device, SOURCE),
+            &cache.shader_module(device, SOURCE)
+        ));
+        assert!(Arc::ptr_eq(
+            &cache.bind_group_layout(device, "Data", &layout),
+            &cache.bind_group_layout(device, "Other Label", &layout)
+        ));
+        assert!(!Arc::ptr_eq(
+            &cache.bind_group_layout(device, "Data", &layout),
+            &cache.bind_group_layout(device, "Data", &entries(true))
+        ));
+    }
+
+    #[test]
+    fn forgets_the_pipelines_of_a_shader() {
+        let Some(context) = context() else {
+            return;
+        };
+        let (device, cache) = (&context.device, PipelineCache::default());
+        let layout = entries(false);
+        let other = SOURCE.replace("2u", "3u");
+
+        let double = cache.compute_pipeline(device, SOURCE, "double", &[&layout]);
+        cache.compute_pipeline(device, &other, "double", &[&layout]);
+        cache.forget(SOURCE);
+
+        assert_eq!(cache.modules.lock().unwrap().len(), 1);
+        assert_eq!(cache.pipelines.lock().unwrap().len(), 1);
+        assert!(!Arc::ptr_eq(
+            &double,
+            &cache.compute_pipeline(device, SOURCE, "double", &[&layout])
+        ));
+    }
+}
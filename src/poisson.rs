@@ -0,0 +1,219 @@
+use anyhow::*;
+use image::{GrayImage, RgbaImage};
+use std::borrow::Cow;
+
+use crate::pingpong::PingPong;
+
+/// Where and how a source patch is cloned into a destination image.
+pub struct CloneSettings<'a> {
+    /// Texels of the source to clone, from its alpha when not given.
+    pub mask: Option<&'a GrayImage>,
+    /// Position of the source's top left corner in the destination.
+    pub offset: (u32, u32),
+    pub iterations: u32,
+}
+
+/// Fills the texels selected by `mask` by diffusing the colors around them
+/// inward, for removing markers, logos or seams. This is cloning a flat patch
+/// covering the holes: without source gradients only the smooth (harmonic)
+/// interpolation of the hole borders remains.
+pub async fn inpaint(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &RgbaImage,
+    mask: &GrayImage,
+    iterations: u32,
+) -> Result<RgbaImage> {
+    if mask.dimensions() != image.dimensions() {
+        bail!("The inpainting mask needs to be the size of the image");
+    }
+
+    let flat = RgbaImage::new(image.width(), image.height());
+
+    let settings = CloneSettings {
+        mask: Some(mask),
+        offset: (0, 0),
+        iterations,
+    };
+
+    seamless_clone(device, queue, image, &flat, &settings).await
+}
+
+/// Pastes `source` into `destination` so that it keeps its own detail but
+/// takes on the colors around it (Poisson image editing), solved with
+/// Jacobi iterations on the GPU.
+pub async fn seamless_clone(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    destination: &RgbaImage,
+    source: &RgbaImage,
+    settings: &CloneSettings<'_>,
+) -> Result<RgbaImage> {
+    let (x, y) = settings.offset;
+    if x + source.width() > destination.width() || y + source.height() > destination.height() {
+        bail!(
+            "A {}x{} source at {},{} doesn't fit into the {}x{} destination",
+            source.width(),
+            source.height(),
+            x,
+            y,
+            destination.width(),
+            destination.height()
+        );
+    }
+
+    if let Some(mask) = settings.mask {
+        if mask.dimensions() != source.dimensions() {
+            bail!("The clone mask needs to be the size of the source");
+        }
+    }
+
+    // The solver runs over the whole destination, with the source placed at
+    // its offset and the mask moved into alpha.
+    let mut placed = RgbaImage::new(destination.width(), destination.height());
+    for (source_x, source_y, pixel) in source.enumerate_pixels() {
+        let mut texel = *pixel;
+        if let Some(mask) = settings.mask {
+            texel.0[3] = mask.get_pixel(source_x, source_y).0[0];
+        }
+        placed.put_pixel(x + source_x, y + source_y, texel);
+    }
+
+    let texture_size = wgpu::Extent3d {
+        width: destination.width(),
+        height: destination.height(),
+        depth_or_array_layers: 1,
+    };
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Poisson Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/poisson.wgsl"))),
+    });
+
+    let storage_entry = |binding, format| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    };
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Poisson Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            crate::input_texture_layout_entry(1),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            storage_entry(3, wgpu::TextureFormat::Rgba32Float),
+            storage_entry(4, wgpu::TextureFormat::Rgba8Unorm),
+        ],
+    });
+
+    let destination_texture = crate::create_input_texture(device, queue, texture_size, destination);
+    let source_texture = crate::create_input_texture(device, queue, texture_size, &placed);
+
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Poisson Output Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+
+    let mut correction = PingPong::new(
+        device,
+        "Poisson Correction Texture",
+        texture_size,
+        wgpu::TextureFormat::Rgba32Float,
+    );
+
+    let destination_view = destination_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let source_view = source_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_groups = correction.bind_groups(
+        device,
+        &bind_group_layout,
+        "Poisson Bind Group",
+        (2, 3),
+        &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&destination_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&source_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&output_view),
+            },
+        ],
+    );
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Poisson Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Poisson Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let init_pipeline = pipeline("init");
+    let jacobi_pipeline = pipeline("jacobi");
+    let compose_pipeline = pipeline("compose");
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Poisson Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Poisson Pass"),
+        });
+
+        compute_pass.set_pipeline(&init_pipeline);
+        compute_pass.set_bind_group(0, correction.step(&bind_groups), &[]);
+        compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+
+        compute_pass.set_pipeline(&jacobi_pipeline);
+        for _ in 0..settings.iterations {
+            compute_pass.set_bind_group(0, correction.step(&bind_groups), &[]);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+        }
+
+        compute_pass.set_pipeline(&compose_pipeline);
+        compute_pass.set_bind_group(0, correction.latest(&bind_groups), &[]);
+        compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let buffer = crate::read_texture(device, queue, &output_texture, 0, texture_size).await?;
+
+    RgbaImage::from_raw(texture_size.width, texture_size.height, buffer)
+        .ok_or_else(|| anyhow!("Poisson buffer does not match the image size"))
+}
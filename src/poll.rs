@@ -0,0 +1,103 @@
+//! Ways of getting the device to finish the work a readback waits for.
+//! wgpu only calls the callback of a buffer map from `Device::poll`, so
+//! something has to poll, and what fits depends on where the library runs.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long [`PollThread`] sleeps once the device has no work left.
+const IDLE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Drives the device while a readback waits for its buffer to get mapped.
+pub trait Poller: Send + Sync {
+    /// Called whenever a readback finds its buffer not mapped yet. Returns
+    /// whether to check again on the next poll of the future; if not, the
+    /// readback sleeps until the map callback wakes it, so something else
+    /// has to keep polling the device.
+    fn poll(&self, device: &wgpu::Device) -> bool;
+}
+
+/// Blocks the thread until the device is done, like `pollster`. Simplest
+/// for the blocking wrappers, but stalls every other task of an executor.
+pub struct Wait;
+
+/// Polls the device without blocking each time the future is, which keeps
+/// other tasks running at the cost of a busy loop. The default.
+pub struct Spin;
+
+/// Leaves polling to the caller, such as a GUI event loop calling
+/// `device.poll(wgpu::Maintain::Poll)` once a frame.
+pub struct External;
+
+/// Polls the device from a thread of its own until dropped, so readbacks
+/// just sleep until their buffer is mapped.
+pub struct PollThread {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Poller for Wait {
+    fn poll(&self, device: &wgpu::Device) -> bool {
+        device.poll(wgpu::Maintain::Wait);
+        true
+    }
+}
+
+impl Poller for Spin {
+    fn poll(&self, device: &wgpu::Device) -> bool {
+        device.poll(wgpu::Maintain::Poll);
+        true
+    }
+}
+
+impl Poller for External {
+    fn poll(&self, _device: &wgpu::Device) -> bool {
+        false
+    }
+}
+
+impl PollThread {
+    /// Starts polling `device`, typically the one of a
+    /// [`GpuContext`](crate::GpuContext).
+    pub fn spawn(device: Arc<wgpu::Device>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    if device.poll(wgpu::Maintain::Wait) {
+                        thread::sleep(IDLE_INTERVAL);
+                    }
+                }
+            }
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Poller for PollThread {
+    fn poll(&self, _device: &wgpu::Device) -> bool {
+        false
+    }
+}
+
+impl Drop for PollThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
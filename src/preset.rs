@@ -0,0 +1,392 @@
+//! Operations and their settings saved with `--save-preset` and replayed with
+//! `--preset`, as TOML, e.g.
+//!
+//! ```toml
+//! base = "toon.toml"
+//! op = "posterize"
+//! iterations = 2
+//!
+//! [params]
+//! levels = 5.0
+//! ```
+//!
+//! `base` names another preset, relative to this one, whose settings apply
+//! first and are overridden by the ones here. A preset holds the one
+//! operation a run applies: a chain of operations is a chain of runs, each
+//! with a preset of its own, so presets compose but don't chain.
+
+use anyhow::*;
+use clap::parser::ValueSource;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::cli::Args;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Preset {
+    /// The preset this one applies on top of, resolved and merged in by
+    /// [`Preset::load`].
+    #[serde(skip_serializing)]
+    base: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub op: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shader: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, f32>,
+}
+
+impl Preset {
+    /// The settings of `args`, with `params` the overrides of the run. A
+    /// parameter set more than once keeps the value set last.
+    pub fn from_args(args: &Args, params: &[(String, f32)]) -> Self {
+        Self {
+            base: None,
+            op: args.shader.is_none().then(|| args.op.clone()),
+            shader: args.shader.clone(),
+            entry_point: args.shader.is_some().then(|| args.entry_point.clone()),
+            iterations: args.iterations,
+            seed: Some(args.seed),
+            params: params.iter().cloned().collect(),
+        }
+    }
+
+    /// Reads the preset at `path` along with the presets it is based on.
+    pub fn load(path: &Path) -> Result<Self> {
+        load_with_bases(path, &mut Vec::new(), None)
+    }
+
+    /// Like [`Preset::load`], refusing bases outside of `root`, for presets
+    /// received from elsewhere.
+    pub fn load_within(path: &Path, root: &Path) -> Result<Self> {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Failed to find {}", root.display()))?;
+
+        load_with_bases(path, &mut Vec::new(), Some(&root))
+    }
+
+    /// The preset at `path` and the presets it is based on, in that order.
+    pub fn files(path: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        load_with_bases(path, &mut files, None)?;
+
+        Ok(files)
+    }
+
+    /// Parses a preset, with relative paths taken from `dir`.
+    fn parse(text: &str, dir: &Path) -> Result<Self> {
+        let mut preset: Self = toml::from_str(text)?;
+        preset.base = preset.base.map(|base| dir.join(base));
+        preset.shader = preset.shader.map(|shader| dir.join(shader));
+
+        Ok(preset)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let shader = self
+            .shader
+            .as_ref()
+            .map(|shader| {
+                shader
+                    .canonicalize()
+                    .with_context(|| format!("Failed to find {}", shader.display()))
+            })
+            .transpose()?;
+        let text = toml::to_string(&Self {
+            base: None,
+            op: self.op.clone(),
+            shader,
+            entry_point: self.entry_point.clone(),
+            iterations: self.iterations,
+            seed: self.seed,
+            params: self.params.clone(),
+        })?;
+
+        fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Fills in the settings of `args` the command line left out, as told by
+    /// `source`, and puts the parameters before the ones given there.
+    pub fn apply(self, args: &mut Args, source: impl Fn(&str) -> Option<ValueSource>) {
+        let given = |id| source(id) == Some(ValueSource::CommandLine);
+
+        // An operation or shader on the command line replaces the kernel of
+        // the preset, along with the parameters meant for it.
+        if !given("op") && !given("shader") {
+            if let Some(op) = self.op {
+                args.op = op;
+            }
+            if self.shader.is_some() {
+                args.shader = self.shader;
+            }
+            if let Some(entry_point) = self.entry_point.filter(|_| !given("entry_point")) {
+                args.entry_point = entry_point;
+            }
+
+            let given_params =
+                std::mem::replace(&mut args.params, self.params.into_iter().collect());
+            args.params.extend(given_params);
+        }
+
+        if !given("iterations") {
+            args.iterations = self.iterations.or(args.iterations);
+        }
+        if let Some(seed) = self.seed.filter(|_| !given("seed")) {
+            args.seed = seed;
+        }
+    }
+
+    /// `self` with `other` on top.
+    fn merge(mut self, other: Self) -> Self {
+        if other.op.is_some() || other.shader.is_some() {
+            self.op = other.op;
+            self.shader = other.shader;
+        }
+        self.entry_point = other.entry_point.or(self.entry_point);
+        self.iterations = other.iterations.or(self.iterations);
+        self.seed = other.seed.or(self.seed);
+        self.params.extend(other.params);
+
+        self
+    }
+}
+
+/// Loads `path` on top of its base, `loaded` holding the presets that are
+/// being loaded already to catch cycles, and every base within `root` if
+/// given.
+fn load_with_bases(path: &Path, loaded: &mut Vec<PathBuf>, root: Option<&Path>) -> Result<Preset> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("Failed to find preset {}", path.display()))?;
+    if loaded.contains(&canonical) {
+        bail!("Preset {} is based on itself", path.display());
+    }
+    if let Some(root) = root.filter(|root| !canonical.starts_with(root)) {
+        bail!(
+            "Preset {} is outside of {}, refusing to read it",
+            path.display(),
+            root.display()
+        );
+    }
+
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read preset {}", path.display()))?;
+    let dir = canonical.parent().unwrap_or(Path::new("."));
+    let mut preset =
+        Preset::parse(&text, dir).with_context(|| format!("Invalid preset {}", path.display()))?;
+    loaded.push(canonical);
+
+    match preset.base.take() {
+        Some(base) => Ok(load_with_bases(&base, loaded, root)?.merge(preset)),
+        None => Ok(preset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for `test`.
+    fn scratch_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wtc-preset-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn parses_settings_and_parameters() -> Result<()> {
+        let preset = Preset::parse(
+            "op = \"blur\"\niterations = 2\nseed = 7\n\n[params]\nsigma = 3\nradius = 1.5\n",
+            Path::new("presets"),
+        )?;
+
+        assert_eq!(preset.op.as_deref(), Some("blur"));
+        assert_eq!((preset.iterations, preset.seed), (Some(2), Some(7)));
+        assert_eq!(
+            preset.params.into_iter().collect::<Vec<_>>(),
+            [("radius".to_string(), 1.5), ("sigma".to_string(), 3.0)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_paths_next_to_the_preset() -> Result<()> {
+        let preset = Preset::parse(
+            "base = \"../toon.toml\"\nshader = \"ink.wgsl\"\nentry-point = \"main\"\n",
+            Path::new("presets"),
+        )?;
+
+        assert_eq!(preset.base, Some(PathBuf::from("presets/../toon.toml")));
+        assert_eq!(preset.shader, Some(PathBuf::from("presets/ink.wgsl")));
+        assert_eq!(preset.entry_point.as_deref(), Some("main"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_and_mistyped_settings() {
+        for text in [
+            "opp = \"blur\"",
+            "[steps]\nop = \"blur\"",
+            "iterations = -1",
+            "seed = \"7\"",
+            "[params]\nsigma = \"wide\"",
+            "op = \"blur",
+        ] {
+            assert!(Preset::parse(text, Path::new("")).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn applies_bases_first() -> Result<()> {
+        let dir = scratch_dir("bases");
+        fs::create_dir(dir.join("styles"))?;
+        fs::write(
+            dir.join("styles/toon.toml"),
+            "op = \"kuwahara\"\nseed = 1\niterations = 4\n\n[params]\nradius = 4.0\nsharpness = 8.0\n",
+        )?;
+        fs::write(
+            dir.join("soft.toml"),
+            "base = \"styles/toon.toml\"\nseed = 2\n\n[params]\nradius = 2.0\n",
+        )?;
+
+        let preset = Preset::load(&dir.join("soft.toml"))?;
+
+        assert_eq!(preset.op.as_deref(), Some("kuwahara"));
+        assert_eq!((preset.iterations, preset.seed), (Some(4), Some(2)));
+        assert_eq!(preset.params["radius"], 2.0);
+        assert_eq!(preset.params["sharpness"], 8.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn replaces_the_kernel_of_the_base() -> Result<()> {
+        let dir = scratch_dir("kernel");
+        fs::write(
+            dir.join("a.toml"),
+            "shader = \"a.wgsl\"\nentry-point = \"a\"\n",
+        )?;
+        fs::write(dir.join("b.toml"), "base = \"a.toml\"\nop = \"blur\"\n")?;
+
+        let preset = Preset::load(&dir.join("b.toml"))?;
+
+        assert_eq!(preset.op.as_deref(), Some("blur"));
+        assert_eq!(preset.shader, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_cycles_of_bases() -> Result<()> {
+        let dir = scratch_dir("cycle");
+        fs::write(dir.join("a.toml"), "base = \"b.toml\"\n")?;
+        fs::write(dir.join("b.toml"), "base = \"./a.toml\"\n")?;
+
+        let err = Preset::load(&dir.join("a.toml")).unwrap_err();
+
+        assert!(err.to_string().contains("is based on itself"), "{:#}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_the_preset_of_a_missing_base() -> Result<()> {
+        let dir = scratch_dir("missing");
+        fs::write(dir.join("a.toml"), "base = \"gone.toml\"\n")?;
+
+        let err = Preset::load(&dir.join("a.toml")).unwrap_err();
+
+        assert!(format!("{:#}", err).contains("gone.toml"), "{:#}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lists_the_presets_it_is_based_on() -> Result<()> {
+        let dir = scratch_dir("files");
+        fs::create_dir(dir.join("styles"))?;
+        fs::write(dir.join("styles/base.toml"), "seed = 1\n")?;
+        fs::write(dir.join("styles/toon.toml"), "base = \"base.toml\"\n")?;
+        fs::write(dir.join("run.toml"), "base = \"styles/toon.toml\"\n")?;
+
+        let files = Preset::files(&dir.join("run.toml"))?;
+
+        let dir = dir.canonicalize()?;
+        assert_eq!(
+            files,
+            [
+                dir.join("run.toml"),
+                dir.join("styles/toon.toml"),
+                dir.join("styles/base.toml")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_bases_outside_of_the_root() -> Result<()> {
+        let dir = scratch_dir("root");
+        fs::create_dir(dir.join("job"))?;
+        fs::write(dir.join("secret.toml"), "seed = 1\n")?;
+        fs::write(dir.join("job/inside.toml"), "seed = 2\n")?;
+
+        for base in ["../secret.toml", "../job/../secret.toml"] {
+            fs::write(dir.join("job/run.toml"), format!("base = {:?}\n", base))?;
+            let err = Preset::load_within(&dir.join("job/run.toml"), &dir.join("job")).unwrap_err();
+            assert!(
+                err.to_string().contains("outside of"),
+                "{}: {:#}",
+                base,
+                err
+            );
+        }
+
+        let absolute = dir.join("secret.toml").canonicalize()?;
+        fs::write(dir.join("job/run.toml"), format!("base = {:?}\n", absolute))?;
+        assert!(Preset::load_within(&dir.join("job/run.toml"), &dir.join("job")).is_err());
+
+        fs::write(dir.join("job/run.toml"), "base = \"./inside.toml\"\n")?;
+        let preset = Preset::load_within(&dir.join("job/run.toml"), &dir.join("job"))?;
+        assert_eq!(preset.seed, Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn saves_what_it_loads() -> Result<()> {
+        let dir = scratch_dir("save");
+        let saved = Preset {
+            op: Some("blur".to_string()),
+            iterations: Some(3),
+            seed: Some(0),
+            params: [("sigma".to_string(), 0.25)].into_iter().collect(),
+            ..Preset::default()
+        };
+        saved.save(&dir.join("blur.toml"))?;
+
+        let loaded = Preset::load(&dir.join("blur.toml"))?;
+
+        assert_eq!(loaded.op, saved.op);
+        assert_eq!((loaded.iterations, loaded.seed), (Some(3), Some(0)));
+        assert_eq!(loaded.params, saved.params);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,560 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use image::RgbaImage;
+use std::{borrow::Cow, f32::consts::PI, fs, path::PathBuf, str::FromStr};
+use wgpu::util::DeviceExt;
+
+/// Antialiasing samples per texel of the preview.
+const SAMPLES: u32 = 4;
+/// Vertical field of view of the preview camera, in degrees.
+const FOV: f32 = 40.0;
+/// The camera looks at the mesh a little from the right and from above.
+const YAW: f32 = -30.0;
+const PITCH: f32 = 20.0;
+const CAMERA_DISTANCE: f32 = 3.2;
+
+/// Shape the `--preview-3d` render puts the output on: `sphere`, `cube` or
+/// the path of a Wavefront OBJ file.
+#[derive(Clone)]
+pub enum Mesh {
+    Sphere,
+    Cube,
+    Obj(PathBuf),
+}
+
+impl FromStr for Mesh {
+    type Err = Error;
+
+    fn from_str(arg: &str) -> Result<Self> {
+        Ok(match arg {
+            "sphere" => Self::Sphere,
+            "cube" => Self::Cube,
+            path => Self::Obj(PathBuf::from(path)),
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+/// Triangles of a mesh, scaled to fit into the unit sphere.
+pub struct Geometry {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl Geometry {
+    /// Largest distance of a vertex from the center along any axis, where
+    /// the texture projections end.
+    fn extent(&self) -> f32 {
+        self.vertices
+            .iter()
+            .flat_map(|vertex| vertex.position)
+            .fold(f32::EPSILON, |extent, coordinate| {
+                extent.max(coordinate.abs())
+            })
+    }
+}
+
+impl Mesh {
+    pub fn load(&self) -> Result<Geometry> {
+        match self {
+            Self::Sphere => Ok(sphere(32, 64)),
+            Self::Cube => Ok(cube()),
+            Self::Obj(path) => {
+                let source = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                parse_obj(&source).with_context(|| format!("Invalid OBJ file {}", path.display()))
+            }
+        }
+    }
+}
+
+fn sphere(stacks: u32, slices: u32) -> Geometry {
+    let mut vertices = Vec::new();
+    for stack in 0..=stacks {
+        let polar = stack as f32 / stacks as f32 * PI;
+        for slice in 0..=slices {
+            let azimuth = slice as f32 / slices as f32 * 2.0 * PI;
+            let position = [
+                polar.sin() * azimuth.cos(),
+                polar.cos(),
+                polar.sin() * azimuth.sin(),
+            ];
+            vertices.push(Vertex {
+                position,
+                normal: position,
+            });
+        }
+    }
+
+    let row = slices + 1;
+    let mut indices = Vec::new();
+    for stack in 0..stacks {
+        for slice in 0..slices {
+            let corner = stack * row + slice;
+            indices.extend([corner, corner + 1, corner + row]);
+            indices.extend([corner + 1, corner + row + 1, corner + row]);
+        }
+    }
+
+    Geometry { vertices, indices }
+}
+
+fn cube() -> Geometry {
+    let half_size = 1.0 / 3f32.sqrt();
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for axis in 0..3 {
+        for sign in [-1.0, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = sign;
+
+            let first = vertices.len() as u32;
+            for (u, v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let mut position = [0.0; 3];
+                position[axis] = sign * half_size;
+                position[(axis + 1) % 3] = u * half_size;
+                position[(axis + 2) % 3] = v * half_size;
+                vertices.push(Vertex { position, normal });
+            }
+
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    Geometry { vertices, indices }
+}
+
+/// Reads the vertex positions and faces of an OBJ file, fanning polygons
+/// into triangles. Normals are recomputed smooth, texture coordinates and
+/// everything else are ignored.
+fn parse_obj(source: &str) -> Result<Geometry> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut indices = Vec::new();
+
+    for line in source.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut position = [0.0; 3];
+                for coordinate in &mut position {
+                    *coordinate = tokens
+                        .next()
+                        .ok_or_else(|| anyhow!("Vertex with fewer than three coordinates"))?
+                        .parse()?;
+                }
+                positions.push(position);
+            }
+            Some("f") => {
+                let corners = tokens
+                    .map(|corner| {
+                        // `v`, `v/vt`, `v//vn` or `v/vt/vn`, counting from
+                        // one or back from the latest vertex when negative.
+                        let index: i64 = corner.split('/').next().unwrap_or_default().parse()?;
+                        let resolved = if index < 0 {
+                            positions.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+
+                        if !(0..positions.len() as i64).contains(&resolved) {
+                            bail!("Face refers to missing vertex {}", index);
+                        }
+                        Ok(resolved as u32)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                for pair in corners.windows(2).skip(1) {
+                    indices.extend([corners[0], pair[0], pair[1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if indices.is_empty() {
+        bail!("No faces found");
+    }
+
+    // Center the mesh and scale it into the unit sphere.
+    let (low, high) =
+        positions
+            .iter()
+            .fold(([f32::MAX; 3], [f32::MIN; 3]), |(low, high), position| {
+                (
+                    [0, 1, 2].map(|axis| low[axis].min(position[axis])),
+                    [0, 1, 2].map(|axis| high[axis].max(position[axis])),
+                )
+            });
+    let center = [0, 1, 2].map(|axis| (low[axis] + high[axis]) * 0.5);
+    let radius = positions
+        .iter()
+        .map(|position| length(sub(*position, center)))
+        .fold(0.0, f32::max)
+        .max(f32::EPSILON);
+
+    let mut vertices: Vec<Vertex> = positions
+        .iter()
+        .map(|position| Vertex {
+            position: sub(*position, center).map(|coordinate| coordinate / radius),
+            normal: [0.0; 3],
+        })
+        .collect();
+
+    // Summing the unnormalized face normals weighs them by area.
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize].position);
+        let normal = cross(sub(b, a), sub(c, a));
+        for &index in triangle {
+            let vertex = &mut vertices[index as usize];
+            vertex.normal = [0, 1, 2].map(|axis| vertex.normal[axis] + normal[axis]);
+        }
+    }
+    for vertex in &mut vertices {
+        let normal_length = length(vertex.normal).max(f32::EPSILON);
+        vertex.normal = vertex.normal.map(|coordinate| coordinate / normal_length);
+    }
+
+    Ok(Geometry { vertices, indices })
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f32; 3]) -> f32 {
+    (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt()
+}
+
+/// Column-major 4x4 matrix, as WGSL expects it.
+type Matrix = [[f32; 4]; 4];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 4]; 4];
+    for (column, b_column) in product.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    product
+}
+
+/// Perspective projection onto wgpu's 0..1 depth range.
+fn perspective(fov: f32, aspect: f32, near: f32, far: f32) -> Matrix {
+    let focal = 1.0 / (fov * 0.5).tan();
+    let depth = far / (near - far);
+    [
+        [focal / aspect, 0.0, 0.0, 0.0],
+        [0.0, focal, 0.0, 0.0],
+        [0.0, 0.0, depth, -1.0],
+        [0.0, 0.0, near * depth, 0.0],
+    ]
+}
+
+fn rotation_x(angle: f32) -> Matrix {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, cos, sin, 0.0],
+        [0.0, -sin, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn rotation_y(angle: f32) -> Matrix {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [cos, 0.0, -sin, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [sin, 0.0, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+/// Layout of `Camera` in `preview.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Camera {
+    view_projection: Matrix,
+    model: Matrix,
+    light: [f32; 4],
+    eye: [f32; 4],
+    extent: f32,
+    padding: [f32; 3],
+}
+
+/// Renders `texture` onto `geometry` with basic lighting, for judging a
+/// material in context rather than as a flat image.
+pub async fn render(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &RgbaImage,
+    geometry: &Geometry,
+    (width, height): (u32, u32),
+) -> Result<RgbaImage> {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Preview Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/preview.wgsl"))),
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Preview Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    // The texture holds sRGB colors; lighting them needs linear ones.
+    let input_texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Preview Input Texture"),
+            size: wgpu::Extent3d {
+                width: texture.width(),
+                height: texture.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba8UnormSrgb],
+        },
+        texture.as_raw(),
+    );
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Preview Sampler"),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let camera = Camera {
+        view_projection: multiply(
+            &perspective(FOV.to_radians(), width as f32 / height as f32, 0.1, 10.0),
+            &[
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, -CAMERA_DISTANCE, 1.0],
+            ],
+        ),
+        model: multiply(
+            &rotation_x(PITCH.to_radians()),
+            &rotation_y(YAW.to_radians()),
+        ),
+        light: [-0.5, 0.7, 0.6, 0.0],
+        eye: [0.0, 0.0, CAMERA_DISTANCE, 1.0],
+        extent: geometry.extent(),
+        padding: [0.0; 3],
+    };
+
+    let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Preview Camera Buffer"),
+        contents: bytemuck::bytes_of(&camera),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Preview Vertex Buffer"),
+        contents: bytemuck::cast_slice(&geometry.vertices),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Preview Index Buffer"),
+        contents: bytemuck::cast_slice(&geometry.indices),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+
+    let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Preview Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: camera_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Preview Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Preview Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader_module,
+            entry_point: "vertex",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+            }],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader_module,
+            entry_point: "fragment",
+            targets: &[Some(wgpu::TextureFormat::Rgba8UnormSrgb.into())],
+        }),
+        // Meshes from files may wind their faces either way, so nothing is
+        // culled and the depth test sorts it out.
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: SAMPLES,
+            ..Default::default()
+        },
+        multiview: None,
+    });
+
+    let output_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let attachment = |label, format, sample_count, usage| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: output_size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[format],
+        })
+    };
+
+    let multisampled_texture = attachment(
+        "Preview Multisampled Texture",
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        SAMPLES,
+        wgpu::TextureUsages::RENDER_ATTACHMENT,
+    );
+    let depth_texture = attachment(
+        "Preview Depth Texture",
+        wgpu::TextureFormat::Depth32Float,
+        SAMPLES,
+        wgpu::TextureUsages::RENDER_ATTACHMENT,
+    );
+    let output_texture = attachment(
+        "Preview Output Texture",
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        1,
+        wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    );
+
+    let multisampled_view =
+        multisampled_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Preview Encoder"),
+    });
+
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Preview Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &multisampled_view,
+                resolve_target: Some(&output_view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.02,
+                        g: 0.02,
+                        b: 0.02,
+                        a: 1.0,
+                    }),
+                    store: false,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..geometry.indices.len() as u32, 0, 0..1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let buffer = crate::read_texture(device, queue, &output_texture, 0, output_size).await?;
+
+    RgbaImage::from_raw(width, height, buffer)
+        .ok_or_else(|| anyhow!("Preview buffer does not match the preview size"))
+}
@@ -0,0 +1,1019 @@
+use bytemuck::Pod;
+#[cfg(feature = "codecs")]
+use image::RgbaImage;
+use std::{borrow::Cow, fs, marker::PhantomData, mem, path::Path, sync::Arc};
+use wgpu::{util::DeviceExt, Device, Queue};
+
+use crate::{
+    check_image_size, expand_workgroup_size,
+    op::{param_block, Op, OpResources},
+    overrides,
+    params::{params_layout_entry, ParamLayout, PARAMS_BINDING, PARAMS_GROUP},
+    read_texel_rows, read_texels, read_texels_into, workgroup_count, workgroup_size, Error,
+    GpuContext, PooledTexture, Result, Rgba8, RowStride, Texel, DATA_PER_PIXEL,
+    DEFAULT_WORKGROUP_SIZE,
+};
+#[cfg(feature = "codecs")]
+use crate::{upload_file, TexelImage};
+
+/// WGSL of the kernel copying its input unchanged, with the `basic` entry
+/// point. Doubles as the starting point for kernels of one's own.
+pub const COPY_SHADER: &str = concat!(
+    include_str!("shaders/lib/pointwise.wgsl"),
+    include_str!("shaders/compute.wgsl")
+);
+
+/// Uploads, processes and reads back RGBA8 images on one device.
+///
+/// Kernels see the input at binding 0 as a `texture_2d<f32>` and write the
+/// output to binding 1, a `texture_storage_2d<rgba8unorm, write>`, once per
+/// output texel, like [`COPY_SHADER`]. They are dispatched in workgroups of
+/// the size they declare, skipping the invocations past the edges of the
+/// output; `@workgroup_size(WORKGROUP_SIZE)` declares the size of
+/// [`TextureProcessor::with_workgroup_size`]. Kernels writing another
+/// [`Texel`] format declare the storage texture with it instead. Kernels made
+/// with [`TextureProcessor::kernel_with_push_constants`] also take a small struct
+/// as `var<push_constant>` on every dispatch, and kernels declaring a
+/// parameter struct, see [`params`](crate::params), get it in a uniform
+/// buffer.
+///
+/// Pipelines come from the [`PipelineCache`](crate::PipelineCache) of the
+/// context, so compiling a kernel again is cheap, and the textures and
+/// buffers of `process` and the like from its [`ResourcePool`](crate::ResourcePool).
+pub struct TextureProcessor {
+    context: GpuContext,
+    workgroup_size: [u32; 2],
+}
+
+/// A compute pipeline of a kernel writing `T` texels, made by
+/// [`TextureProcessor::kernel`] or [`TextureProcessor::kernel_for`].
+pub struct Kernel<T: Texel = Rgba8> {
+    pipeline: Arc<wgpu::ComputePipeline>,
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    workgroup_size: [u32; 3],
+    /// Bytes of the push constant range, 0 without one.
+    push_constant_size: u32,
+    params: Option<KernelParams>,
+    texel: PhantomData<T>,
+}
+
+/// The parameter struct of a kernel and what binds it.
+struct KernelParams {
+    layout: ParamLayout,
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// Group 1, which the pipeline layout can't skip.
+    empty_bind_group: wgpu::BindGroup,
+}
+
+impl<T: Texel> Kernel<T> {
+    /// The parameter struct the kernel declares, if any.
+    pub fn params(&self) -> Option<&ParamLayout> {
+        self.params.as_ref().map(|params| &params.layout)
+    }
+}
+
+impl TextureProcessor {
+    /// Sets up the default adapter, see [`GpuContext::new`].
+    pub async fn new() -> Result<Self> {
+        Ok(Self::with_context(GpuContext::new().await?))
+    }
+
+    /// Blocking [`TextureProcessor::new`], for callers outside of any async
+    /// runtime.
+    pub fn new_blocking() -> Result<Self> {
+        futures::executor::block_on(Self::new())
+    }
+
+    /// Processes on a context set up by the caller.
+    pub fn with_context(context: GpuContext) -> Self {
+        Self {
+            context,
+            workgroup_size: DEFAULT_WORKGROUP_SIZE,
+        }
+    }
+
+    /// Has kernels declaring `@workgroup_size(WORKGROUP_SIZE)` run in
+    /// workgroups of `width`x`height` invocations rather than
+    /// [`DEFAULT_WORKGROUP_SIZE`].
+    pub fn with_workgroup_size(mut self, [width, height]: [u32; 2]) -> Self {
+        self.workgroup_size = [width, height];
+        self
+    }
+
+    pub fn context(&self) -> &GpuContext {
+        &self.context
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.context.device
+    }
+
+    pub fn queue(&self) -> &Queue {
+        &self.context.queue
+    }
+
+    /// Compiles the kernel at `entry_point` of the WGSL in `shader`.
+    pub fn kernel(&self, shader: &str, entry_point: &str) -> Kernel {
+        self.kernel_for(shader, entry_point)
+    }
+
+    /// Compiles the kernel at `entry_point` of the WGSL in `shader`, which
+    /// writes `T` texels.
+    pub fn kernel_for<T: Texel>(&self, shader: &str, entry_point: &str) -> Kernel<T> {
+        self.build_kernel(shader, entry_point, 0)
+    }
+
+    /// Compiles the kernel at `entry_point` of the WGSL in `shader`, which
+    /// writes `T` texels, with its `override` constants specialized by name
+    /// or id to `constants`, see [`overrides`](crate::overrides). Plain
+    /// kernels take the defaults.
+    pub fn kernel_with_constants<T: Texel>(
+        &self,
+        shader: &str,
+        entry_point: &str,
+        constants: &[(&str, f64)],
+    ) -> Result<Kernel<T>> {
+        let shader = expand_workgroup_size(shader, self.workgroup_size);
+        let shader = overrides::specialize(&shader, constants)?;
+
+        Ok(self.build_kernel(&shader, entry_point, 0))
+    }
+
+    /// Compiles the kernel at `entry_point` of the WGSL in `shader`, which
+    /// writes `T` texels and takes a `P` as push constants, such as an
+    /// effect strength or the dimensions of the image, handed to it with
+    /// [`TextureProcessor::dispatch_with`]. The context needs to be set up
+    /// with enough [`AdapterOptions::push_constant_size`](crate::AdapterOptions::push_constant_size).
+    pub fn kernel_with_push_constants<T: Texel, P: Pod>(
+        &self,
+        shader: &str,
+        entry_point: &str,
+    ) -> Result<Kernel<T>> {
+        let size = mem::size_of::<P>() as u32;
+
+        if !self
+            .device()
+            .features()
+            .contains(wgpu::Features::PUSH_CONSTANTS)
+            || self.device().limits().max_push_constant_size < size
+        {
+            return Err(Error::PushConstantsUnsupported(size));
+        }
+
+        Ok(self.build_kernel(shader, entry_point, size))
+    }
+
+    /// `shader` as it is compiled, with the workgroup size of the processor
+    /// and the defaults of its `override` constants filled in.
+    fn prepare<'a>(&self, shader: &'a str) -> Cow<'a, str> {
+        let expanded = expand_workgroup_size(shader, self.workgroup_size);
+        // A declaration without a default fails to compile later.
+        match overrides::specialize(&expanded, &[]) {
+            Ok(Cow::Owned(specialized)) => Cow::Owned(specialized),
+            _ => expanded,
+        }
+    }
+
+    fn build_kernel<T: Texel>(
+        &self,
+        shader: &str,
+        entry_point: &str,
+        push_constant_size: u32,
+    ) -> Kernel<T> {
+        let shader = self.prepare(shader);
+        let (device, pipelines) = (self.device(), &self.context.pipelines);
+
+        let entries = [
+            input_texture_layout_entry(0),
+            storage_texture_layout_entry(1, T::FORMAT),
+        ];
+        let params_entries = [params_layout_entry()];
+
+        // A shader that doesn't parse fails to compile below.
+        let layout = ParamLayout::reflect(&shader).ok().flatten();
+        let mut layouts: Vec<&[wgpu::BindGroupLayoutEntry]> = vec![&entries];
+        if layout.is_some() {
+            layouts.extend([&[], &params_entries[..]]);
+        }
+
+        let pipeline = pipelines.compute_pipeline_with_push_constants(
+            device,
+            &shader,
+            entry_point,
+            &layouts,
+            push_constant_size,
+        );
+        let params = layout.map(|layout| KernelParams {
+            layout,
+            bind_group_layout: pipelines.bind_group_layout(
+                device,
+                "Bind Group Layout",
+                &params_entries,
+            ),
+            empty_bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Processor Empty Bind Group"),
+                layout: &pipelines.bind_group_layout(device, "Bind Group Layout", &[]),
+                entries: &[],
+            }),
+        });
+
+        Kernel {
+            pipeline,
+            bind_group_layout: pipelines.bind_group_layout(device, "Bind Group Layout", &entries),
+            workgroup_size: workgroup_size(&shader, entry_point),
+            push_constant_size,
+            params,
+            texel: PhantomData,
+        }
+    }
+
+    /// Like [`TextureProcessor::kernel_for`], reporting a shader that
+    /// doesn't compile or doesn't fit the bindings as an error.
+    pub async fn compile<T: Texel>(&self, shader: &str, entry_point: &str) -> Result<Kernel<T>> {
+        log::debug!("Compiling kernel '{}'", entry_point);
+
+        self.device()
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let kernel = self.kernel_for(shader, entry_point);
+
+        match self.device().pop_error_scope().await {
+            Some(err) => {
+                self.context.pipelines.forget(&self.prepare(shader));
+                Err(Error::InvalidKernel(err.to_string()))
+            }
+            None => Ok(kernel),
+        }
+    }
+
+    /// Compiles the kernel at `entry_point` of the WGSL file at `path`,
+    /// reporting a shader that doesn't compile or doesn't fit the bindings
+    /// as an error.
+    pub async fn load_kernel(&self, path: impl AsRef<Path>, entry_point: &str) -> Result<Kernel> {
+        let path = path.as_ref();
+        let shader = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        self.compile(&shader, entry_point)
+            .await
+            .map_err(|err| match err {
+                Error::InvalidKernel(message) => {
+                    Error::InvalidKernel(format!("{}: {}", path.display(), message))
+                }
+                err => err,
+            })
+    }
+
+    /// Creates a texture kernels can read from `pixels`, `width`x`height`
+    /// tightly packed RGBA8 texels.
+    pub fn upload(&self, width: u32, height: u32, pixels: &[u8]) -> Result<wgpu::Texture> {
+        check_upload(self.device(), width, height, pixels)?;
+
+        Ok(create_input_texture(
+            self.device(),
+            self.queue(),
+            texture_size(width, height),
+            pixels,
+        ))
+    }
+
+    /// Like [`TextureProcessor::upload`], decoding the image file at `path`
+    /// without a copy of it on the CPU where it can, see [`upload_file`].
+    #[cfg(feature = "codecs")]
+    pub fn upload_file(&self, path: impl AsRef<Path>) -> Result<wgpu::Texture> {
+        upload_file(self.device(), self.queue(), path)
+    }
+
+    /// Creates a `width`x`height` texture kernels can write to and that can
+    /// be read back.
+    pub fn create_output(&self, width: u32, height: u32) -> wgpu::Texture {
+        self.create_output_for::<Rgba8>(width, height)
+    }
+
+    /// Like [`TextureProcessor::create_output`], for kernels writing `T`.
+    pub fn create_output_for<T: Texel>(&self, width: u32, height: u32) -> wgpu::Texture {
+        self.device().create_texture(&output_texture_descriptor(
+            texture_size(width, height),
+            T::FORMAT,
+        ))
+    }
+
+    /// Like [`TextureProcessor::upload`], into a texture of the pool of the
+    /// context for the runs `process` and the like do.
+    fn upload_pooled(&self, width: u32, height: u32, pixels: &[u8]) -> Result<PooledTexture> {
+        check_upload(self.device(), width, height, pixels)?;
+
+        let size = texture_size(width, height);
+        let texture = self
+            .context
+            .pool
+            .texture(self.device(), &input_texture_descriptor(size));
+        write_input_texture(self.queue(), &texture, size, pixels);
+
+        Ok(texture)
+    }
+
+    /// Like [`TextureProcessor::create_output_for`], from the pool of the
+    /// context. A recycled texture is zeroed, as kernels may leave texels
+    /// of it unwritten.
+    fn create_pooled_output<T: Texel>(&self, width: u32, height: u32) -> PooledTexture {
+        let size = texture_size(width, height);
+        let texture = self
+            .context
+            .pool
+            .texture(self.device(), &output_texture_descriptor(size, T::FORMAT));
+
+        if texture.is_recycled() {
+            self.context
+                .pool
+                .zero_texture(self.device(), self.queue(), &texture);
+        }
+
+        texture
+    }
+
+    /// Runs `kernel` once for every texel of `output`, which needs to be in
+    /// the format of `T`.
+    pub fn dispatch<T: Texel>(
+        &self,
+        kernel: &Kernel<T>,
+        input: &wgpu::Texture,
+        output: &wgpu::Texture,
+    ) {
+        self.encode_dispatch(kernel, input, output, &[], &[]);
+    }
+
+    /// Like [`TextureProcessor::dispatch`], handing `constants` to a kernel
+    /// made with [`TextureProcessor::kernel_with_push_constants`]. Nothing
+    /// is rebuilt between dispatches with different constants.
+    pub fn dispatch_with<T: Texel, P: Pod>(
+        &self,
+        kernel: &Kernel<T>,
+        input: &wgpu::Texture,
+        output: &wgpu::Texture,
+        constants: &P,
+    ) -> Result<()> {
+        let constants = push_constant_bytes(kernel, constants)?;
+        self.encode_dispatch(kernel, input, output, constants, &[]);
+
+        Ok(())
+    }
+
+    /// Like [`TextureProcessor::dispatch`], handing `params`, laid out by
+    /// [`Kernel::params`], to the parameter struct of `kernel`. Plain
+    /// dispatches leave it zeroed.
+    pub fn dispatch_with_params<T: Texel>(
+        &self,
+        kernel: &Kernel<T>,
+        input: &wgpu::Texture,
+        output: &wgpu::Texture,
+        params: &[u8],
+    ) -> Result<()> {
+        check_param_bytes(kernel, params)?;
+        self.encode_dispatch(kernel, input, output, &[], params);
+
+        Ok(())
+    }
+
+    fn encode_dispatch<T: Texel>(
+        &self,
+        kernel: &Kernel<T>,
+        input: &wgpu::Texture,
+        output: &wgpu::Texture,
+        constants: &[u8],
+        params: &[u8],
+    ) {
+        let input_view = input.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = self.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Processor Bind Group"),
+            layout: &kernel.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&output_view),
+                },
+            ],
+        });
+
+        let params_bind_group = kernel.params.as_ref().map(|kernel_params| {
+            let zeros;
+            let contents = if params.is_empty() {
+                zeros = vec![0; kernel_params.layout.size() as usize];
+                &zeros
+            } else {
+                params
+            };
+            let buffer = self
+                .device()
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Processor Params Buffer"),
+                    contents,
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+
+            self.device().create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Processor Params Bind Group"),
+                layout: &kernel_params.bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: PARAMS_BINDING,
+                    resource: buffer.as_entire_binding(),
+                }],
+            })
+        });
+
+        let mut encoder = self
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Processor Encoder"),
+            });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Processor Pass"),
+            });
+            compute_pass.set_pipeline(&kernel.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            if let (Some(kernel_params), Some(params_bind_group)) =
+                (&kernel.params, &params_bind_group)
+            {
+                compute_pass.set_bind_group(1, &kernel_params.empty_bind_group, &[]);
+                compute_pass.set_bind_group(PARAMS_GROUP, params_bind_group, &[]);
+            }
+            if !constants.is_empty() {
+                compute_pass.set_push_constants(0, constants);
+            }
+            let (x, y) = workgroup_count(output.width(), output.height(), kernel.workgroup_size);
+            compute_pass.dispatch_workgroups(x, y, 1);
+        }
+
+        log::debug!("Dispatching {}x{} texels", output.width(), output.height());
+
+        self.queue().submit(Some(encoder.finish()));
+    }
+
+    /// Like [`TextureProcessor::dispatch`], reporting the bind group or
+    /// submission wgpu rejects as an error.
+    async fn dispatch_checked<T: Texel>(
+        &self,
+        kernel: &Kernel<T>,
+        input: &wgpu::Texture,
+        output: &wgpu::Texture,
+        constants: &[u8],
+        params: &[u8],
+    ) -> Result<()> {
+        self.device()
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+
+        self.encode_dispatch(kernel, input, output, constants, params);
+
+        match self.device().pop_error_scope().await {
+            Some(err) => Err(Error::Validation(err.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads back the tightly packed RGBA8 texels of `texture`.
+    pub async fn read(&self, texture: &wgpu::Texture) -> Result<Vec<u8>> {
+        self.read_with_stride(texture, RowStride::Packed).await
+    }
+
+    /// Reads back the RGBA8 texels of `texture` with rows `stride` apart,
+    /// `stride.bytes(4 * width)` bytes. Fails if wgpu reported an error
+    /// since, see [`GpuContext::check`].
+    pub async fn read_with_stride(
+        &self,
+        texture: &wgpu::Texture,
+        stride: RowStride,
+    ) -> Result<Vec<u8>> {
+        let pixels = read_texels(
+            self.device(),
+            self.queue(),
+            texture,
+            0,
+            wgpu::Origin3d::ZERO,
+            texture.size(),
+            DATA_PER_PIXEL,
+            stride,
+            &self.context.pool,
+            self.context.poller.as_ref(),
+        )
+        .await?;
+
+        self.context.check()?;
+
+        Ok(pixels)
+    }
+
+    /// Like [`TextureProcessor::read`], writing the texels into the start of
+    /// `output` instead, such as a frame buffer the caller owns. Fails with
+    /// [`Error::OutputTooSmall`] unless it holds all of them.
+    pub async fn read_into(&self, texture: &wgpu::Texture, output: &mut [u8]) -> Result<()> {
+        self.read_into_with_stride(texture, output, RowStride::Packed)
+            .await
+    }
+
+    /// Like [`TextureProcessor::read_into`], with the rows of `output`
+    /// `stride` apart. The padding between them is left as it is.
+    pub async fn read_into_with_stride(
+        &self,
+        texture: &wgpu::Texture,
+        output: &mut [u8],
+        stride: RowStride,
+    ) -> Result<()> {
+        read_texels_into(
+            self.device(),
+            self.queue(),
+            texture,
+            0,
+            wgpu::Origin3d::ZERO,
+            texture.size(),
+            DATA_PER_PIXEL,
+            output,
+            stride,
+            &self.context.pool,
+            self.context.poller.as_ref(),
+        )
+        .await?;
+
+        self.context.check()
+    }
+
+    /// Reads back the tightly packed RGBA8 texels of `texture`, handing them
+    /// to `on_rows` `rows_per_chunk` rows at a time with the index of the
+    /// first, such as to feed an encoder. See [`crate::view_rows`].
+    pub async fn read_rows(
+        &self,
+        texture: &wgpu::Texture,
+        rows_per_chunk: u32,
+        on_rows: impl FnMut(u32, &[u8]),
+    ) -> Result<()> {
+        read_texel_rows(
+            self.device(),
+            self.queue(),
+            texture,
+            texture.size(),
+            DATA_PER_PIXEL,
+            rows_per_chunk,
+            &self.context.pool,
+            self.context.poller.as_ref(),
+            on_rows,
+        )
+        .await?;
+
+        self.context.check()
+    }
+
+    /// Reads back the texels of `texture`, which needs to be in the format
+    /// of `T`, as an image. Fails if wgpu reported an error since, see
+    /// [`GpuContext::check`].
+    #[cfg(feature = "codecs")]
+    pub async fn read_image<T: Texel>(&self, texture: &wgpu::Texture) -> Result<TexelImage<T>> {
+        if texture.format() != T::FORMAT {
+            return Err(Error::UnsupportedFormat {
+                expected: T::FORMAT,
+                actual: texture.format(),
+            });
+        }
+
+        let bytes = read_texels(
+            self.device(),
+            self.queue(),
+            texture,
+            0,
+            wgpu::Origin3d::ZERO,
+            texture.size(),
+            T::BYTES,
+            RowStride::Packed,
+            &self.context.pool,
+            self.context.poller.as_ref(),
+        )
+        .await?;
+
+        self.context.check()?;
+
+        let image =
+            TexelImage::<T>::from_raw(texture.width(), texture.height(), T::channels(&bytes))
+                .expect("Read back texels match the texture size");
+
+        Ok(image)
+    }
+
+    /// Uploads `pixels`, runs `kernel` over them and reads back an output of
+    /// the same size.
+    pub async fn process(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        kernel: &Kernel,
+    ) -> Result<Vec<u8>> {
+        let input = self.upload_pooled(width, height, pixels)?;
+        let output = self.create_pooled_output::<Rgba8>(width, height);
+
+        self.dispatch_checked(kernel, &input, &output, &[], &[])
+            .await?;
+
+        self.read(&output).await
+    }
+
+    /// Like [`TextureProcessor::process`], reading the output back into
+    /// `output`, see [`TextureProcessor::read_into`].
+    pub async fn process_into(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        kernel: &Kernel,
+        output: &mut [u8],
+    ) -> Result<()> {
+        let input = self.upload_pooled(width, height, pixels)?;
+        let texture = self.create_pooled_output::<Rgba8>(width, height);
+
+        self.dispatch_checked(kernel, &input, &texture, &[], &[])
+            .await?;
+
+        self.read_into(&texture, output).await
+    }
+
+    /// Like [`TextureProcessor::process`], handing `constants` to a kernel
+    /// made with [`TextureProcessor::kernel_with_push_constants`].
+    pub async fn process_with<P: Pod>(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        kernel: &Kernel,
+        constants: &P,
+    ) -> Result<Vec<u8>> {
+        let constants = push_constant_bytes(kernel, constants)?;
+        let input = self.upload_pooled(width, height, pixels)?;
+        let output = self.create_pooled_output::<Rgba8>(width, height);
+
+        self.dispatch_checked(kernel, &input, &output, constants, &[])
+            .await?;
+
+        self.read(&output).await
+    }
+
+    /// Like [`TextureProcessor::process`], handing `params` to the parameter
+    /// struct of `kernel`, see [`TextureProcessor::dispatch_with_params`].
+    pub async fn process_with_params(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        kernel: &Kernel,
+        params: &[u8],
+    ) -> Result<Vec<u8>> {
+        check_param_bytes(kernel, params)?;
+        let input = self.upload_pooled(width, height, pixels)?;
+        let output = self.create_pooled_output::<Rgba8>(width, height);
+
+        self.dispatch_checked(kernel, &input, &output, &[], params)
+            .await?;
+
+        self.read(&output).await
+    }
+
+    /// Runs `op` over `inputs`, `width`x`height` tightly packed RGBA8 images
+    /// each, with `params` by name on top of the defaults, and reads back the
+    /// output.
+    pub async fn apply(
+        &self,
+        op: &dyn Op,
+        width: u32,
+        height: u32,
+        inputs: &[&[u8]],
+        params: &[(&str, f32)],
+    ) -> Result<Vec<u8>> {
+        if inputs.len() != op.inputs() as usize {
+            return Err(Error::InputCount {
+                op: op.name().to_string(),
+                expected: op.inputs(),
+                actual: inputs.len(),
+            });
+        }
+
+        let block = param_block(op, params)?;
+        let shader = op.shader();
+        let shader = expand_workgroup_size(&shader, self.workgroup_size);
+        let shader = overrides::specialize(&shader, &[])?;
+        let op_workgroup_size = workgroup_size(&shader, op.entry_point());
+        let (device, pipelines) = (self.device(), &self.context.pipelines);
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let entries = op.layout_entries();
+        let bind_group_layout = pipelines.bind_group_layout(device, "Bind Group Layout", &entries);
+        let pipeline = pipelines.compute_pipeline(device, &shader, op.entry_point(), &[&entries]);
+
+        if let Some(err) = device.pop_error_scope().await {
+            pipelines.forget(&shader);
+            return Err(Error::InvalidKernel(format!("{}: {}", op.name(), err)));
+        }
+
+        let textures = inputs
+            .iter()
+            .map(|pixels| self.upload_pooled(width, height, pixels))
+            .collect::<Result<Vec<_>>>()?;
+        let views: Vec<_> = textures
+            .iter()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect();
+
+        let output = self.create_pooled_output::<Rgba8>(width, height);
+        let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Op Params Buffer"),
+            contents: bytemuck::cast_slice(&block),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let bind_group = op.bind_group(
+            device,
+            &bind_group_layout,
+            &OpResources {
+                inputs: &views,
+                output: &output_view,
+                params: &params_buffer,
+            },
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Op Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Op Pass"),
+            });
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            op.encode(&mut compute_pass, output.size(), op_workgroup_size);
+        }
+
+        log::debug!("Applying '{}' to {}x{} texels", op.name(), width, height);
+
+        self.queue().submit(Some(encoder.finish()));
+
+        if let Some(err) = device.pop_error_scope().await {
+            return Err(Error::Validation(err.to_string()));
+        }
+
+        self.read(&output).await
+    }
+
+    /// Blocking [`TextureProcessor::process`], for callers outside of any
+    /// async runtime. Inside one, await `process` instead, which never blocks.
+    pub fn process_blocking(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        kernel: &Kernel,
+    ) -> Result<Vec<u8>> {
+        futures::executor::block_on(self.process(width, height, pixels, kernel))
+    }
+
+    /// Runs `kernel` over `input` and reads back an output of the same size,
+    /// with the channel type of the format the kernel writes.
+    #[cfg(feature = "codecs")]
+    pub async fn process_image<T: Texel>(
+        &self,
+        input: &RgbaImage,
+        kernel: &Kernel<T>,
+    ) -> Result<TexelImage<T>> {
+        let (width, height) = input.dimensions();
+        let input = self.upload_pooled(width, height, input)?;
+        let output = self.create_pooled_output::<T>(width, height);
+
+        self.dispatch_checked(kernel, &input, &output, &[], &[])
+            .await?;
+
+        self.read_image::<T>(&output).await
+    }
+
+    /// Runs `kernel` over the image file at `input` and writes the output to
+    /// `output`, in the format its extension names.
+    #[cfg(feature = "codecs")]
+    pub async fn process_file(
+        &self,
+        input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        kernel: &Kernel,
+    ) -> Result<()> {
+        let output = output.as_ref();
+
+        let input = self.upload_file(input)?;
+        let texture = self.create_pooled_output::<Rgba8>(input.width(), input.height());
+
+        self.dispatch_checked(kernel, &input, &texture, &[], &[])
+            .await?;
+
+        self.read_image::<Rgba8>(&texture)
+            .await?
+            .save(output)
+            .map_err(|source| Error::Encode {
+                path: output.to_path_buf(),
+                source,
+            })
+    }
+}
+
+/// The bytes of `constants`, if they are what `kernel` takes.
+fn push_constant_bytes<'a, T: Texel, P: Pod>(
+    kernel: &Kernel<T>,
+    constants: &'a P,
+) -> Result<&'a [u8]> {
+    let bytes = bytemuck::bytes_of(constants);
+
+    if bytes.len() as u32 != kernel.push_constant_size {
+        return Err(Error::PushConstantSize {
+            expected: kernel.push_constant_size,
+            actual: bytes.len() as u32,
+        });
+    }
+
+    Ok(bytes)
+}
+
+/// Fails unless `params` fill the parameter struct of `kernel`.
+fn check_param_bytes<T: Texel>(kernel: &Kernel<T>, params: &[u8]) -> Result<()> {
+    let layout = kernel
+        .params()
+        .ok_or_else(|| Error::InvalidParams("the kernel declares none".to_string()))?;
+
+    if params.len() != layout.size() as usize {
+        return Err(Error::InvalidParams(format!(
+            "the kernel takes {} bytes, got {}",
+            layout.size(),
+            params.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Fails unless `pixels` are `width`x`height` tightly packed RGBA8 texels
+/// that fit a texture of `device`.
+fn check_upload(device: &Device, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+    check_image_size(device, width, height, DATA_PER_PIXEL)?;
+
+    let expected = width as usize * height as usize * DATA_PER_PIXEL as usize;
+    if pixels.len() != expected {
+        return Err(Error::SizeMismatch {
+            width,
+            height,
+            expected,
+            actual: pixels.len(),
+        });
+    }
+
+    Ok(())
+}
+
+fn texture_size(width: u32, height: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Creates an RGBA8 texture that can be sampled and copied, filled with
+/// `buffer`.
+pub fn create_input_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_size: wgpu::Extent3d,
+    buffer: &[u8],
+) -> wgpu::Texture {
+    let texture = device.create_texture(&input_texture_descriptor(texture_size));
+
+    write_input_texture(queue, &texture, texture_size, buffer);
+
+    texture
+}
+
+/// Descriptor of the RGBA8 textures of [`create_input_texture`].
+pub fn input_texture_descriptor(texture_size: wgpu::Extent3d) -> wgpu::TextureDescriptor<'static> {
+    wgpu::TextureDescriptor {
+        label: Some("Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    }
+}
+
+/// Replaces the texels of an RGBA8 texture with `buffer`, tightly packed.
+pub fn write_input_texture(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    texture_size: wgpu::Extent3d,
+    buffer: &[u8],
+) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        buffer,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(DATA_PER_PIXEL * texture_size.width),
+            rows_per_image: Some(texture_size.height),
+        },
+        texture_size,
+    );
+}
+
+/// Descriptor of the textures kernels write texels of `format` to: bound
+/// for storage, read by the next kernel, read back and filled by copies.
+pub fn output_texture_descriptor(
+    texture_size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+) -> wgpu::TextureDescriptor<'static> {
+    wgpu::TextureDescriptor {
+        label: Some("Output Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    }
+}
+
+pub fn input_texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+pub fn output_texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    storage_texture_layout_entry(binding, wgpu::TextureFormat::Rgba8Unorm)
+}
+
+/// Like [`output_texture_layout_entry`], for a texture of `format` kernels
+/// read as well, which needs [`GpuContext::supports_read_write_storage`].
+pub fn read_write_texture_layout_entry(
+    binding: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format,
+            access: wgpu::StorageTextureAccess::ReadWrite,
+        },
+        count: None,
+    }
+}
+
+fn storage_texture_layout_entry(
+    binding: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::*;
+
+use crate::{
+    gradient::Gradient,
+    ops::{OpSpec, MAX_PARAMS},
+};
+
+/// Operations with a CPU implementation to check the GPU against.
+pub const OPERATIONS: &[&str] = &["copy", "blur", "box-blur", "crossfade", "gradient-map"];
+
+/// Radius the blur shader stops growing its kernel at.
+const MAX_BLUR_RADIUS: i32 = 64;
+
+/// Renders `op` on the CPU from `width`x`height` tightly packed RGBA8
+/// `inputs`, following its shader closely enough that only rounding should
+/// tell them apart.
+pub fn render(
+    op: &OpSpec<'_>,
+    width: u32,
+    height: u32,
+    inputs: &[&[u8]],
+    params: &[f32; MAX_PARAMS],
+    gradient: Option<&Gradient>,
+) -> Result<Vec<u8>> {
+    let input = Image {
+        width: width as i32,
+        height: height as i32,
+        texels: inputs[0],
+    };
+    let param = |name| op.param_index(name).map_or(0.0, |index| params[index]);
+
+    let output = match op.name {
+        "copy" => input.texels.to_vec(),
+        "blur" => blur(&input, param("sigma")),
+        "box-blur" => box_blur(&input, param("radius") as i32),
+        "crossfade" => {
+            let progress = param("progress");
+            input
+                .texels
+                .iter()
+                .zip(inputs[1])
+                .map(|(&a, &b)| unorm(mix(norm(a), norm(b), progress)))
+                .collect()
+        }
+        "gradient-map" => {
+            let table = gradient
+                .ok_or_else(|| anyhow!("Operation 'gradient-map' needs a gradient"))?
+                .table();
+            input
+                .texels
+                .chunks_exact(4)
+                .flat_map(|texel| {
+                    let luminance = luminance([norm(texel[0]), norm(texel[1]), norm(texel[2])]);
+                    let index = (luminance.clamp(0.0, 1.0) * 255.0).round() as usize;
+                    let mapped = &table[index * 4..index * 4 + 4];
+                    [
+                        mapped[0],
+                        mapped[1],
+                        mapped[2],
+                        unorm(norm(texel[3]) * norm(mapped[3])),
+                    ]
+                })
+                .collect()
+        }
+        _ => bail!(
+            "Operation '{}' has no CPU reference, --verify supports {}",
+            op.name,
+            OPERATIONS.join(", ")
+        ),
+    };
+
+    Ok(output)
+}
+
+struct Image<'a> {
+    width: i32,
+    height: i32,
+    texels: &'a [u8],
+}
+
+impl Image<'_> {
+    fn texel(&self, x: i32, y: i32) -> [f32; 4] {
+        let x = x.clamp(0, self.width - 1);
+        let y = y.clamp(0, self.height - 1);
+        let index = ((y * self.width + x) * 4) as usize;
+
+        [0, 1, 2, 3].map(|channel| norm(self.texels[index + channel]))
+    }
+}
+
+fn blur(input: &Image, sigma: f32) -> Vec<u8> {
+    if sigma <= 0.0 {
+        return input.texels.to_vec();
+    }
+
+    let radius = ((sigma * 3.0).ceil() as i32).min(MAX_BLUR_RADIUS);
+
+    let mut output = Vec::with_capacity(input.texels.len());
+    for y in 0..input.height {
+        for x in 0..input.width {
+            // Premultiplied linear color, like the shader.
+            let mut sum = [0.0; 4];
+            let mut weight_sum = 0.0;
+
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let [r, g, b, a] = input.texel(x + dx, y + dy);
+                    let weight = (-((dx * dx + dy * dy) as f32) / (2.0 * sigma * sigma)).exp();
+
+                    for (channel, value) in [r, g, b].into_iter().enumerate() {
+                        sum[channel] += srgb_to_linear(value) * a * weight;
+                    }
+                    sum[3] += a * weight;
+                    weight_sum += weight;
+                }
+            }
+
+            let rgb = [0, 1, 2].map(|channel| {
+                if sum[3] > 0.0 {
+                    linear_to_srgb(sum[channel] / sum[3])
+                } else {
+                    0.0
+                }
+            });
+
+            output.extend(rgb.map(unorm));
+            output.push(unorm(sum[3] / weight_sum));
+        }
+    }
+
+    output
+}
+
+/// Mean of the texels within `radius`, clipped to the image.
+fn box_blur(input: &Image, radius: i32) -> Vec<u8> {
+    let (width, height) = (input.width, input.height);
+
+    let mut output = Vec::with_capacity(input.texels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (low_x, high_x) = ((x - radius).max(0), (x + radius).min(width - 1));
+            let (low_y, high_y) = ((y - radius).max(0), (y + radius).min(height - 1));
+
+            let mut sum = [0u64; 4];
+            for sample_y in low_y..=high_y {
+                let row = (sample_y * width) as usize;
+                for sample_x in low_x..=high_x {
+                    let index = (row + sample_x as usize) * 4;
+                    for (channel, sum) in sum.iter_mut().enumerate() {
+                        *sum += u64::from(input.texels[index + channel]);
+                    }
+                }
+            }
+
+            let area = ((high_x - low_x + 1) * (high_y - low_y + 1)) as f32;
+            output.extend(sum.map(|sum| unorm(sum as f32 / (area * 255.0))));
+        }
+    }
+
+    output
+}
+
+fn norm(value: u8) -> f32 {
+    value as f32 / 255.0
+}
+
+/// Stores a value the way an `rgba8unorm` storage texture does.
+fn unorm(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
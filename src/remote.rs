@@ -0,0 +1,788 @@
+//! Running jobs on the GPU of another machine: `serve` takes jobs over TCP
+//! into a [`JobQueue`] and runs them one at a time on the GPU, `submit` sends the
+//! command line of a run with the files it reads, waits for its turn and
+//! writes the files the run wrote where its command line put them. `status`
+//! lists the jobs of a server.
+//!
+//! A message is a JSON header listing the files that follow it, the header
+//! and every file each as a little-endian `u64` length and the bytes.
+
+use anyhow::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufReader, BufWriter, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+use wgpu_texture_copy::GpuContext;
+
+use crate::{
+    cli::{self, Args},
+    job_queue::{JobQueue, JobStatus},
+    memory,
+    metrics::{self, Metrics, Outcome, Stage},
+    output::{self, IfExists},
+    preset::Preset,
+    preview::Mesh,
+    shader,
+    tiled::TiledImage,
+};
+
+/// Largest header or file taken, to fail on garbage rather than allocate it.
+const MAX_FRAME: u64 = 4 << 30;
+
+/// Keys of the files a run writes, by the option naming them. Extra outputs
+/// are written next to these, so all of a directory comes back.
+const OUTPUTS: [&str; 5] = ["output", "report", "save-preset", "emit-lock", "ascii-text"];
+
+/// Keys of the files that come along with another input, by the key of
+/// that input: the files the shader includes, the presets the preset is
+/// based on and the tiles of a DeepZoom input. They are named by their path
+/// from the directory of that input, and put under it on the other side.
+const COMPANIONS: [(&str, &str); 3] = [
+    ("include", "shader"),
+    ("preset-base", "preset"),
+    ("input-tile", "input"),
+];
+
+/// What a client asks the server for.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Message {
+    Job(Request),
+    /// The [`JobStatus`] of the jobs queued, running and finished.
+    Status,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    version: String,
+    args: Vec<String>,
+    priority: i32,
+    files: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    error: Option<String>,
+    files: Vec<Entry>,
+}
+
+impl Response {
+    fn failed(err: &Error) -> Self {
+        Self {
+            error: Some(format!("{:#}", err)),
+            files: Vec::new(),
+        }
+    }
+}
+
+/// A file of a message: what it is to the run, and its name or path with
+/// `/` separators.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    name: String,
+}
+
+struct Job {
+    request: Request,
+    files: Vec<Vec<u8>>,
+    stream: TcpStream,
+    queued: Instant,
+}
+
+/// Runs every path of `args` by `visit` with its key: the files the run
+/// reads, then the ones of [`OUTPUTS`].
+fn visit_paths(args: &mut Args, mut visit: impl FnMut(&str, &mut PathBuf)) {
+    if let Some(path) = &mut args.input {
+        visit("input", path);
+    }
+    if let Some(pack) = &mut args.pack {
+        for (channel, path) in pack.sources_mut() {
+            visit(&format!("pack-{}", channel), path);
+        }
+    }
+    let inputs = [
+        ("second", &mut args.second),
+        ("gradient", &mut args.gradient_image),
+        ("shader", &mut args.shader),
+        ("preset", &mut args.preset),
+        ("lock", &mut args.from_lock),
+    ];
+    for (key, path) in inputs {
+        if let Some(path) = path {
+            visit(key, path);
+        }
+    }
+    if let Some(Mesh::Obj(path)) = &mut args.preview_3d {
+        visit("mesh", path);
+    }
+
+    let outputs = [
+        ("output", &mut args.output),
+        ("report", &mut args.report),
+        ("save-preset", &mut args.save_preset),
+        ("emit-lock", &mut args.emit_lock),
+        ("ascii-text", &mut args.ascii_text),
+    ];
+    for (key, path) in outputs {
+        if let Some(path) = path {
+            visit(key, path);
+        }
+    }
+}
+
+/// Sends the run of `args` to the `serve` machine at `args.server`.
+pub fn submit(args: &cli::SubmitArgs) -> Result<()> {
+    let mut run = cli::Cli::parse_run(&args.args, Preset::load)?;
+
+    let mut inputs = Vec::new();
+    let mut outputs = HashMap::new();
+    visit_paths(&mut run, |key, path| match OUTPUTS.contains(&key) {
+        true => {
+            outputs.insert(key.to_string(), path.clone());
+        }
+        false => inputs.push((key.to_string(), file_name(path), path.clone())),
+    });
+    if let Some(path) = &run.shader {
+        inputs.extend(companions("include", path, shader_includes(path)?)?);
+    }
+    if let Some(path) = &run.preset {
+        let bases = Preset::files(path)?.split_off(1);
+        inputs.extend(companions("preset-base", path, bases)?);
+    }
+    if let Some(path) = &run.input {
+        inputs.extend(companions(
+            "input-tile",
+            path,
+            TiledImage::tile_files(path)?,
+        )?);
+    }
+
+    let mut entries = Vec::new();
+    let mut files = Vec::new();
+    for (key, name, path) in inputs {
+        files.push(fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?);
+        entries.push(Entry { key, name });
+    }
+
+    let stream = TcpStream::connect(&args.server)
+        .with_context(|| format!("Failed to connect to {}", args.server))?;
+    log::info!("Submitting to {}", args.server);
+    send(
+        &stream,
+        &Message::Job(Request {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            args: args.args.clone(),
+            priority: args.priority,
+            files: entries,
+        }),
+        &files,
+    )?;
+
+    let mut reader = BufReader::new(&stream);
+    let response: Response = read_header(&mut reader)?;
+    if let Some(err) = response.error {
+        bail!("The job failed on {}: {}", args.server, err);
+    }
+
+    for entry in &response.files {
+        let bytes = read_frame(&mut reader)?;
+        let base = outputs
+            .get(&entry.key)
+            .ok_or_else(|| anyhow!("The server sent a file as '{}'", entry.key))?;
+        let path = base
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(relative_path(&entry.name)?);
+
+        if !output::should_write(std::slice::from_ref(&path), run.if_exists)? {
+            log::info!("Keeping {}", path.display());
+            continue;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, bytes).with_context(|| format!("Failed to write {}", path.display()))?;
+        log::info!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Prints the jobs queued, running and finished on the `serve` machine at
+/// `args.server`.
+pub fn status(args: &cli::StatusArgs) -> Result<()> {
+    let stream = TcpStream::connect(&args.server)
+        .with_context(|| format!("Failed to connect to {}", args.server))?;
+    send(&stream, &Message::Status, &[])?;
+    let jobs: Vec<JobStatus> = read_header(&mut BufReader::new(&stream))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&jobs)?);
+        return Ok(());
+    }
+
+    for job in jobs {
+        let seconds = job
+            .seconds
+            .map_or_else(String::new, |seconds| format!(" in {:.1}s", seconds));
+        println!(
+            "{:>5} {:<8} priority {:<3} {:<15} {}{}",
+            job.id,
+            job.state.name(),
+            job.priority,
+            job.client
+                .map_or_else(|| "another client".to_string(), |client| client.to_string()),
+            job.args.join(" "),
+            seconds
+        );
+        if let Some(error) = job.error {
+            println!("      {}", error);
+        }
+    }
+
+    Ok(())
+}
+
+/// The files the shader at `path` includes, besides the bundled ones.
+fn shader_includes(path: &Path) -> Result<Vec<PathBuf>> {
+    let source =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let shader = shader::preprocess(&source, Some(path.parent().unwrap_or(Path::new(""))))?;
+
+    Ok(shader.files)
+}
+
+/// `files` to send along with the input at `path` as `key`, named by their
+/// path from its directory.
+fn companions(
+    key: &str,
+    path: &Path,
+    files: Vec<PathBuf>,
+) -> Result<Vec<(String, String, PathBuf)>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+    files
+        .into_iter()
+        .map(|file| {
+            let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+            let name = match canonical.strip_prefix(&dir) {
+                std::result::Result::Ok(relative) => relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                Err(_) => bail!(
+                    "{} is outside the directory of {}, which can't be submitted",
+                    file.display(),
+                    path.display()
+                ),
+            };
+
+            Ok((key.to_string(), name, file))
+        })
+        .collect()
+}
+
+/// What the threads of `serve` share.
+struct Server {
+    queue: JobQueue<Job>,
+    metrics: Metrics,
+    /// Where the files of every job are put while it runs.
+    scratch_dir: PathBuf,
+    /// Held by the job running on the GPU. wgpu reports errors outside of
+    /// error scopes for the whole device, and its scopes are for the whole
+    /// device too, so jobs running side by side would fail each other.
+    gpu: Mutex<()>,
+}
+
+/// Takes jobs on `args.listen` and runs them on `context` until the device
+/// is lost, keeping their files in `scratch_dir`. Up to `args.max_jobs` of
+/// them are taken at once, but only one runs on the GPU at a time.
+pub fn serve(context: &GpuContext, args: &cli::ServeArgs, scratch_dir: &Path) -> Result<()> {
+    let listener = TcpListener::bind(&args.listen)
+        .with_context(|| format!("Failed to listen on {}", args.listen))?;
+    let metrics_listener = args
+        .metrics
+        .as_ref()
+        .map(|address| {
+            TcpListener::bind(address)
+                .with_context(|| format!("Failed to serve metrics on {}", address))
+        })
+        .transpose()?;
+    let address = listener.local_addr()?;
+    log::info!("Taking jobs on {}", address);
+
+    let server = Arc::new(Server {
+        queue: JobQueue::new(args.quota),
+        metrics: Metrics::default(),
+        scratch_dir: scratch_dir.to_path_buf(),
+        gpu: Mutex::new(()),
+    });
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let mut threads = Vec::new();
+    {
+        let (server, stopped) = (server.clone(), stopped.clone());
+        threads.push((
+            address,
+            thread::spawn(move || accept(listener, &server, &stopped)),
+        ));
+    }
+    if let Some(listener) = metrics_listener {
+        let metrics_address = listener.local_addr()?;
+        log::info!("Serving metrics on http://{}/metrics", metrics_address);
+
+        let (server, stopped) = (server.clone(), stopped.clone());
+        threads.push((
+            metrics_address,
+            thread::spawn(move || {
+                metrics::serve_metrics(listener, &stopped, || {
+                    let (queued, running) = server.queue.counts();
+                    server.metrics.render(queued, running)
+                })
+            }),
+        ));
+    }
+
+    thread::scope(|scope| {
+        for _ in 0..args.max_jobs {
+            scope.spawn(|| run_jobs(context, &server));
+        }
+    });
+
+    // Wakes the listening threads up to stop, freeing the addresses for the
+    // next context.
+    stopped.store(true, Ordering::Relaxed);
+    for (address, thread) in threads {
+        let _ = TcpStream::connect(address);
+        let _ = thread.join();
+    }
+
+    bail!(wgpu_texture_copy::Error::DeviceLost)
+}
+
+fn accept(listener: TcpListener, server: &Arc<Server>, stopped: &AtomicBool) {
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        let stream = match stream {
+            std::result::Result::Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Failed to accept a connection: {}", err);
+                continue;
+            }
+        };
+
+        // Jobs are read in parallel, so a slow upload doesn't hold up the
+        // queue.
+        let server = server.clone();
+        thread::spawn(move || {
+            let peer = match stream.peer_addr() {
+                std::result::Result::Ok(address) => address.ip(),
+                Err(err) => return log::warn!("Failed to accept a connection: {}", err),
+            };
+            if let Err(err) = handle_connection(stream, peer, &server) {
+                log::warn!("Failed to take a job from {}: {:#}", peer, err);
+            }
+        });
+    }
+}
+
+fn handle_connection(stream: TcpStream, peer: IpAddr, server: &Server) -> Result<()> {
+    let started = Instant::now();
+    let mut reader = BufReader::new(&stream);
+    let request = match read_header(&mut reader)? {
+        Message::Status => {
+            return send(&stream, &server.queue.statuses(peer), &[]);
+        }
+        Message::Job(request) => request,
+    };
+
+    let files = request
+        .files
+        .iter()
+        .map(|_| read_frame(&mut reader))
+        .collect::<Result<_>>()?;
+    drop(reader);
+    server.metrics.observe(Stage::Receive, started.elapsed());
+
+    if request.version != env!("CARGO_PKG_VERSION") {
+        log::warn!(
+            "A job was submitted by version {}, this is {}",
+            request.version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+
+    let (priority, args) = (request.priority, request.args.clone());
+    let reply = stream.try_clone()?;
+    let job = Job {
+        request,
+        files,
+        stream,
+        queued: Instant::now(),
+    };
+    match server.queue.push(peer, priority, args, job) {
+        std::result::Result::Ok(id) => {
+            log::info!("Queued job {} from {}", id, peer);
+            Ok(())
+        }
+        Err(err) => {
+            server.metrics.count_job(Outcome::Rejected);
+            send(&reply, &Response::failed(&err), &[])
+        }
+    }
+}
+
+fn run_jobs(context: &GpuContext, server: &Server) {
+    let metrics = &server.metrics;
+
+    while let Some((id, job)) = server.queue.pop() {
+        metrics.observe(Stage::Queue, job.queued.elapsed());
+        let dir = server
+            .scratch_dir
+            .join(format!("wtc-job-{}-{}", process::id(), id));
+
+        let result = run_job(context, server, &dir, &job.request, job.files);
+
+        let (response, files) = match result {
+            std::result::Result::Ok((entries, files)) => {
+                log::info!("Finished job {}", id);
+                metrics.count_job(Outcome::Finished);
+                (
+                    Response {
+                        error: None,
+                        files: entries,
+                    },
+                    files,
+                )
+            }
+            Err(err) => {
+                log::warn!("Job {} failed: {:#}", id, err);
+                metrics.count_job(Outcome::Failed);
+                (Response::failed(&err), Vec::new())
+            }
+        };
+        server.queue.finish(id, response.error.clone());
+
+        let started = Instant::now();
+        match send(&job.stream, &response, &files) {
+            std::result::Result::Ok(()) => metrics.observe(Stage::Send, started.elapsed()),
+            Err(err) => log::warn!("Failed to send job {} back: {:#}", id, err),
+        }
+        if let Err(err) = fs::remove_dir_all(&dir) {
+            log::warn!("Failed to remove {}: {}", dir.display(), err);
+        }
+
+        if context.is_lost() {
+            let err = anyhow!(wgpu_texture_copy::Error::DeviceLost);
+            for job in server.queue.close() {
+                metrics.count_job(Outcome::Failed);
+                let _ = send(&job.stream, &Response::failed(&err), &[]);
+            }
+        }
+    }
+}
+
+/// Runs `request` with its files under `dir`, returning the files the run
+/// wrote.
+fn run_job(
+    context: &GpuContext,
+    server: &Server,
+    dir: &Path,
+    request: &Request,
+    files: Vec<Vec<u8>>,
+) -> Result<(Vec<Entry>, Vec<Vec<u8>>)> {
+    let input_dir = dir.join("in");
+    let output_dir = dir.join("out");
+
+    let mut received = HashMap::new();
+    for (entry, bytes) in request.files.iter().zip(files) {
+        let companion_of = COMPANIONS
+            .iter()
+            .find(|(key, _)| *key == entry.key)
+            .map(|(_, owner)| owner);
+        let path = match companion_of {
+            Some(owner) => input_dir.join(owner).join(relative_path(&entry.name)?),
+            None => input_dir
+                .join(relative_path(&entry.key)?)
+                .join(relative_path(&entry.name)?.file_name().unwrap_or_default()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, bytes)?;
+
+        if companion_of.is_none() {
+            received.insert(entry.key.clone(), path);
+        }
+    }
+
+    // Bases outside of the job would read the files of this machine.
+    let mut args = cli::Cli::parse_run(&request.args, |_| {
+        let path = received
+            .get("preset")
+            .ok_or_else(|| anyhow!("The job reads its preset without sending it"))?;
+
+        Preset::load_within(path, &input_dir.join("preset"))
+    })?;
+    // Nothing is there yet on this side; the client decides what to keep.
+    args.if_exists = IfExists::Overwrite;
+
+    let mut missing = None;
+    visit_paths(&mut args, |key, path| {
+        if OUTPUTS.contains(&key) {
+            let name = path.file_name().unwrap_or_default().to_owned();
+            *path = output_dir.join(key).join(name);
+            return;
+        }
+        match received.get(key) {
+            Some(received) => *path = received.clone(),
+            None => missing = Some(key.to_string()),
+        }
+    });
+    if let Some(key) = missing {
+        bail!("The job reads its {} without sending it", key);
+    }
+    for key in OUTPUTS {
+        fs::create_dir_all(output_dir.join(key))?;
+    }
+
+    {
+        // A poisoned lock just means the job before panicked.
+        let _gpu = server.gpu.lock().unwrap_or_else(|err| err.into_inner());
+        let started = Instant::now();
+        // Checking either way keeps an error of this job from failing the next.
+        let processed = crate::process(context, args);
+        let checked = context.check();
+        server.metrics.observe(Stage::Run, started.elapsed());
+        server
+            .metrics
+            .record_memory(memory::take_peak(), context.pool.free_bytes());
+        processed?;
+        checked?;
+    }
+
+    let mut paths = Vec::new();
+    list_files(&output_dir, &mut paths)?;
+
+    let mut entries = Vec::new();
+    let mut files = Vec::new();
+    for path in paths {
+        let mut components = path
+            .strip_prefix(&output_dir)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned());
+        let key = components.next().unwrap_or_default();
+        let name = components.collect::<Vec<_>>().join("/");
+
+        files.push(fs::read(&path)?);
+        entries.push(Entry { key, name });
+    }
+
+    Ok((entries, files))
+}
+
+/// Every file under `dir`, recursively.
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        match path.is_dir() {
+            true => list_files(&path, files)?,
+            false => files.push(path),
+        }
+    }
+
+    Ok(())
+}
+
+/// The file name of `path`, for the other side to put it under.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `name` from a message as a relative path, refusing ones leaving the
+/// directory they are put under.
+fn relative_path(name: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(name);
+    let is_relative = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    if name.is_empty() || !is_relative {
+        bail!("Refusing the file name '{}'", name);
+    }
+
+    Ok(path)
+}
+
+fn send(stream: &TcpStream, header: &impl Serialize, files: &[Vec<u8>]) -> Result<()> {
+    let mut writer = BufWriter::new(stream);
+    write_frame(&mut writer, &serde_json::to_vec(header)?)?;
+    for bytes in files {
+        write_frame(&mut writer, bytes)?;
+    }
+
+    Ok(writer.flush()?)
+}
+
+fn read_header<T: DeserializeOwned>(reader: &mut impl Read) -> Result<T> {
+    serde_json::from_slice(&read_frame(reader)?).context("Malformed message header")
+}
+
+fn write_frame(writer: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)?;
+
+    Ok(())
+}
+
+fn read_frame(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut length = [0; 8];
+    reader.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length);
+    if length > MAX_FRAME {
+        bail!("Refusing a message part of {} bytes", length);
+    }
+
+    let mut bytes = vec![0; length as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The paths `visit_paths` finds in the run of `args`, by key.
+    fn visited(args: &[&str]) -> Vec<(String, PathBuf)> {
+        let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut run = cli::Cli::parse_run(&args, |_| std::result::Result::Ok(Preset::default()))
+            .unwrap_or_else(|err| panic!("{:?}: {:#}", args, err));
+
+        let mut paths = Vec::new();
+        visit_paths(&mut run, |key, path| {
+            paths.push((key.to_string(), path.clone()))
+        });
+
+        paths
+    }
+
+    fn path(key: &str, path: &str) -> (String, PathBuf) {
+        (key.to_string(), PathBuf::from(path))
+    }
+
+    #[test]
+    fn visits_every_path_of_a_run() {
+        let fields: &[(&str, &[&str])] = &[
+            ("second", &["--second", "b.png"]),
+            ("gradient", &["--gradient-image", "ramp.png"]),
+            ("shader", &["--shader", "ink.wgsl", "--entry-point", "main"]),
+            ("preset", &["--preset", "toon.toml"]),
+            ("lock", &["--from-lock", "run.lock"]),
+            ("mesh", &["--preview-3d", "bunny.obj"]),
+            ("report", &["--report", "report.json"]),
+            ("save-preset", &["--save-preset", "saved.toml"]),
+            ("emit-lock", &["--emit-lock", "emitted.lock"]),
+            ("ascii-text", &["--ascii-text", "art.txt"]),
+        ];
+
+        for (key, flags) in fields {
+            let mut args = vec!["in.png", "-o", "out/x.png"];
+            args.extend(*flags);
+
+            let (input, output, field) = (
+                path("input", "in.png"),
+                path("output", "out/x.png"),
+                path(key, flags[1]),
+            );
+            // The files read come first.
+            let expected = match OUTPUTS.contains(key) {
+                true => [input, output, field],
+                false => [input, field, output],
+            };
+
+            assert_eq!(visited(&args), expected, "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn visits_every_output() {
+        let paths = visited(&[
+            "in.png",
+            "-o",
+            "x.png",
+            "--report",
+            "r.json",
+            "--save-preset",
+            "p.toml",
+            "--emit-lock",
+            "e.lock",
+            "--ascii-text",
+            "a.txt",
+        ]);
+
+        let keys: Vec<_> = paths.iter().skip(1).map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, OUTPUTS);
+    }
+
+    #[test]
+    fn visits_every_packed_channel() {
+        let paths = visited(&[
+            "--pack",
+            "r=rough.png,a=ao.png",
+            "--op",
+            "pack",
+            "-o",
+            "x.png",
+        ]);
+
+        assert_eq!(
+            paths,
+            [
+                path("pack-r", "rough.png"),
+                path("pack-a", "ao.png"),
+                path("output", "x.png")
+            ]
+        );
+    }
+
+    #[test]
+    fn visits_no_path_of_built_in_meshes() {
+        let paths = visited(&["in.png", "-o", "x.png", "--preview-3d", "sphere"]);
+
+        assert_eq!(paths, [path("input", "in.png"), path("output", "x.png")]);
+    }
+
+    #[test]
+    fn refuses_names_leaving_their_directory() {
+        for name in ["", "/etc/passwd", "../x.toml", "a/../../x.toml"] {
+            assert!(relative_path(name).is_err(), "{}", name);
+        }
+        assert_eq!(
+            relative_path("styles/toon.toml").unwrap(),
+            Path::new("styles/toon.toml")
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::*;
+use serde::Serialize;
+use std::{fs, path::Path};
+
+use crate::{diff::DiffStats, kmeans::Centroid, trim::Bounds};
+
+/// Machine-readable summary of a run, written with `--report`.
+#[derive(Serialize)]
+pub struct Report {
+    pub input: String,
+    pub output: String,
+    pub operation: String,
+    pub width: u32,
+    pub height: u32,
+    pub frames: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trimmed: Vec<TrimEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub srgb_check: Vec<SrgbCheckEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub clusters: Vec<Centroid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub verification: Vec<VerifyEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gpu_timings: Vec<TimingEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub invocations: Vec<InvocationEntry>,
+    /// Bytes of the textures and buffers of the operation at their peak.
+    pub peak_gpu_memory: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_budget: Option<u64>,
+}
+
+/// Where a trimmed image sat inside its untrimmed `source_width` x
+/// `source_height` original, so engines can restore its position.
+#[derive(Serialize)]
+pub struct TrimEntry {
+    pub frame: usize,
+    pub cell: usize,
+    pub source_width: u32,
+    pub source_height: u32,
+    #[serde(flatten)]
+    pub bounds: Bounds,
+}
+
+/// Difference between the gamma and linear space filtered mip `level`.
+#[derive(Serialize)]
+pub struct SrgbCheckEntry {
+    pub frame: usize,
+    pub level: usize,
+    pub width: u32,
+    pub height: u32,
+    #[serde(flatten)]
+    pub stats: DiffStats,
+}
+
+/// Deviation of the GPU output of a frame and cell from the CPU reference.
+#[derive(Serialize)]
+pub struct VerifyEntry {
+    pub frame: usize,
+    pub cell: usize,
+    #[serde(flatten)]
+    pub stats: DiffStats,
+}
+
+/// Milliseconds the GPU spent on the compute passes and the readback copy of
+/// a frame and cell.
+#[derive(Serialize)]
+pub struct TimingEntry {
+    pub frame: usize,
+    pub cell: usize,
+    pub compute_ms: f64,
+    pub copy_ms: f64,
+}
+
+/// Compute shader invocations of a frame and cell, against the texels of
+/// every pass they were dispatched for.
+#[derive(Serialize)]
+pub struct InvocationEntry {
+    pub frame: usize,
+    pub cell: usize,
+    pub invocations: u64,
+    pub texels: u64,
+}
+
+impl Report {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+
+        fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,437 @@
+//! Textures and readback buffers handed back once the work done with them is
+//! over, for the next images of the same size. Then a batch doesn't create
+//! and free them for every image, churning through video memory.
+
+use std::{
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+/// Bytes of free resources a pool keeps by default.
+pub const DEFAULT_POOL_BUDGET: u64 = 256 << 20;
+
+/// What a resource was created for, which the next user has to ask for
+/// exactly.
+#[derive(PartialEq)]
+enum Key {
+    Buffer {
+        size: u64,
+        usage: wgpu::BufferUsages,
+    },
+    Texture {
+        size: wgpu::Extent3d,
+        mip_level_count: u32,
+        sample_count: u32,
+        dimension: wgpu::TextureDimension,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+        view_formats: Vec<wgpu::TextureFormat>,
+    },
+}
+
+enum Resource {
+    Buffer(wgpu::Buffer),
+    Texture(wgpu::Texture),
+}
+
+/// Free resources, the ones handed back first in front.
+struct Free {
+    entries: Vec<(Key, Resource, u64)>,
+    bytes: u64,
+    budget: u64,
+}
+
+impl Free {
+    fn take(&mut self, key: &Key) -> Option<Resource> {
+        let index = self.entries.iter().rposition(|(entry, ..)| entry == key)?;
+        let (_, resource, bytes) = self.entries.remove(index);
+        self.bytes -= bytes;
+
+        Some(resource)
+    }
+
+    /// Keeps `resource`, dropping the oldest ones beyond the budget.
+    fn give(&mut self, key: Key, resource: Resource, bytes: u64) {
+        self.entries.push((key, resource, bytes));
+        self.bytes += bytes;
+
+        while self.bytes > self.budget {
+            let (_, _, bytes) = self.entries.remove(0);
+            self.bytes -= bytes;
+        }
+    }
+}
+
+pub struct ResourcePool {
+    free: Arc<Mutex<Free>>,
+    /// Zeroed buffers to clear textures from, of a power of two of bytes
+    /// each.
+    zeros: Mutex<Vec<wgpu::Buffer>>,
+}
+
+impl Default for ResourcePool {
+    fn default() -> Self {
+        Self::with_budget(DEFAULT_POOL_BUDGET)
+    }
+}
+
+impl ResourcePool {
+    /// A pool keeping up to `budget` bytes of free resources.
+    pub fn with_budget(budget: u64) -> Self {
+        Self {
+            free: Arc::new(Mutex::new(Free {
+                entries: Vec::new(),
+                bytes: 0,
+                budget,
+            })),
+            zeros: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A buffer for `descriptor`, a free one of the same size and usage if
+    /// there is one. Buffers go back to the pool unmapped, so `descriptor`
+    /// can't ask for one mapped at creation.
+    pub fn buffer(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::BufferDescriptor,
+    ) -> PooledBuffer {
+        debug_assert!(!descriptor.mapped_at_creation);
+
+        let key = Key::Buffer {
+            size: descriptor.size,
+            usage: descriptor.usage,
+        };
+        let buffer = match self.free.lock().unwrap().take(&key) {
+            Some(Resource::Buffer(buffer)) => buffer,
+            _ => device.create_buffer(descriptor),
+        };
+
+        PooledBuffer {
+            buffer: Some((key, buffer)),
+            free: self.free.clone(),
+        }
+    }
+
+    /// A texture for `descriptor`, a free one created for the same if there
+    /// is one. A recycled texture keeps the texels of its last user rather
+    /// than starting out zeroed.
+    pub fn texture(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::TextureDescriptor,
+    ) -> PooledTexture {
+        let key = Key::Texture {
+            size: descriptor.size,
+            mip_level_count: descriptor.mip_level_count,
+            sample_count: descriptor.sample_count,
+            dimension: descriptor.dimension,
+            format: descriptor.format,
+            usage: descriptor.usage,
+            view_formats: descriptor.view_formats.to_vec(),
+        };
+        let (texture, recycled) = match self.free.lock().unwrap().take(&key) {
+            Some(Resource::Texture(texture)) => (texture, true),
+            _ => (device.create_texture(descriptor), false),
+        };
+
+        PooledTexture {
+            texture: Some((key, texture)),
+            recycled,
+            free: self.free.clone(),
+        }
+    }
+
+    /// Zeroes every texel of `texture`, such as a recycled one, with
+    /// `clear_texture` where the device has `CLEAR_TEXTURE`. Elsewhere the
+    /// texels are copied from a zeroed buffer the pool keeps for textures of
+    /// about the same size, so nothing is uploaded from the host; `texture`
+    /// needs `COPY_DST` usage and an uncompressed color format for that.
+    pub fn zero_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Zero Texture Encoder"),
+        });
+
+        if device.features().contains(wgpu::Features::CLEAR_TEXTURE) {
+            encoder.clear_texture(texture, &wgpu::ImageSubresourceRange::default());
+        } else {
+            self.copy_zeros(device, &mut encoder, texture);
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Copies zeros into every mip level and layer of `texture` from a
+    /// buffer of `zeros`.
+    fn copy_zeros(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let size = texture.size();
+        let texel_bytes = texture.format().block_size(None).unwrap_or(4);
+        let bytes_per_row =
+            (size.width * texel_bytes).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        // Layer by layer, the first level being the largest.
+        let bytes = bytes_per_row as u64 * size.height as u64;
+
+        let mut zeros = self.zeros.lock().unwrap();
+        if !zeros.iter().any(|buffer| buffer.size() >= bytes) {
+            // Buffers start out zeroed, and nothing writes these.
+            zeros.push(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Zeros Buffer"),
+                size: bytes.next_power_of_two(),
+                usage: wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }));
+            zeros.sort_by_key(wgpu::Buffer::size);
+        }
+        let Some(buffer) = zeros.iter().find(|buffer| buffer.size() >= bytes) else {
+            unreachable!("A zeroed buffer of {} bytes was just made", bytes);
+        };
+
+        for mip_level in 0..texture.mip_level_count() {
+            let level_size = size.mip_level_size(mip_level, texture.dimension());
+
+            for layer in 0..level_size.depth_or_array_layers {
+                encoder.copy_buffer_to_texture(
+                    wgpu::ImageCopyBuffer {
+                        buffer,
+                        layout: wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: Some(bytes_per_row),
+                            rows_per_image: None,
+                        },
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        depth_or_array_layers: 1,
+                        ..level_size
+                    },
+                );
+            }
+        }
+    }
+
+    /// Bytes of the free resources kept.
+    pub fn free_bytes(&self) -> u64 {
+        self.free.lock().unwrap().bytes
+    }
+
+    /// Drops every free resource.
+    pub fn clear(&self) {
+        let mut free = self.free.lock().unwrap();
+        free.entries.clear();
+        free.bytes = 0;
+        self.zeros.lock().unwrap().clear();
+    }
+}
+
+/// A buffer of a [`ResourcePool`], going back to it when dropped.
+pub struct PooledBuffer {
+    buffer: Option<(Key, wgpu::Buffer)>,
+    free: Arc<Mutex<Free>>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = wgpu::Buffer;
+
+    fn deref(&self) -> &wgpu::Buffer {
+        &self.buffer.as_ref().unwrap().1
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some((key, buffer)) = self.buffer.take() {
+            let bytes = buffer.size();
+            self.free
+                .lock()
+                .unwrap()
+                .give(key, Resource::Buffer(buffer), bytes);
+        }
+    }
+}
+
+/// A texture of a [`ResourcePool`], going back to it when dropped.
+pub struct PooledTexture {
+    texture: Option<(Key, wgpu::Texture)>,
+    recycled: bool,
+    free: Arc<Mutex<Free>>,
+}
+
+impl PooledTexture {
+    /// Whether the texture had a user before.
+    pub fn is_recycled(&self) -> bool {
+        self.recycled
+    }
+}
+
+impl Deref for PooledTexture {
+    type Target = wgpu::Texture;
+
+    fn deref(&self) -> &wgpu::Texture {
+        &self.texture.as_ref().unwrap().1
+    }
+}
+
+impl Drop for PooledTexture {
+    fn drop(&mut self) {
+        if let Some((key, texture)) = self.texture.take() {
+            let bytes = texture_bytes(&texture);
+            self.free
+                .lock()
+                .unwrap()
+                .give(key, Resource::Texture(texture), bytes);
+        }
+    }
+}
+
+/// Bytes `texture` takes, about, counting a whole mip chain as a third more.
+fn texture_bytes(texture: &wgpu::Texture) -> u64 {
+    let size = texture.size();
+    let texels = size.width as u64 * size.height as u64 * size.depth_or_array_layers as u64;
+    let texel_bytes = texture.format().block_size(None).unwrap_or(4) as u64;
+    let bytes = texels * texel_bytes * texture.sample_count() as u64;
+
+    match texture.mip_level_count() {
+        1 => bytes,
+        _ => bytes + bytes / 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{output_texture_descriptor, read_texture, tests::context};
+
+    fn readback(size: u64) -> wgpu::BufferDescriptor<'static> {
+        wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        }
+    }
+
+    fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    #[test]
+    fn hands_out_resources_made_for_the_same() {
+        let Some(context) = context() else {
+            return;
+        };
+        let (device, pool) = (&context.device, ResourcePool::default());
+
+        drop(pool.buffer(device, &readback(256)));
+        assert_eq!(pool.free_bytes(), 256);
+
+        // Another size or usage makes a buffer of its own.
+        let storage = wgpu::BufferDescriptor {
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            ..readback(256)
+        };
+        let others = [
+            pool.buffer(device, &readback(512)),
+            pool.buffer(device, &storage),
+        ];
+        assert_eq!(pool.free_bytes(), 256);
+        let same = pool.buffer(device, &readback(256));
+        assert_eq!(pool.free_bytes(), 0);
+        drop((others, same));
+        assert_eq!(pool.free_bytes(), 1024);
+
+        let rgba = output_texture_descriptor(extent(8, 4), wgpu::TextureFormat::Rgba8Unorm);
+        assert!(!pool.texture(device, &rgba).is_recycled());
+        let float = output_texture_descriptor(extent(8, 4), wgpu::TextureFormat::Rgba32Float);
+        assert!(!pool.texture(device, &float).is_recycled());
+        let larger = output_texture_descriptor(extent(8, 8), wgpu::TextureFormat::Rgba8Unorm);
+        assert!(!pool.texture(device, &larger).is_recycled());
+        assert!(pool.texture(device, &rgba).is_recycled());
+    }
+
+    #[test]
+    fn drops_the_oldest_beyond_the_budget() {
+        let Some(context) = context() else {
+            return;
+        };
+        let (device, pool) = (&context.device, ResourcePool::with_budget(1024));
+
+        for size in [512, 256, 512] {
+            drop(pool.buffer(device, &readback(size)));
+        }
+        // The second 512 bytes are the first ones again.
+        assert_eq!(pool.free_bytes(), 768);
+
+        let kept = pool.buffer(device, &readback(512));
+        assert_eq!(pool.free_bytes(), 256);
+        let made = pool.buffer(device, &readback(512));
+        assert_eq!(pool.free_bytes(), 256);
+        // Handing both back goes over the budget, which the 256 bytes handed
+        // back first make room for.
+        drop((kept, made));
+        assert_eq!(pool.free_bytes(), 1024);
+        let _made = pool.buffer(device, &readback(256));
+        assert_eq!(pool.free_bytes(), 1024);
+
+        pool.clear();
+        assert_eq!(pool.free_bytes(), 0);
+    }
+
+    #[test]
+    fn zeroes_recycled_textures() -> crate::Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+        let (device, queue, pool) = (&context.device, &context.queue, ResourcePool::default());
+        let size = extent(70, 3);
+        let texels = vec![0xAB; 70 * 3 * 4];
+
+        for copy in [false, true] {
+            let texture = pool.texture(
+                device,
+                &output_texture_descriptor(size, wgpu::TextureFormat::Rgba8Unorm),
+            );
+            crate::write_input_texture(queue, &texture, size, &texels);
+
+            let mut encoder = device.create_command_encoder(&Default::default());
+            match copy {
+                true => pool.copy_zeros(device, &mut encoder, &texture),
+                false => encoder.clear_texture(&texture, &wgpu::ImageSubresourceRange::default()),
+            }
+            if !copy && !device.features().contains(wgpu::Features::CLEAR_TEXTURE) {
+                continue;
+            }
+            queue.submit(Some(encoder.finish()));
+
+            let read = futures::executor::block_on(read_texture(device, queue, &texture, 0, size))?;
+            assert!(read.iter().all(|&byte| byte == 0), "copying: {}", copy);
+        }
+        // One buffer of zeros for the 3 rows padded to 512 bytes.
+        assert_eq!(pool.zeros.lock().unwrap().len(), 1);
+        assert_eq!(pool.zeros.lock().unwrap()[0].size(), 2048);
+
+        Ok(())
+    }
+}
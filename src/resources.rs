@@ -0,0 +1,248 @@
+use crate::shader::Shader;
+use anyhow::*;
+use image::{io::Reader, GrayImage};
+use std::io::Cursor;
+use wgpu::util::DeviceExt;
+use wgpu_texture_copy::{
+    params::{params_layout_entry, PARAMS_BINDING},
+    PipelineCache,
+};
+
+/// Library includes declaring bindings of the bundled textures. Shaders that
+/// pull in either get all of them bound at [`BUNDLED_TEXTURES_GROUP`].
+pub const NOISE_TEXTURES_INCLUDE: &str = "noise_textures.wgsl";
+pub const GLYPH_ATLAS_INCLUDE: &str = "glyph_atlas.wgsl";
+pub const BUNDLED_TEXTURES_GROUP: u32 = 1;
+
+/// 64x64 tileable blue noise produced with void-and-cluster (sigma 1.5), one
+/// rank per pixel scaled to the full 8-bit range.
+const BLUE_NOISE_PNG: &[u8] = include_bytes!("resources/blue_noise.png");
+
+/// 8x8 white-on-black glyphs side by side, ordered by ink coverage to match
+/// [`GLYPH_RAMP`].
+const GLYPH_ATLAS_PNG: &[u8] = include_bytes!("resources/glyphs.png");
+
+/// Characters of the glyph atlas, from the emptiest to the densest.
+pub const GLYPH_RAMP: &str = " .:-+*#&@M";
+
+/// 5x7 white-on-black glyphs of the printable ASCII characters from the
+/// space on, in 6x8 cells side by side, for labels.
+pub const FONT_PNG: &[u8] = include_bytes!("resources/font.png");
+
+const BAYER_BITS: u32 = 3;
+
+pub struct BundledTextures {
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl BundledTextures {
+    /// Whether `shader` declares any of the bundled texture bindings.
+    pub fn used_by(shader: &Shader) -> bool {
+        shader.includes(NOISE_TEXTURES_INCLUDE) || shader.includes(GLYPH_ATLAS_INCLUDE)
+    }
+
+    /// Layout of the bind group, as the pipelines of shaders using the
+    /// textures take it.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        [texture_entry(0), texture_entry(1), texture_entry(2)]
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &PipelineCache,
+    ) -> Result<Self> {
+        let blue_noise = decode_luma(BLUE_NOISE_PNG)?;
+
+        let blue_noise_view = create_r8_texture(
+            device,
+            queue,
+            "Blue Noise Texture",
+            blue_noise.width(),
+            blue_noise.height(),
+            blue_noise.as_raw(),
+        );
+
+        let bayer_size = 1 << BAYER_BITS;
+        let bayer_view = create_r8_texture(
+            device,
+            queue,
+            "Bayer Texture",
+            bayer_size,
+            bayer_size,
+            &bayer_matrix(BAYER_BITS),
+        );
+
+        let glyph_atlas = decode_luma(GLYPH_ATLAS_PNG)?;
+
+        let glyph_atlas_view = create_r8_texture(
+            device,
+            queue,
+            "Glyph Atlas Texture",
+            glyph_atlas.width(),
+            glyph_atlas.height(),
+            glyph_atlas.as_raw(),
+        );
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            "Bundled Textures Bind Group Layout",
+            &Self::layout_entries(),
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bundled Textures Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&blue_noise_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&bayer_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&glyph_atlas_view),
+                },
+            ],
+        });
+
+        Ok(Self { bind_group })
+    }
+}
+
+/// The uniform buffer of the parameter struct a `--shader` declares, at
+/// [`PARAMS_GROUP`](wgpu_texture_copy::params::PARAMS_GROUP).
+pub struct ShaderParams {
+    pub bind_group: wgpu::BindGroup,
+    /// Stands in for the bundled textures of shaders without them, as the
+    /// groups of a pipeline layout can't skip one.
+    pub empty_bind_group: wgpu::BindGroup,
+}
+
+impl ShaderParams {
+    pub fn new(device: &wgpu::Device, pipelines: &PipelineCache, contents: &[u8]) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shader Params Buffer"),
+            contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = pipelines.bind_group_layout(
+            device,
+            "Shader Params Bind Group Layout",
+            &[params_layout_entry()],
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shader Params Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: PARAMS_BINDING,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        let empty_layout = pipelines.bind_group_layout(device, "Empty Bind Group Layout", &[]);
+
+        let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Empty Bind Group"),
+            layout: &empty_layout,
+            entries: &[],
+        });
+
+        Self {
+            bind_group,
+            empty_bind_group,
+        }
+    }
+}
+
+pub fn decode_luma(png: &[u8]) -> Result<GrayImage> {
+    Ok(Reader::new(Cursor::new(png))
+        .with_guessed_format()?
+        .decode()?
+        .into_luma8())
+}
+
+pub fn create_r8_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: &str,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[wgpu::TextureFormat::R8Unorm],
+    });
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(width),
+            rows_per_image: Some(height),
+        },
+        size,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Ordered dither thresholds for a `2^bits` square matrix, stored so that
+/// sampling yields `(index + 0.5) / cells`.
+fn bayer_matrix(bits: u32) -> Vec<u8> {
+    let size = 1u32 << bits;
+    let cells = size * size;
+
+    let mut output = Vec::with_capacity(cells as usize);
+
+    for y in 0..size {
+        for x in 0..size {
+            let mut index = 0;
+
+            for bit in 0..bits {
+                let x_bit = (x >> bit) & 1;
+                let y_bit = (y >> bit) & 1;
+                index = (index << 2) | [[0, 2], [3, 1]][y_bit as usize][x_bit as usize];
+            }
+
+            output.push(((index * 2 + 1) * 128 / cells) as u8);
+        }
+    }
+
+    output
+}
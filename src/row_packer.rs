@@ -0,0 +1,230 @@
+//! Readback without row padding. Copies from textures into buffers pad every
+//! row to [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`], which the CPU then strips
+//! row by row; [`RowPacker`] instead packs the texels into a storage buffer
+//! with a compute pass and copies that as it is, so the mapped data is the
+//! image already.
+
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+use crate::{PipelineCache, DATA_PER_PIXEL, U8_SIZE};
+
+/// Invocations along each side of a workgroup in `pack_rows.wgsl`.
+const WORKGROUP_SIZE: u32 = 16;
+
+const LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 3] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+/// Packs a region of an RGBA8 unorm texture into tightly packed rows.
+pub struct RowPacker {
+    pipeline: Arc<wgpu::ComputePipeline>,
+    bind_group: wgpu::BindGroup,
+    packed_buffer: wgpu::Buffer,
+    workgroups: (u32, u32),
+}
+
+impl RowPacker {
+    /// Bytes of `size` texels packed.
+    pub fn packed_bytes(size: wgpu::Extent3d) -> u64 {
+        (size.width * DATA_PER_PIXEL * U8_SIZE) as u64 * size.height as u64
+    }
+
+    /// Whether the storage buffers of `device` hold `size` texels.
+    pub fn fits(device: &wgpu::Device, size: wgpu::Extent3d) -> bool {
+        let limits = device.limits();
+        let bytes = Self::packed_bytes(size);
+
+        bytes <= limits.max_storage_buffer_binding_size as u64 && bytes <= limits.max_buffer_size
+    }
+
+    /// Packs the `size` texels at `origin` of the base level of `texture`,
+    /// which needs [`wgpu::TextureUsages::TEXTURE_BINDING`].
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &PipelineCache,
+        texture: &wgpu::Texture,
+        origin: wgpu::Origin3d,
+        size: wgpu::Extent3d,
+    ) -> Self {
+        let bind_group_layout =
+            pipelines.bind_group_layout(device, "Pack Rows Bind Group Layout", &LAYOUT_ENTRIES);
+        let pipeline = pipelines.compute_pipeline(
+            device,
+            include_str!("shaders/pack_rows.wgsl"),
+            "pack_rows",
+            &[&LAYOUT_ENTRIES],
+        );
+
+        let packed_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Packed Rows Buffer"),
+            size: Self::packed_bytes(size),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let region_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Pack Rows Region Buffer"),
+            contents: bytemuck::cast_slice(&[origin.x, origin.y, size.width, size.height]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Pack Rows Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: packed_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: region_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+            packed_buffer,
+            workgroups: (
+                size.width.div_ceil(WORKGROUP_SIZE),
+                size.height.div_ceil(WORKGROUP_SIZE),
+            ),
+        }
+    }
+
+    /// Records packing the texels as they are when `encoder` runs, and
+    /// copying them to the start of `destination`.
+    pub fn record(&self, encoder: &mut wgpu::CommandEncoder, destination: &wgpu::Buffer) {
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Pack Rows Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, 1);
+        }
+
+        encoder.copy_buffer_to_buffer(
+            &self.packed_buffer,
+            0,
+            destination,
+            0,
+            self.packed_buffer.size(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+
+    #[test]
+    fn packs_a_region_without_padding() -> crate::Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+        let (device, queue) = (&context.device, &context.queue);
+
+        // Rows of 37 texels pad to 256 bytes when copied from the texture.
+        let size = wgpu::Extent3d {
+            width: 37,
+            height: 5,
+            depth_or_array_layers: 1,
+        };
+        let texels: Vec<u8> = (0..37 * 5 * 4).map(|i| (i * 31 % 251) as u8).collect();
+        let texture = crate::create_input_texture(device, queue, size, &texels);
+
+        let region = wgpu::Extent3d {
+            width: 30,
+            height: 3,
+            depth_or_array_layers: 1,
+        };
+        let packer = RowPacker::new(
+            device,
+            &context.pipelines,
+            &texture,
+            wgpu::Origin3d { x: 3, y: 1, z: 0 },
+            region,
+        );
+        let destination = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: RowPacker::packed_bytes(region),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        packer.record(&mut encoder, &destination);
+        queue.submit(Some(encoder.finish()));
+
+        let packed = futures::executor::block_on(crate::read_buffer(device, queue, &destination))?;
+        let expected: Vec<u8> = texels
+            .chunks_exact(37 * 4)
+            .skip(1)
+            .take(3)
+            .flat_map(|row| &row[3 * 4..33 * 4])
+            .copied()
+            .collect();
+        assert_eq!(packed.len(), 30 * 3 * 4);
+        assert_eq!(packed, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fits_within_the_storage_binding_limit() {
+        let Some(context) = context() else {
+            return;
+        };
+
+        let limit = context.device.limits().max_storage_buffer_binding_size as u64;
+        let extent = |width, height| wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        assert_eq!(RowPacker::packed_bytes(extent(3, 2)), 24);
+        assert!(RowPacker::fits(&context.device, extent(64, 64)));
+        assert!(!RowPacker::fits(
+            &context.device,
+            extent(1024, (limit / 4096 + 1) as u32)
+        ));
+    }
+}
@@ -0,0 +1,455 @@
+//! Prefix sums on the GPU.
+//!
+//! [`scan`] handles a whole slice at once. [`Scan`] records scans of
+//! segments of an existing storage buffer into an encoder, for compute work
+//! that keeps its data on the GPU, such as the rows and columns of a
+//! summed-area table.
+
+use bytemuck::{Pod, Zeroable};
+use std::{marker::PhantomData, sync::Arc};
+use wgpu::util::DeviceExt;
+
+use crate::{Error, GpuContext, PipelineCache, Result};
+
+/// Threads per workgroup in `scan.wgsl`.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Elements per block when scanning a contiguous buffer, see [`scan`].
+const BLOCK_LENGTH: u32 = 1024;
+
+/// Most workgroups dispatched along one dimension.
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// Element types that can be scanned. Integer sums wrap around on overflow,
+/// so differences of them stay exact.
+pub trait ScanElement: Pod {
+    /// Name of the type in WGSL.
+    const WGSL_TYPE: &'static str;
+}
+
+impl ScanElement for u32 {
+    const WGSL_TYPE: &'static str = "u32";
+}
+
+impl ScanElement for f32 {
+    const WGSL_TYPE: &'static str = "f32";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanKind {
+    /// Every element becomes the sum of itself and all before it.
+    Inclusive,
+    /// Every element becomes the sum of all before it, the first one zero.
+    Exclusive,
+}
+
+/// Where the segments of a scanned buffer are, in elements. Segment `s`
+/// starts at `offset + (s / inner) * outer_stride + (s % inner) *
+/// inner_stride` and has `length` elements `element_stride` apart.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct ScanLayout {
+    pub length: u32,
+    pub element_stride: u32,
+    pub offset: u32,
+    pub inner: u32,
+    pub inner_stride: u32,
+    pub outer_stride: u32,
+    pub segments: u32,
+}
+
+impl ScanLayout {
+    /// A single segment of `length` consecutive elements.
+    pub fn contiguous(length: u32) -> Self {
+        Self::rows(length, 1)
+    }
+
+    /// `segments` consecutive segments of `length` elements each, like the
+    /// rows of a row-major matrix.
+    pub fn rows(length: u32, segments: u32) -> Self {
+        Self {
+            length,
+            element_stride: 1,
+            offset: 0,
+            inner: 1,
+            inner_stride: 0,
+            outer_stride: length,
+            segments,
+        }
+    }
+}
+
+/// Layout of `Layout` in `scan.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Uniforms {
+    layout: ScanLayout,
+    exclusive: u32,
+}
+
+/// Bindings of `scan.wgsl`: the scanned buffer and its layout.
+const ENTRIES: [wgpu::BindGroupLayoutEntry; 2] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+/// Pipelines scanning storage buffers of `T` in place.
+pub struct Scan<T> {
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    scan_pipeline: Arc<wgpu::ComputePipeline>,
+    propagate_pipeline: Arc<wgpu::ComputePipeline>,
+    element: PhantomData<T>,
+}
+
+impl<T: ScanElement> Scan<T> {
+    /// The pipelines for `T`, compiled once per element type and kept in
+    /// `pipelines` for the scans after the first.
+    pub fn new(device: &wgpu::Device, pipelines: &PipelineCache) -> Self {
+        let source = format!(
+            "alias Element = {};\n\n{}",
+            T::WGSL_TYPE,
+            include_str!("shaders/scan.wgsl")
+        );
+
+        Self {
+            bind_group_layout: pipelines.bind_group_layout(
+                device,
+                "Scan Bind Group Layout",
+                &ENTRIES,
+            ),
+            scan_pipeline: pipelines.compute_pipeline(device, &source, "scan", &[&ENTRIES]),
+            propagate_pipeline: pipelines.compute_pipeline(
+                device,
+                &source,
+                "propagate",
+                &[&ENTRIES],
+            ),
+            element: PhantomData,
+        }
+    }
+
+    /// Records a scan of every segment of `buffer`, which needs `STORAGE`
+    /// usage. Segments are scanned independently and one workgroup each, so
+    /// many short segments are faster than few long ones.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        layout: ScanLayout,
+        kind: ScanKind,
+    ) -> Result<()> {
+        let bind_group = self.bind_group(device, buffer, layout, kind);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Scan Pass"),
+        });
+        compute_pass.set_pipeline(&self.scan_pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        dispatch(&mut compute_pass, layout.segments)
+    }
+
+    /// Records an inclusive scan of the first `blocks * BLOCK_LENGTH`
+    /// elements of `buffer`: every block on its own, then the block totals,
+    /// then the totals added back to the blocks.
+    fn encode_blocked(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        blocks: u32,
+    ) -> Result<()> {
+        let blocks_layout = ScanLayout::rows(BLOCK_LENGTH, blocks);
+        let totals_layout = ScanLayout {
+            length: blocks,
+            element_stride: BLOCK_LENGTH,
+            offset: BLOCK_LENGTH - 1,
+            ..ScanLayout::contiguous(blocks)
+        };
+
+        self.encode(device, encoder, buffer, blocks_layout, ScanKind::Inclusive)?;
+        self.encode(device, encoder, buffer, totals_layout, ScanKind::Inclusive)?;
+
+        let bind_group = self.bind_group(device, buffer, blocks_layout, ScanKind::Inclusive);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Scan Propagate Pass"),
+        });
+        compute_pass.set_pipeline(&self.propagate_pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        dispatch(&mut compute_pass, blocks * BLOCK_LENGTH / WORKGROUP_SIZE)
+    }
+
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        buffer: &wgpu::Buffer,
+        layout: ScanLayout,
+        kind: ScanKind,
+    ) -> wgpu::BindGroup {
+        let uniforms = Uniforms {
+            layout,
+            exclusive: (kind == ScanKind::Exclusive) as u32,
+        };
+
+        let uniforms_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Scan Layout Buffer"),
+            contents: bytemuck::bytes_of(&uniforms),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Scan Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniforms_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
+
+/// Dispatches `workgroups` workgroups, wrapping into a second dimension past
+/// the per-dimension limit.
+fn dispatch(compute_pass: &mut wgpu::ComputePass, workgroups: u32) -> Result<()> {
+    if workgroups > MAX_WORKGROUPS * MAX_WORKGROUPS {
+        return Err(Error::TooManyWorkgroups(workgroups));
+    }
+
+    let columns = workgroups.clamp(1, MAX_WORKGROUPS);
+    compute_pass.dispatch_workgroups(columns, workgroups.div_ceil(columns), 1);
+
+    Ok(())
+}
+
+/// Prefix sums of `values`, computed on the GPU. Long inputs are scanned in
+/// blocks so every workgroup has a share of the work. The pipelines are kept
+/// in `context` for later scans of the same element type.
+pub async fn scan<T: ScanElement>(
+    context: &GpuContext,
+    values: &[T],
+    kind: ScanKind,
+) -> Result<Vec<T>> {
+    let (device, queue) = (&context.device, &context.queue);
+
+    if values.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let length = u32::try_from(values.len()).map_err(|_| Error::TooManyElements(values.len()))?;
+    let blocks = length.div_ceil(BLOCK_LENGTH);
+
+    // Pad to whole blocks so every block has the same length.
+    let mut contents = values.to_vec();
+    if blocks > 1 {
+        contents.resize((blocks * BLOCK_LENGTH) as usize, T::zeroed());
+    }
+
+    let size = std::mem::size_of_val(contents.as_slice()) as u64;
+    let limit = device.limits().max_storage_buffer_binding_size as u64;
+    if size > limit {
+        return Err(Error::BufferTooLarge {
+            length,
+            size,
+            limit,
+        });
+    }
+
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Scan Buffer"),
+        contents: bytemuck::cast_slice(&contents),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+
+    let scan = Scan::<T>::new(device, &context.pipelines);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Scan Encoder"),
+    });
+
+    if blocks > 1 {
+        scan.encode_blocked(device, &mut encoder, &buffer, blocks)?;
+    } else {
+        scan.encode(
+            device,
+            &mut encoder,
+            &buffer,
+            ScanLayout::contiguous(length),
+            kind,
+        )?;
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes = crate::read_buffer(device, queue, &buffer).await?;
+    let mut sums: Vec<T> = bytemuck::pod_collect_to_vec(&bytes);
+    sums.truncate(values.len());
+
+    // The blocked scan is inclusive, shift it by one for an exclusive one.
+    if blocks > 1 && kind == ScanKind::Exclusive {
+        sums.rotate_right(1);
+        sums[0] = T::zeroed();
+    }
+
+    Ok(sums)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+
+    /// Prefix sums on the CPU, wrapping around like the GPU ones.
+    fn expected(values: &[u32], kind: ScanKind) -> Vec<u32> {
+        let mut sum = 0u32;
+        values
+            .iter()
+            .map(|value| {
+                let before = sum;
+                sum = sum.wrapping_add(*value);
+                match kind {
+                    ScanKind::Inclusive => sum,
+                    ScanKind::Exclusive => before,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sums_single_and_blocked_inputs() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        // One element, a whole block, one past it and a padded last block.
+        for length in [1, 1024, 1025, 3 * 1024 + 77] {
+            let values: Vec<u32> = (0..length).map(|index| index % 13 + 1).collect();
+            for kind in [ScanKind::Inclusive, ScanKind::Exclusive] {
+                let sums = futures::executor::block_on(scan(&context, &values, kind))?;
+                assert_eq!(sums, expected(&values, kind), "{:?} of {}", kind, length);
+            }
+        }
+
+        let floats = futures::executor::block_on(scan(
+            &context,
+            &[0.5f32, 1.0, 2.0, -4.0],
+            ScanKind::Exclusive,
+        ))?;
+        assert_eq!(floats, [0.0, 0.5, 1.5, 3.5]);
+        assert!(
+            futures::executor::block_on(scan::<u32>(&context, &[], ScanKind::Inclusive))?
+                .is_empty()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn wraps_integer_sums_around() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        let values = [u32::MAX, 2, u32::MAX - 1, 5];
+        let sums = futures::executor::block_on(scan(&context, &values, ScanKind::Inclusive))?;
+        assert_eq!(sums, [u32::MAX, 1, u32::MAX, 4]);
+
+        // Across the totals of blocks too.
+        let values = vec![u32::MAX / 1000; 2048];
+        let sums = futures::executor::block_on(scan(&context, &values, ScanKind::Inclusive))?;
+        assert_eq!(sums, expected(&values, ScanKind::Inclusive));
+
+        Ok(())
+    }
+
+    #[test]
+    fn scans_the_columns_of_a_matrix() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+        let device = &context.device;
+
+        // 3 columns of 4 rows, each column its own segment.
+        let matrix: Vec<u32> = (1..=12).collect();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&matrix),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        let columns = ScanLayout {
+            length: 4,
+            element_stride: 3,
+            offset: 0,
+            inner: 3,
+            inner_stride: 1,
+            outer_stride: 0,
+            segments: 3,
+        };
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        Scan::<u32>::new(device, &context.pipelines).encode(
+            device,
+            &mut encoder,
+            &buffer,
+            columns,
+            ScanKind::Exclusive,
+        )?;
+        context.queue.submit(Some(encoder.finish()));
+
+        let bytes =
+            futures::executor::block_on(crate::read_buffer(device, &context.queue, &buffer))?;
+        let sums: Vec<u32> = bytemuck::pod_collect_to_vec(&bytes);
+        #[rustfmt::skip]
+        assert_eq!(sums, [
+            0, 0, 0,
+            1, 2, 3,
+            5, 7, 9,
+            12, 15, 18,
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compiles_once_per_element_type() {
+        let Some(context) = context() else {
+            return;
+        };
+        let (device, pipelines) = (&context.device, &context.pipelines);
+
+        let first = Scan::<u32>::new(device, pipelines);
+        let second = Scan::<u32>::new(device, pipelines);
+        let floats = Scan::<f32>::new(device, pipelines);
+
+        assert!(Arc::ptr_eq(&first.scan_pipeline, &second.scan_pipeline));
+        assert!(Arc::ptr_eq(
+            &first.propagate_pipeline,
+            &second.propagate_pipeline
+        ));
+        assert!(!Arc::ptr_eq(&first.scan_pipeline, &floats.scan_pipeline));
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::*;
+use image::{imageops, RgbaImage};
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::{pingpong::PingPong, shader};
+
+/// Shrinks `image` to `width`x`height` by seam carving: connected paths of
+/// texels crossing the least detail are removed one at a time, so the
+/// salient content keeps its proportions. Columns go first, then rows.
+pub async fn retarget(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &RgbaImage,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage> {
+    if width > image.width() || height > image.height() {
+        bail!(
+            "Content-aware resizing only shrinks, can't go from {}x{} to {}x{}",
+            image.width(),
+            image.height(),
+            width,
+            height
+        );
+    }
+
+    let carver = Carver::new(device)?;
+
+    let narrowed = carver.carve(device, queue, image, width).await?;
+
+    // Rows are carved as the columns of the transposed image.
+    let transposed = imageops::rotate90(&narrowed);
+    let carved = carver.carve(device, queue, &transposed, height).await?;
+
+    Ok(imageops::rotate270(&carved))
+}
+
+struct Carver {
+    bind_group_layout: wgpu::BindGroupLayout,
+    energy_pipeline: wgpu::ComputePipeline,
+    advance_pipeline: wgpu::ComputePipeline,
+    accumulate_pipeline: wgpu::ComputePipeline,
+    remove_pipeline: wgpu::ComputePipeline,
+}
+
+/// WGSL of the seam carving passes.
+pub const SHADER: &str = include_str!("shaders/seam.wgsl");
+
+impl Carver {
+    fn new(device: &wgpu::Device) -> Result<Self> {
+        let shader = shader::preprocess(SHADER, None)?;
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Seam Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+        });
+
+        let buffer_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Seam Bind Group Layout"),
+            entries: &[
+                crate::input_texture_layout_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    count: None,
+                },
+                buffer_entry(2, false),
+                buffer_entry(3, false),
+                buffer_entry(4, true),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Seam Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Seam Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point,
+            })
+        };
+
+        Ok(Self {
+            energy_pipeline: pipeline("energy"),
+            advance_pipeline: pipeline("advance"),
+            accumulate_pipeline: pipeline("accumulate"),
+            remove_pipeline: pipeline("remove"),
+            bind_group_layout,
+        })
+    }
+
+    /// Removes vertical seams from `image` until it is `width` texels wide.
+    async fn carve(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &RgbaImage,
+        width: u32,
+    ) -> Result<RgbaImage> {
+        let texture_size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let texels = (texture_size.width * texture_size.height) as usize;
+
+        let mut textures = PingPong::new(
+            device,
+            "Seam Texture",
+            texture_size,
+            wgpu::TextureFormat::Rgba8Unorm,
+        );
+        textures.upload(queue, image);
+
+        let state_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Seam State Buffer"),
+            size: 8,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cost_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Seam Cost Buffer"),
+            contents: bytemuck::cast_slice(&vec![0f32; texels]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let seam_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Seam Buffer"),
+            size: (texture_size.height as usize * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_groups = textures.bind_groups(
+            device,
+            &self.bind_group_layout,
+            "Seam Bind Group",
+            (0, 1),
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: state_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: cost_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: seam_buffer.as_entire_binding(),
+                },
+            ],
+        );
+
+        for current_width in (width + 1..=image.width()).rev() {
+            queue.write_buffer(&state_buffer, 0, bytemuck::cast_slice(&[current_width, 0]));
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Seam Cost Encoder"),
+            });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Seam Cost Pass"),
+                });
+                compute_pass.set_bind_group(0, textures.latest(&bind_groups), &[]);
+
+                compute_pass.set_pipeline(&self.energy_pipeline);
+                compute_pass.dispatch_workgroups(current_width, texture_size.height, 1);
+
+                for _ in 1..texture_size.height {
+                    compute_pass.set_pipeline(&self.advance_pipeline);
+                    compute_pass.dispatch_workgroups(1, 1, 1);
+                    compute_pass.set_pipeline(&self.accumulate_pipeline);
+                    compute_pass.dispatch_workgroups(current_width, 1, 1);
+                }
+            }
+
+            queue.submit(Some(encoder.finish()));
+
+            let cost: Vec<f32> = bytemuck::pod_collect_to_vec(
+                &crate::read_buffer(device, queue, &cost_buffer).await?,
+            );
+            let seam = cheapest_seam(&cost, texture_size.width, current_width);
+
+            queue.write_buffer(&seam_buffer, 0, bytemuck::cast_slice(&seam));
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Seam Removal Encoder"),
+            });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Seam Removal Pass"),
+                });
+                compute_pass.set_pipeline(&self.remove_pipeline);
+                compute_pass.set_bind_group(0, textures.step(&bind_groups), &[]);
+                compute_pass.dispatch_workgroups(current_width - 1, texture_size.height, 1);
+            }
+
+            queue.submit(Some(encoder.finish()));
+        }
+
+        let buffer =
+            crate::read_texture(device, queue, textures.latest_texture(), 0, texture_size).await?;
+        let carved = RgbaImage::from_raw(texture_size.width, texture_size.height, buffer)
+            .ok_or_else(|| anyhow!("Seam buffer does not match the image size"))?;
+
+        Ok(imageops::crop_imm(&carved, 0, 0, width, texture_size.height).to_image())
+    }
+}
+
+/// Traces the cheapest seam back up from the bottom row of the accumulated
+/// `cost`, returning its column per row.
+fn cheapest_seam(cost: &[f32], stride: u32, width: u32) -> Vec<u32> {
+    let rows: Vec<&[f32]> = cost
+        .chunks_exact(stride as usize)
+        .map(|row| &row[..width as usize])
+        .collect();
+
+    let cheapest = |row: &[f32], columns: std::ops::Range<usize>| {
+        columns
+            .min_by(|&a, &b| row[a].total_cmp(&row[b]))
+            .unwrap_or_default()
+    };
+
+    let mut seam = vec![0; rows.len()];
+    let mut x = cheapest(rows[rows.len() - 1], 0..width as usize);
+
+    for (y, row) in rows.iter().enumerate().rev() {
+        if y + 1 < rows.len() {
+            x = cheapest(row, x.saturating_sub(1)..(x + 2).min(width as usize));
+        }
+        seam[y] = x as u32;
+    }
+
+    seam
+}
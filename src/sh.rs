@@ -0,0 +1,183 @@
+use anyhow::*;
+use serde::Serialize;
+use std::borrow::Cow;
+
+use crate::shader;
+
+/// Coefficients of the first three bands, the most [`project`] computes.
+pub const COEFFICIENTS: usize = 9;
+
+/// Clamped cosine convolution per band, turning radiance into irradiance
+/// (Ramamoorthi and Hanrahan 2001).
+const IRRADIANCE_BANDS: [f32; 3] = [
+    std::f32::consts::PI,
+    2.0 * std::f32::consts::PI / 3.0,
+    std::f32::consts::PI / 4.0,
+];
+
+/// Spherical harmonics of an environment, written as JSON.
+#[derive(Serialize)]
+pub struct SphericalHarmonics {
+    /// Bands, 2 for four coefficients and 3 for nine.
+    pub order: u32,
+    /// Whether the coefficients hold irradiance rather than radiance.
+    pub irradiance: bool,
+    /// RGB per basis function in (l, m) order, y being up.
+    pub coefficients: Vec<[f32; 3]>,
+}
+
+impl SphericalHarmonics {
+    /// The first `order` bands of `coefficients`, convolved into irradiance
+    /// if asked to.
+    pub fn new(coefficients: &[[f32; 3]; COEFFICIENTS], order: u32, irradiance: bool) -> Self {
+        let count = (order * order) as usize;
+        let coefficients = coefficients[..count]
+            .iter()
+            .enumerate()
+            .map(|(index, color)| {
+                let band = (index as f32).sqrt() as usize;
+                let scale = if irradiance {
+                    IRRADIANCE_BANDS[band]
+                } else {
+                    1.0
+                };
+                color.map(|channel| channel * scale)
+            })
+            .collect();
+
+        Self {
+            order,
+            irradiance,
+            coefficients,
+        }
+    }
+}
+
+/// Projects an equirectangular environment map, as uploaded by
+/// [`crate::ibl::upload_environment`], onto the spherical harmonics of the
+/// first three bands.
+pub async fn project(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    environment: &wgpu::Texture,
+) -> Result<[[f32; 3]; COEFFICIENTS]> {
+    let shader = shader::preprocess(include_str!("shaders/sh.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("SH Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("SH Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            storage_entry(1),
+            storage_entry(2),
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("SH Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("SH Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let rows_pipeline = pipeline("project_rows");
+    let sum_pipeline = pipeline("sum_rows");
+
+    let coefficients_size = (16 * COEFFICIENTS) as wgpu::BufferAddress;
+
+    let rows_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("SH Rows Buffer"),
+        size: coefficients_size * environment.height() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    let total_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("SH Total Buffer"),
+        size: coefficients_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let environment_view = environment.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("SH Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&environment_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: rows_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: total_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("SH Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("SH Pass"),
+        });
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+
+        compute_pass.set_pipeline(&rows_pipeline);
+        compute_pass.dispatch_workgroups(environment.height(), 1, 1);
+
+        compute_pass.set_pipeline(&sum_pipeline);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let bytes = crate::read_buffer(device, queue, &total_buffer).await?;
+    let values: Vec<[f32; 4]> = bytemuck::pod_collect_to_vec(&bytes);
+
+    let mut coefficients = [[0.0; 3]; COEFFICIENTS];
+    for (coefficient, [r, g, b, _]) in coefficients.iter_mut().zip(values) {
+        *coefficient = [r, g, b];
+    }
+
+    Ok(coefficients)
+}
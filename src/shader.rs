@@ -0,0 +1,575 @@
+use anyhow::*;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use wgpu_texture_copy::{expand_workgroup_size, overrides};
+
+/// Shader snippets shipped with the crate, available to every shader via
+/// `#include "<name>"`.
+const LIBRARY: &[(&str, &str)] = &[
+    ("common.wgsl", include_str!("shaders/lib/common.wgsl")),
+    ("globals.wgsl", include_str!("shaders/lib/globals.wgsl")),
+    ("color.wgsl", include_str!("shaders/lib/color.wgsl")),
+    ("noise.wgsl", include_str!("shaders/lib/noise.wgsl")),
+    ("sampling.wgsl", include_str!("shaders/lib/sampling.wgsl")),
+    (
+        POINTWISE_INCLUDE,
+        include_str!("shaders/lib/pointwise.wgsl"),
+    ),
+    (
+        "projection.wgsl",
+        include_str!("shaders/lib/projection.wgsl"),
+    ),
+    (
+        crate::resources::NOISE_TEXTURES_INCLUDE,
+        include_str!("shaders/lib/noise_textures.wgsl"),
+    ),
+    (
+        crate::resources::GLYPH_ATLAS_INCLUDE,
+        include_str!("shaders/lib/glyph_atlas.wgsl"),
+    ),
+];
+
+const INCLUDE_DIRECTIVE: &str = "#include";
+
+/// Declaration in `globals.wgsl` that `--strict-math` turns on.
+const STRICT_MATH_DECLARATION: &str = "const STRICT_MATH: bool = false;";
+
+/// Library file of the bindings of operations that only read the input
+/// texel they write, which `--in-place` runs on one texture.
+pub const POINTWISE_INCLUDE: &str = "pointwise.wgsl";
+
+/// What `--in-place` replaces the contents of [`POINTWISE_INCLUDE`] with, on
+/// an RGBA8 texture or one packing RGBA8 texels into 32-bit ones.
+const POINTWISE_IN_PLACE: &str = include_str!("shaders/lib/pointwise_in_place.wgsl");
+const POINTWISE_PACKED: &str = include_str!("shaders/lib/pointwise_packed.wgsl");
+
+/// A preprocessed shader ready to be handed to wgpu.
+#[derive(Debug)]
+pub struct Shader {
+    pub source: String,
+    /// Contents of every file pulled in, by its path from the directory of
+    /// the shader, or `lib:<name>` for the bundled ones.
+    pub includes: BTreeMap<String, String>,
+    /// Files the includes were read from, besides the bundled and locked
+    /// ones.
+    pub files: Vec<PathBuf>,
+    library_includes: HashSet<&'static str>,
+}
+
+impl Shader {
+    /// Whether the bundled library file `name` was pulled in, directly or
+    /// transitively, which is how optional bindings are detected.
+    pub fn includes(&self, name: &str) -> bool {
+        self.library_includes.contains(name)
+    }
+
+    /// Declares `@workgroup_size(WORKGROUP_SIZE)` entry points with `width`
+    /// by `height` invocations.
+    pub fn set_workgroup_size(&mut self, size: [u32; 2]) {
+        if let Cow::Owned(source) = expand_workgroup_size(&self.source, size) {
+            self.source = source;
+        }
+    }
+
+    /// Turns the `override` constants of the shader into `const` ones,
+    /// `constants` by name or id and the rest at their defaults.
+    pub fn specialize(&mut self, constants: &[(String, f32)]) -> Result<()> {
+        let constants: Vec<_> = constants
+            .iter()
+            .map(|(key, value)| (key.as_str(), *value as f64))
+            .collect();
+
+        if let Cow::Owned(source) = overrides::specialize(&self.source, &constants)? {
+            self.source = source;
+        }
+
+        Ok(())
+    }
+
+    /// Makes the `strict` helpers of `globals.wgsl` round every value they
+    /// are handed on its own.
+    pub fn set_strict_math(&mut self) {
+        self.source = self
+            .source
+            .replace(STRICT_MATH_DECLARATION, "const STRICT_MATH: bool = true;");
+    }
+}
+
+enum Origin<'a> {
+    Library,
+    /// Files in `dir`, which is at `path` from the directory of the shader.
+    File {
+        dir: PathBuf,
+        path: PathBuf,
+    },
+    /// The [`Shader::includes`] of an earlier run, looked up like files at
+    /// `path`, or in the bundled library alone when `None`.
+    Locked {
+        includes: &'a BTreeMap<String, String>,
+        path: Option<PathBuf>,
+    },
+}
+
+impl<'a> Origin<'a> {
+    fn file(dir: &Path) -> Self {
+        Self::File {
+            dir: dir.to_path_buf(),
+            path: PathBuf::new(),
+        }
+    }
+
+    fn locked(includes: &'a BTreeMap<String, String>) -> Self {
+        Self::Locked {
+            includes,
+            path: Some(PathBuf::new()),
+        }
+    }
+}
+
+/// An include found by [`resolve`].
+struct Resolved<'a> {
+    /// Tells the files included already apart.
+    key: String,
+    /// What [`Shader::includes`] holds it by.
+    lock_key: String,
+    contents: String,
+    /// Where the includes of the file are looked up.
+    origin: Origin<'a>,
+}
+
+/// Expands `#include "<name>"` directives in `source`.
+///
+/// Names are looked up relative to `base_dir` first (when the shader was
+/// loaded from disk) and then in the bundled library. Every file is included
+/// at most once, so shared helpers can be pulled in from several places.
+pub fn preprocess(source: &str, base_dir: Option<&Path>) -> Result<Shader> {
+    let origin = match base_dir {
+        Some(dir) => Origin::file(dir),
+        None => Origin::Library,
+    };
+
+    expand_all(source, &origin, None)
+}
+
+/// Like [`preprocess`], taking every include from `includes`, the
+/// [`Shader::includes`] of an earlier run, rather than from disk or the
+/// bundled library as they are now.
+pub fn preprocess_locked(source: &str, includes: &BTreeMap<String, String>) -> Result<Shader> {
+    expand_all(source, &Origin::locked(includes), None)
+}
+
+/// Like [`preprocess`], or [`preprocess_locked`] given `includes`, with
+/// [`POINTWISE_INCLUDE`] expanded to bindings that read and write the output
+/// alone, declared `read_write` with `format`: `Rgba8Unorm`, or `R32Uint`
+/// holding the bytes of RGBA8 texels. [`Shader::includes`] keeps the
+/// bindings as shipped, so locks don't depend on `--in-place`.
+pub fn preprocess_in_place(
+    source: &str,
+    base_dir: Option<&Path>,
+    includes: Option<&BTreeMap<String, String>>,
+    format: wgpu::TextureFormat,
+) -> Result<Shader> {
+    let origin = match (includes, base_dir) {
+        (Some(includes), _) => Origin::locked(includes),
+        (None, Some(dir)) => Origin::file(dir),
+        (None, None) => Origin::Library,
+    };
+
+    expand_all(source, &origin, Some(format))
+}
+
+fn expand_all(
+    source: &str,
+    origin: &Origin,
+    in_place: Option<wgpu::TextureFormat>,
+) -> Result<Shader> {
+    let mut included = HashSet::new();
+    let mut includes = BTreeMap::new();
+    let mut files = Vec::new();
+    let mut output = String::with_capacity(source.len());
+
+    expand(
+        source,
+        origin,
+        in_place,
+        &mut included,
+        &mut includes,
+        &mut files,
+        &mut output,
+    )?;
+
+    let library_includes = LIBRARY
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| included.contains(&library_key(name)))
+        .collect();
+
+    Ok(Shader {
+        source: output,
+        includes,
+        files,
+        library_includes,
+    })
+}
+
+fn expand(
+    source: &str,
+    origin: &Origin,
+    in_place: Option<wgpu::TextureFormat>,
+    included: &mut HashSet<String>,
+    includes: &mut BTreeMap<String, String>,
+    files: &mut Vec<PathBuf>,
+    output: &mut String,
+) -> Result<()> {
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+
+        if !trimmed.starts_with(INCLUDE_DIRECTIVE) {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        let name = parse_include(&trimmed[INCLUDE_DIRECTIVE.len()..])
+            .ok_or_else(|| anyhow!("Malformed include on line {}: {}", index + 1, trimmed))?;
+
+        let Resolved {
+            key,
+            lock_key,
+            contents,
+            origin: next_origin,
+        } = resolve(name, origin)?;
+
+        if included.contains(&key) {
+            continue;
+        }
+        if let Origin::File { .. } = next_origin {
+            files.push(PathBuf::from(&key));
+        }
+        let bindings = match in_place {
+            Some(format) if key == library_key(POINTWISE_INCLUDE) => Some(match format {
+                wgpu::TextureFormat::R32Uint => POINTWISE_PACKED,
+                _ => POINTWISE_IN_PLACE,
+            }),
+            _ => None,
+        };
+        included.insert(key);
+
+        includes.insert(lock_key, contents.clone());
+        expand(
+            bindings.unwrap_or(&contents),
+            &next_origin,
+            in_place,
+            included,
+            includes,
+            files,
+            output,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn parse_include(rest: &str) -> Option<&str> {
+    let rest = rest.trim();
+
+    rest.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn resolve<'a>(name: &str, origin: &Origin<'a>) -> Result<Resolved<'a>> {
+    if let Origin::Locked { includes, path } = origin {
+        let file = path
+            .as_ref()
+            .map(|path| (path_key(&path.join(name)), path.join(name)))
+            .filter(|(lock_key, _)| includes.contains_key(lock_key));
+
+        // Bundled files keep their library key, so optional bindings are
+        // detected.
+        let (key, lock_key, path) = match file {
+            Some((lock_key, path)) => (
+                format!("locked:{}", lock_key),
+                lock_key,
+                path.parent().map(Path::to_path_buf),
+            ),
+            None => (library_key(name), locked_library_key(name), None),
+        };
+        let contents = includes
+            .get(&lock_key)
+            .ok_or_else(|| anyhow!("Shader include \"{}\" is missing from the lock", name))?;
+
+        return Ok(Resolved {
+            key,
+            lock_key,
+            contents: contents.clone(),
+            origin: Origin::Locked { includes, path },
+        });
+    }
+
+    if let Origin::File { dir, path } = origin {
+        let file = dir.join(name);
+
+        if file.is_file() {
+            let contents = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read include {}", file.display()))?;
+            let key = file.canonicalize().unwrap_or(file);
+            let path = path.join(name);
+
+            return Ok(Resolved {
+                lock_key: path_key(&path),
+                origin: Origin::File {
+                    dir: key.parent().map(Path::to_path_buf).unwrap_or_default(),
+                    path: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+                },
+                key: key.display().to_string(),
+                contents,
+            });
+        }
+    }
+
+    LIBRARY
+        .iter()
+        .find(|(library_name, _)| *library_name == name)
+        .map(|(library_name, contents)| Resolved {
+            key: library_key(library_name),
+            lock_key: locked_library_key(library_name),
+            contents: contents.to_string(),
+            origin: Origin::Library,
+        })
+        .ok_or_else(|| anyhow!("Shader include \"{}\" could not be resolved", name))
+}
+
+/// `path`, relative to the directory of the shader, with its `.` and `..`
+/// taken out where they can be and `/` between its components on every
+/// platform. Absolute paths are kept as they are.
+fn path_key(path: &Path) -> String {
+    if path.has_root() {
+        return path.display().to_string();
+    }
+
+    let mut components: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if components.last().is_some_and(|last| last != "..") => {
+                components.pop();
+            }
+            component => components.push(component.as_os_str().to_string_lossy().into_owned()),
+        }
+    }
+
+    components.join("/")
+}
+
+fn locked_library_key(name: &str) -> String {
+    format!("lib:{}", name)
+}
+
+fn library_key(name: &str) -> String {
+    format!("library:{}", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNEL: &str = "#include \"pointwise.wgsl\"\nfn main() {}\n";
+
+    /// A directory holding `files`, by their paths in it.
+    fn shader_tree(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wtc-shader-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn includes_every_file_once() -> Result<()> {
+        let once = preprocess("#include \"color.wgsl\"\n#include \"common.wgsl\"\n", None)?;
+        let repeated = preprocess(
+            "#include \"color.wgsl\"\n#include \"common.wgsl\"\n#include \"color.wgsl\"\n",
+            None,
+        )?;
+
+        // `common.wgsl` includes `color.wgsl` again, and `noise.wgsl`.
+        assert_eq!(repeated.source, once.source);
+        assert_eq!(
+            once.source,
+            preprocess("#include \"common.wgsl\"\n", None)?.source
+        );
+        assert!(repeated.includes("noise.wgsl") && !repeated.includes("sampling.wgsl"));
+        assert_eq!(
+            repeated.includes.keys().collect::<Vec<_>>(),
+            ["lib:color.wgsl", "lib:common.wgsl", "lib:noise.wgsl"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_includes_next_to_the_file_including_them() -> Result<()> {
+        let dir = shader_tree(
+            "nested",
+            &[
+                ("lib/blend.wgsl", "#include \"weights.wgsl\"\nfn blend() {}"),
+                // Shadows the library one of the same name, for this tree.
+                ("lib/weights.wgsl", "fn weights() {}"),
+                ("color.wgsl", "fn mine() {}"),
+            ],
+        );
+        let shader = preprocess(
+            "#include \"lib/blend.wgsl\"\n#include \"color.wgsl\"\n",
+            Some(&dir),
+        )?;
+
+        assert_eq!(
+            shader.source,
+            "fn weights() {}\nfn blend() {}\nfn mine() {}\n"
+        );
+        assert_eq!(shader.files.len(), 3);
+        assert!(!shader.includes("color.wgsl"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn locks_includes_of_the_same_name_apart() -> Result<()> {
+        let dir = shader_tree(
+            "same-name",
+            &[
+                ("a/common.wgsl", "#include \"noise.wgsl\"\nfn a() {}"),
+                ("b/common.wgsl", "#include \"noise.wgsl\"\nfn b() {}"),
+                // Shadows the bundled one for `b` only.
+                ("b/noise.wgsl", "fn b_noise() {}"),
+            ],
+        );
+        let source = "#include \"a/common.wgsl\"\n#include \"b/./common.wgsl\"\n";
+        let shader = preprocess(source, Some(&dir))?;
+
+        assert_eq!(
+            shader.includes.keys().collect::<Vec<_>>(),
+            [
+                "a/common.wgsl",
+                "b/common.wgsl",
+                "b/noise.wgsl",
+                "lib:noise.wgsl"
+            ]
+        );
+        assert!(shader.includes("noise.wgsl"));
+
+        fs::remove_dir_all(&dir)?;
+        let locked = preprocess_locked(source, &shader.includes)?;
+        assert_eq!(locked.source, shader.source);
+        assert_eq!(locked.includes, shader.includes);
+        assert!(locked.includes("noise.wgsl"));
+        assert!(locked.source.contains("fn b_noise() {}\nfn b() {}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_at_includes_including_each_other() -> Result<()> {
+        let dir = shader_tree(
+            "cycle",
+            &[
+                ("a.wgsl", "#include \"b.wgsl\"\nfn a() {}"),
+                ("b.wgsl", "#include \"a.wgsl\"\nfn b() {}"),
+            ],
+        );
+        let shader = preprocess("#include \"a.wgsl\"\n#include \"a.wgsl\"\n", Some(&dir))?;
+
+        assert_eq!(shader.source, "fn b() {}\nfn a() {}\n");
+        assert_eq!(shader.files.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_includes_it_cannot_find() {
+        let err = preprocess("#include \"missing.wgsl\"\n", None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Shader include \"missing.wgsl\" could not be resolved"
+        );
+
+        let locked = BTreeMap::from([("lib:color.wgsl".to_string(), String::new())]);
+        let err = preprocess_locked("#include \"noise.wgsl\"\n", &locked).unwrap_err();
+        assert!(err.to_string().contains("missing from the lock"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_includes() {
+        for line in [
+            "#include color.wgsl",
+            "#include \"color.wgsl",
+            "#include",
+            "#include <color.wgsl>",
+        ] {
+            let err = preprocess(&format!("fn main() {{}}\n  {}\n", line), None).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("Malformed include on line 2: {}", line),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn expands_pointwise_bindings_in_place() -> Result<()> {
+        let shader = preprocess_in_place(KERNEL, None, None, wgpu::TextureFormat::Rgba8Unorm)?;
+
+        assert!(shader.includes(POINTWISE_INCLUDE));
+        assert!(shader.source.starts_with(POINTWISE_IN_PLACE));
+        assert!(!shader.source.contains("textureInput"));
+        assert!(shader.source.ends_with("fn main() {}\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn packs_in_place_texels_into_32_bits() -> Result<()> {
+        let shader = preprocess_in_place(KERNEL, None, None, wgpu::TextureFormat::R32Uint)?;
+
+        assert!(shader
+            .source
+            .contains("texture_storage_2d<r32uint, read_write>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn locks_the_shipped_pointwise_bindings_in_place() -> Result<()> {
+        let shipped = preprocess(KERNEL, None)?;
+        let in_place = preprocess_in_place(
+            KERNEL,
+            None,
+            Some(&shipped.includes),
+            wgpu::TextureFormat::Rgba8Unorm,
+        )?;
+
+        assert_eq!(in_place.includes, shipped.includes);
+        assert!(in_place.source.starts_with(POINTWISE_IN_PLACE));
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_kernels_without_pointwise_bindings_alone() -> Result<()> {
+        let source = "fn main() {}\n";
+        let shader = preprocess_in_place(source, None, None, wgpu::TextureFormat::Rgba8Unorm)?;
+
+        assert!(!shader.includes(POINTWISE_INCLUDE));
+        assert_eq!(shader.source, source);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+#include "globals.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+const MAX_RADIUS: i32 = 64;
+
+// Fills the color of fully transparent texels with the average color of the
+// nearest non-transparent texels within `radius`, keeping alpha untouched, so
+// filtering and mipmapping don't pull in black from empty areas.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn alpha_bleed(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let center = textureLoad(textureInput, coord, 0);
+
+  if center.a > 0.0 {
+    textureStore(textureOutput, coord, center);
+    return;
+  }
+
+  let radius = min(i32(param(0u)), MAX_RADIUS);
+  let size = vec2<i32>(globals.size);
+
+  var nearest = f32(radius * radius + 1);
+
+  for (var y = -radius; y <= radius; y = y + 1) {
+    for (var x = -radius; x <= radius; x = x + 1) {
+      let p = coord + vec2<i32>(x, y);
+      let distance = f32(x * x + y * y);
+
+      if any(p < vec2<i32>(0)) || any(p >= size) || distance >= nearest {
+        continue;
+      }
+
+      if textureLoad(textureInput, p, 0).a > 0.0 {
+        nearest = distance;
+      }
+    }
+  }
+
+  if nearest > f32(radius * radius) {
+    textureStore(textureOutput, coord, center);
+    return;
+  }
+
+  // Average over a one pixel wide band beyond the nearest distance to avoid
+  // streaks from picking a single source texel.
+  let band = (sqrt(nearest) + 1.0) * (sqrt(nearest) + 1.0);
+  var sum = vec3<f32>(0.0);
+  var count = 0.0;
+
+  for (var y = -radius; y <= radius; y = y + 1) {
+    for (var x = -radius; x <= radius; x = x + 1) {
+      let p = coord + vec2<i32>(x, y);
+
+      if any(p < vec2<i32>(0)) || any(p >= size) || f32(x * x + y * y) > band {
+        continue;
+      }
+
+      let color = textureLoad(textureInput, p, 0);
+      if color.a > 0.0 {
+        sum = strict3(sum + color.rgb);
+        count = count + 1.0;
+      }
+    }
+  }
+
+  textureStore(textureOutput, coord, vec4<f32>(sum / count, 0.0));
+}
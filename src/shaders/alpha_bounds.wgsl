@@ -0,0 +1,25 @@
+struct Bounds {
+  min_x: atomic<u32>,
+  min_y: atomic<u32>,
+  max_x: atomic<u32>,
+  max_y: atomic<u32>,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> bounds: Bounds;
+@group(0) @binding(2)
+var<uniform> threshold: vec4<f32>;
+
+@compute @workgroup_size(1)
+fn alpha_bounds(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let color = textureLoad(textureInput, vec2<i32>(i32(global_id.x), i32(global_id.y)), 0);
+
+  if color.a > threshold.x {
+    atomicMin(&bounds.min_x, global_id.x);
+    atomicMin(&bounds.min_y, global_id.y);
+    atomicMax(&bounds.max_x, global_id.x);
+    atomicMax(&bounds.max_y, global_id.y);
+  }
+}
@@ -0,0 +1,45 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+#include "glyph_atlas.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+// Character mosaic: every `cell` texels square block is replaced by the atlas
+// glyph whose ink coverage matches the block's mean luminance, scaled to the
+// block. With `color` set the glyph is tinted with the block's mean color,
+// otherwise it is drawn white on black.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn ascii(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let cell = u32(param(0u));
+  let size = vec2<u32>(textureDimensions(textureInput));
+
+  let block = global_id.xy / cell;
+  let start = block * cell;
+  let end = min(start + cell, size);
+
+  var sum = vec3<f32>(0.0);
+  for (var y = start.y; y < end.y; y++) {
+    for (var x = start.x; x < end.x; x++) {
+      sum = strict3(sum + textureLoad(textureInput, vec2<i32>(i32(x), i32(y)), 0).rgb);
+    }
+  }
+  let extent = end - start;
+  let mean = sum / f32(extent.x * extent.y);
+
+  let count = glyph_count();
+  let index = min(u32(luminance(mean) * f32(count)), count - 1u);
+
+  let local = (global_id.xy - start) * GLYPH_SIZE / cell;
+  let ink = glyph(index, local);
+
+  let tint = select(vec3<f32>(1.0), mean, param(1u) > 0.5);
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  textureStore(textureOutput, coord, vec4<f32>(tint * ink, 1.0));
+}
@@ -0,0 +1,32 @@
+#include "noise.wgsl"
+
+struct Audit {
+  differs: atomic<u32>,
+  hash_xor: atomic<u32>,
+  hash_sum: atomic<u32>,
+  _padding: u32,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> audit: Audit;
+
+// Flags whether any texel differs from the first one and folds every texel
+// into an order-independent 64-bit fingerprint of position and value.
+@compute @workgroup_size(1)
+fn audit_texture(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let size = textureDimensions(textureInput);
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+  let value = pack4x8unorm(textureLoad(textureInput, coord, 0));
+  let first = pack4x8unorm(textureLoad(textureInput, vec2<i32>(0, 0), 0));
+
+  if value != first {
+    atomicOr(&audit.differs, 1u);
+  }
+
+  let index = global_id.y * u32(size.x) + global_id.x;
+  atomicXor(&audit.hash_xor, pcg(index ^ pcg(value)));
+  atomicAdd(&audit.hash_sum, pcg(value + pcg(index + 0x9e3779b9u)));
+}
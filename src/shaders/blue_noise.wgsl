@@ -0,0 +1,231 @@
+// Void-and-cluster (Ulichney 1993) over a tileable square. The energy of
+// every texel is the Gaussian-weighted count of minority texels around it;
+// the tightest cluster is the minority texel of highest energy and the
+// largest void the majority texel of lowest. Each step finds one of them
+// row by row, picks it in a single invocation and updates the energy
+// around it, all without returning to the CPU.
+
+#include "noise.wgsl"
+
+struct Settings {
+  size: u32,
+  seed: u32,
+  // Fraction of minority texels in the initial pattern.
+  density: f32,
+  sigma: f32,
+  // Texels the energy is spread over around a minority texel, both ways.
+  radius: i32,
+}
+
+struct Texel {
+  energy: f32,
+  // 1 for minority texels.
+  minority: u32,
+  rank: u32,
+  // The prototype pattern, which both ranking phases start from.
+  saved_energy: f32,
+  saved_minority: u32,
+}
+
+struct Candidate {
+  energy: f32,
+  index: u32,
+}
+
+struct State {
+  // Texel whose energy contribution the next update adds or removes.
+  chosen: u32,
+  sign: f32,
+  // Texel the last swap removed; the prototype is stable once the largest
+  // void is that very texel.
+  removed: u32,
+  converged: u32,
+  minority: u32,
+  // Minority texels of the prototype.
+  prototype: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> settings: Settings;
+@group(0) @binding(1)
+var<storage, read_write> texels: array<Texel>;
+@group(0) @binding(2)
+var<storage, read_write> candidates: array<Candidate>;
+@group(0) @binding(3)
+var<storage, read_write> state: State;
+
+fn texel_index(x: i32, y: i32) -> u32 {
+  let size = i32(settings.size);
+  return u32(((y % size) + size) % size) * settings.size + u32(((x % size) + size) % size);
+}
+
+// Offsets covered around a texel, kept within one tile.
+fn window() -> vec2<i32> {
+  let extent = min(2 * settings.radius + 1, i32(settings.size));
+  return vec2<i32>(-extent / 2, extent - extent / 2);
+}
+
+fn weight(offset: vec2<i32>) -> f32 {
+  let distance2 = f32(dot(offset, offset));
+  return exp(-distance2 / (2.0 * settings.sigma * settings.sigma));
+}
+
+@compute @workgroup_size(1)
+fn seed(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = global_id.y * settings.size + global_id.x;
+  let minority = random(global_id.xy, settings.seed) < settings.density;
+
+  texels[index] = Texel(0.0, u32(minority), 0u, 0.0, 0u);
+}
+
+// Counts the initial minority texels, making sure there is one at least.
+@compute @workgroup_size(1)
+fn count() {
+  var minority = 0u;
+  for (var index = 0u; index < arrayLength(&texels); index++) {
+    minority += texels[index].minority;
+  }
+
+  if minority == 0u {
+    texels[0].minority = 1u;
+    minority = 1u;
+  }
+
+  state = State(0u, 0.0, 0u, 0u, minority, 0u);
+}
+
+@compute @workgroup_size(1)
+fn convolve(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let bounds = window();
+  let center = vec2<i32>(global_id.xy);
+
+  var energy = 0.0;
+  for (var y = bounds.x; y < bounds.y; y++) {
+    for (var x = bounds.x; x < bounds.y; x++) {
+      let neighbor = texels[texel_index(center.x + x, center.y + y)].minority;
+      energy += f32(neighbor) * weight(vec2<i32>(x, y));
+    }
+  }
+
+  texels[global_id.y * settings.size + global_id.x].energy = energy;
+}
+
+// Highest energy among the minority texels of every row, or with `voids`
+// the lowest among the others.
+fn find_in_row(row: u32, voids: bool) {
+  var best = Candidate(-1.0, settings.size * settings.size);
+  if voids {
+    best.energy = 3.4e38;
+  }
+
+  for (var x = 0u; x < settings.size; x++) {
+    let index = row * settings.size + x;
+    let texel = texels[index];
+    if (texel.minority == 0u) != voids {
+      continue;
+    }
+
+    if (voids && texel.energy < best.energy) || (!voids && texel.energy > best.energy) {
+      best = Candidate(texel.energy, index);
+    }
+  }
+
+  candidates[row] = best;
+}
+
+@compute @workgroup_size(1)
+fn cluster_rows(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  find_in_row(global_id.x, false);
+}
+
+@compute @workgroup_size(1)
+fn void_rows(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  find_in_row(global_id.x, true);
+}
+
+fn pick(voids: bool) -> u32 {
+  var best = candidates[0];
+  for (var row = 1u; row < settings.size; row++) {
+    let candidate = candidates[row];
+    if (voids && candidate.energy < best.energy) || (!voids && candidate.energy > best.energy) {
+      best = candidate;
+    }
+  }
+
+  return best.index;
+}
+
+fn flip(index: u32, minority: bool) {
+  texels[index].minority = u32(minority);
+  state.chosen = index;
+  state.sign = select(-1.0, 1.0, minority);
+}
+
+@compute @workgroup_size(1)
+fn swap_remove() {
+  state.sign = 0.0;
+  if state.converged == 0u {
+    let index = pick(false);
+    state.removed = index;
+    flip(index, false);
+  }
+}
+
+@compute @workgroup_size(1)
+fn swap_insert() {
+  state.sign = 0.0;
+  if state.converged == 0u {
+    let index = pick(true);
+    state.converged = u32(index == state.removed);
+    flip(index, true);
+  }
+}
+
+@compute @workgroup_size(1)
+fn save(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = global_id.y * settings.size + global_id.x;
+  texels[index].saved_energy = texels[index].energy;
+  texels[index].saved_minority = texels[index].minority;
+  if index == 0u {
+    state.prototype = state.minority;
+  }
+}
+
+@compute @workgroup_size(1)
+fn restore(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = global_id.y * settings.size + global_id.x;
+  texels[index].energy = texels[index].saved_energy;
+  texels[index].minority = texels[index].saved_minority;
+  if index == 0u {
+    state.minority = state.prototype;
+  }
+}
+
+// Ranks the prototype's minority texels from the tightest cluster down.
+@compute @workgroup_size(1)
+fn rank_remove() {
+  let index = pick(false);
+  state.minority -= 1u;
+  texels[index].rank = state.minority;
+  flip(index, false);
+}
+
+// Ranks the remaining texels from the largest void up.
+@compute @workgroup_size(1)
+fn rank_insert() {
+  let index = pick(true);
+  texels[index].rank = state.minority;
+  state.minority += 1u;
+  flip(index, true);
+}
+
+// Adds or removes the energy of the chosen texel around it.
+@compute @workgroup_size(1)
+fn update(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let bounds = window();
+  let offset = vec2<i32>(global_id.xy) + bounds.x;
+  let center = vec2<i32>(i32(state.chosen % settings.size), i32(state.chosen / settings.size));
+
+  let index = texel_index(center.x + offset.x, center.y + offset.y);
+  texels[index].energy += state.sign * weight(offset);
+}
@@ -0,0 +1,49 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+const MAX_RADIUS: i32 = 64;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn blur(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let sigma = param(0u);
+
+  if sigma <= 0.0 {
+    textureStore(textureOutput, coord, textureLoad(textureInput, coord, 0));
+    return;
+  }
+
+  let radius = min(i32(ceil(sigma * 3.0)), MAX_RADIUS);
+  let max_coord = vec2<i32>(globals.size) - 1;
+
+  // Accumulate premultiplied linear color so transparent texels don't bleed.
+  var sum = vec4<f32>(0.0);
+  var weight_sum = 0.0;
+
+  for (var y = -radius; y <= radius; y = y + 1) {
+    for (var x = -radius; x <= radius; x = x + 1) {
+      let sample_coord = clamp(coord + vec2<i32>(x, y), vec2<i32>(0), max_coord);
+      let color = textureLoad(textureInput, sample_coord, 0);
+      let weight = exp(-f32(x * x + y * y) / (2.0 * sigma * sigma));
+
+      sum = strict4(sum + strict4(vec4<f32>(srgb_to_linear(color.rgb) * color.a, color.a) * weight));
+      weight_sum = strict(weight_sum + weight);
+    }
+  }
+
+  var rgb = vec3<f32>(0.0);
+  if sum.a > 0.0 {
+    rgb = linear_to_srgb(sum.rgb / sum.a);
+  }
+
+  textureStore(textureOutput, coord, vec4<f32>(rgb, sum.a / weight_sum));
+}
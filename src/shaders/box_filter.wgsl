@@ -0,0 +1,59 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3)
+var<storage, read> sums: array<vec4<u32>>;
+
+// Table entry at `coord`, zero left of and above the image.
+fn table(coord: vec2<i32>) -> vec4<u32> {
+  if coord.x < 0 || coord.y < 0 {
+    return vec4<u32>(0u);
+  }
+
+  return sums[u32(coord.y) * globals.size.x + u32(coord.x)];
+}
+
+// Mean color of the texels within `radius` of `coord`, clipped to the image,
+// from four lookups whatever the radius.
+fn box_mean(coord: vec2<i32>, radius: i32) -> vec4<f32> {
+  let low = max(coord - radius, vec2<i32>(0)) - 1;
+  let high = min(coord + radius, vec2<i32>(globals.size) - 1);
+
+  let sum = table(high) - table(vec2<i32>(low.x, high.y)) - table(vec2<i32>(high.x, low.y))
+    + table(low);
+  let area = f32((high.x - low.x) * (high.y - low.y));
+
+  return vec4<f32>(sum) / (area * 255.0);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn box_blur(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+
+  textureStore(textureOutput, coord, box_mean(coord, i32(param(0u))));
+}
+
+// Black where a texel is darker than the mean of its surroundings by more
+// than `offset`, white elsewhere, so uneven lighting doesn't swamp the result.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn adaptive_threshold(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let color = textureLoad(textureInput, coord, 0);
+
+  let mean = luminance(box_mean(coord, i32(param(0u))).rgb);
+  let value = select(0.0, 1.0, luminance(color.rgb) >= mean - param(1u));
+
+  textureStore(textureOutput, coord, vec4<f32>(vec3<f32>(value), color.a));
+}
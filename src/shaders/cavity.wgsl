@@ -0,0 +1,53 @@
+#include "globals.wgsl"
+#include "sampling.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3)
+var textureCavity: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(4)
+var textureEdges: texture_storage_2d<rgba8unorm, write>;
+
+// Downhill slope of the surface at `coord`, in image directions (y down),
+// from a tangent-space normal map with green pointing up, or down for
+// `directx` ones.
+fn downhill(coord: vec2<i32>, directx: bool) -> vec2<f32> {
+  let normal = load_clamped(textureInput, coord).rgb * 2.0 - 1.0;
+  var slope = normal.xy / max(normal.z, 0.1);
+  if !directx {
+    slope.y = -slope.y;
+  }
+  return slope;
+}
+
+fn gray(value: f32) -> vec4<f32> {
+  return vec4<f32>(vec3<f32>(value), 1.0);
+}
+
+// Curvature from the divergence of the normals `radius` texels apart:
+// normals spread apart over convex edges and lean together in cavities.
+// The output is gray where flat, brighter on edges and darker in cavities,
+// with cavity and edge masks next to it.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cavity(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let radius = max(i32(param(0u)), 1);
+  let directx = param(2u) >= 0.5;
+
+  let x = vec2<i32>(radius, 0);
+  let y = vec2<i32>(0, radius);
+  let divergence = (downhill(coord + x, directx).x - downhill(coord - x, directx).x
+    + downhill(coord + y, directx).y - downhill(coord - y, directx).y) / f32(2 * radius);
+
+  let curvature = divergence * param(1u);
+
+  textureStore(textureOutput, coord, gray(0.5 + 0.5 * tanh(curvature)));
+  textureStore(textureCavity, coord, gray(clamp(-curvature, 0.0, 1.0)));
+  textureStore(textureEdges, coord, gray(clamp(curvature, 0.0, 1.0)));
+}
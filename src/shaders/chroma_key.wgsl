@@ -0,0 +1,54 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+#include "sampling.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3)
+var textureMask: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(4)
+var textureEdges: texture_storage_2d<rgba8unorm, write>;
+
+// Blue and red difference of `c`, where hues lie around the origin.
+fn chroma(c: vec3<f32>) -> vec2<f32> {
+  let y = luminance(c);
+  return vec2<f32>(c.b - y, c.r - y);
+}
+
+// Opacity of `coord`: zero where its chroma points along the key hue by
+// more than the threshold plus the softness, rising to one at the threshold.
+// Chroma off the key hue counts against it, so other saturated colors stay.
+fn matte(coord: vec2<i32>) -> f32 {
+  let key = normalize(chroma(hsv_to_rgb(vec3<f32>(param(0u) / 360.0, 1.0, 1.0))));
+  let c = chroma(load_clamped(textureInput, coord).rgb);
+
+  let along = dot(c, key);
+  let keyness = along - length(c - key * along);
+
+  return 1.0 - smoothstep(param(1u), param(1u) + max(param(2u), 1.0e-4), keyness);
+}
+
+// Keys out a backdrop color, writing the keyed color, its matte and the
+// outline of the matte in one pass.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn chroma_key(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let color = textureLoad(textureInput, coord, 0);
+  let alpha = matte(coord);
+
+  let slope = vec2<f32>(
+    matte(coord + vec2<i32>(1, 0)) - matte(coord - vec2<i32>(1, 0)),
+    matte(coord + vec2<i32>(0, 1)) - matte(coord - vec2<i32>(0, 1)),
+  );
+  let edge = clamp(length(slope), 0.0, 1.0);
+
+  textureStore(textureOutput, coord, vec4<f32>(color.rgb, color.a * alpha));
+  textureStore(textureMask, coord, vec4<f32>(vec3<f32>(alpha), 1.0));
+  textureStore(textureEdges, coord, vec4<f32>(vec3<f32>(edge), 1.0));
+}
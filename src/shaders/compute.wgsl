@@ -1,11 +1,13 @@
-@group(0) @binding(0)
-var textureInput: texture_2d<f32>;
-@group(0) @binding(1)
-var textureOutput: texture_storage_2d<rgba8unorm, write>;
+// Copies the input unchanged, through the bindings of `pointwise.wgsl`:
+// the library prepends them, operations include them.
 
-@compute @workgroup_size(1)
+@compute @workgroup_size(WORKGROUP_SIZE)
 fn basic(@builtin(global_invocation_id) global_id: vec3<u32>) {
-  var color = textureLoad(textureInput, vec2<i32>(i32(global_id.x), i32(global_id.y)), 0);
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
 
-  textureStore(textureOutput, vec2<i32>(i32(global_id.x), i32(global_id.y)), color);
-}
\ No newline at end of file
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+  store_output(coord, load_input(coord));
+}
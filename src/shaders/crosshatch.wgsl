@@ -0,0 +1,44 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+#include "pointwise.wgsl"
+
+fn rotate(p: vec2<f32>, angle: f32) -> vec2<f32> {
+  let c = cos(angle);
+  let s = sin(angle);
+  return vec2<f32>(c * p.x - s * p.y, s * p.x + c * p.y);
+}
+
+// Distance to the nearest of the parallel lines `spacing` apart at `angle`.
+fn hatch(position: vec2<f32>, angle: f32, spacing: f32) -> f32 {
+  let offset = rotate(position, -angle).y / spacing;
+  return abs(fract(offset) - 0.5) * spacing;
+}
+
+// Pen hatching: darker areas get more layers of lines, each at its own
+// angle.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn crosshatch(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let spacing = param(0u);
+  let width = param(1u);
+
+  let position = vec2<f32>(coord) + 0.5;
+  let color = load_input(coord);
+  let darkness = 1.0 - clamp(luminance(color.rgb), 0.0, 1.0);
+
+  var angles = array<f32, 4>(45.0, -45.0, 0.0, 90.0);
+
+  var ink = 0.0;
+  for (var layer = 0; layer < 4; layer++) {
+    if darkness > f32(layer) * 0.2 + 0.1 {
+      let line = hatch(position, radians(angles[layer]), spacing);
+      ink = max(ink, 1.0 - smoothstep(width * 0.5 - 0.5, width * 0.5 + 0.5, line));
+    }
+  }
+
+  store_output(coord, vec4<f32>(vec3<f32>(1.0 - ink), color.a));
+}
@@ -0,0 +1,34 @@
+struct Stats {
+  sum: atomic<u32>,
+  max: atomic<u32>,
+  differing: atomic<u32>,
+}
+
+@group(0) @binding(0)
+var textureFirst: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2)
+var<storage, read_write> stats: Stats;
+@group(0) @binding(3)
+var textureSecond: texture_2d<f32>;
+
+// Differences are scaled up in the output so small ones stay visible.
+const AMPLIFICATION: f32 = 4.0;
+
+// Writes the per-channel difference of both inputs and accumulates the
+// largest channel difference of every texel, in 8-bit steps.
+@compute @workgroup_size(1)
+fn diff(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let difference = abs(textureLoad(textureFirst, coord, 0) - textureLoad(textureSecond, coord, 0));
+
+  let largest = u32(round(max(max(difference.r, difference.g), max(difference.b, difference.a)) * 255.0));
+  atomicAdd(&stats.sum, largest);
+  atomicMax(&stats.max, largest);
+  if largest > 0u {
+    atomicAdd(&stats.differing, 1u);
+  }
+
+  textureStore(textureOutput, coord, vec4<f32>(min(difference.rgb * AMPLIFICATION, vec3<f32>(1.0)), 1.0));
+}
@@ -0,0 +1,174 @@
+// Hydraulic erosion with the virtual pipe model (Mei et al. 2007): water
+// flows between neighbouring texels through pipes driven by the difference
+// of their water levels, dissolving terrain where it runs fast and dropping
+// it where it slows down. Heights, water and sediment are in texels.
+
+#include "sampling.wgsl"
+
+struct Settings {
+  // Water added to every texel per time unit.
+  rain: f32,
+  time_step: f32,
+  // How much sediment flowing water can carry, per unit of speed and tilt.
+  capacity: f32,
+  // Rates at which the missing sediment is dissolved and the excess one
+  // deposited.
+  dissolving: f32,
+  deposition: f32,
+  evaporation: f32,
+  gravity: f32,
+  // Lower bound of the tilt, so flat areas still erode a little.
+  min_tilt: f32,
+}
+
+// Red is the terrain height, green the water depth and blue the suspended
+// sediment.
+@group(0) @binding(0)
+var terrainInput: texture_2d<f32>;
+// Outflow to the left, right, top and bottom neighbour.
+@group(0) @binding(1)
+var fluxInput: texture_2d<f32>;
+@group(0) @binding(2)
+var velocityInput: texture_2d<f32>;
+@group(0) @binding(3)
+var terrainOutput: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(4)
+var fluxOutput: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5)
+var velocityOutput: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(6)
+var<uniform> settings: Settings;
+// Final height and water discharge of every texel, row by row.
+@group(0) @binding(7)
+var<storage, read_write> maps: array<vec2<f32>>;
+
+fn water_level(coord: vec2<i32>) -> f32 {
+  let terrain = load_clamped(terrainInput, coord);
+  return terrain.x + terrain.y;
+}
+
+fn height(coord: vec2<i32>) -> f32 {
+  return load_clamped(terrainInput, coord).x;
+}
+
+// No water flows in from beyond the map.
+fn outflow(coord: vec2<i32>) -> vec4<f32> {
+  let size = vec2<i32>(textureDimensions(fluxInput));
+  if any(coord < vec2<i32>(0)) || any(coord >= size) {
+    return vec4<f32>(0.0);
+  }
+  return textureLoad(fluxInput, coord, 0);
+}
+
+@compute @workgroup_size(1)
+fn flux(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+  let size = vec2<i32>(textureDimensions(terrainInput));
+  let terrain = textureLoad(terrainInput, coord, 0);
+
+  let level = terrain.x + terrain.y;
+  let neighbours = vec4<f32>(
+    water_level(coord - vec2<i32>(1, 0)),
+    water_level(coord + vec2<i32>(1, 0)),
+    water_level(coord - vec2<i32>(0, 1)),
+    water_level(coord + vec2<i32>(0, 1)),
+  );
+
+  var pipes = max(
+    textureLoad(fluxInput, coord, 0) + settings.time_step * settings.gravity * (level - neighbours),
+    vec4<f32>(0.0),
+  );
+
+  // The borders are walls.
+  let border = vec4<bool>(coord.x == 0, coord.x == size.x - 1, coord.y == 0, coord.y == size.y - 1);
+  pipes = select(pipes, vec4<f32>(0.0), border);
+
+  // Never let more water out than there is.
+  let total = dot(pipes, vec4<f32>(1.0)) * settings.time_step;
+  if total > terrain.y {
+    pipes *= terrain.y / total;
+  }
+
+  textureStore(fluxOutput, coord, pipes);
+}
+
+@compute @workgroup_size(1)
+fn water(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+  let terrain = textureLoad(terrainInput, coord, 0);
+
+  let leaving = textureLoad(fluxInput, coord, 0);
+  let arriving = vec4<f32>(
+    outflow(coord - vec2<i32>(1, 0)).y,
+    outflow(coord + vec2<i32>(1, 0)).x,
+    outflow(coord - vec2<i32>(0, 1)).w,
+    outflow(coord + vec2<i32>(0, 1)).z,
+  );
+
+  let depth = max(
+    terrain.y + settings.time_step * (dot(arriving, vec4<f32>(1.0)) - dot(leaving, vec4<f32>(1.0))),
+    0.0,
+  );
+
+  // Water passing through per unit of depth.
+  let passing = vec2<f32>(arriving.x - leaving.x + leaving.y - arriving.y, arriving.z - leaving.z + leaving.w - arriving.w) * 0.5;
+  let mean_water = (terrain.y + depth) * 0.5;
+  var velocity = vec2<f32>(0.0);
+  if mean_water > 1e-3 {
+    velocity = passing / mean_water;
+  }
+
+  let gradient = vec2<f32>(
+    height(coord + vec2<i32>(1, 0)) - height(coord - vec2<i32>(1, 0)),
+    height(coord + vec2<i32>(0, 1)) - height(coord - vec2<i32>(0, 1)),
+  ) * 0.5;
+  let slope = length(gradient);
+  let tilt = max(slope / sqrt(1.0 + slope * slope), settings.min_tilt);
+
+  // Thin films of water have little to carry sediment in, however fast
+  // they run.
+  let capacity = settings.capacity * tilt * length(velocity) * min(depth, 1.0);
+
+  var ground = terrain.x;
+  var sediment = terrain.z;
+  if capacity > sediment {
+    let dissolved = settings.dissolving * (capacity - sediment) * settings.time_step;
+    ground -= dissolved;
+    sediment += dissolved;
+  } else {
+    let deposited = settings.deposition * (sediment - capacity) * settings.time_step;
+    ground += deposited;
+    sediment -= deposited;
+  }
+
+  textureStore(terrainOutput, coord, vec4<f32>(ground, depth, sediment, 0.0));
+  textureStore(velocityOutput, coord, vec4<f32>(velocity, 0.0, 0.0));
+}
+
+// Moves the sediment along with the water, then lets some of the water
+// evaporate and new rain fall.
+@compute @workgroup_size(1)
+fn advect(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+  let terrain = textureLoad(terrainInput, coord, 0);
+  let velocity = textureLoad(velocityInput, coord, 0).xy;
+
+  let origin = vec2<f32>(coord) + 0.5 - velocity * settings.time_step;
+  let sediment = sample_bilinear(terrainInput, origin).z;
+
+  let depth = terrain.y * (1.0 - settings.evaporation * settings.time_step)
+    + settings.rain * settings.time_step;
+
+  textureStore(terrainOutput, coord, vec4<f32>(terrain.x, depth, sediment, 0.0));
+}
+
+// Settles what the water still carries and writes out the result.
+@compute @workgroup_size(1)
+fn output(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+  let width = textureDimensions(terrainInput).x;
+  let terrain = textureLoad(terrainInput, coord, 0);
+  let velocity = textureLoad(velocityInput, coord, 0).xy;
+
+  maps[global_id.y * width + global_id.x] = vec2<f32>(terrain.x + terrain.z, length(velocity) * terrain.y);
+}
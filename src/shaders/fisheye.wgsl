@@ -0,0 +1,60 @@
+#include "sampling.wgsl"
+#include "projection.wgsl"
+
+const PI: f32 = 3.14159265;
+
+struct Settings {
+  // Lens centers in texels of the dual-lens frame, front then back.
+  front_center: vec2<f32>,
+  back_center: vec2<f32>,
+  // Radius of the image circles, in texels.
+  radius: f32,
+  // Half the field of view of each lens, in radians.
+  half_fov: f32,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2)
+var<uniform> settings: Settings;
+
+// Sample of an equidistant fisheye lens looking along +z in `direction`
+// and its blend weight, which fades out past 90 degrees so the lenses
+// cross-fade where their fields of view overlap.
+fn lens(direction: vec3<f32>, center: vec2<f32>) -> vec4<f32> {
+  let angle = acos(clamp(direction.z, -1.0, 1.0));
+  if angle > settings.half_fov {
+    return vec4<f32>(0.0);
+  }
+
+  let planar = length(direction.xy);
+  let axis = select(vec2<f32>(0.0), direction.xy / planar, planar > 1.0e-6);
+  let position = center + axis * angle / settings.half_fov * settings.radius;
+
+  let overlap = max(settings.half_fov - PI * 0.5, 1.0e-3);
+  let weight = clamp((settings.half_fov - angle) / overlap, 0.0, 1.0);
+
+  return vec4<f32>(sample_bilinear(textureInput, position).rgb * weight, weight);
+}
+
+// Equirectangular output: longitude across, latitude down, with the front
+// lens in the middle.
+@compute @workgroup_size(1)
+fn equirectangular(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let size = vec2<f32>(textureDimensions(textureOutput));
+  let uv = (vec2<f32>(global_id.xy) + 0.5) / size;
+
+  let longitude = (uv.x - 0.5) * 2.0 * PI;
+  let latitude = (uv.y - 0.5) * PI;
+  let direction = panorama_direction(PROJECTION_SPHERICAL, longitude, latitude);
+
+  let front = lens(direction, settings.front_center);
+  let back = lens(rotate_yaw(direction, PI), settings.back_center);
+
+  let sum = front + back;
+  let color = select(vec3<f32>(0.0), sum.rgb / sum.a, sum.a > 0.0);
+
+  textureStore(textureOutput, vec2<i32>(global_id.xy), vec4<f32>(color, 1.0));
+}
@@ -0,0 +1,86 @@
+#include "globals.wgsl"
+#include "noise.wgsl"
+#include "sampling.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+// Every effect draws its randomness from the seed, varied per frame so
+// sequences flicker.
+fn glitch_seed() -> u32 {
+  return pcg(globals.seed ^ pcg(globals.frame));
+}
+
+// Shifts red and blue in opposite horizontal directions, by `amount` texels
+// plus a random extra per band of rows.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn channel_shift(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let amount = param(0u);
+
+  let band = random(vec2<u32>(0u, global_id.y / 8u), glitch_seed());
+  let shift = i32(round(amount * (0.5 + band)));
+
+  let color = textureLoad(textureInput, coord, 0);
+  let red = load_clamped(textureInput, coord + vec2<i32>(shift, 0)).r;
+  let blue = load_clamped(textureInput, coord - vec2<i32>(shift, 0)).b;
+
+  textureStore(textureOutput, coord, vec4<f32>(red, color.g, blue, color.a));
+}
+
+// Moves random blocks of `block` texels sideways by up to `strength`
+// texels; `probability` is the share of blocks affected.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn block_displace(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let block = u32(param(0u));
+  let strength = param(1u);
+  let probability = param(2u);
+
+  let cell = global_id.xy / block;
+  let chance = random2(cell, glitch_seed());
+
+  var source = coord;
+  if chance.x < probability {
+    source.x += i32(round((chance.y * 2.0 - 1.0) * strength));
+  }
+
+  textureStore(textureOutput, coord, load_clamped(textureInput, source));
+}
+
+// CRT-style scanlines: every `spacing`-th row is darkened by `intensity`,
+// and rows jitter sideways and get grain according to `noise`.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scanlines(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let intensity = param(0u);
+  let noise = param(1u);
+  let spacing = max(u32(param(2u)), 1u);
+  let seed = glitch_seed();
+
+  let jitter = (random(vec2<u32>(0u, global_id.y), seed) - 0.5) * noise * 8.0;
+  var color = sample_bilinear(textureInput, vec2<f32>(coord) + 0.5 + vec2<f32>(jitter, 0.0));
+
+  if global_id.y % spacing == 0u {
+    color = vec4<f32>(color.rgb * (1.0 - intensity), color.a);
+  }
+
+  let grain = (random(global_id.xy, seed + 1u) - 0.5) * noise * 0.5;
+  let rgb = clamp(color.rgb + grain, vec3<f32>(0.0), vec3<f32>(1.0));
+
+  textureStore(textureOutput, coord, vec4<f32>(rgb, color.a));
+}
@@ -0,0 +1,23 @@
+#include "color.wgsl"
+#include "pointwise.wgsl"
+
+// 256x1 gradient, dark to bright.
+@group(0) @binding(3)
+var textureGradient: texture_2d<f32>;
+
+// Replaces every color with the gradient at its luminance. The gradient's
+// alpha multiplies the input alpha.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn gradient_map(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let color = load_input(coord);
+
+  let index = i32(round(clamp(luminance(color.rgb), 0.0, 1.0) * 255.0));
+  let mapped = textureLoad(textureGradient, vec2<i32>(index, 0), 0);
+
+  store_output(coord, vec4<f32>(mapped.rgb, color.a * mapped.a));
+}
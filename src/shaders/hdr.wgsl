@@ -0,0 +1,62 @@
+#include "color.wgsl"
+
+struct Settings {
+  // Exposure time of the current input relative to EV 0, 2^ev.
+  exposure: f32,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> settings: Settings;
+// Weighted radiance sums in RGB and the weight sum in alpha, turned into
+// the merged radiance by `resolve`.
+@group(0) @binding(2)
+var<storage, read_write> radiance: array<vec4<f32>>;
+// Radiance from the input best exposed at every texel in RGB and how far
+// it is from mid gray in alpha, for texels no input exposes usefully.
+@group(0) @binding(3)
+var<storage, read_write> fallback: array<vec4<f32>>;
+
+fn index(p: vec2<u32>) -> u32 {
+  return p.y * u32(textureDimensions(textureInput).x) + p.x;
+}
+
+@compute @workgroup_size(1)
+fn init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  radiance[i] = vec4<f32>(0.0);
+  fallback[i] = vec4<f32>(0.0, 0.0, 0.0, 1.0e30);
+}
+
+// Adds one exposure. Values are linearized assuming an sRGB response and
+// weighted by a hat function, so texels near black or white, which carry
+// noise or clipping, contribute the least.
+@compute @workgroup_size(1)
+fn accumulate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  let value = textureLoad(textureInput, vec2<i32>(global_id.xy), 0).rgb;
+  let estimate = srgb_to_linear(value) / settings.exposure;
+
+  let weights = vec3<f32>(1.0) - abs(2.0 * value - vec3<f32>(1.0));
+  let weight = min(weights.r, min(weights.g, weights.b));
+  radiance[i] += vec4<f32>(estimate * weight, weight);
+
+  let distance = abs(max(value.r, max(value.g, value.b)) - 0.5);
+  if distance < fallback[i].a {
+    fallback[i] = vec4<f32>(estimate, distance);
+  }
+}
+
+@compute @workgroup_size(1)
+fn resolve(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  let sum = radiance[i];
+
+  var merged = fallback[i].rgb;
+  if sum.a > 1.0e-4 {
+    merged = sum.rgb / sum.a;
+  }
+
+  radiance[i] = vec4<f32>(merged, 1.0);
+}
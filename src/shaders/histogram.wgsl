@@ -0,0 +1,16 @@
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> bins: array<atomic<u32>, 1024>;
+
+// Counts the 8-bit values of every channel, 256 bins per channel in RGBA
+// order.
+@compute @workgroup_size(1)
+fn histogram(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let color = textureLoad(textureInput, vec2<i32>(i32(global_id.x), i32(global_id.y)), 0);
+  let values = vec4<u32>(round(color * 255.0));
+
+  for (var channel = 0u; channel < 4u; channel++) {
+    atomicAdd(&bins[channel * 256u + values[channel]], 1u);
+  }
+}
@@ -0,0 +1,26 @@
+#include "pointwise.wgsl"
+
+// 256x1 table mapping every 8-bit value of a channel to its matched value.
+@group(0) @binding(4)
+var textureLookup: texture_2d<f32>;
+
+fn lookup(value: f32, channel: u32) -> f32 {
+  return textureLoad(textureLookup, vec2<i32>(i32(round(value * 255.0)), 0), 0)[channel];
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn histogram_match(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let color = load_input(coord);
+
+  store_output(coord, vec4<f32>(
+    lookup(color.r, 0u),
+    lookup(color.g, 1u),
+    lookup(color.b, 2u),
+    color.a,
+  ));
+}
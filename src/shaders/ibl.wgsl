@@ -0,0 +1,225 @@
+// Prefiltering of environment maps for image-based lighting: the
+// environment is first projected onto a mipmapped cubemap, whose levels
+// then feed the GGX specular and the diffuse irradiance convolutions. The
+// BRDF lookup table completing the split sum is integrated here as well.
+
+#include "sampling.wgsl"
+
+const PI: f32 = 3.14159265;
+
+struct Settings {
+  // Perceptual roughness of the specular level being filtered.
+  roughness: f32,
+  samples: u32,
+  // Face size of the first source level, in texels.
+  source_size: f32,
+  // Source level the downsampling and irradiance convolution read.
+  source_level: f32,
+  // Cube face being written.
+  face: u32,
+}
+
+// Equirectangular, longitude 0 in the middle and up at the top.
+@group(0) @binding(0)
+var environment: texture_2d<f32>;
+@group(0) @binding(1)
+var source: texture_cube<f32>;
+@group(0) @binding(2)
+var sourceSampler: sampler;
+@group(0) @binding(3)
+var<uniform> settings: Settings;
+// A single face, as not every backend writes cubemap layers at once.
+@group(0) @binding(4)
+var faceOutput: texture_storage_2d<rgba16float, write>;
+// Scale and bias to the Fresnel reflectance at normal incidence, by row
+// and column.
+@group(0) @binding(5)
+var<storage, read_write> lut: array<vec2<f32>>;
+
+// World direction through the center of texel `coord` of cube face `face`,
+// in the usual +x, -x, +y, -y, +z, -z order.
+fn face_direction(face: u32, coord: vec2<u32>, size: u32) -> vec3<f32> {
+  let st = (vec2<f32>(coord) + 0.5) / f32(size) * 2.0 - 1.0;
+  let s = st.x;
+  let t = st.y;
+
+  var direction: vec3<f32>;
+  switch face {
+    case 0u: { direction = vec3<f32>(1.0, -t, -s); }
+    case 1u: { direction = vec3<f32>(-1.0, -t, s); }
+    case 2u: { direction = vec3<f32>(s, 1.0, t); }
+    case 3u: { direction = vec3<f32>(s, -1.0, -t); }
+    case 4u: { direction = vec3<f32>(s, -t, 1.0); }
+    default: { direction = vec3<f32>(-s, -t, -1.0); }
+  }
+  return normalize(direction);
+}
+
+fn output_direction(global_id: vec3<u32>) -> vec3<f32> {
+  return face_direction(settings.face, global_id.xy, textureDimensions(faceOutput).x);
+}
+
+fn store(global_id: vec3<u32>, color: vec3<f32>) {
+  textureStore(faceOutput, vec2<i32>(global_id.xy), vec4<f32>(color, 1.0));
+}
+
+@compute @workgroup_size(1)
+fn project(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let direction = output_direction(global_id);
+  let size = vec2<f32>(textureDimensions(environment));
+
+  let longitude = atan2(direction.x, direction.z);
+  let polar = acos(clamp(direction.y, -1.0, 1.0));
+  let position = vec2<f32>(0.5 + longitude / (2.0 * PI), polar / PI) * size;
+
+  store(global_id, sample_bilinear(environment, position).rgb);
+}
+
+// Averages 2x2 texels of the level above: the center of every texel lies
+// on the corner shared by the four it covers, where bilinear filtering
+// weighs them evenly.
+@compute @workgroup_size(1)
+fn downsample(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let direction = output_direction(global_id);
+
+  store(global_id, textureSampleLevel(source, sourceSampler, direction, settings.source_level).rgb);
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+  return vec2<f32>(f32(index) / f32(count), f32(reverseBits(index)) * 2.3283064365386963e-10);
+}
+
+// Half vector around `normal` distributed like the GGX normal distribution
+// of squared roughness `alpha`.
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, alpha: f32) -> vec3<f32> {
+  let phi = 2.0 * PI * xi.x;
+  let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+  let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+  let half_vector = vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+
+  var up = vec3<f32>(0.0, 0.0, 1.0);
+  if abs(normal.z) > 0.999 {
+    up = vec3<f32>(1.0, 0.0, 0.0);
+  }
+  let tangent = normalize(cross(up, normal));
+  let bitangent = cross(normal, tangent);
+
+  return normalize(tangent * half_vector.x + bitangent * half_vector.y + normal * half_vector.z);
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+  let alpha2 = alpha * alpha;
+  let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+  return alpha2 / (PI * denominator * denominator);
+}
+
+// Split-sum prefiltering (Karis 2013) with the view along the normal. Every
+// sample reads a source level as blurry as the solid angle it stands for,
+// which keeps a few hundred samples free of fireflies.
+@compute @workgroup_size(1)
+fn specular(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let normal = output_direction(global_id);
+
+  if settings.roughness == 0.0 {
+    store(global_id, textureSampleLevel(source, sourceSampler, normal, 0.0).rgb);
+    return;
+  }
+
+  let alpha = settings.roughness * settings.roughness;
+  let texel_solid_angle = 4.0 * PI / (6.0 * settings.source_size * settings.source_size);
+
+  var color = vec3<f32>(0.0);
+  var weight = 0.0;
+  for (var index = 0u; index < settings.samples; index++) {
+    let half_vector = importance_sample_ggx(hammersley(index, settings.samples), normal, alpha);
+    let light = 2.0 * dot(normal, half_vector) * half_vector - normal;
+
+    let n_dot_l = dot(normal, light);
+    if n_dot_l <= 0.0 {
+      continue;
+    }
+
+    // With the view along the normal, N.H equals V.H and the pdf of the
+    // light direction reduces to D / 4.
+    let n_dot_h = max(dot(normal, half_vector), 0.0);
+    let pdf = distribution_ggx(n_dot_h, alpha) / 4.0 + 1e-4;
+    let sample_solid_angle = 1.0 / (f32(settings.samples) * pdf + 1e-4);
+    let level = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+
+    color += textureSampleLevel(source, sourceSampler, light, level).rgb * n_dot_l;
+    weight += n_dot_l;
+  }
+
+  store(global_id, color / max(weight, 1e-4));
+}
+
+// Cosine-weighted mean of the incoming light over the hemisphere around
+// every direction, sampled on an even grid of angles.
+@compute @workgroup_size(1)
+fn irradiance(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let normal = output_direction(global_id);
+
+  var up = vec3<f32>(0.0, 1.0, 0.0);
+  if abs(normal.y) > 0.999 {
+    up = vec3<f32>(0.0, 0.0, 1.0);
+  }
+  let right = normalize(cross(up, normal));
+  up = cross(normal, right);
+
+  let step_angle = 0.05;
+  var color = vec3<f32>(0.0);
+  var count = 0.0;
+  for (var phi = 0.0; phi < 2.0 * PI; phi += step_angle) {
+    for (var theta = 0.0; theta < 0.5 * PI; theta += step_angle) {
+      let tangent = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+      let direction = tangent.x * right + tangent.y * up + tangent.z * normal;
+
+      let radiance = textureSampleLevel(source, sourceSampler, direction, settings.source_level).rgb;
+      color += radiance * cos(theta) * sin(theta);
+      count += 1.0;
+    }
+  }
+
+  store(global_id, PI * color / count);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, k: f32) -> f32 {
+  return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Integrates the specular BRDF over the hemisphere for N.V along the
+// columns and the roughness down the rows, split into the terms scaling
+// and biased onto F0 (Karis 2013).
+@compute @workgroup_size(1)
+fn brdf(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) workgroups: vec3<u32>) {
+  let width = workgroups.x;
+  let coord = (vec2<f32>(global_id.xy) + 0.5) / f32(width);
+  let n_dot_v = coord.x;
+  let roughness = coord.y;
+
+  let normal = vec3<f32>(0.0, 0.0, 1.0);
+  let view = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+  let alpha = roughness * roughness;
+  let k = alpha / 2.0;
+
+  var terms = vec2<f32>(0.0);
+  for (var index = 0u; index < settings.samples; index++) {
+    let half_vector = importance_sample_ggx(hammersley(index, settings.samples), normal, alpha);
+    let light = 2.0 * dot(view, half_vector) * half_vector - view;
+
+    let n_dot_l = light.z;
+    if n_dot_l <= 0.0 {
+      continue;
+    }
+
+    let n_dot_h = max(half_vector.z, 0.0);
+    let v_dot_h = max(dot(view, half_vector), 0.0);
+    let geometry = geometry_schlick_ggx(n_dot_v, k) * geometry_schlick_ggx(n_dot_l, k);
+    let visibility = geometry * v_dot_h / max(n_dot_h * n_dot_v, 1e-5);
+    let fresnel = pow(1.0 - v_dot_h, 5.0);
+
+    terms += vec2<f32>(1.0 - fresnel, fresnel) * visibility;
+  }
+
+  lut[global_id.y * width + global_id.x] = terms / f32(settings.samples);
+}
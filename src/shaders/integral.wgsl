@@ -0,0 +1,14 @@
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> sums: array<vec4<u32>>;
+
+// Seeds the table with the 8-bit texel values, to be scanned along rows and
+// then columns.
+@compute @workgroup_size(1)
+fn load(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let size = textureDimensions(textureInput);
+  let color = textureLoad(textureInput, vec2<i32>(global_id.xy), 0);
+
+  sums[global_id.y * size.x + global_id.x] = vec4<u32>(round(color * 255.0));
+}
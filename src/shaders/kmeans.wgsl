@@ -0,0 +1,91 @@
+struct Settings {
+  clusters: u32,
+  // Weight of the normalized position against the color, 0 clusters by
+  // color alone.
+  spatial: f32,
+}
+
+struct Centroid {
+  color: vec4<f32>,
+  // Normalized to 0..1.
+  position: vec2<f32>,
+  texels: u32,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> settings: Settings;
+@group(0) @binding(2)
+var<storage, read_write> centroids: array<Centroid>;
+// Per cluster, 64-bit sums of the fixed-point color, the position and the
+// texel count, as SUM_FIELDS pairs of low and high words.
+@group(0) @binding(3)
+var<storage, read_write> sums: array<atomic<u32>>;
+
+const SUM_FIELDS: u32 = 6u;
+const COLOR_SCALE: f32 = 255.0;
+
+fn add(cluster: u32, field: u32, value: u32) {
+  let index = (cluster * SUM_FIELDS + field) * 2u;
+  let low = atomicAdd(&sums[index], value);
+  if low + value < low {
+    atomicAdd(&sums[index + 1u], 1u);
+  }
+}
+
+fn take(cluster: u32, field: u32) -> f32 {
+  let index = (cluster * SUM_FIELDS + field) * 2u;
+  let low = atomicExchange(&sums[index], 0u);
+  let high = atomicExchange(&sums[index + 1u], 0u);
+  return f32(high) * 4294967296.0 + f32(low);
+}
+
+@compute @workgroup_size(1)
+fn assign(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let size = vec2<f32>(textureDimensions(textureInput));
+  let color = textureLoad(textureInput, vec2<i32>(global_id.xy), 0).rgb;
+  let position = (vec2<f32>(global_id.xy) + 0.5) / size;
+
+  var best = 0u;
+  var best_distance = 1.0e30;
+  for (var cluster = 0u; cluster < settings.clusters; cluster++) {
+    let centroid = centroids[cluster];
+    let color_offset = color - centroid.color.rgb;
+    let position_offset = (position - centroid.position) * settings.spatial;
+    let distance = dot(color_offset, color_offset) + dot(position_offset, position_offset);
+
+    if distance < best_distance {
+      best_distance = distance;
+      best = cluster;
+    }
+  }
+
+  let fixed = vec3<u32>(round(color * COLOR_SCALE));
+  add(best, 0u, fixed.r);
+  add(best, 1u, fixed.g);
+  add(best, 2u, fixed.b);
+  add(best, 3u, global_id.x);
+  add(best, 4u, global_id.y);
+  add(best, 5u, 1u);
+}
+
+// Moves every centroid to the mean of its texels and clears the sums.
+// Clusters that lost all texels keep their centroid.
+@compute @workgroup_size(1)
+fn update(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let cluster = global_id.x;
+  let size = vec2<f32>(textureDimensions(textureInput));
+
+  let color = vec3<f32>(take(cluster, 0u), take(cluster, 1u), take(cluster, 2u));
+  let position = vec2<f32>(take(cluster, 3u), take(cluster, 4u));
+  let texels = take(cluster, 5u);
+
+  centroids[cluster].texels = u32(texels);
+  if texels == 0.0 {
+    return;
+  }
+
+  centroids[cluster].color = vec4<f32>(color / (texels * COLOR_SCALE), 1.0);
+  centroids[cluster].position = (position / texels + 0.5) / size;
+}
@@ -0,0 +1,74 @@
+#include "globals.wgsl"
+#include "sampling.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+const PI: f32 = 3.14159265;
+const MAX_SECTORS: i32 = 8;
+// Sharpness of the sector weighting, from Papari et al.
+const Q: f32 = 8.0;
+
+// Generalized Kuwahara filter: the disc of `radius` around every texel is
+// split into `sectors` wedges and their means are blended, favouring the
+// wedges with the lowest variance so edges stay crisp.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn kuwahara(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let radius = i32(param(0u));
+  let sectors = clamp(i32(param(1u)), 1, MAX_SECTORS);
+  let sigma = f32(radius) * 0.5;
+
+  var sum = array<vec3<f32>, 8>();
+  var squares = array<vec3<f32>, 8>();
+  var weights = array<f32, 8>();
+
+  for (var y = -radius; y <= radius; y++) {
+    for (var x = -radius; x <= radius; x++) {
+      let offset = vec2<f32>(f32(x), f32(y));
+      let distance_squared = dot(offset, offset);
+      if distance_squared > f32(radius * radius) {
+        continue;
+      }
+
+      let color = load_clamped(textureInput, coord + vec2<i32>(x, y)).rgb;
+      let weight = exp(-distance_squared / (2.0 * sigma * sigma));
+
+      // The center texel belongs to every sector.
+      var first = 0;
+      var last = sectors - 1;
+      if x != 0 || y != 0 {
+        let angle = atan2(offset.y, offset.x) + PI;
+        first = min(i32(angle / (2.0 * PI) * f32(sectors)), sectors - 1);
+        last = first;
+      }
+
+      for (var sector = first; sector <= last; sector++) {
+        sum[sector] = strict3(sum[sector] + strict3(color * weight));
+        squares[sector] = strict3(squares[sector] + strict3(color * color * weight));
+        weights[sector] = strict(weights[sector] + weight);
+      }
+    }
+  }
+
+  var color = vec3<f32>(0.0);
+  var total = 0.0;
+  for (var sector = 0; sector < sectors; sector++) {
+    let mean = sum[sector] / weights[sector];
+    let variance = abs(squares[sector] / weights[sector] - strict3(mean * mean));
+    let deviation = sqrt(variance.r + variance.g + variance.b) * 255.0;
+    let weight = 1.0 / (1.0 + pow(deviation, Q));
+
+    color = strict3(color + strict3(mean * weight));
+    total = strict(total + weight);
+  }
+
+  let alpha = textureLoad(textureInput, coord, 0).a;
+  textureStore(textureOutput, coord, vec4<f32>(color / total, alpha));
+}
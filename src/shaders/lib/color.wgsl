@@ -0,0 +1,42 @@
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+  let low = c / 12.92;
+  let high = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+  return select(high, low, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+  let low = c * 12.92;
+  let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+  return select(high, low, c <= vec3<f32>(0.0031308));
+}
+
+fn luminance(c: vec3<f32>) -> f32 {
+  return dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn rgb_to_hsv(c: vec3<f32>) -> vec3<f32> {
+  let k = vec4<f32>(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+  let p = select(vec4<f32>(c.gb, k.xy), vec4<f32>(c.bg, k.wz), c.g < c.b);
+  let q = select(vec4<f32>(c.r, p.yzx), vec4<f32>(p.xyw, c.r), c.r < p.x);
+  let d = q.x - min(q.w, q.y);
+  let e = 1.0e-10;
+  return vec3<f32>(abs(q.z + (q.w - q.y) / (6.0 * d + e)), d / (q.x + e), q.x);
+}
+
+fn hsv_to_rgb(c: vec3<f32>) -> vec3<f32> {
+  let k = vec4<f32>(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+  let p = abs(fract(c.xxx + k.xyz) * 6.0 - k.www);
+  return c.z * mix(k.xxx, clamp(p - k.xxx, vec3<f32>(0.0), vec3<f32>(1.0)), c.y);
+}
+
+// CIELAB (D65) of an sRGB color, with L in 0..100.
+fn rgb_to_lab(c: vec3<f32>) -> vec3<f32> {
+  let rgb = srgb_to_linear(c);
+  let xyz = vec3<f32>(
+    dot(rgb, vec3<f32>(0.4124, 0.3576, 0.1805)) / 0.95047,
+    dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722)),
+    dot(rgb, vec3<f32>(0.0193, 0.1192, 0.9505)) / 1.08883,
+  );
+  let f = select(7.787 * xyz + 16.0 / 116.0, pow(xyz, vec3<f32>(1.0 / 3.0)), xyz > vec3<f32>(0.008856));
+  return vec3<f32>(116.0 * f.y - 16.0, 500.0 * (f.x - f.y), 200.0 * (f.y - f.z));
+}
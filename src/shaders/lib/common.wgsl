@@ -0,0 +1,2 @@
+#include "color.wgsl"
+#include "noise.wgsl"
@@ -0,0 +1,41 @@
+struct Globals {
+  size: vec2<u32>,
+  seed: u32,
+  frame: u32,
+  params: array<vec4<f32>, 4>,
+}
+
+@group(0) @binding(2)
+var<uniform> globals: Globals;
+
+// Value of the operation parameter declared at `index`.
+fn param(index: u32) -> f32 {
+  return globals.params[index / 4u][index % 4u];
+}
+
+// Set by --strict-math.
+const STRICT_MATH: bool = false;
+
+// `value` rounded on its own under --strict-math: its bits pass through a
+// mask drivers can't tell is all ones, so it isn't fused into an fma or
+// reassociated with the arithmetic around it.
+fn strict(value: f32) -> f32 {
+  return strict4(vec4<f32>(value)).x;
+}
+
+fn strict2(value: vec2<f32>) -> vec2<f32> {
+  return strict4(vec4<f32>(value, 0.0, 0.0)).xy;
+}
+
+fn strict3(value: vec3<f32>) -> vec3<f32> {
+  return strict4(vec4<f32>(value, 0.0)).xyz;
+}
+
+fn strict4(value: vec4<f32>) -> vec4<f32> {
+  if !STRICT_MATH {
+    return value;
+  }
+
+  let mask = select(0xffffffffu, 0u, globals.size.x == 0u);
+  return bitcast<vec4<f32>>(bitcast<vec4<u32>>(value) & vec4<u32>(mask));
+}
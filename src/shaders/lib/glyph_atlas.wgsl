@@ -0,0 +1,14 @@
+@group(1) @binding(2)
+var glyphAtlas: texture_2d<f32>;
+
+const GLYPH_SIZE: u32 = 8u;
+
+fn glyph_count() -> u32 {
+  return u32(textureDimensions(glyphAtlas).x) / GLYPH_SIZE;
+}
+
+// Ink of texel `p` (within 0..GLYPH_SIZE) of glyph `index`.
+fn glyph(index: u32, p: vec2<u32>) -> f32 {
+  let coord = vec2<u32>(index * GLYPH_SIZE + p.x, p.y);
+  return textureLoad(glyphAtlas, vec2<i32>(coord), 0).r;
+}
@@ -0,0 +1,81 @@
+fn hash21(p: vec2<f32>) -> f32 {
+  var p3 = fract(vec3<f32>(p.xyx) * 0.1031);
+  p3 = p3 + dot(p3, p3.yzx + 33.33);
+  return fract((p3.x + p3.y) * p3.z);
+}
+
+fn hash22(p: vec2<f32>) -> vec2<f32> {
+  var p3 = fract(vec3<f32>(p.xyx) * vec3<f32>(0.1031, 0.1030, 0.0973));
+  p3 = p3 + dot(p3, p3.yzx + 33.33);
+  return fract((p3.xx + p3.yz) * p3.zy);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+  let i = floor(p);
+  let f = fract(p);
+  let u = f * f * (3.0 - 2.0 * f);
+
+  let a = hash21(i);
+  let b = hash21(i + vec2<f32>(1.0, 0.0));
+  let c = hash21(i + vec2<f32>(0.0, 1.0));
+  let d = hash21(i + vec2<f32>(1.0, 1.0));
+
+  return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn gradient_noise(p: vec2<f32>) -> f32 {
+  let i = floor(p);
+  let f = fract(p);
+  let u = f * f * (3.0 - 2.0 * f);
+
+  let ga = hash22(i) * 2.0 - 1.0;
+  let gb = hash22(i + vec2<f32>(1.0, 0.0)) * 2.0 - 1.0;
+  let gc = hash22(i + vec2<f32>(0.0, 1.0)) * 2.0 - 1.0;
+  let gd = hash22(i + vec2<f32>(1.0, 1.0)) * 2.0 - 1.0;
+
+  let va = dot(ga, f);
+  let vb = dot(gb, f - vec2<f32>(1.0, 0.0));
+  let vc = dot(gc, f - vec2<f32>(0.0, 1.0));
+  let vd = dot(gd, f - vec2<f32>(1.0, 1.0));
+
+  return mix(mix(va, vb, u.x), mix(vc, vd, u.x), u.y);
+}
+
+fn fbm(p: vec2<f32>, octaves: u32) -> f32 {
+  var value = 0.0;
+  var amplitude = 0.5;
+  var q = p;
+  for (var i = 0u; i < octaves; i = i + 1u) {
+    value = value + amplitude * gradient_noise(q);
+    q = q * 2.0;
+    amplitude = amplitude * 0.5;
+  }
+  return value;
+}
+
+// PCG hash (Jarzynski & Olano, "Hash Functions for GPU Rendering").
+fn pcg(v: u32) -> u32 {
+  let state = v * 747796405u + 2891336453u;
+  let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
+}
+
+fn pcg2d(v: vec2<u32>) -> vec2<u32> {
+  var q = v * 1664525u + 1013904223u;
+  q.x = q.x + q.y * 1664525u;
+  q.y = q.y + q.x * 1664525u;
+  q = q ^ (q >> vec2<u32>(16u));
+  q.x = q.x + q.y * 1664525u;
+  q.y = q.y + q.x * 1664525u;
+  q = q ^ (q >> vec2<u32>(16u));
+  return q;
+}
+
+// Uniform random value in [0, 1) for a pixel, stable for a given seed.
+fn random(p: vec2<u32>, seed: u32) -> f32 {
+  return f32(pcg(p.x ^ pcg(p.y ^ pcg(seed))) >> 8u) / 16777216.0;
+}
+
+fn random2(p: vec2<u32>, seed: u32) -> vec2<f32> {
+  return vec2<f32>(pcg2d(p ^ vec2<u32>(pcg(seed))) >> vec2<u32>(8u)) / 16777216.0;
+}
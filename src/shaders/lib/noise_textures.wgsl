@@ -0,0 +1,14 @@
+@group(1) @binding(0)
+var blueNoiseTexture: texture_2d<f32>;
+@group(1) @binding(1)
+var bayerTexture: texture_2d<f32>;
+
+fn blue_noise(p: vec2<u32>) -> f32 {
+  let size = vec2<u32>(textureDimensions(blueNoiseTexture));
+  return textureLoad(blueNoiseTexture, vec2<i32>(p % size), 0).r;
+}
+
+fn bayer(p: vec2<u32>) -> f32 {
+  let size = vec2<u32>(textureDimensions(bayerTexture));
+  return textureLoad(bayerTexture, vec2<i32>(p % size), 0).r;
+}
@@ -0,0 +1,17 @@
+// Bindings of operations that read nothing of the input but the texel they
+// write, through `load_input` and `store_output`. With `--in-place` they are
+// swapped for those of `pointwise_in_place.wgsl` or, where storage textures
+// of RGBA8 can't be read, `pointwise_packed.wgsl`.
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+fn load_input(coord: vec2<i32>) -> vec4<f32> {
+  return textureLoad(textureInput, coord, 0);
+}
+
+fn store_output(coord: vec2<i32>, color: vec4<f32>) {
+  textureStore(textureOutput, coord, color);
+}
@@ -0,0 +1,13 @@
+// `pointwise.wgsl` for `--in-place`: the input is uploaded into the output,
+// which every invocation reads its texel of before overwriting it.
+
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, read_write>;
+
+fn load_input(coord: vec2<i32>) -> vec4<f32> {
+  return textureLoad(textureOutput, coord);
+}
+
+fn store_output(coord: vec2<i32>, color: vec4<f32>) {
+  textureStore(textureOutput, coord, color);
+}
@@ -0,0 +1,14 @@
+// `pointwise_in_place.wgsl` on a texture of 32-bit texels, each holding the
+// four channels of an RGBA8 texel in the same bytes, for adapters that only
+// read and write storage textures of single 32-bit channels.
+
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<r32uint, read_write>;
+
+fn load_input(coord: vec2<i32>) -> vec4<f32> {
+  return unpack4x8unorm(textureLoad(textureOutput, coord).r);
+}
+
+fn store_output(coord: vec2<i32>, color: vec4<f32>) {
+  textureStore(textureOutput, coord, vec4<u32>(pack4x8unorm(color), 0u, 0u, 0u));
+}
@@ -0,0 +1,22 @@
+// View directions use +z forward, +x right and +y down, matching image rows.
+
+const PROJECTION_CYLINDRICAL: u32 = 0u;
+const PROJECTION_SPHERICAL: u32 = 1u;
+
+// Direction through a panorama position: `longitude` in radians, and the
+// height on the unit cylinder or the latitude in radians.
+fn panorama_direction(projection: u32, longitude: f32, vertical: f32) -> vec3<f32> {
+  if projection == PROJECTION_CYLINDRICAL {
+    return normalize(vec3<f32>(sin(longitude), vertical, cos(longitude)));
+  }
+
+  let latitude = cos(vertical);
+  return vec3<f32>(latitude * sin(longitude), sin(vertical), latitude * cos(longitude));
+}
+
+// Turns `direction` by `angle` radians around the vertical axis.
+fn rotate_yaw(direction: vec3<f32>, angle: f32) -> vec3<f32> {
+  let c = cos(angle);
+  let s = sin(angle);
+  return vec3<f32>(c * direction.x - s * direction.z, direction.y, s * direction.x + c * direction.z);
+}
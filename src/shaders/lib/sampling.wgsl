@@ -0,0 +1,21 @@
+// Bilinear sample at continuous texel coordinates (texel centers at +0.5),
+// clamped to the edge. Works on any texture without a sampler binding.
+fn sample_bilinear(texture: texture_2d<f32>, position: vec2<f32>) -> vec4<f32> {
+  let size = vec2<i32>(textureDimensions(texture));
+  let p = position - 0.5;
+  let base = vec2<i32>(floor(p));
+  let f = fract(p);
+  let max_coord = size - 1;
+
+  let a = textureLoad(texture, clamp(base, vec2<i32>(0), max_coord), 0);
+  let b = textureLoad(texture, clamp(base + vec2<i32>(1, 0), vec2<i32>(0), max_coord), 0);
+  let c = textureLoad(texture, clamp(base + vec2<i32>(0, 1), vec2<i32>(0), max_coord), 0);
+  let d = textureLoad(texture, clamp(base + vec2<i32>(1, 1), vec2<i32>(0), max_coord), 0);
+
+  return mix(mix(a, b, f.x), mix(c, d, f.x), f.y);
+}
+
+fn load_clamped(texture: texture_2d<f32>, coord: vec2<i32>) -> vec4<f32> {
+  let max_coord = vec2<i32>(textureDimensions(texture)) - 1;
+  return textureLoad(texture, clamp(coord, vec2<i32>(0), max_coord), 0);
+}
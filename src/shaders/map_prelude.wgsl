@@ -0,0 +1,16 @@
+// Bindings and helpers of `map_buffer` kernels, which define
+// `@compute @workgroup_size(WORKGROUP_SIZE) fn map(...)` and run once per
+// input element.
+
+@group(0) @binding(0)
+var<storage, read> input: array<f32>;
+@group(0) @binding(1)
+var<storage, read_write> output: array<f32>;
+@group(0) @binding(2)
+var<storage, read> params: array<f32>;
+
+// Index of the element the invocation at `global_id` maps, which can be past
+// the end of the input once the dispatch wraps into rows.
+fn element_index(global_id: vec3<u32>) -> u32 {
+  return global_id.y * MAP_COLUMNS + global_id.x;
+}
@@ -0,0 +1,142 @@
+#include "color.wgsl"
+
+struct Settings {
+  alpha_scale: f32,
+  // 0: box, 1: Kaiser, 2: Lanczos
+  filter_type: u32,
+  // Non-zero to filter color in linear space.
+  linear: u32,
+  // 0: color, 1: renormalized normals, 2: normals with Toksvig roughness in
+  // alpha
+  normal_mode: u32,
+}
+
+@group(0) @binding(0)
+var textureSource: texture_2d<f32>;
+@group(0) @binding(1)
+var textureDestination: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(3)
+var<uniform> settings: Settings;
+
+const PI: f32 = 3.14159265;
+// Kernel half-width in destination texels for the windowed filters.
+const FILTER_RADIUS: f32 = 3.0;
+const KAISER_ALPHA: f32 = 4.0;
+
+fn load_source(coord: vec2<i32>) -> vec4<f32> {
+  let max_coord = vec2<i32>(textureDimensions(textureSource)) - 1;
+  let color = textureLoad(textureSource, clamp(coord, vec2<i32>(0), max_coord), 0);
+
+  if settings.linear != 0u {
+    return vec4<f32>(srgb_to_linear(color.rgb), color.a);
+  }
+  return color;
+}
+
+fn sinc(x: f32) -> f32 {
+  if abs(x) < 1.0e-5 {
+    return 1.0;
+  }
+  return sin(PI * x) / (PI * x);
+}
+
+// Zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f32) -> f32 {
+  var sum = 1.0;
+  var term = 1.0;
+  for (var k = 1; k < 16; k++) {
+    let f = x / (2.0 * f32(k));
+    term *= f * f;
+    sum += term;
+  }
+  return sum;
+}
+
+// Weight of a source texel `t` destination texels away from the center.
+fn kernel(t: f32) -> f32 {
+  let x = abs(t);
+
+  switch settings.filter_type {
+    case 1u: {
+      if x >= FILTER_RADIUS {
+        return 0.0;
+      }
+      let r = x / FILTER_RADIUS;
+      return sinc(x) * bessel_i0(KAISER_ALPHA * sqrt(1.0 - r * r)) / bessel_i0(KAISER_ALPHA);
+    }
+    case 2u: {
+      if x >= FILTER_RADIUS {
+        return 0.0;
+      }
+      return sinc(x) * sinc(x / FILTER_RADIUS);
+    }
+    default: {
+      return select(0.0, 1.0, x < 0.5);
+    }
+  }
+}
+
+// Filters the previous level down to half its size.
+@compute @workgroup_size(1)
+fn downsample(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  // Source texel centers relative to the destination center are at odd
+  // multiples of half a source texel.
+  let center = vec2<f32>(coord) * 2.0 + 1.0;
+
+  var taps = 1;
+  if settings.filter_type != 0u {
+    taps = i32(FILTER_RADIUS) * 2;
+  }
+
+  var color = vec4<f32>(0.0);
+  var total = 0.0;
+  for (var y = -taps; y < taps; y++) {
+    for (var x = -taps; x < taps; x++) {
+      let source = coord * 2 + 1 + vec2<i32>(x, y);
+      let offset = (vec2<f32>(source) + 0.5 - center) * 0.5;
+      let weight = kernel(offset.x) * kernel(offset.y);
+
+      color += load_source(source) * weight;
+      total += weight;
+    }
+  }
+  color = clamp(color / total, vec4<f32>(0.0), vec4<f32>(1.0));
+
+  if settings.linear != 0u {
+    color = vec4<f32>(linear_to_srgb(color.rgb), color.a);
+  }
+
+  textureStore(textureDestination, coord, color);
+}
+
+@compute @workgroup_size(1)
+fn alpha_histogram(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let alpha = textureLoad(textureSource, vec2<i32>(i32(global_id.x), i32(global_id.y)), 0).a;
+  atomicAdd(&histogram[u32(round(alpha * 255.0))], 1u);
+}
+
+// Turns a filtered level of the raw chain into its final form.
+@compute @workgroup_size(1)
+fn finalize(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  var color = textureLoad(textureSource, coord, 0);
+
+  if settings.normal_mode != 0u {
+    // Averaging unit normals shortens them the more they diverge.
+    let normal = color.rgb * 2.0 - 1.0;
+    let len = max(length(normal), 1.0e-4);
+    color = vec4<f32>(normal / len * 0.5 + 0.5, color.a);
+
+    if settings.normal_mode == 2u {
+      let variance = (1.0 - min(len, 1.0)) / len;
+      color.a = sqrt(color.a * color.a + variance);
+    }
+  }
+
+  color.a *= settings.alpha_scale;
+
+  textureStore(textureDestination, coord, clamp(color, vec4<f32>(0.0), vec4<f32>(1.0)));
+}
@@ -0,0 +1,79 @@
+struct Settings {
+  // Top left of the cell in the sheet and its size, the label included.
+  slot_origin: vec2<u32>,
+  slot_size: vec2<u32>,
+  // Top left and size of the image in the sheet.
+  image_origin: vec2<u32>,
+  image_size: vec2<u32>,
+  // Top left of the label text in the sheet, texels per glyph texel and
+  // characters of the text.
+  label_origin: vec2<u32>,
+  label_scale: u32,
+  label_length: u32,
+  label_color: vec4<f32>,
+  // Character codes of the text, four to an element.
+  text: array<vec4<u32>, 16>,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2)
+var<uniform> settings: Settings;
+@group(0) @binding(3)
+var fontAtlas: texture_2d<f32>;
+
+// Glyph cells of the font, 5x7 glyphs with a column and row of spacing.
+const GLYPH_CELL: vec2<u32> = vec2<u32>(6u, 8u);
+const FIRST_CHARACTER: u32 = 32u;
+
+// Average of the input texels under texel `p` of the image in the sheet.
+fn shrunk(p: vec2<u32>) -> vec4<f32> {
+  let input_size = vec2<u32>(textureDimensions(textureInput));
+  let start = p * input_size / settings.image_size;
+  let end = max((p + 1u) * input_size / settings.image_size, start + 1u);
+
+  var sum = vec4<f32>(0.0);
+  for (var y = start.y; y < end.y; y++) {
+    for (var x = start.x; x < end.x; x++) {
+      sum += textureLoad(textureInput, vec2<i32>(vec2<u32>(x, y)), 0);
+    }
+  }
+
+  return sum / f32((end.x - start.x) * (end.y - start.y));
+}
+
+// Whether texel `p` of the sheet is inked by the label text.
+fn ink(p: vec2<u32>) -> bool {
+  if any(p < settings.label_origin) {
+    return false;
+  }
+
+  let glyph_texel = (p - settings.label_origin) / settings.label_scale;
+  let index = glyph_texel.x / GLYPH_CELL.x;
+  if index >= settings.label_length || glyph_texel.y >= GLYPH_CELL.y {
+    return false;
+  }
+
+  let code = settings.text[index / 4u][index % 4u];
+  let atlas_texel = vec2<u32>((code - FIRST_CHARACTER) * GLYPH_CELL.x + glyph_texel.x % GLYPH_CELL.x, glyph_texel.y);
+
+  return textureLoad(fontAtlas, vec2<i32>(atlas_texel), 0).r > 0.5;
+}
+
+// Draws one cell of the sheet: its image shrunk to fit and the ink of its
+// label. The rest keeps the background the sheet was cleared to.
+@compute @workgroup_size(8, 8)
+fn cell(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= settings.slot_size) {
+    return;
+  }
+
+  let p = settings.slot_origin + global_id.xy;
+  if all(p >= settings.image_origin) && all(p < settings.image_origin + settings.image_size) {
+    textureStore(textureOutput, vec2<i32>(p), shrunk(p - settings.image_origin));
+  } else if ink(p) {
+    textureStore(textureOutput, vec2<i32>(p), settings.label_color);
+  }
+}
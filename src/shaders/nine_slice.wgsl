@@ -0,0 +1,62 @@
+#include "globals.wgsl"
+#include "sampling.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+// Maps an output coordinate (texel center) along one axis back to the source.
+// Borders keep their size, the middle band is stretched or tiled. If the
+// output is smaller than both borders the whole axis is scaled instead.
+fn map_axis(x: f32, output_size: f32, input_size: f32, start: f32, end: f32, tile: bool) -> f32 {
+  let output_middle = output_size - start - end;
+  let input_middle = input_size - start - end;
+
+  if output_middle <= 0.0 || input_middle <= 0.0 {
+    return x * input_size / output_size;
+  }
+
+  if x < start {
+    return x;
+  }
+
+  if x >= output_size - end {
+    return x - (output_size - input_size);
+  }
+
+  let offset = x - start;
+
+  if tile {
+    return start + offset - floor(offset / input_middle) * input_middle;
+  }
+
+  return start + offset * input_middle / output_middle;
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn nine_slice(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let input_size = vec2<f32>(textureDimensions(textureInput));
+  let output_size = vec2<f32>(globals.size);
+  let tile = param(4u) >= 0.5;
+
+  let position = vec2<f32>(global_id.xy) + 0.5;
+  let source = vec2<f32>(
+    map_axis(position.x, output_size.x, input_size.x, param(0u), param(1u), tile),
+    map_axis(position.y, output_size.y, input_size.y, param(2u), param(3u), tile),
+  );
+
+  var color: vec4<f32>;
+  if tile {
+    color = load_clamped(textureInput, vec2<i32>(floor(source)));
+  } else {
+    color = sample_bilinear(textureInput, source);
+  }
+
+  textureStore(textureOutput, coord, color);
+}
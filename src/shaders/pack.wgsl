@@ -0,0 +1,30 @@
+#include "color.wgsl"
+
+@group(0) @binding(0)
+var textureRed: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3)
+var textureGreen: texture_2d<f32>;
+@group(0) @binding(4)
+var textureBlue: texture_2d<f32>;
+@group(0) @binding(5)
+var textureAlpha: texture_2d<f32>;
+
+// Every output channel is the gray level of the matching input. Values are
+// packed as stored, without any color space conversion.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn pack(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+
+  textureStore(textureOutput, coord, vec4<f32>(
+    luminance(textureLoad(textureRed, coord, 0).rgb),
+    luminance(textureLoad(textureGreen, coord, 0).rgb),
+    luminance(textureLoad(textureBlue, coord, 0).rgb),
+    luminance(textureLoad(textureAlpha, coord, 0).rgb),
+  ));
+}
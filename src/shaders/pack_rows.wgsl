@@ -0,0 +1,26 @@
+// Packs a region of an RGBA8 texture into consecutive rows of `u32` texels,
+// to read it back without the row padding of texture to buffer copies.
+
+struct Region {
+  origin: vec2<u32>,
+  size: vec2<u32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> packed: array<u32>;
+@group(0) @binding(2)
+var<uniform> region: Region;
+
+@compute @workgroup_size(16, 16)
+fn pack_rows(@builtin(global_invocation_id) id: vec3<u32>) {
+  if id.x >= region.size.x || id.y >= region.size.y {
+    return;
+  }
+
+  // Unorm texels load as exact multiples of 1/255, which pack back into
+  // the bytes they came from.
+  let texel = textureLoad(source, vec2<i32>(region.origin + id.xy), 0);
+  packed[id.y * region.size.x + id.x] = pack4x8unorm(texel);
+}
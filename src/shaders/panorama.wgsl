@@ -0,0 +1,177 @@
+#include "sampling.wgsl"
+#include "projection.wgsl"
+
+struct Tile {
+  // Direction the tile was taken in, in radians.
+  yaw: f32,
+  // Focal length of the tiles and the panorama, in texels.
+  focal: f32,
+  // Longitude at the left edge of the panorama.
+  origin: f32,
+  projection: u32,
+}
+
+// A pyramid level, and the ones above and below it, in the pyramid buffer.
+struct Level {
+  size: vec2<u32>,
+  offset: u32,
+  top: u32,
+  fine_size: vec2<u32>,
+  fine_offset: u32,
+  coarse_offset: u32,
+  coarse_size: vec2<u32>,
+}
+
+struct Texel {
+  // Color premultiplied by how much of it is covered by the tile, and the
+  // coverage. After collapsing, the blended color.
+  color: vec4<f32>,
+  // Sum of the masked Laplacian of every tile, and of the masks.
+  blend: vec4<f32>,
+  mask: f32,
+}
+
+@group(0) @binding(0)
+var tileTexture: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> tile: Tile;
+
+@group(1) @binding(0)
+var<uniform> level: Level;
+
+@group(2) @binding(0)
+var<storage, read_write> pyramid: array<Texel>;
+// Best seam weight of any tile per panorama texel, as f32 bits.
+@group(2) @binding(1)
+var<storage, read_write> best: array<atomic<u32>>;
+@group(2) @binding(2)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+fn at(offset: u32, size: vec2<u32>, p: vec2<i32>) -> u32 {
+  let clamped = vec2<u32>(clamp(p, vec2<i32>(0), vec2<i32>(size) - 1));
+  return offset + clamped.y * size.x + clamped.x;
+}
+
+// Where the panorama texel `p` falls in the tile, and how far that is from
+// the tile's nearest edge; zero outside the tile.
+fn project(p: vec2<u32>) -> vec3<f32> {
+  let size = vec2<f32>(textureDimensions(tileTexture));
+  let rows = f32(level.size.y);
+
+  let longitude = tile.origin + (f32(p.x) + 0.5) / tile.focal;
+  let vertical = (f32(p.y) + 0.5 - rows * 0.5) / tile.focal;
+  let direction = rotate_yaw(panorama_direction(tile.projection, longitude, vertical), tile.yaw);
+
+  if direction.z <= 0.0 {
+    return vec3<f32>(0.0);
+  }
+
+  let position = tile.focal * direction.xy / direction.z + size * 0.5;
+  let edges = min(position, size - position);
+  return vec3<f32>(position, max(min(edges.x, edges.y), 0.0));
+}
+
+@compute @workgroup_size(1)
+fn weigh(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let weight = project(global_id.xy).z;
+  atomicMax(&best[global_id.y * level.size.x + global_id.x], bitcast<u32>(weight));
+}
+
+// Warps the tile into the panorama and masks the texels where it has the
+// best weight, so every texel belongs to the tile whose edge is farthest.
+@compute @workgroup_size(1)
+fn warp(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let projected = project(global_id.xy);
+  let index = at(level.offset, level.size, vec2<i32>(global_id.xy));
+
+  var color = vec4<f32>(0.0);
+  var mask = 0.0;
+  if projected.z > 0.0 {
+    color = vec4<f32>(sample_bilinear(tileTexture, projected.xy).rgb, 1.0);
+    let best_weight = bitcast<f32>(atomicLoad(&best[global_id.y * level.size.x + global_id.x]));
+    mask = select(0.0, 1.0, projected.z >= best_weight);
+  }
+
+  pyramid[index].color = color;
+  pyramid[index].mask = mask;
+}
+
+// Downsamples the finer level into this one with a separable [1 3 3 1]
+// kernel.
+@compute @workgroup_size(1)
+fn reduce(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  var weights = array<f32, 4>(0.125, 0.375, 0.375, 0.125);
+  let base = vec2<i32>(global_id.xy) * 2 - 1;
+
+  var color = vec4<f32>(0.0);
+  var mask = 0.0;
+  for (var y = 0; y < 4; y++) {
+    for (var x = 0; x < 4; x++) {
+      let weight = weights[x] * weights[y];
+      let texel = pyramid[at(level.fine_offset, level.fine_size, base + vec2<i32>(x, y))];
+      color += texel.color * weight;
+      mask += texel.mask * weight;
+    }
+  }
+
+  let index = at(level.offset, level.size, vec2<i32>(global_id.xy));
+  pyramid[index].color = color;
+  pyramid[index].mask = mask;
+}
+
+fn unpremultiply(color: vec4<f32>) -> vec3<f32> {
+  return select(vec3<f32>(0.0), color.rgb / color.a, color.a > 1.0e-5);
+}
+
+// Bilinear sample of the coarser level under texel `p` of this one.
+fn expand(p: vec2<u32>, collapsed: bool) -> vec3<f32> {
+  let position = (vec2<f32>(p) + 0.5) * 0.5 - 0.5;
+  let base = vec2<i32>(floor(position));
+  let f = fract(position);
+
+  var samples: array<vec3<f32>, 4>;
+  for (var corner = 0; corner < 4; corner++) {
+    let coord = base + vec2<i32>(corner % 2, corner / 2);
+    let color = pyramid[at(level.coarse_offset, level.coarse_size, coord)].color;
+    samples[corner] = select(unpremultiply(color), color.rgb, collapsed);
+  }
+
+  return mix(mix(samples[0], samples[1], f.x), mix(samples[2], samples[3], f.x), f.y);
+}
+
+// Adds the tile's band at this level, weighted by its mask, to the blend.
+@compute @workgroup_size(1)
+fn accumulate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = at(level.offset, level.size, vec2<i32>(global_id.xy));
+  let texel = pyramid[index];
+
+  var band = unpremultiply(texel.color);
+  if level.top == 0u {
+    band -= expand(global_id.xy, false);
+  }
+
+  pyramid[index].blend += vec4<f32>(band * texel.mask, texel.mask);
+}
+
+// Rebuilds the blended image from the top of the pyramid down.
+@compute @workgroup_size(1)
+fn collapse(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = at(level.offset, level.size, vec2<i32>(global_id.xy));
+  let blend = pyramid[index].blend;
+
+  var color = select(vec3<f32>(0.0), blend.rgb / blend.a, blend.a > 1.0e-5);
+  if level.top == 0u {
+    color += expand(global_id.xy, true);
+  }
+
+  pyramid[index].color = vec4<f32>(color, 1.0);
+}
+
+@compute @workgroup_size(1)
+fn output(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = at(level.offset, level.size, vec2<i32>(global_id.xy));
+  let covered = atomicLoad(&best[global_id.y * level.size.x + global_id.x]) > 0u;
+
+  let color = clamp(pyramid[index].color.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+  textureStore(textureOutput, vec2<i32>(global_id.xy), vec4<f32>(color, select(0.0, 1.0, covered)));
+}
@@ -0,0 +1,88 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+// How far a texel looks for the ends of its run.
+const MAX_SCAN: i32 = 1024;
+
+fn load_along(line: i32, index: i32, vertical: bool) -> vec4<f32> {
+  if vertical {
+    return textureLoad(textureInput, vec2<i32>(line, index), 0);
+  }
+  return textureLoad(textureInput, vec2<i32>(index, line), 0);
+}
+
+fn sort_key(color: vec4<f32>) -> f32 {
+  return luminance(color.rgb);
+}
+
+// Sorts the runs of texels brighter than `threshold` along every row, or
+// column with `vertical`, by luminance. Runs are cut into segments of at
+// most `length` texels. No texel depends on another's result: each one
+// finds its segment and picks the texel whose rank in it matches its own
+// position.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn pixel_sort(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let threshold = param(0u);
+  let vertical = param(1u) >= 0.5;
+  let length = i32(param(2u));
+
+  let size = vec2<i32>(textureDimensions(textureInput));
+  var line = coord.y;
+  var index = coord.x;
+  var count = size.x;
+  if vertical {
+    line = coord.x;
+    index = coord.y;
+    count = size.y;
+  }
+
+  let color = load_along(line, index, vertical);
+  if sort_key(color) <= threshold {
+    textureStore(textureOutput, coord, color);
+    return;
+  }
+
+  var start = index;
+  while start > 0 && index - start < MAX_SCAN && sort_key(load_along(line, start - 1, vertical)) > threshold {
+    start--;
+  }
+  var end = index;
+  while end < count - 1 && end - index < MAX_SCAN && sort_key(load_along(line, end + 1, vertical)) > threshold {
+    end++;
+  }
+
+  let segment_start = start + (index - start) / length * length;
+  let segment_end = min(segment_start + length - 1, end);
+  let rank = index - segment_start;
+
+  for (var candidate = segment_start; candidate <= segment_end; candidate++) {
+    let value = load_along(line, candidate, vertical);
+    let key = sort_key(value);
+
+    // Ties are broken by position so every rank is taken exactly once.
+    var below = 0;
+    for (var other = segment_start; other <= segment_end; other++) {
+      let other_key = sort_key(load_along(line, other, vertical));
+      if other_key < key || (other_key == key && other < candidate) {
+        below++;
+      }
+    }
+
+    if below == rank {
+      textureStore(textureOutput, coord, value);
+      return;
+    }
+  }
+
+  textureStore(textureOutput, coord, color);
+}
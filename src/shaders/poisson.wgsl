@@ -0,0 +1,70 @@
+// Seamless cloning: the result inside the mask keeps the gradients of the
+// source while matching the destination along the mask border. Writing it
+// as source + correction, the correction is the harmonic (Laplace) solution
+// whose border values are destination - source, which Jacobi iterations
+// converge to from zero.
+
+@group(0) @binding(0)
+var destination: texture_2d<f32>;
+// Source placed over the destination, with the mask in alpha.
+@group(0) @binding(1)
+var source: texture_2d<f32>;
+@group(0) @binding(2)
+var correctionInput: texture_2d<f32>;
+@group(0) @binding(3)
+var correctionOutput: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(4)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+fn inside(coord: vec2<i32>) -> bool {
+  return textureLoad(source, coord, 0).a > 0.5;
+}
+
+fn correction(coord: vec2<i32>) -> vec4<f32> {
+  let size = vec2<i32>(textureDimensions(correctionInput));
+  return textureLoad(correctionInput, clamp(coord, vec2<i32>(0), size - 1), 0);
+}
+
+@compute @workgroup_size(1)
+fn init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+
+  var value = vec4<f32>(0.0);
+  if !inside(coord) {
+    value = vec4<f32>(textureLoad(destination, coord, 0).rgb - textureLoad(source, coord, 0).rgb, 0.0);
+  }
+
+  textureStore(correctionOutput, coord, value);
+}
+
+// One Jacobi step: texels inside the mask take the mean of their four
+// neighbours, the ones outside hold their fixed border value.
+@compute @workgroup_size(1)
+fn jacobi(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+
+  var value = correction(coord);
+  if inside(coord) {
+    value = 0.25 * (
+      correction(coord + vec2<i32>(1, 0)) +
+      correction(coord - vec2<i32>(1, 0)) +
+      correction(coord + vec2<i32>(0, 1)) +
+      correction(coord - vec2<i32>(0, 1))
+    );
+  }
+
+  textureStore(correctionOutput, coord, value);
+}
+
+@compute @workgroup_size(1)
+fn compose(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+  let background = textureLoad(destination, coord, 0);
+
+  var color = background.rgb;
+  if inside(coord) {
+    color = clamp(textureLoad(source, coord, 0).rgb + correction(coord).rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+  }
+
+  textureStore(textureOutput, coord, vec4<f32>(color, background.a));
+}
@@ -0,0 +1,75 @@
+// Renders a texture onto a mesh without texture coordinates by projecting
+// it along the three axes (triplanar mapping) and blending the projections
+// by how much the surface faces each axis.
+
+struct Camera {
+  view_projection: mat4x4<f32>,
+  // Rotation of the mesh, also applied to its normals.
+  model: mat4x4<f32>,
+  // Direction towards the light and the camera position, in world space.
+  light: vec4<f32>,
+  eye: vec4<f32>,
+  // Half the size of the mesh's bounding box, which every projection
+  // covers with the whole texture.
+  extent: f32,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureSampler: sampler;
+@group(0) @binding(2)
+var<uniform> camera: Camera;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  // Object space, so the texture stays on the mesh as it turns.
+  @location(0) position: vec3<f32>,
+  @location(1) normal: vec3<f32>,
+  @location(2) world_position: vec3<f32>,
+  @location(3) world_normal: vec3<f32>,
+}
+
+@vertex
+fn vertex(@location(0) position: vec3<f32>, @location(1) normal: vec3<f32>) -> VertexOutput {
+  let world_position = (camera.model * vec4<f32>(position, 1.0)).xyz;
+
+  var out: VertexOutput;
+  out.clip_position = camera.view_projection * vec4<f32>(world_position, 1.0);
+  out.position = position;
+  out.normal = normal;
+  out.world_position = world_position;
+  out.world_normal = (camera.model * vec4<f32>(normal, 0.0)).xyz;
+  return out;
+}
+
+fn triplanar(position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+  var weights = pow(abs(normal), vec3<f32>(4.0));
+  weights /= max(weights.x + weights.y + weights.z, 1e-5);
+
+  // Texture space runs right and down.
+  let uv = vec3<f32>(0.5, -0.5, 0.5) * position / camera.extent + 0.5;
+  let x = textureSample(textureInput, textureSampler, uv.zy);
+  let y = textureSample(textureInput, textureSampler, uv.xz);
+  let z = textureSample(textureInput, textureSampler, uv.xy);
+
+  return x * weights.x + y * weights.y + z * weights.z;
+}
+
+// A key light with a soft specular highlight, plus some ambient light so
+// the side turned away doesn't go black.
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+  let albedo = triplanar(in.position, normalize(in.normal));
+
+  let normal = normalize(in.world_normal);
+  let light = normalize(camera.light.xyz);
+  let view = normalize(camera.eye.xyz - in.world_position);
+  let half_vector = normalize(light + view);
+
+  let diffuse = max(dot(normal, light), 0.0);
+  let specular = pow(max(dot(normal, half_vector), 0.0), 32.0) * 0.25 * step(0.0, diffuse);
+
+  let color = albedo.rgb * (0.15 + 0.85 * diffuse) + specular;
+  return vec4<f32>(color, 1.0);
+}
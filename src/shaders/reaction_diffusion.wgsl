@@ -0,0 +1,82 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+#include "noise.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+// The state keeps both concentrations at 16 bits, split over two 8-bit
+// channels each, since single steps change them by less than 1/255.
+fn encode(concentrations: vec2<f32>) -> vec4<f32> {
+  let fixed = vec2<u32>(round(clamp(concentrations, vec2<f32>(0.0), vec2<f32>(1.0)) * 65535.0));
+  return vec4<f32>(vec4<u32>(fixed.x >> 8u, fixed.x & 255u, fixed.y >> 8u, fixed.y & 255u)) / 255.0;
+}
+
+fn decode(texel: vec4<f32>) -> vec2<f32> {
+  let bytes = vec4<u32>(round(texel * 255.0));
+  return vec2<f32>(vec2<u32>(bytes.x << 8u | bytes.y, bytes.z << 8u | bytes.w)) / 65535.0;
+}
+
+// Concentrations at `coord`, wrapping around so the pattern tiles.
+fn state(coord: vec2<i32>) -> vec2<f32> {
+  let size = vec2<i32>(globals.size);
+  return decode(textureLoad(textureInput, (coord + size) % size, 0));
+}
+
+// Starts with chemical U everywhere and V where the input is dark, plus a
+// sprinkling of V blocks so even a blank input grows a pattern. Smaller
+// spots diffuse away before they react.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn seed(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let darkness = 1.0 - luminance(textureLoad(textureInput, coord, 0).rgb);
+  let sprinkle = select(0.0, 1.0, random(global_id.xy / 8u, globals.seed) < 0.05);
+
+  let v = max(step(0.5, darkness), sprinkle);
+  textureStore(textureOutput, coord, encode(vec2<f32>(1.0 - v * 0.5, v * 0.25)));
+}
+
+// One Gray-Scott step: U feeds in, V consumes it and decays.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn reaction_diffusion(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let feed = param(0u);
+  let kill = param(1u);
+
+  let center = state(coord);
+  let laplacian = -center
+    + 0.2 * (state(coord + vec2<i32>(1, 0)) + state(coord - vec2<i32>(1, 0))
+      + state(coord + vec2<i32>(0, 1)) + state(coord - vec2<i32>(0, 1)))
+    + 0.05 * (state(coord + vec2<i32>(1, 1)) + state(coord - vec2<i32>(1, 1))
+      + state(coord + vec2<i32>(1, -1)) + state(coord - vec2<i32>(1, -1)));
+
+  let reaction = center.x * center.y * center.y;
+  let u = center.x + laplacian.x - reaction + feed * (1.0 - center.x);
+  let v = center.y + 0.5 * laplacian.y + reaction - (kill + feed) * center.y;
+
+  textureStore(textureOutput, coord, encode(vec2<f32>(u, v)));
+}
+
+// Shows the final state as grayscale, light where U dominates.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn resolve(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let concentrations = state(coord);
+  let value = clamp(concentrations.x - concentrations.y, 0.0, 1.0);
+
+  textureStore(textureOutput, coord, vec4<f32>(vec3<f32>(value), 1.0));
+}
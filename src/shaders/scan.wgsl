@@ -0,0 +1,100 @@
+// Prefix sums over segments of a buffer of `Element`s, which the host
+// declares as `u32` or `f32` ahead of this file. One workgroup scans one
+// segment; segment `s` starts at
+// `offset + (s / inner) * outer_stride + (s % inner) * inner_stride` and has
+// `length` elements `element_stride` apart, which covers rows, columns and
+// interleaved channels alike.
+
+const WORKGROUP_SIZE: u32 = 256u;
+
+struct Layout {
+  length: u32,
+  element_stride: u32,
+  offset: u32,
+  inner: u32,
+  inner_stride: u32,
+  outer_stride: u32,
+  segments: u32,
+  exclusive: u32,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> values: array<Element>;
+@group(0) @binding(1)
+var<uniform> scan_layout: Layout;
+
+var<workgroup> partial: array<Element, WORKGROUP_SIZE>;
+var<workgroup> carry: Element;
+
+@compute @workgroup_size(256)
+fn scan(
+  @builtin(workgroup_id) workgroup_id: vec3<u32>,
+  @builtin(num_workgroups) num_workgroups: vec3<u32>,
+  @builtin(local_invocation_index) local: u32,
+) {
+  let segment = workgroup_id.x + workgroup_id.y * num_workgroups.x;
+  if segment >= scan_layout.segments {
+    return;
+  }
+
+  let base = scan_layout.offset + segment / scan_layout.inner * scan_layout.outer_stride
+    + segment % scan_layout.inner * scan_layout.inner_stride;
+
+  if local == 0u {
+    carry = Element(0);
+  }
+  workgroupBarrier();
+
+  // Scan the segment a chunk at a time, carrying the running total over.
+  for (var start = 0u; start < scan_layout.length; start = start + WORKGROUP_SIZE) {
+    let index = start + local;
+    let address = base + index * scan_layout.element_stride;
+
+    var value = Element(0);
+    if index < scan_layout.length {
+      value = values[address];
+    }
+    partial[local] = value;
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < WORKGROUP_SIZE; offset = offset * 2u) {
+      var previous = Element(0);
+      if local >= offset {
+        previous = partial[local - offset];
+      }
+      workgroupBarrier();
+      partial[local] = partial[local] + previous;
+      workgroupBarrier();
+    }
+
+    let total = carry + partial[local];
+    if index < scan_layout.length {
+      values[address] = select(total, total - value, scan_layout.exclusive != 0u);
+    }
+    workgroupBarrier();
+
+    if local == WORKGROUP_SIZE - 1u {
+      carry = total;
+    }
+    workgroupBarrier();
+  }
+}
+
+// Second half of a blocked scan of a contiguous buffer: after the blocks of
+// `length` elements and then their last elements were scanned inclusively,
+// adds the total before every block to the rest of it.
+@compute @workgroup_size(256)
+fn propagate(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+  @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+  let index = global_id.x + global_id.y * num_workgroups.x * WORKGROUP_SIZE;
+  let block = index / scan_layout.length;
+
+  if block == 0u || block >= scan_layout.segments
+    || index % scan_layout.length == scan_layout.length - 1u {
+    return;
+  }
+
+  values[index] = values[index] + values[block * scan_layout.length - 1u];
+}
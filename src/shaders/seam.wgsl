@@ -0,0 +1,88 @@
+#include "color.wgsl"
+
+struct State {
+  // Width of the image still left, the textures keep their full size.
+  width: u32,
+  // Row the next `accumulate` pass works on.
+  row: u32,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2)
+var<storage, read_write> state: State;
+// Energy, then the cost of the cheapest seam from the top to every texel,
+// rows packed at the full texture width.
+@group(0) @binding(3)
+var<storage, read_write> cost: array<f32>;
+// Column of the seam to remove, per row.
+@group(0) @binding(4)
+var<storage, read> seam: array<u32>;
+
+fn brightness(x: i32, y: i32) -> f32 {
+  let height = i32(textureDimensions(textureInput).y);
+  let coord = vec2<i32>(clamp(x, 0, i32(state.width) - 1), clamp(y, 0, height - 1));
+  return luminance(textureLoad(textureInput, coord, 0).rgb);
+}
+
+fn index(x: u32, y: u32) -> u32 {
+  return y * u32(textureDimensions(textureInput).x) + x;
+}
+
+// Sobel gradient magnitude of the luminance.
+@compute @workgroup_size(1)
+fn energy(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if global_id.x >= state.width {
+    return;
+  }
+
+  let x = i32(global_id.x);
+  let y = i32(global_id.y);
+
+  let gx = brightness(x + 1, y - 1) + 2.0 * brightness(x + 1, y) + brightness(x + 1, y + 1)
+    - brightness(x - 1, y - 1) - 2.0 * brightness(x - 1, y) - brightness(x - 1, y + 1);
+  let gy = brightness(x - 1, y + 1) + 2.0 * brightness(x, y + 1) + brightness(x + 1, y + 1)
+    - brightness(x - 1, y - 1) - 2.0 * brightness(x, y - 1) - brightness(x + 1, y - 1);
+
+  cost[index(global_id.x, global_id.y)] = sqrt(gx * gx + gy * gy);
+}
+
+// Moves `accumulate` on to the next row; rows depend on the one above, so
+// they run as separate dispatches.
+@compute @workgroup_size(1)
+fn advance() {
+  state.row += 1u;
+}
+
+@compute @workgroup_size(1)
+fn accumulate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let x = global_id.x;
+  if x >= state.width {
+    return;
+  }
+
+  let above = state.row - 1u;
+  var cheapest = cost[index(x, above)];
+  if x > 0u {
+    cheapest = min(cheapest, cost[index(x - 1u, above)]);
+  }
+  if x + 1u < state.width {
+    cheapest = min(cheapest, cost[index(x + 1u, above)]);
+  }
+
+  cost[index(x, state.row)] += cheapest;
+}
+
+// Shifts the texels right of the seam one to the left.
+@compute @workgroup_size(1)
+fn remove(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  var x = global_id.x;
+  if x >= seam[global_id.y] {
+    x += 1u;
+  }
+
+  let color = textureLoad(textureInput, vec2<i32>(i32(x), i32(global_id.y)), 0);
+  textureStore(textureOutput, vec2<i32>(global_id.xy), color);
+}
@@ -0,0 +1,46 @@
+#include "globals.wgsl"
+#include "pointwise.wgsl"
+
+// Cluster colors from texel 0, their normalized positions as 16-bit x in
+// red-green and y in blue-alpha from texel 128.
+@group(0) @binding(3)
+var lookupTable: texture_2d<f32>;
+
+const POSITIONS: i32 = 128;
+
+fn centroid_position(cluster: i32) -> vec2<f32> {
+  let bytes = round(textureLoad(lookupTable, vec2<i32>(POSITIONS + cluster, 0), 0) * 255.0);
+  return vec2<f32>(bytes.r * 256.0 + bytes.g, bytes.b * 256.0 + bytes.a) / 65535.0;
+}
+
+// Paints every texel in the color of the nearest k-means cluster.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn segment(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let clusters = i32(param(0u));
+  let spatial = param(1u);
+
+  let size = vec2<f32>(textureDimensions(textureOutput));
+  let texel = load_input(coord);
+  let position = (vec2<f32>(global_id.xy) + 0.5) / size;
+
+  var best = vec3<f32>(0.0);
+  var best_distance = 1.0e30;
+  for (var cluster = 0; cluster < clusters; cluster++) {
+    let color = textureLoad(lookupTable, vec2<i32>(cluster, 0), 0).rgb;
+    let color_offset = texel.rgb - color;
+    let position_offset = (position - centroid_position(cluster)) * spatial;
+    let distance = dot(color_offset, color_offset) + dot(position_offset, position_offset);
+
+    if distance < best_distance {
+      best_distance = distance;
+      best = color;
+    }
+  }
+
+  store_output(coord, vec4<f32>(best, texel.a));
+}
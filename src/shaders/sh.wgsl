@@ -0,0 +1,75 @@
+// Projection of an equirectangular environment map onto the real spherical
+// harmonics of the first three bands. Every row is integrated on its own,
+// then the rows are summed up.
+
+const PI: f32 = 3.14159265;
+const COEFFICIENTS: u32 = 9u;
+
+struct Coefficients {
+  // Colors in rgb, one per basis function.
+  values: array<vec4<f32>, 9>,
+}
+
+// Equirectangular, longitude 0 in the middle and up at the top.
+@group(0) @binding(0)
+var environment: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> rows: array<Coefficients>;
+@group(0) @binding(2)
+var<storage, read_write> total: Coefficients;
+
+// Basis function `index` in (l, m) order with y up, without the
+// Condon-Shortley phase.
+fn basis(index: u32, direction: vec3<f32>) -> f32 {
+  let x = direction.x;
+  let y = direction.y;
+  let z = direction.z;
+
+  switch index {
+    case 0u: { return 0.282095; }
+    case 1u: { return 0.488603 * y; }
+    case 2u: { return 0.488603 * z; }
+    case 3u: { return 0.488603 * x; }
+    case 4u: { return 1.092548 * x * y; }
+    case 5u: { return 1.092548 * y * z; }
+    case 6u: { return 0.315392 * (3.0 * z * z - 1.0); }
+    case 7u: { return 1.092548 * x * z; }
+    default: { return 0.546274 * (x * x - y * y); }
+  }
+}
+
+@compute @workgroup_size(1)
+fn project_rows(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let size = textureDimensions(environment);
+  let y = global_id.x;
+
+  // Every texel of the row covers the same solid angle, which shrinks
+  // towards the poles.
+  let polar = (f32(y) + 0.5) / f32(size.y) * PI;
+  let solid_angle = 2.0 * PI / f32(size.x) * PI / f32(size.y) * sin(polar);
+
+  var sums: array<vec4<f32>, 9>;
+  for (var x = 0u; x < size.x; x++) {
+    let longitude = ((f32(x) + 0.5) / f32(size.x) - 0.5) * 2.0 * PI;
+    let direction = vec3<f32>(sin(polar) * sin(longitude), cos(polar), sin(polar) * cos(longitude));
+    let radiance = textureLoad(environment, vec2<u32>(x, y), 0).rgb * solid_angle;
+
+    for (var index = 0u; index < COEFFICIENTS; index++) {
+      sums[index] += vec4<f32>(radiance * basis(index, direction), 0.0);
+    }
+  }
+
+  rows[y].values = sums;
+}
+
+@compute @workgroup_size(1)
+fn sum_rows() {
+  var sums: array<vec4<f32>, 9>;
+  for (var y = 0u; y < arrayLength(&rows); y++) {
+    for (var index = 0u; index < COEFFICIENTS; index++) {
+      sums[index] += rows[y].values[index];
+    }
+  }
+
+  total.values = sums;
+}
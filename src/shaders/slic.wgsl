@@ -0,0 +1,152 @@
+#include "color.wgsl"
+
+struct Settings {
+  // Grid spacing the superpixels start from, in texels.
+  spacing: u32,
+  columns: u32,
+  rows: u32,
+  // Weight of spatial distance against color distance.
+  compactness: f32,
+}
+
+struct Center {
+  lab: vec4<f32>,
+  position: vec2<f32>,
+  // Mean sRGB color of the assigned texels, for the mosaic.
+  rgb: vec4<f32>,
+}
+
+// Fixed-point running sums of the texels assigned to a center.
+struct Sums {
+  lab: array<atomic<u32>, 3>,
+  position: array<atomic<u32>, 2>,
+  rgb: array<atomic<u32>, 3>,
+  count: atomic<u32>,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2)
+var<uniform> settings: Settings;
+@group(0) @binding(3)
+var<storage, read_write> centers: array<Center>;
+@group(0) @binding(4)
+var<storage, read_write> sums: array<Sums>;
+@group(0) @binding(5)
+var<storage, read_write> labels: array<u32>;
+
+// Lab is shifted to positive values and scaled before summing.
+const LAB_OFFSET: vec3<f32> = vec3<f32>(0.0, 128.0, 128.0);
+const LAB_SCALE: f32 = 64.0;
+const RGB_SCALE: f32 = 255.0;
+
+fn texel_index(p: vec2<u32>) -> u32 {
+  return p.y * u32(textureDimensions(textureInput).x) + p.x;
+}
+
+// Centers start in the middle of the grid cells.
+@compute @workgroup_size(1)
+fn init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let size = vec2<u32>(textureDimensions(textureInput));
+  let position = min(global_id.xy * settings.spacing + settings.spacing / 2u, size - 1u);
+  let color = textureLoad(textureInput, vec2<i32>(position), 0).rgb;
+
+  let index = global_id.y * settings.columns + global_id.x;
+  centers[index].lab = vec4<f32>(rgb_to_lab(color), 0.0);
+  centers[index].position = vec2<f32>(position);
+  centers[index].rgb = vec4<f32>(color, 1.0);
+}
+
+// Labels every texel with the closest center among the grid cells around
+// it, and adds it to that center's sums.
+@compute @workgroup_size(1)
+fn assign(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+  let color = textureLoad(textureInput, coord, 0).rgb;
+  let lab = rgb_to_lab(color);
+  let position = vec2<f32>(global_id.xy);
+
+  let cell = vec2<i32>(global_id.xy / settings.spacing);
+  let grid = vec2<i32>(i32(settings.columns), i32(settings.rows));
+  let spatial_weight = settings.compactness / f32(settings.spacing);
+
+  var best = 0u;
+  var best_distance = 1.0e30;
+  for (var y = max(cell.y - 1, 0); y <= min(cell.y + 1, grid.y - 1); y++) {
+    for (var x = max(cell.x - 1, 0); x <= min(cell.x + 1, grid.x - 1); x++) {
+      let index = u32(y * grid.x + x);
+      let center = centers[index];
+
+      let color_distance = length(lab - center.lab.xyz);
+      let spatial_distance = length(position - center.position) * spatial_weight;
+      let distance = color_distance * color_distance + spatial_distance * spatial_distance;
+
+      if distance < best_distance {
+        best_distance = distance;
+        best = index;
+      }
+    }
+  }
+
+  labels[texel_index(global_id.xy)] = best;
+
+  let fixed_lab = vec3<u32>((lab + LAB_OFFSET) * LAB_SCALE);
+  let fixed_rgb = vec3<u32>(color * RGB_SCALE);
+  for (var channel = 0u; channel < 3u; channel++) {
+    atomicAdd(&sums[best].lab[channel], fixed_lab[channel]);
+    atomicAdd(&sums[best].rgb[channel], fixed_rgb[channel]);
+  }
+  atomicAdd(&sums[best].position[0], global_id.x);
+  atomicAdd(&sums[best].position[1], global_id.y);
+  atomicAdd(&sums[best].count, 1u);
+}
+
+// Moves every center to the mean of its texels and clears the sums for the
+// next iteration. Centers that lost all texels stay where they are.
+@compute @workgroup_size(1)
+fn update(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let index = global_id.y * settings.columns + global_id.x;
+
+  let count = atomicExchange(&sums[index].count, 0u);
+  var lab = vec3<f32>(0.0);
+  var rgb = vec3<f32>(0.0);
+  for (var channel = 0u; channel < 3u; channel++) {
+    lab[channel] = f32(atomicExchange(&sums[index].lab[channel], 0u));
+    rgb[channel] = f32(atomicExchange(&sums[index].rgb[channel], 0u));
+  }
+  let position = vec2<f32>(
+    f32(atomicExchange(&sums[index].position[0], 0u)),
+    f32(atomicExchange(&sums[index].position[1], 0u)),
+  );
+
+  if count == 0u {
+    return;
+  }
+
+  let texels = f32(count);
+  centers[index].lab = vec4<f32>(lab / (texels * LAB_SCALE) - LAB_OFFSET, 0.0);
+  centers[index].position = position / texels;
+  centers[index].rgb = vec4<f32>(rgb / (texels * RGB_SCALE), 1.0);
+}
+
+// Every texel in the mean color of its superpixel.
+@compute @workgroup_size(1)
+fn mosaic(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coord = vec2<i32>(global_id.xy);
+  let label = labels[texel_index(global_id.xy)];
+  let alpha = textureLoad(textureInput, coord, 0).a;
+
+  textureStore(textureOutput, coord, vec4<f32>(centers[label].rgb.rgb, alpha));
+}
+
+// Superpixel index of every texel as a 24-bit number in RGB, least
+// significant byte in red.
+@compute @workgroup_size(1)
+fn label_map(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let label = labels[texel_index(global_id.xy)];
+  let bytes = vec3<u32>(label, label >> 8u, label >> 16u) & vec3<u32>(255u);
+
+  textureStore(textureOutput, vec2<i32>(global_id.xy), vec4<f32>(vec3<f32>(bytes) / 255.0, 1.0));
+}
@@ -0,0 +1,86 @@
+struct Settings {
+  inputs: u32,
+  // Bit the median search decides next.
+  bit: u32,
+}
+
+// Per channel state of the bitwise median search: the high bits found so
+// far and the median's rank among the values sharing them.
+struct Selection {
+  prefix: vec4<u32>,
+  rank: vec4<u32>,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2)
+var<uniform> settings: Settings;
+// Sums of the inputs for the mean, counts for the median search.
+@group(0) @binding(3)
+var<storage, read_write> sums: array<vec4<u32>>;
+@group(0) @binding(4)
+var<storage, read_write> selection: array<Selection>;
+
+fn index(p: vec2<u32>) -> u32 {
+  return p.y * u32(textureDimensions(textureInput).x) + p.x;
+}
+
+fn value(p: vec2<u32>) -> vec4<u32> {
+  return vec4<u32>(round(textureLoad(textureInput, vec2<i32>(p), 0) * 255.0));
+}
+
+@compute @workgroup_size(1)
+fn accumulate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  sums[i] += value(global_id.xy);
+}
+
+@compute @workgroup_size(1)
+fn mean(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  let inputs = settings.inputs;
+  let mean = (sums[i] + inputs / 2u) / inputs;
+
+  textureStore(textureOutput, vec2<i32>(global_id.xy), vec4<f32>(mean) / 255.0);
+}
+
+// The median is found one bit at a time, from the highest: every input adds
+// the values that match the prefix so far and have a zero at `bit`, then
+// `select` takes that branch if the median's rank falls among them.
+@compute @workgroup_size(1)
+fn median_init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  selection[i].prefix = vec4<u32>(0u);
+  selection[i].rank = vec4<u32>((settings.inputs - 1u) / 2u);
+  sums[i] = vec4<u32>(0u);
+}
+
+@compute @workgroup_size(1)
+fn count(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  let value = value(global_id.xy);
+
+  let matches = (value >> vec4<u32>(settings.bit + 1u)) == selection[i].prefix;
+  let zero = ((value >> vec4<u32>(settings.bit)) & vec4<u32>(1u)) == vec4<u32>(0u);
+  sums[i] += select(vec4<u32>(0u), vec4<u32>(1u), matches & zero);
+}
+
+@compute @workgroup_size(1)
+fn select_bit(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  let zeros = sums[i];
+  let state = selection[i];
+
+  let low = state.rank < zeros;
+  selection[i].prefix = (state.prefix << vec4<u32>(1u)) | select(vec4<u32>(1u), vec4<u32>(0u), low);
+  selection[i].rank = select(state.rank - zeros, state.rank, low);
+  sums[i] = vec4<u32>(0u);
+}
+
+@compute @workgroup_size(1)
+fn median(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let i = index(global_id.xy);
+  textureStore(textureOutput, vec2<i32>(global_id.xy), vec4<f32>(selection[i].prefix) / 255.0);
+}
@@ -0,0 +1,42 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+#include "sampling.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+const PI: f32 = 3.14159265;
+
+fn rotate(p: vec2<f32>, angle: f32) -> vec2<f32> {
+  let c = cos(angle);
+  let s = sin(angle);
+  return vec2<f32>(c * p.x - s * p.y, s * p.x + c * p.y);
+}
+
+// Dot screen: the image is split into rotated cells of `size` texels, each
+// showing a black dot whose area matches the darkness at the cell center.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn halftone(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let size = param(0u);
+  let angle = radians(param(1u));
+
+  let position = vec2<f32>(coord) + 0.5;
+  let screen = rotate(position, -angle);
+  let center = (floor(screen / size) + 0.5) * size;
+
+  let sampled = sample_bilinear(textureInput, rotate(center, angle));
+  let darkness = 1.0 - clamp(luminance(sampled.rgb), 0.0, 1.0);
+  let radius = size * sqrt(darkness / PI);
+
+  let ink = 1.0 - smoothstep(radius - 0.5, radius + 0.5, distance(screen, center));
+  let alpha = textureLoad(textureInput, coord, 0).a;
+
+  textureStore(textureOutput, coord, vec4<f32>(vec3<f32>(1.0 - ink), alpha));
+}
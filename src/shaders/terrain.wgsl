@@ -0,0 +1,186 @@
+// Maps derived from a heightmap, taken from the luminance of the input.
+// Where steepness matters the first parameter is the height of white above
+// black, in texels.
+
+#include "globals.wgsl"
+#include "color.wgsl"
+#include "sampling.wgsl"
+
+const PI: f32 = 3.14159265;
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+
+fn elevation(coord: vec2<i32>) -> f32 {
+  return luminance(load_clamped(textureInput, coord).rgb);
+}
+
+// Gradient of the elevation from the Sobel operator, growing right and
+// down.
+fn gradient(coord: vec2<i32>) -> vec2<f32> {
+  let a = elevation(coord + vec2<i32>(-1, -1));
+  let b = elevation(coord + vec2<i32>(0, -1));
+  let c = elevation(coord + vec2<i32>(1, -1));
+  let d = elevation(coord + vec2<i32>(-1, 0));
+  let f = elevation(coord + vec2<i32>(1, 0));
+  let g = elevation(coord + vec2<i32>(-1, 1));
+  let h = elevation(coord + vec2<i32>(0, 1));
+  let i = elevation(coord + vec2<i32>(1, 1));
+
+  return vec2<f32>((c + 2.0 * f + i) - (a + 2.0 * d + g), (g + 2.0 * h + i) - (a + 2.0 * b + c)) / 8.0;
+}
+
+fn gray(value: f32) -> vec4<f32> {
+  return vec4<f32>(vec3<f32>(value), 1.0);
+}
+
+// Steepness from black for flat to white for vertical.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn slope(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let angle = atan(length(gradient(coord)) * param(0u));
+
+  textureStore(textureOutput, coord, gray(angle / (PI * 0.5)));
+}
+
+// Compass direction the terrain faces, clockwise from black for north (up)
+// through gray for south back to white. Flat texels are black.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn aspect(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let downhill = -gradient(coord);
+
+  var bearing = 0.0;
+  if length(downhill) > 1e-6 {
+    bearing = atan2(downhill.x, -downhill.y);
+    if bearing < 0.0 {
+      bearing += 2.0 * PI;
+    }
+  }
+
+  textureStore(textureOutput, coord, gray(bearing / (2.0 * PI)));
+}
+
+// Gray where the terrain is flat or evenly sloped, brighter on convex
+// ridges and darker in concave valleys.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn curvature(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let laplacian = elevation(coord + vec2<i32>(1, 0)) + elevation(coord - vec2<i32>(1, 0))
+    + elevation(coord + vec2<i32>(0, 1)) + elevation(coord - vec2<i32>(0, 1))
+    - 4.0 * elevation(coord);
+  let bend = laplacian * param(0u);
+
+  textureStore(textureOutput, coord, gray(0.5 - 0.5 * tanh(bend)));
+}
+
+// Flow accumulation: every texel drains into its steepest lower neighbour
+// (D8) and collects itself plus everything draining into it. The state
+// keeps the count in 24 bits of red, green and blue and the drain direction
+// in alpha, and every step passes counts one texel further downstream.
+
+const NO_DRAIN: u32 = 8u;
+
+fn neighbour(direction: u32) -> vec2<i32> {
+  var offsets = array<vec2<i32>, 8>(
+    vec2<i32>(1, 0), vec2<i32>(1, 1), vec2<i32>(0, 1), vec2<i32>(-1, 1),
+    vec2<i32>(-1, 0), vec2<i32>(-1, -1), vec2<i32>(0, -1), vec2<i32>(1, -1),
+  );
+  return offsets[direction];
+}
+
+fn inside(coord: vec2<i32>) -> bool {
+  return all(coord >= vec2<i32>(0)) && all(coord < vec2<i32>(globals.size));
+}
+
+fn encode_flow(count: u32, drain: u32) -> vec4<f32> {
+  let bytes = vec4<u32>((count >> 16u) & 255u, (count >> 8u) & 255u, count & 255u, drain);
+  return vec4<f32>(bytes) / 255.0;
+}
+
+// Count and drain direction of the state at `coord`.
+fn decode_flow(coord: vec2<i32>) -> vec2<u32> {
+  let bytes = vec4<u32>(round(textureLoad(textureInput, coord, 0) * 255.0));
+  return vec2<u32>(bytes.x << 16u | bytes.y << 8u | bytes.z, bytes.w);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn flow_seed(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let here = elevation(coord);
+
+  var drain = NO_DRAIN;
+  var steepest = 0.0;
+  for (var direction = 0u; direction < 8u; direction++) {
+    let offset = neighbour(direction);
+    if !inside(coord + offset) {
+      continue;
+    }
+
+    let drop = (here - elevation(coord + offset)) / length(vec2<f32>(offset));
+    if drop > steepest {
+      steepest = drop;
+      drain = direction;
+    }
+  }
+
+  textureStore(textureOutput, coord, encode_flow(1u, drain));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn flow_accumulation(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let state = decode_flow(coord);
+
+  var count = 1u;
+  for (var direction = 0u; direction < 8u; direction++) {
+    let source = coord + neighbour(direction);
+    if !inside(source) {
+      continue;
+    }
+
+    // The neighbour drains here if its direction points back.
+    let upstream = decode_flow(source);
+    if upstream.y == (direction + 4u) % 8u {
+      count += upstream.x;
+    }
+  }
+
+  textureStore(textureOutput, coord, encode_flow(min(count, 0xffffffu), state.y));
+}
+
+// Shows the counts on a log scale, white for all texels of the image.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn flow_resolve(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(global_id.xy);
+  let count = f32(decode_flow(coord).x);
+  let texels = f32(globals.size.x * globals.size.y);
+
+  textureStore(textureOutput, coord, gray(log2(count) / max(log2(texels), 1.0)));
+}
@@ -0,0 +1,38 @@
+struct Settings {
+  // Layer of the output to write the thumbnail to.
+  layer: u32,
+  padding: u32,
+  // Size of the thumbnail, from the top left of the layer.
+  size: vec2<u32>,
+}
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d_array<rgba8unorm, write>;
+@group(0) @binding(2)
+var<uniform> settings: Settings;
+
+// Averages all input texels under a thumbnail texel, so reducing by a lot
+// doesn't skip any of them and alias the way a few bilinear taps would.
+@compute @workgroup_size(8, 8)
+fn thumbnail(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  let coords = global_id.xy;
+  if coords.x >= settings.size.x || coords.y >= settings.size.y {
+    return;
+  }
+
+  let input_size = vec2<u32>(textureDimensions(textureInput));
+  let start = coords * input_size / settings.size;
+  let end = max((coords + 1u) * input_size / settings.size, start + 1u);
+
+  var sum = vec4<f32>(0.0);
+  for (var y = start.y; y < end.y; y++) {
+    for (var x = start.x; x < end.x; x++) {
+      sum += textureLoad(textureInput, vec2<i32>(vec2<u32>(x, y)), 0);
+    }
+  }
+  let count = f32((end.x - start.x) * (end.y - start.y));
+
+  textureStore(textureOutput, vec2<i32>(coords), i32(settings.layer), sum / count);
+}
@@ -0,0 +1,120 @@
+#include "globals.wgsl"
+#include "color.wgsl"
+#include "noise.wgsl"
+#include "sampling.wgsl"
+
+@group(0) @binding(0)
+var textureInput: texture_2d<f32>;
+@group(0) @binding(1)
+var textureOutput: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3)
+var textureSecond: texture_2d<f32>;
+
+// Every transition takes its progress from 0 (first input) to 1 (second
+// input) as parameter 0.
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn crossfade(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let a = textureLoad(textureInput, coord, 0);
+  let b = textureLoad(textureSecond, coord, 0);
+
+  textureStore(textureOutput, coord, mix(a, b, param(0u)));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn wipe(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let progress = param(0u);
+  let angle = radians(param(1u));
+  // smoothstep is undefined for equal edges, so a hard edge is the
+  // narrowest soft one.
+  let softness = max(param(2u), 1.0e-4);
+
+  let uv = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(globals.size);
+  let direction = vec2<f32>(cos(angle), sin(angle));
+
+  // Project onto the wipe direction and normalize so the edge crosses the
+  // whole image as progress goes from 0 to 1.
+  let extent = abs(direction.x) + abs(direction.y);
+  let position = (dot(uv - 0.5, direction) / extent) + 0.5;
+  let edge = progress * (1.0 + softness);
+  let amount = 1.0 - smoothstep(edge - softness, edge, position);
+
+  let a = textureLoad(textureInput, coord, 0);
+  let b = textureLoad(textureSecond, coord, 0);
+
+  textureStore(textureOutput, coord, mix(a, b, amount));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn dissolve(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let progress = param(0u);
+  let softness = max(param(1u), 1.0e-4);
+
+  let threshold = random(global_id.xy, globals.seed) * (1.0 - softness) + softness;
+  let amount = smoothstep(threshold - softness, threshold, progress);
+
+  let a = textureLoad(textureInput, coord, 0);
+  let b = textureLoad(textureSecond, coord, 0);
+
+  textureStore(textureOutput, coord, mix(a, b, amount));
+}
+
+fn gray(texture: texture_2d<f32>, coord: vec2<i32>) -> f32 {
+  return luminance(load_clamped(texture, coord).rgb);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn morph(@builtin(global_invocation_id) global_id: vec3<u32>) {
+  if any(global_id.xy >= textureDimensions(textureOutput)) {
+    return;
+  }
+
+  let coord = vec2<i32>(i32(global_id.x), i32(global_id.y));
+  let progress = param(0u);
+  let radius = i32(param(1u));
+
+  // Single-scale Lucas-Kanade flow from the first input to the second.
+  var m = vec3<f32>(0.0);
+  var b = vec2<f32>(0.0);
+
+  for (var y = -radius; y <= radius; y = y + 1) {
+    for (var x = -radius; x <= radius; x = x + 1) {
+      let p = coord + vec2<i32>(x, y);
+      let dx = 0.25 * (gray(textureInput, p + vec2<i32>(1, 0)) - gray(textureInput, p - vec2<i32>(1, 0))
+        + gray(textureSecond, p + vec2<i32>(1, 0)) - gray(textureSecond, p - vec2<i32>(1, 0)));
+      let dy = 0.25 * (gray(textureInput, p + vec2<i32>(0, 1)) - gray(textureInput, p - vec2<i32>(0, 1))
+        + gray(textureSecond, p + vec2<i32>(0, 1)) - gray(textureSecond, p - vec2<i32>(0, 1)));
+      let dt = gray(textureSecond, p) - gray(textureInput, p);
+
+      m = strict3(m + strict3(vec3<f32>(dx * dx, dx * dy, dy * dy)));
+      b = strict2(b + strict2(vec2<f32>(dx * dt, dy * dt)));
+    }
+  }
+
+  let det = strict(m.x * m.z) - strict(m.y * m.y);
+  var flow = vec2<f32>(0.0);
+  if abs(det) > 1.0e-6 {
+    flow = -(strict2(vec2<f32>(m.z * b.x, m.x * b.y)) - strict2(vec2<f32>(m.y * b.y, m.y * b.x))) / det;
+  }
+
+  let position = vec2<f32>(global_id.xy) + 0.5;
+  let from_first = sample_bilinear(textureInput, position - progress * flow);
+  let from_second = sample_bilinear(textureSecond, position + (1.0 - progress) * flow);
+
+  textureStore(textureOutput, coord, mix(from_first, from_second, progress));
+}
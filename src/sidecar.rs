@@ -0,0 +1,120 @@
+//! `<output>.json` files written next to every output with `--sidecar`,
+//! recording what went into it so cooks can be audited and reproduced: the
+//! inputs, the operations in the order they ran with their parameters and
+//! shaders, the adapter and the version of the tool.
+//!
+//! Files and shaders are identified by their 64-bit FNV-1a hash, which is
+//! stable across builds and platforms.
+
+use anyhow::*;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(Serialize)]
+pub struct Sidecar {
+    pub version: &'static str,
+    pub output: String,
+    /// Index of the frame in a numbered sequence, absent for outputs that
+    /// hold every frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame: Option<usize>,
+    pub frames: usize,
+    pub inputs: Vec<Input>,
+    pub operations: Vec<Operation>,
+    pub adapter: Adapter,
+}
+
+#[derive(Serialize)]
+pub struct Input {
+    /// What the file was read as, e.g. `input` or `second`.
+    pub role: String,
+    pub path: String,
+    pub hash: String,
+}
+
+#[derive(Serialize)]
+pub struct Operation {
+    pub name: String,
+    /// Hash of the WGSL the pipeline was created from, includes expanded
+    /// and constants specialized.
+    pub shader_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_point: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, f32>,
+    /// Parameters interpolated across the frames, as `key=start..end`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub animate: Vec<String>,
+    /// Values of `override` constants of the shader by name or id.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub constants: BTreeMap<String, f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct Adapter {
+    pub name: String,
+    pub backend: String,
+    pub device_type: String,
+    pub driver: String,
+    pub driver_info: String,
+}
+
+impl Adapter {
+    pub fn new(info: &wgpu::AdapterInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            backend: format!("{:?}", info.backend),
+            device_type: format!("{:?}", info.device_type),
+            driver: info.driver.clone(),
+            driver_info: info.driver_info.clone(),
+        }
+    }
+}
+
+impl Input {
+    /// The file at `path`, read as `role`.
+    pub fn new(role: impl Into<String>, path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Ok(Self {
+            role: role.into(),
+            path: path.display().to_string(),
+            hash: hash(&bytes),
+        })
+    }
+}
+
+impl Sidecar {
+    /// `out.png.json` for `out.png`.
+    pub fn path(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".json");
+
+        PathBuf::from(path)
+    }
+
+    pub fn save(&self, output: &Path) -> Result<()> {
+        let path = Self::path(output);
+        let json = serde_json::to_string_pretty(self)?;
+
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// `fnv1a64:` and the 16 hex digits of the hash of `bytes`.
+pub fn hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+
+    format!("fnv1a64:{:016x}", hash)
+}
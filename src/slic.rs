@@ -0,0 +1,224 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use image::RgbaImage;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+use crate::shader;
+
+/// Size of `Center` in `slic.wgsl`.
+const CENTER_SIZE: u64 = 48;
+/// Size of `Sums` in `slic.wgsl`.
+const SUMS_SIZE: u64 = 36;
+
+pub struct SlicSettings {
+    /// Grid spacing of the initial superpixels, roughly their final size.
+    pub spacing: u32,
+    /// Higher values give more regular superpixels, lower ones follow color
+    /// edges more closely.
+    pub compactness: f32,
+    pub iterations: u32,
+    /// Write superpixel indices instead of their mean colors.
+    pub labels: bool,
+}
+
+/// Layout of `Settings` in `slic.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PassSettings {
+    spacing: u32,
+    columns: u32,
+    rows: u32,
+    compactness: f32,
+}
+
+/// Groups the texels of `image` into superpixels with SLIC, k-means in
+/// Lab + position space restricted to the grid cells around every center,
+/// and draws them as a mean-color mosaic or a label map.
+pub async fn superpixels(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &RgbaImage,
+    settings: &SlicSettings,
+) -> Result<RgbaImage> {
+    let texture_size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+
+    let columns = image.width().div_ceil(settings.spacing);
+    let rows = image.height().div_ceil(settings.spacing);
+    let centers = (columns * rows) as u64;
+
+    let shader = shader::preprocess(include_str!("shaders/slic.wgsl"), None)?;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("SLIC Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+    });
+
+    let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let storage = wgpu::BufferBindingType::Storage { read_only: false };
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("SLIC Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                },
+                count: None,
+            },
+            buffer_entry(2, wgpu::BufferBindingType::Uniform),
+            buffer_entry(3, storage),
+            buffer_entry(4, storage),
+            buffer_entry(5, storage),
+        ],
+    });
+
+    let input_texture = crate::create_input_texture(device, queue, texture_size, image);
+
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("SLIC Output Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("SLIC Settings Buffer"),
+        contents: bytemuck::bytes_of(&PassSettings {
+            spacing: settings.spacing,
+            columns,
+            rows,
+            compactness: settings.compactness,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let storage_buffer = |label, size| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    };
+
+    let centers_buffer = storage_buffer("SLIC Centers Buffer", centers * CENTER_SIZE);
+    let sums_buffer = storage_buffer("SLIC Sums Buffer", centers * SUMS_SIZE);
+    let labels_buffer = storage_buffer(
+        "SLIC Labels Buffer",
+        (texture_size.width * texture_size.height) as u64 * 4,
+    );
+
+    let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("SLIC Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&output_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: settings_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: centers_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: sums_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: labels_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("SLIC Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("SLIC Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let init_pipeline = pipeline("init");
+    let assign_pipeline = pipeline("assign");
+    let update_pipeline = pipeline("update");
+    let output_pipeline = pipeline(if settings.labels {
+        "label_map"
+    } else {
+        "mosaic"
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("SLIC Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("SLIC Pass"),
+        });
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+
+        compute_pass.set_pipeline(&init_pipeline);
+        compute_pass.dispatch_workgroups(columns, rows, 1);
+
+        for _ in 0..settings.iterations {
+            compute_pass.set_pipeline(&assign_pipeline);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+            compute_pass.set_pipeline(&update_pipeline);
+            compute_pass.dispatch_workgroups(columns, rows, 1);
+        }
+
+        // The labels of the last iteration match the means its update
+        // moved the centers to.
+        compute_pass.set_pipeline(&output_pipeline);
+        compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let buffer = crate::read_texture(device, queue, &output_texture, 0, texture_size).await?;
+
+    RgbaImage::from_raw(texture_size.width, texture_size.height, buffer)
+        .ok_or_else(|| anyhow!("SLIC buffer does not match the image size"))
+}
@@ -0,0 +1,234 @@
+use anyhow::*;
+use image::{imageops, RgbaImage};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Layout of a sprite sheet as `COLUMNSxROWS` equally sized cells, indexed
+/// row by row from the top left.
+#[derive(Clone, Copy, Debug)]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl FromStr for Grid {
+    type Err = Error;
+
+    fn from_str(arg: &str) -> Result<Self> {
+        let (columns, rows) = arg
+            .split_once('x')
+            .ok_or_else(|| anyhow!("Expected COLUMNSxROWS, got '{}'", arg))?;
+
+        let grid = Self {
+            columns: columns.trim().parse().context("Invalid column count")?,
+            rows: rows.trim().parse().context("Invalid row count")?,
+        };
+
+        if grid.columns == 0 || grid.rows == 0 {
+            bail!("Grid must have at least one column and one row");
+        }
+
+        Ok(grid)
+    }
+}
+
+impl Grid {
+    pub const SINGLE: Self = Self {
+        columns: 1,
+        rows: 1,
+    };
+
+    pub fn cells(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    pub fn cell_size(&self, width: u32, height: u32) -> Result<(u32, u32)> {
+        if !width.is_multiple_of(self.columns) || !height.is_multiple_of(self.rows) {
+            bail!(
+                "A {}x{} image can't be split into a {}x{} grid of equal cells",
+                width,
+                height,
+                self.columns,
+                self.rows
+            );
+        }
+
+        Ok((width / self.columns, height / self.rows))
+    }
+
+    /// Top-left pixel of cell `index` for cells of `cell_width`x`cell_height`.
+    pub fn cell_origin(&self, index: u32, cell_width: u32, cell_height: u32) -> (u32, u32) {
+        (
+            (index % self.columns) * cell_width,
+            (index / self.columns) * cell_height,
+        )
+    }
+
+    pub fn slice(&self, image: &RgbaImage) -> Result<Vec<RgbaImage>> {
+        let (cell_width, cell_height) = self.cell_size(image.width(), image.height())?;
+
+        let cells = (0..self.cells())
+            .map(|index| {
+                let (x, y) = self.cell_origin(index, cell_width, cell_height);
+                imageops::crop_imm(image, x, y, cell_width, cell_height).to_image()
+            })
+            .collect();
+
+        Ok(cells)
+    }
+}
+
+/// File for an exported cell, next to `output`: `<stem>_cell_<index>` for a
+/// single frame, `<stem>_<frame>_cell_<index>` for sequences.
+pub fn cell_path(output: &Path, frame: usize, frames: usize, cell: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = output
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_string());
+
+    let name = if frames > 1 {
+        format!("{}_{:04}_cell_{:03}.{}", stem, frame, cell, extension)
+    } else {
+        format!("{}_cell_{:03}.{}", stem, cell, extension)
+    };
+
+    output.with_file_name(name)
+}
+
+/// Sorts frame files by the last number in their file name, so `frame_10`
+/// follows `frame_9`.
+pub fn sort_numbered(paths: &mut [PathBuf]) {
+    paths.sort_by_cached_key(|path| {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let digits_end = stem
+            .rfind(|c: char| c.is_ascii_digit())
+            .map_or(0, |index| index + 1);
+        let digits_start = stem[..digits_end]
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map_or(0, |index| index + 1);
+
+        let number = stem[digits_start..digits_end].parse::<u64>().ok();
+
+        (
+            path.parent().map(Path::to_path_buf),
+            stem[..digits_start].to_string(),
+            number,
+            stem.clone(),
+        )
+    });
+}
+
+pub struct SheetLayout {
+    pub columns: Option<u32>,
+    pub padding: u32,
+    pub power_of_two: bool,
+}
+
+/// Places `frames` into a sheet of equally sized cells (the largest frame
+/// size) using GPU texture copies, row by row from the top left.
+pub async fn assemble(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    frames: &[RgbaImage],
+    layout: &SheetLayout,
+) -> Result<RgbaImage> {
+    if frames.is_empty() {
+        bail!("No frames to assemble");
+    }
+
+    let count = frames.len() as u32;
+    let columns = layout
+        .columns
+        .unwrap_or_else(|| (count as f64).sqrt().ceil() as u32)
+        .clamp(1, count);
+    let rows = count.div_ceil(columns);
+
+    let cell_width = frames.iter().map(RgbaImage::width).max().unwrap_or(0);
+    let cell_height = frames.iter().map(RgbaImage::height).max().unwrap_or(0);
+
+    let mut width = columns * cell_width + (columns + 1) * layout.padding;
+    let mut height = rows * cell_height + (rows + 1) * layout.padding;
+
+    if layout.power_of_two {
+        width = width.next_power_of_two();
+        height = height.next_power_of_two();
+    }
+
+    let sheet_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    // Freshly created textures are zeroed, which leaves the padding and any
+    // unused cells transparent.
+    let sheet = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Sprite Sheet Texture"),
+        size: sheet_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Sprite Sheet Encoder"),
+    });
+
+    // Keep the frame textures alive until the copies are submitted.
+    let mut textures = Vec::with_capacity(frames.len());
+
+    for (index, frame) in frames.iter().enumerate() {
+        let frame_size = wgpu::Extent3d {
+            width: frame.width(),
+            height: frame.height(),
+            depth_or_array_layers: 1,
+        };
+
+        let texture = crate::create_input_texture(device, queue, frame_size, frame.as_raw());
+
+        let column = index as u32 % columns;
+        let row = index as u32 / columns;
+
+        encoder.copy_texture_to_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyTexture {
+                texture: &sheet,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: layout.padding + column * (cell_width + layout.padding),
+                    y: layout.padding + row * (cell_height + layout.padding),
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            frame_size,
+        );
+
+        textures.push(texture);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let buffer = crate::read_texture(device, queue, &sheet, 0, sheet_size).await?;
+
+    RgbaImage::from_raw(width, height, buffer)
+        .ok_or_else(|| anyhow!("Sprite sheet buffer does not match {}x{}", width, height))
+}
@@ -0,0 +1,235 @@
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use clap::ValueEnum;
+use image::RgbaImage;
+use std::{borrow::Cow, path::PathBuf};
+
+/// How aligned inputs are combined into one image.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StackMode {
+    /// Average, for noise reduction or a long exposure.
+    Mean,
+    /// Per-channel median, which also drops things present in only a few
+    /// inputs, such as passers-by. Reads every input once per bit.
+    Median,
+}
+
+/// Layout of `Settings` in `stack.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    inputs: u32,
+    bit: u32,
+}
+
+/// Combines the images at `paths`, which all need to be the same size. They
+/// are loaded and uploaded one at a time into a single input texture, with
+/// running sums or median search state accumulated in GPU buffers.
+pub async fn stack(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    paths: &[PathBuf],
+    mode: StackMode,
+) -> Result<RgbaImage> {
+    let (width, height) = image::image_dimensions(&paths[0])
+        .with_context(|| format!("Failed to read {}", paths[0].display()))?;
+
+    let texture_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let texels = (texture_size.width * texture_size.height) as u64;
+
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Stack Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/stack.wgsl"))),
+    });
+
+    let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let storage = wgpu::BufferBindingType::Storage { read_only: false };
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Stack Bind Group Layout"),
+        entries: &[
+            crate::input_texture_layout_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                },
+                count: None,
+            },
+            buffer_entry(2, wgpu::BufferBindingType::Uniform),
+            buffer_entry(3, storage),
+            buffer_entry(4, storage),
+        ],
+    });
+
+    let input_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Stack Input Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Stack Output Texture"),
+        size: texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+    });
+
+    let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Stack Settings Buffer"),
+        size: std::mem::size_of::<Settings>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let storage_buffer = |label, size| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    };
+
+    // One vec4<u32> per texel for the sums, two for the selection state.
+    let sums_buffer = storage_buffer("Stack Sums Buffer", texels * 16);
+    let selection_buffer = storage_buffer("Stack Selection Buffer", texels * 32);
+
+    let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Stack Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&output_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: settings_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: sums_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: selection_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Stack Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = |entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Stack Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point,
+        })
+    };
+
+    let run = |pipeline: &wgpu::ComputePipeline, bit| {
+        let settings = Settings {
+            inputs: paths.len() as u32,
+            bit,
+        };
+        queue.write_buffer(&settings_buffer, 0, bytemuck::bytes_of(&settings));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Stack Encoder"),
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Stack Pass"),
+            });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+    };
+
+    // Runs `pipeline` over every input in turn.
+    let stream = |pipeline: &wgpu::ComputePipeline, bit| -> Result<()> {
+        for path in paths {
+            let image = crate::load_image(path)?;
+            if image.dimensions() != (texture_size.width, texture_size.height) {
+                bail!(
+                    "{} is {}x{}, stacked inputs need to match the first one's {}x{}",
+                    path.display(),
+                    image.width(),
+                    image.height(),
+                    texture_size.width,
+                    texture_size.height
+                );
+            }
+
+            crate::write_input_texture(queue, &input_texture, texture_size, &image);
+            run(pipeline, bit);
+        }
+
+        Ok(())
+    };
+
+    match mode {
+        StackMode::Mean => {
+            stream(&pipeline("accumulate"), 0)?;
+            run(&pipeline("mean"), 0);
+        }
+        StackMode::Median => {
+            let count_pipeline = pipeline("count");
+            let select_pipeline = pipeline("select_bit");
+
+            run(&pipeline("median_init"), 0);
+            for bit in (0..8).rev() {
+                stream(&count_pipeline, bit)?;
+                run(&select_pipeline, bit);
+            }
+            run(&pipeline("median"), 0);
+        }
+    }
+
+    let buffer = crate::read_texture(device, queue, &output_texture, 0, texture_size).await?;
+
+    RgbaImage::from_raw(texture_size.width, texture_size.height, buffer)
+        .ok_or_else(|| anyhow!("Stack buffer does not match the image size"))
+}
@@ -0,0 +1,79 @@
+#[cfg(feature = "codecs")]
+use image::{ImageBuffer, Pixel, Rgba};
+
+/// A format kernels can write their output in, with the pixel type it is
+/// read back as, so a kernel's output can only end up in images of its depth.
+pub trait Texel {
+    /// Format of the storage texture at binding 1.
+    const FORMAT: wgpu::TextureFormat;
+    /// Bytes per texel of [`Texel::FORMAT`].
+    const BYTES: u32;
+
+    #[cfg(feature = "codecs")]
+    type Pixel: Pixel;
+
+    /// Converts read back texels, tightly packed, into channels.
+    #[cfg(feature = "codecs")]
+    fn channels(bytes: &[u8]) -> Vec<Subpixel<Self>>;
+}
+
+#[cfg(feature = "codecs")]
+type Subpixel<T> = <<T as Texel>::Pixel as Pixel>::Subpixel;
+
+/// An image of `T`'s pixel type.
+#[cfg(feature = "codecs")]
+pub type TexelImage<T> = ImageBuffer<<T as Texel>::Pixel, Vec<Subpixel<T>>>;
+
+/// 8 bits per channel, written to a `texture_storage_2d<rgba8unorm, write>`.
+pub struct Rgba8;
+
+/// 16 bits per channel. 16 bit unorm textures can't be storage bound, so
+/// kernels write a `texture_storage_2d<rgba32float, write>` that is quantized
+/// on readback.
+pub struct Rgba16;
+
+/// 32 bit floats per channel, written to a
+/// `texture_storage_2d<rgba32float, write>`.
+pub struct Rgba32F;
+
+impl Texel for Rgba8 {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+    const BYTES: u32 = 4;
+
+    #[cfg(feature = "codecs")]
+    type Pixel = Rgba<u8>;
+
+    #[cfg(feature = "codecs")]
+    fn channels(bytes: &[u8]) -> Vec<u8> {
+        bytes.to_vec()
+    }
+}
+
+impl Texel for Rgba16 {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+    const BYTES: u32 = 16;
+
+    #[cfg(feature = "codecs")]
+    type Pixel = Rgba<u16>;
+
+    #[cfg(feature = "codecs")]
+    fn channels(bytes: &[u8]) -> Vec<u16> {
+        Rgba32F::channels(bytes)
+            .into_iter()
+            .map(|value| (value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+            .collect()
+    }
+}
+
+impl Texel for Rgba32F {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+    const BYTES: u32 = 16;
+
+    #[cfg(feature = "codecs")]
+    type Pixel = Rgba<f32>;
+
+    #[cfg(feature = "codecs")]
+    fn channels(bytes: &[u8]) -> Vec<f32> {
+        bytemuck::pod_collect_to_vec(bytes)
+    }
+}
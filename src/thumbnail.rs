@@ -0,0 +1,389 @@
+//! Many small previews at once. Inputs are decoded on all cores, JPEGs
+//! straight at a fraction of their size, and shrunk on the GPU a batch at a
+//! time into the layers of one array texture, which is read back in one go.
+
+use anyhow::*;
+use bytemuck::{Pod, Zeroable};
+use clap::ValueEnum;
+use image::{
+    codecs::{
+        jpeg::{JpegDecoder, JpegEncoder},
+        png::{self, PngEncoder},
+    },
+    imageops, DynamicImage, ImageEncoder, ImageFormat, RgbaImage,
+};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+use wgpu::util::DeviceExt;
+
+use crate::shader;
+
+/// Threads of [`parallel_map`], all cores when zero.
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Workgroup size of `thumbnail.wgsl`.
+const WORKGROUP_SIZE: [u32; 3] = [8, 8, 1];
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ThumbnailFormat {
+    /// Quick to write and small, without alpha.
+    Jpeg,
+    /// Fast compression, keeping alpha.
+    Png,
+}
+
+impl ThumbnailFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+}
+
+/// Layout of `Settings` in `thumbnail.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Settings {
+    layer: u32,
+    padding: u32,
+    size: [u32; 2],
+}
+
+/// Size of the thumbnail of a `width`x`height` image, its longest side at
+/// most `max`. Images that small already keep their size.
+pub fn fit(width: u32, height: u32, max: u32) -> (u32, u32) {
+    if width <= max && height <= max {
+        return (width, height);
+    }
+
+    let scale = max as f64 / width.max(height) as f64;
+    let scaled = |length: u32| ((length as f64 * scale).round() as u32).clamp(1, max);
+
+    (scaled(width), scaled(height))
+}
+
+/// Decodes the image at `path` for a thumbnail of at most `max` texels a
+/// side. JPEGs are decoded at the smallest of 1/8, 1/4 and 1/2 their size
+/// that is still larger than the thumbnail, and images beyond `limit` a
+/// side are shrunk on the CPU to fit a texture.
+pub fn decode(path: &Path, max: u32, limit: u32) -> Result<RgbaImage> {
+    let open = || {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("Failed to open {}", path.display()))
+    };
+
+    let image = if ImageFormat::from_path(path).ok() == Some(ImageFormat::Jpeg) {
+        let mut decoder = JpegDecoder::new(open()?)?;
+        let (width, height) = image::ImageDecoder::dimensions(&decoder);
+        let (width, height) = fit(width, height, max);
+        decoder.scale(
+            width.min(u16::MAX as u32) as u16,
+            height.min(u16::MAX as u32) as u16,
+        )?;
+
+        DynamicImage::from_decoder(decoder)?.into_rgba8()
+    } else {
+        image::io::Reader::new(open()?)
+            .with_guessed_format()?
+            .decode()?
+            .into_rgba8()
+    };
+
+    if image.width() <= limit && image.height() <= limit {
+        return Ok(image);
+    }
+
+    let (width, height) = fit(image.width(), image.height(), limit);
+
+    Ok(imageops::resize(
+        &image,
+        width,
+        height,
+        imageops::FilterType::Triangle,
+    ))
+}
+
+/// Writes `thumbnail` to `path`, dropping alpha for JPEG.
+pub fn encode(
+    thumbnail: &RgbaImage,
+    path: &Path,
+    format: ThumbnailFormat,
+    quality: u8,
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let (width, height) = thumbnail.dimensions();
+
+    match format {
+        ThumbnailFormat::Jpeg => {
+            let rgb = DynamicImage::ImageRgba8(thumbnail.clone()).into_rgb8();
+            JpegEncoder::new_with_quality(&mut writer, quality).write_image(
+                rgb.as_raw(),
+                width,
+                height,
+                image::ColorType::Rgb8,
+            )?;
+        }
+        ThumbnailFormat::Png => {
+            PngEncoder::new_with_quality(
+                &mut writer,
+                png::CompressionType::Fast,
+                png::FilterType::Sub,
+            )
+            .write_image(thumbnail.as_raw(), width, height, image::ColorType::Rgba8)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Has [`parallel_map`] run on `threads` threads rather than all cores.
+pub fn set_threads(threads: usize) {
+    THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// `f` of every item, on all cores or those of [`set_threads`].
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = match THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+    let chunk_size = items.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<_>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+pub struct Thumbnailer {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Thumbnailer {
+    pub fn new(device: &wgpu::Device) -> Result<Self> {
+        let shader = shader::preprocess(include_str!("shaders/thumbnail.wgsl"), None)?;
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Thumbnail Shader Module"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(shader.source)),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Thumbnail Bind Group Layout"),
+            entries: &[
+                crate::input_texture_layout_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Thumbnail Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Thumbnail Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "thumbnail",
+        });
+
+        Ok(Self {
+            bind_group_layout,
+            pipeline,
+        })
+    }
+
+    /// Thumbnails of at most `max` texels a side of `images`, one layer of
+    /// an array texture each. All of them are shrunk in one submission and
+    /// read back in one copy, so there can be no more than the device has
+    /// array layers.
+    pub async fn shrink(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[RgbaImage],
+        max: u32,
+    ) -> Result<Vec<RgbaImage>> {
+        let sizes: Vec<_> = images
+            .iter()
+            .map(|image| fit(image.width(), image.height(), max))
+            .collect();
+
+        let output_size = wgpu::Extent3d {
+            width: sizes.iter().map(|size| size.0).max().unwrap_or(1),
+            height: sizes.iter().map(|size| size.1).max().unwrap_or(1),
+            depth_or_array_layers: images.len() as u32,
+        };
+        if output_size.depth_or_array_layers > device.limits().max_texture_array_layers {
+            bail!(
+                "A batch of {} thumbnails exceeds the device limit of {} array layers",
+                images.len(),
+                device.limits().max_texture_array_layers
+            );
+        }
+
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Thumbnail Output Texture"),
+            size: output_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        });
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Thumbnail Encoder"),
+        });
+
+        // Keep the inputs alive until the passes are submitted.
+        let mut inputs = Vec::with_capacity(images.len());
+
+        for (layer, (image, &(width, height))) in images.iter().zip(&sizes).enumerate() {
+            let input_size = wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            };
+            let input = crate::create_input_texture(device, queue, input_size, image.as_raw());
+            let input_view = input.create_view(&wgpu::TextureViewDescriptor::default());
+
+            let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Thumbnail Settings Buffer"),
+                contents: bytemuck::bytes_of(&Settings {
+                    layer: layer as u32,
+                    padding: 0,
+                    size: [width, height],
+                }),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Thumbnail Bind Group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&input_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&output_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: settings_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Thumbnail Pass"),
+                });
+                compute_pass.set_pipeline(&self.pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                let (x, y) = crate::workgroup_count(width, height, WORKGROUP_SIZE);
+                compute_pass.dispatch_workgroups(x, y, 1);
+            }
+
+            inputs.push(input);
+        }
+
+        // All layers go into one readback buffer, one after the other.
+        let align_width = crate::align_up(
+            output_size.width * crate::DATA_PER_PIXEL,
+            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+        );
+        let rows = output_size.height * output_size.depth_or_array_layers;
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Thumbnail Buffer"),
+            size: (align_width * rows) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &output_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(align_width),
+                    rows_per_image: Some(output_size.height),
+                },
+            },
+            output_size,
+        );
+
+        queue.submit(Some(encoder.finish()));
+        drop(inputs);
+
+        let buffer =
+            crate::view_into_buffer(device, output_size.width, rows, &output_buffer).await?;
+        let layer_bytes = (output_size.width * output_size.height * crate::DATA_PER_PIXEL) as usize;
+
+        let thumbnails = buffer
+            .chunks(layer_bytes)
+            .zip(sizes)
+            .map(|(layer, (width, height))| {
+                let layer =
+                    RgbaImage::from_raw(output_size.width, output_size.height, layer.to_vec())
+                        .expect("Read back layers match the texture size");
+
+                imageops::crop_imm(&layer, 0, 0, width, height).to_image()
+            })
+            .collect();
+
+        Ok(thumbnails)
+    }
+}
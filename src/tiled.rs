@@ -0,0 +1,446 @@
+//! Images read a chunk at a time, for inputs beyond any single texture:
+//! DeepZoom pyramids, whose full-size level is read, and TIFFs too large for
+//! a texture, read by tile or strip. Chunks are decoded when a region first
+//! touches them and kept until [`TiledImage::evict_above`] drops them.
+
+use anyhow::*;
+use image::{imageops, RgbaImage};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use tiff::{
+    decoder::{ChunkType, Decoder, DecodingResult, Limits},
+    ColorType,
+};
+
+use crate::deepzoom;
+
+pub struct TiledImage {
+    width: u32,
+    height: u32,
+    chunk_width: u32,
+    chunk_height: u32,
+    source: Source,
+    chunks: HashMap<(u32, u32), RgbaImage>,
+}
+
+enum Source {
+    /// `<column>_<row>.<format>` files in `dir`, covering `overlap` texels of
+    /// their neighbours on each side.
+    DeepZoom {
+        dir: PathBuf,
+        format: String,
+        overlap: u32,
+    },
+    Tiff {
+        decoder: Box<Decoder<BufReader<File>>>,
+        color: ColorType,
+        tiles: bool,
+    },
+}
+
+impl TiledImage {
+    /// Opens `path` for reading a chunk at a time if it is a DeepZoom
+    /// descriptor, or a TIFF with a side longer than `max_dimension`. `None`
+    /// for anything to load whole.
+    pub fn open(path: &Path, max_dimension: u32) -> Result<Option<Self>> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("dzi") => Self::open_deepzoom(path).map(Some),
+            Some("tif" | "tiff") => Self::open_tiff(path, max_dimension),
+            _ => Ok(None),
+        }
+    }
+
+    /// The tiles the DeepZoom descriptor at `path` is read from, those of
+    /// its full-size level. None for other images, which are read whole.
+    pub fn tile_files(path: &Path) -> Result<Vec<PathBuf>> {
+        let Some(Source::DeepZoom { dir, .. }) =
+            Self::open(path, u32::MAX)?.map(|image| image.source)
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut files = Vec::new();
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+
+    /// Level `dir` of a DeepZoom pyramid, `width`x`height` in tiles of
+    /// `tile_size` in `format`.
+    pub fn deepzoom_level(
+        dir: PathBuf,
+        width: u32,
+        height: u32,
+        settings: &deepzoom::DeepZoom,
+        format: &str,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            chunk_width: settings.tile_size,
+            chunk_height: settings.tile_size,
+            source: Source::DeepZoom {
+                dir,
+                format: format.to_string(),
+                overlap: settings.overlap,
+            },
+            chunks: HashMap::new(),
+        }
+    }
+
+    fn open_deepzoom(path: &Path) -> Result<Self> {
+        let descriptor = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let attribute = |name: &str| {
+            xml_attribute(&descriptor, name)
+                .ok_or_else(|| anyhow!("{} has no {} attribute", path.display(), name))
+        };
+        let number = |name: &str| -> Result<u32> {
+            attribute(name)?
+                .parse()
+                .with_context(|| format!("Invalid {} in {}", name, path.display()))
+        };
+
+        let (width, height) = (number("Width")?, number("Height")?);
+        let tile_size = number("TileSize")?;
+        if width == 0 || height == 0 || tile_size == 0 {
+            bail!("{} describes an empty image", path.display());
+        }
+
+        let level = deepzoom::max_level(width, height);
+        let dir = deepzoom::tiles_dir(path).join(level.to_string());
+
+        Ok(Self {
+            width,
+            height,
+            chunk_width: tile_size,
+            chunk_height: tile_size,
+            source: Source::DeepZoom {
+                dir,
+                format: attribute("Format")?.to_string(),
+                overlap: number("Overlap")?,
+            },
+            chunks: HashMap::new(),
+        })
+    }
+
+    fn open_tiff(path: &Path, max_dimension: u32) -> Result<Option<Self>> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // The offsets of the chunks of a large image outgrow the default
+        // limits; chunks themselves are decoded one at a time.
+        let mut decoder = Decoder::new(BufReader::new(file))
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .with_limits(Limits::unlimited());
+
+        let (width, height) = decoder.dimensions()?;
+        if width <= max_dimension && height <= max_dimension {
+            return Ok(None);
+        }
+
+        let color = decoder.colortype()?;
+        match color {
+            ColorType::Gray(8 | 16)
+            | ColorType::GrayA(8 | 16)
+            | ColorType::RGB(8 | 16)
+            | ColorType::RGBA(8 | 16) => {}
+            color => bail!(
+                "{} is {:?}, only 8 and 16 bit gray, RGB and RGBA TIFFs are read in tiles",
+                path.display(),
+                color
+            ),
+        }
+
+        let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+        let tiles = decoder.get_chunk_type() == ChunkType::Tile;
+
+        Ok(Some(Self {
+            width,
+            height,
+            chunk_width,
+            chunk_height,
+            source: Source::Tiff {
+                decoder: Box::new(decoder),
+                color,
+                tiles,
+            },
+            chunks: HashMap::new(),
+        }))
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The `width`x`height` region at `x`, `y`, which may reach past the
+    /// edges of the image; texels there repeat the nearest edge.
+    pub fn read(&mut self, x: i64, y: i64, width: u32, height: u32) -> Result<RgbaImage> {
+        let clamp = |value: i64, size: u32| value.clamp(0, size as i64 - 1) as u32;
+        let (left, top) = (clamp(x, self.width), clamp(y, self.height));
+        let right = clamp(x + width as i64 - 1, self.width) + 1;
+        let bottom = clamp(y + height as i64 - 1, self.height) + 1;
+
+        let mut inner = RgbaImage::new(right - left, bottom - top);
+        for row in top / self.chunk_height..bottom.div_ceil(self.chunk_height) {
+            for column in left / self.chunk_width..right.div_ceil(self.chunk_width) {
+                let (chunk_x, chunk_y) = (column * self.chunk_width, row * self.chunk_height);
+                let chunk = self.chunk(column, row)?;
+
+                imageops::replace(
+                    &mut inner,
+                    chunk,
+                    chunk_x as i64 - left as i64,
+                    chunk_y as i64 - top as i64,
+                );
+            }
+        }
+
+        if (left as i64, top as i64, inner.dimensions()) == (x, y, (width, height)) {
+            return Ok(inner);
+        }
+
+        Ok(RgbaImage::from_fn(width, height, |column, row| {
+            let source_x = clamp(x + column as i64, self.width) - left;
+            let source_y = clamp(y + row as i64, self.height) - top;
+
+            *inner.get_pixel(source_x, source_y)
+        }))
+    }
+
+    /// Drops the chunks that end above row `y`, which later regions don't
+    /// reach.
+    pub fn evict_above(&mut self, y: i64) {
+        let chunk_height = self.chunk_height as i64;
+        self.chunks
+            .retain(|&(_, row), _| (row as i64 + 1) * chunk_height > y);
+    }
+
+    fn chunk(&mut self, column: u32, row: u32) -> Result<&RgbaImage> {
+        if !self.chunks.contains_key(&(column, row)) {
+            let chunk = self.load_chunk(column, row)?;
+            self.chunks.insert((column, row), chunk);
+        }
+
+        Ok(&self.chunks[&(column, row)])
+    }
+
+    fn load_chunk(&mut self, column: u32, row: u32) -> Result<RgbaImage> {
+        let (x, y) = (column * self.chunk_width, row * self.chunk_height);
+        let width = self.chunk_width.min(self.width - x);
+        let height = self.chunk_height.min(self.height - y);
+
+        match &mut self.source {
+            Source::DeepZoom {
+                dir,
+                format,
+                overlap,
+            } => {
+                let path = dir.join(format!("{}_{}.{}", column, row, format));
+                let tile = crate::load_image(&path)?;
+
+                // Tiles past the first of their row or column start with the
+                // overlap.
+                let left = if column > 0 { *overlap } else { 0 };
+                let top = if row > 0 { *overlap } else { 0 };
+                if tile.width() < left + width || tile.height() < top + height {
+                    bail!(
+                        "{} is {}x{}, too small for its place in the pyramid",
+                        path.display(),
+                        tile.width(),
+                        tile.height()
+                    );
+                }
+
+                Ok(imageops::crop_imm(&tile, left, top, width, height).to_image())
+            }
+            Source::Tiff {
+                decoder,
+                color,
+                tiles,
+            } => {
+                let index = if *tiles {
+                    row * self.width.div_ceil(self.chunk_width) + column
+                } else {
+                    row
+                };
+
+                let samples: Vec<u8> = match decoder.read_chunk(index)? {
+                    DecodingResult::U8(samples) => samples,
+                    DecodingResult::U16(samples) => {
+                        samples.iter().map(|sample| (sample >> 8) as u8).collect()
+                    }
+                    _ => bail!("Unexpected sample format in TIFF chunk {}", index),
+                };
+
+                let channels = match color {
+                    ColorType::Gray(_) => 1,
+                    ColorType::GrayA(_) => 2,
+                    ColorType::RGB(_) => 3,
+                    _ => 4,
+                };
+                let texels = samples
+                    .chunks_exact(channels)
+                    .flat_map(|texel| match texel {
+                        &[gray] => [gray, gray, gray, 255],
+                        &[gray, alpha] => [gray, gray, gray, alpha],
+                        &[r, g, b] => [r, g, b, 255],
+                        texel => [texel[0], texel[1], texel[2], texel[3]],
+                    })
+                    .collect();
+
+                RgbaImage::from_raw(width, height, texels)
+                    .ok_or_else(|| anyhow!("TIFF chunk {} is smaller than expected", index))
+            }
+        }
+    }
+}
+
+/// Value of the first `name="..."` in `xml`.
+fn xml_attribute<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("{}=\"", name);
+    let start = xml
+        .match_indices(&pattern)
+        .map(|(index, _)| index)
+        // A whole attribute name, not the end of a longer one.
+        .find(|&index| {
+            !xml[..index]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric)
+        })?
+        + pattern.len();
+    let end = xml[start..].find('"')?;
+
+    Some(&xml[start..start + end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deepzoom::{DeepZoom, PyramidWriter},
+        mipmap::{FilterSpace, MipFilter, MipmapSettings},
+    };
+    use image::{Rgb, RgbImage, Rgba};
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wtc-tiled-{}-{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn finds_whole_attributes() {
+        let xml = r#"<Image TileSize="256" Overlap="1"><Size Width="10" Height="6"/></Image>"#;
+
+        assert_eq!(xml_attribute(xml, "TileSize"), Some("256"));
+        assert_eq!(xml_attribute(xml, "Size"), None);
+        assert_eq!(xml_attribute(xml, "Height"), Some("6"));
+        assert_eq!(xml_attribute(xml, "Format"), None);
+    }
+
+    #[test]
+    fn reads_large_tiffs_by_strip() -> Result<()> {
+        let dir = temp_dir("tiff");
+        let path = dir.join("strips.tif");
+        let source = RgbImage::from_fn(100, 90, |x, y| Rgb([x as u8, y as u8, (x ^ y) as u8]));
+        let mut encoder = tiff::encoder::TiffEncoder::new(File::create(&path)?)?;
+        let mut strips = encoder.new_image::<tiff::encoder::colortype::RGB8>(100, 90)?;
+        strips.rows_per_strip(16)?;
+        strips.write_data(source.as_raw())?;
+
+        assert!(TiledImage::open(&path, 100)?.is_none());
+        assert!(TiledImage::open(&dir.join("strips.png"), 1)?.is_none());
+        let mut tiled = TiledImage::open(&path, 64)?.unwrap();
+        assert_eq!(tiled.dimensions(), (100, 90));
+        assert_eq!(tiled.chunk_height, 16);
+
+        let texel = |x: u32, y: u32| {
+            let Rgb([r, g, b]) = *source.get_pixel(x, y);
+            Rgba([r, g, b, 255])
+        };
+        let region = tiled.read(40, 20, 30, 50)?;
+        assert!(region
+            .enumerate_pixels()
+            .all(|(x, y, pixel)| *pixel == texel(x + 40, y + 20)));
+
+        // Past the corner, the edge repeats.
+        let corner = tiled.read(98, -2, 4, 4)?;
+        assert_eq!(corner.get_pixel(0, 0), &texel(98, 0));
+        assert_eq!(corner.get_pixel(3, 3), &texel(99, 1));
+
+        tiled.evict_above(60);
+        assert!(tiled
+            .chunks
+            .keys()
+            .all(|&(_, row)| (row + 1) * tiled.chunk_height > 60));
+
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_the_full_level_of_a_pyramid() -> Result<()> {
+        let dir = temp_dir("dzi");
+        let output = dir.join("pyramid.png");
+        let settings = DeepZoom {
+            tile_size: 8,
+            overlap: 2,
+            mipmaps: MipmapSettings {
+                filter: MipFilter::Box,
+                space: FilterSpace::Linear,
+                alpha_coverage: None,
+                normal_map: false,
+                toksvig: false,
+            },
+        };
+        let image = RgbaImage::from_fn(19, 12, |x, y| Rgba([x as u8 * 13, y as u8 * 21, 7, 200]));
+
+        let mut writer = PyramidWriter::new(&output, 19, 12, &settings)?;
+        let (columns, rows) = writer.grid();
+        for row in 0..rows {
+            for column in 0..columns {
+                let bounds = writer.tile_bounds(column, row);
+                let tile =
+                    imageops::crop_imm(&image, bounds.x, bounds.y, bounds.width, bounds.height);
+                writer.write_tile(column, row, &tile.to_image())?;
+            }
+        }
+        writer.finish()?;
+
+        let descriptor = deepzoom::descriptor_path(&output);
+        let mut tiled = TiledImage::open(&descriptor, 0)?.unwrap();
+        assert_eq!(tiled.dimensions(), (19, 12));
+        assert_eq!(tiled.read(0, 0, 19, 12)?, image);
+        assert_eq!(
+            TiledImage::tile_files(&descriptor)?.len(),
+            (columns * rows) as usize
+        );
+        assert!(TiledImage::tile_files(&output)?.is_empty());
+
+        fs::write(
+            &descriptor,
+            r#"<Image TileSize="8"><Size Width="0" Height="4"/></Image>"#,
+        )?;
+        assert!(TiledImage::open(&descriptor, 0).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! Time the GPU spends on the stages of a frame, from timestamp queries.
+
+use anyhow::*;
+
+/// Timestamps written before the compute pass, between it and the copy into
+/// the readback buffer and after that copy.
+const TIMESTAMPS: u32 = 3;
+
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+/// Milliseconds spent on each stage of a frame.
+#[derive(Clone, Copy)]
+pub struct GpuTimings {
+    /// The compute passes, including the copy out of the ping-pong texture
+    /// when iterating.
+    pub compute: f64,
+    /// The copy of the output into the readback buffer.
+    pub copy: f64,
+}
+
+impl GpuTimer {
+    /// A timer, or `None` when `device` has no timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Timestamp Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: TIMESTAMPS,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Buffer"),
+            size: TIMESTAMPS as wgpu::BufferAddress * wgpu::QUERY_SIZE as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            period: queue.get_timestamp_period(),
+        })
+    }
+
+    /// Writes timestamp `index`, 0 before the compute pass, 1 before the copy
+    /// and 2 after it.
+    pub fn mark(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        encoder.write_timestamp(&self.query_set, index);
+    }
+
+    /// Records copying the timestamps into a buffer `read` can map, after
+    /// the last of them.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..TIMESTAMPS, &self.resolve_buffer, 0);
+    }
+
+    /// The timings of the frame submitted last.
+    pub async fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<GpuTimings> {
+        let bytes = crate::read_buffer(device, queue, &self.resolve_buffer).await?;
+        let ticks: &[u64] = bytemuck::cast_slice(&bytes);
+
+        // Timestamps of some drivers aren't monotonic across stages.
+        let millis = |from: usize, to: usize| {
+            ticks[to].saturating_sub(ticks[from]) as f64 * self.period as f64 / 1e6
+        };
+
+        Ok(GpuTimings {
+            compute: millis(0, 1),
+            copy: millis(1, 2),
+        })
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::*;
+use serde::Serialize;
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+
+/// WGSL of the alpha bounds reduction.
+pub const SHADER: &str = include_str!("shaders/alpha_bounds.wgsl");
+
+/// Pixel rectangle inside an image.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Bounds {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Bounding box of the texels of `texture` whose alpha exceeds `threshold`,
+/// computed with an atomic min/max reduction. `None` if every texel is
+/// transparent.
+pub async fn alpha_bounds(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    texture_size: wgpu::Extent3d,
+    threshold: f32,
+) -> Result<Option<Bounds>> {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Alpha Bounds Shader Module"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Alpha Bounds Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let initial: [u32; 4] = [u32::MAX, u32::MAX, 0, 0];
+
+    let bounds_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Alpha Bounds Buffer"),
+        contents: bytemuck::cast_slice(&initial),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+
+    let threshold_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Alpha Threshold Buffer"),
+        contents: bytemuck::cast_slice(&[threshold, 0.0, 0.0, 0.0]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Alpha Bounds Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: bounds_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: threshold_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Alpha Bounds Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Alpha Bounds Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: "alpha_bounds",
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Alpha Bounds Encoder"),
+    });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Alpha Bounds Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(texture_size.width, texture_size.height, 1);
+    }
+
+    queue.submit(Some(encoder.finish()));
+
+    let values = crate::read_buffer(device, queue, &bounds_buffer).await?;
+    let [min_x, min_y, max_x, max_y]: [u32; 4] = bytemuck::pod_read_unaligned(&values);
+
+    if min_x > max_x || min_y > max_y {
+        return Ok(None);
+    }
+
+    Ok(Some(Bounds {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x + 1,
+        height: max_y - min_y + 1,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::context;
+    use image::{Rgba, RgbaImage};
+    use wgpu_texture_copy::GpuContext;
+
+    fn bounds(context: &GpuContext, image: &RgbaImage, threshold: f32) -> Result<Option<Bounds>> {
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture =
+            wgpu_texture_copy::create_input_texture(&context.device, &context.queue, size, image);
+
+        futures::executor::block_on(alpha_bounds(
+            &context.device,
+            &context.queue,
+            &texture,
+            size,
+            threshold,
+        ))
+    }
+
+    #[test]
+    fn bounds_the_texels_above_the_threshold() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        // A faint smudge at the top left, two opaque specks further in.
+        let mut image = RgbaImage::new(9, 7);
+        image.put_pixel(0, 0, Rgba([255, 255, 255, 20]));
+        image.put_pixel(2, 5, Rgba([0, 0, 0, 255]));
+        image.put_pixel(6, 3, Rgba([10, 20, 30, 200]));
+
+        let opaque = bounds(&context, &image, 0.5)?.unwrap();
+        assert_eq!(
+            (opaque.x, opaque.y, opaque.width, opaque.height),
+            (2, 3, 5, 3)
+        );
+        let any = bounds(&context, &image, 0.0)?.unwrap();
+        assert_eq!((any.x, any.y, any.width, any.height), (0, 0, 7, 6));
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_nothing_in_a_transparent_image() -> Result<()> {
+        let Some(context) = context() else {
+            return Ok(());
+        };
+
+        let image = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 0]));
+        assert!(bounds(&context, &image, 0.0)?.is_none());
+        // Nothing exceeds full opacity.
+        let image = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        assert!(bounds(&context, &image, 1.0)?.is_none());
+
+        Ok(())
+    }
+}
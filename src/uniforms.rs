@@ -0,0 +1,31 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::ops::MAX_PARAMS;
+
+/// Mirrors `Globals` in `shaders/lib/globals.wgsl`, bound for every shader at
+/// group 0, binding 2.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct Globals {
+    pub size: [u32; 2],
+    pub seed: u32,
+    pub frame: u32,
+    pub params: [[f32; 4]; MAX_PARAMS / 4],
+}
+
+impl Globals {
+    pub fn new(width: u32, height: u32, seed: u32, frame: u32, params: &[f32; MAX_PARAMS]) -> Self {
+        let mut packed = [[0.0; 4]; MAX_PARAMS / 4];
+
+        for (index, value) in params.iter().enumerate() {
+            packed[index / 4][index % 4] = *value;
+        }
+
+        Self {
+            size: [width, height],
+            seed,
+            frame,
+            params: packed,
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Uploads without a second copy of the image. `Queue::write_texture` copies
+//! the data it gets into a staging buffer of its own; [`upload_mapped`]
+//! instead hands out a staging buffer mapped at creation to write the texels
+//! into, such as by decoding straight into it, and copies that into the
+//! texture on the GPU.
+
+use wgpu::{Device, Queue};
+
+use crate::{align_up, check_image_size, input_texture_descriptor, Result, DATA_PER_PIXEL};
+
+/// Texels of what is written into the mapping of [`upload_mapped`], which
+/// widens them to RGBA8 in place the way `image`'s `to_rgba8` does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceLayout {
+    Luma,
+    LumaAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl SourceLayout {
+    /// Bytes per texel.
+    pub fn channels(self) -> u32 {
+        match self {
+            Self::Luma => 1,
+            Self::LumaAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+/// Creates a `width`x`height` texture like
+/// [`create_input_texture`](crate::create_input_texture), whose texels `write`
+/// puts into a mapped staging buffer. It gets exactly `width * height` tightly
+/// packed texels of `layout`, which are spread to the padded RGBA8 rows of the
+/// copy afterwards.
+pub fn upload_mapped(
+    device: &Device,
+    queue: &Queue,
+    width: u32,
+    height: u32,
+    layout: SourceLayout,
+    write: impl FnOnce(&mut [u8]) -> Result<()>,
+) -> Result<wgpu::Texture> {
+    check_image_size(device, width, height, DATA_PER_PIXEL)?;
+
+    let stride = align_up(width * DATA_PER_PIXEL, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Upload Staging Buffer"),
+        size: stride as u64 * height as u64,
+        usage: wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: true,
+    });
+
+    {
+        let mut mapping = staging.slice(..).get_mapped_range_mut();
+        let packed = width as usize * height as usize * layout.channels() as usize;
+
+        write(&mut mapping[..packed])?;
+        widen_rows(&mut mapping, width, height, layout, stride);
+    }
+    staging.unmap();
+
+    let texture_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&input_texture_descriptor(texture_size));
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Upload Encoder"),
+    });
+    encoder.copy_buffer_to_texture(
+        wgpu::ImageCopyBuffer {
+            buffer: &staging,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(stride),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.as_image_copy(),
+        texture_size,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    Ok(texture)
+}
+
+/// Moves the tightly packed texels of `layout` at the start of `mapping` to
+/// RGBA8 rows `stride` apart. Every texel only moves towards the end, so
+/// going from the last one back never overwrites one that is yet to move.
+fn widen_rows(mapping: &mut [u8], width: u32, height: u32, layout: SourceLayout, stride: u32) {
+    let (width, stride) = (width as usize, stride as usize);
+    let channels = layout.channels() as usize;
+    let row_bytes = width * channels;
+
+    for y in (0..height as usize).rev() {
+        let (source, target) = (y * row_bytes, y * stride);
+
+        if layout == SourceLayout::Rgba {
+            mapping.copy_within(source..source + row_bytes, target);
+            continue;
+        }
+
+        for x in (0..width).rev() {
+            let texel = &mapping[source + x * channels..][..channels];
+            let widened = match layout {
+                SourceLayout::Luma => [texel[0], texel[0], texel[0], u8::MAX],
+                SourceLayout::LumaAlpha => [texel[0], texel[0], texel[0], texel[1]],
+                SourceLayout::Rgb => [texel[0], texel[1], texel[2], u8::MAX],
+                SourceLayout::Rgba => unreachable!(),
+            };
+
+            mapping[target + x * 4..][..4].copy_from_slice(&widened);
+        }
+    }
+}
+
+/// Decodes the image file at `path` into a texture like
+/// [`create_input_texture`](crate::create_input_texture). PNGs and JPEGs of 8
+/// bits per channel are decoded straight into the staging buffer of
+/// [`upload_mapped`]; anything else is decoded to an RGBA8 image first.
+#[cfg(feature = "codecs")]
+pub fn upload_file(
+    device: &Device,
+    queue: &Queue,
+    path: impl AsRef<std::path::Path>,
+) -> Result<wgpu::Texture> {
+    use image::{
+        codecs::{jpeg::JpegDecoder, png::PngDecoder},
+        io::Reader,
+        ImageFormat,
+    };
+
+    use crate::Error;
+
+    let path = path.as_ref();
+    let io_error = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let decode_error = |source| Error::Decode {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let reader = Reader::open(path)
+        .and_then(Reader::with_guessed_format)
+        .map_err(io_error)?;
+
+    match reader.format() {
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader.into_inner()).map_err(decode_error)?;
+            upload_decoded(device, queue, decoder, path)
+        }
+        Some(ImageFormat::Jpeg) => {
+            let decoder = JpegDecoder::new(reader.into_inner()).map_err(decode_error)?;
+            upload_decoded(device, queue, decoder, path)
+        }
+        _ => {
+            let image = reader.decode().map_err(decode_error)?;
+            upload_image(device, queue, &image.to_rgba8())
+        }
+    }
+}
+
+#[cfg(feature = "codecs")]
+fn upload_decoded<'a>(
+    device: &Device,
+    queue: &Queue,
+    decoder: impl image::ImageDecoder<'a>,
+    path: &std::path::Path,
+) -> Result<wgpu::Texture> {
+    use image::ColorType;
+
+    let decode_error = |source| crate::Error::Decode {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let (width, height) = decoder.dimensions();
+    let layout = match decoder.color_type() {
+        ColorType::L8 => SourceLayout::Luma,
+        ColorType::La8 => SourceLayout::LumaAlpha,
+        ColorType::Rgb8 => SourceLayout::Rgb,
+        ColorType::Rgba8 => SourceLayout::Rgba,
+        _ => {
+            let image = image::DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+            return upload_image(device, queue, &image.to_rgba8());
+        }
+    };
+
+    upload_mapped(device, queue, width, height, layout, |mapping| {
+        decoder.read_image(mapping).map_err(decode_error)
+    })
+}
+
+#[cfg(feature = "codecs")]
+fn upload_image(device: &Device, queue: &Queue, image: &image::RgbaImage) -> Result<wgpu::Texture> {
+    let (width, height) = image.dimensions();
+
+    upload_mapped(
+        device,
+        queue,
+        width,
+        height,
+        SourceLayout::Rgba,
+        |mapping| {
+            mapping.copy_from_slice(image);
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Widens the packed 2x2 texels of `layout` into rows 12 bytes apart,
+    /// 4 bytes of padding after each.
+    fn widen(packed: &[u8], layout: SourceLayout) -> Vec<u8> {
+        let mut mapping = vec![0xAA; 24];
+        mapping[..packed.len()].copy_from_slice(packed);
+        widen_rows(&mut mapping, 2, 2, layout, 12);
+
+        mapping
+    }
+
+    fn texel_rows(mapping: &[u8]) -> [&[u8]; 2] {
+        [&mapping[..8], &mapping[12..20]]
+    }
+
+    #[test]
+    fn widens_luma() {
+        let widened = widen(&[1, 2, 3, 4], SourceLayout::Luma);
+
+        assert_eq!(
+            texel_rows(&widened),
+            [[1, 1, 1, 255, 2, 2, 2, 255], [3, 3, 3, 255, 4, 4, 4, 255]]
+        );
+    }
+
+    #[test]
+    fn widens_luma_alpha() {
+        let widened = widen(&[1, 10, 2, 20, 3, 30, 4, 40], SourceLayout::LumaAlpha);
+
+        assert_eq!(
+            texel_rows(&widened),
+            [[1, 1, 1, 10, 2, 2, 2, 20], [3, 3, 3, 30, 4, 4, 4, 40]]
+        );
+    }
+
+    #[test]
+    fn widens_rgb() {
+        let packed: Vec<u8> = (1..=12).collect();
+        let widened = widen(&packed, SourceLayout::Rgb);
+
+        assert_eq!(
+            texel_rows(&widened),
+            [
+                [1, 2, 3, 255, 4, 5, 6, 255],
+                [7, 8, 9, 255, 10, 11, 12, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_rgba_rows_to_the_stride() {
+        let packed: Vec<u8> = (1..=16).collect();
+        let widened = widen(&packed, SourceLayout::Rgba);
+
+        assert_eq!(
+            texel_rows(&widened),
+            [[1, 2, 3, 4, 5, 6, 7, 8], [9, 10, 11, 12, 13, 14, 15, 16]]
+        );
+        // Past the texels of the last row nothing was written.
+        assert_eq!(widened[20..], [0xAA; 4]);
+    }
+
+    #[test]
+    fn widens_nothing_of_zero_rows() {
+        let mut mapping = vec![7; 4];
+        widen_rows(&mut mapping, 1, 0, SourceLayout::Luma, 4);
+
+        assert_eq!(mapping, [7; 4]);
+    }
+}